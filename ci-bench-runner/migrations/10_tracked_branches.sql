@@ -0,0 +1,4 @@
+ALTER TABLE bench_runs ADD COLUMN branch TEXT NOT NULL DEFAULT 'main';
+ALTER TABLE regressions ADD COLUMN branch TEXT NOT NULL DEFAULT 'main';
+
+CREATE INDEX idx_bench_runs_branch ON bench_runs(branch, created_utc);
@@ -0,0 +1,3 @@
+ALTER TABLE jobs ADD COLUMN requester TEXT;
+ALTER TABLE jobs ADD COLUMN cpu_seconds REAL;
+ALTER TABLE jobs ADD COLUMN wall_seconds REAL;
@@ -0,0 +1 @@
+ALTER TABLE comparison_runs ADD COLUMN invalidated INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+ALTER TABLE bench_results ADD COLUMN result_unit INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE scenario_diffs ADD COLUMN result_unit INTEGER NOT NULL DEFAULT 0;
+
+UPDATE bench_results SET result_unit = 1 WHERE scenario_kind = 1;
+UPDATE scenario_diffs SET result_unit = 1 WHERE scenario_kind = 1;
@@ -0,0 +1,7 @@
+CREATE TABLE fork_approvals(
+    pr_number INTEGER NOT NULL,
+    commit_sha TEXT NOT NULL,
+    approved_by TEXT NOT NULL,
+    created_utc TEXT NOT NULL,
+    PRIMARY KEY (pr_number, commit_sha)
+) STRICT;
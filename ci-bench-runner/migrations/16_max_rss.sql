@@ -0,0 +1 @@
+ALTER TABLE comparison_runs ADD COLUMN max_rss_scenarios_missing_in_baseline TEXT;
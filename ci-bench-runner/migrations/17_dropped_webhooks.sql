@@ -0,0 +1,6 @@
+CREATE TABLE dropped_webhooks(
+    id INTEGER PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    event TEXT NOT NULL,
+    repository TEXT
+) STRICT;
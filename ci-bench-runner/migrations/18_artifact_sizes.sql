@@ -0,0 +1,7 @@
+CREATE TABLE artifact_sizes(
+    commit_key TEXT NOT NULL,
+    artifact TEXT NOT NULL,
+    size_bytes INTEGER NOT NULL,
+    created_utc TEXT NOT NULL,
+    PRIMARY KEY (commit_key, artifact)
+) STRICT;
@@ -0,0 +1,23 @@
+CREATE TABLE comparison_missing_scenarios(
+    comparison_run_id BLOB NOT NULL,
+    scenario_kind INTEGER NOT NULL,
+    scenario_name TEXT NOT NULL,
+    PRIMARY KEY (comparison_run_id, scenario_kind, scenario_name),
+    FOREIGN KEY (comparison_run_id) REFERENCES comparison_runs(id)
+) STRICT;
+
+INSERT OR IGNORE INTO comparison_missing_scenarios (comparison_run_id, scenario_kind, scenario_name)
+SELECT comparison_runs.id, 0, missing.value
+FROM comparison_runs, json_each(comparison_runs.icount_scenarios_missing_in_baseline) AS missing;
+
+INSERT OR IGNORE INTO comparison_missing_scenarios (comparison_run_id, scenario_kind, scenario_name)
+SELECT comparison_runs.id, 1, missing.value
+FROM comparison_runs, json_each(comparison_runs.walltime_scenarios_missing_in_baseline) AS missing;
+
+INSERT OR IGNORE INTO comparison_missing_scenarios (comparison_run_id, scenario_kind, scenario_name)
+SELECT comparison_runs.id, 2, missing.value
+FROM comparison_runs, json_each(comparison_runs.max_rss_scenarios_missing_in_baseline) AS missing;
+
+ALTER TABLE comparison_runs DROP COLUMN icount_scenarios_missing_in_baseline;
+ALTER TABLE comparison_runs DROP COLUMN walltime_scenarios_missing_in_baseline;
+ALTER TABLE comparison_runs DROP COLUMN max_rss_scenarios_missing_in_baseline;
@@ -0,0 +1 @@
+ALTER TABLE bench_runs ADD COLUMN commit_sha TEXT;
@@ -0,0 +1 @@
+ALTER TABLE scenario_diffs ADD COLUMN cachegrind_diff_overflow_bytes INTEGER;
@@ -0,0 +1,14 @@
+CREATE TABLE api_comparisons(
+    id BLOB PRIMARY KEY,
+    client TEXT NOT NULL,
+    baseline_ref TEXT NOT NULL,
+    candidate_ref TEXT NOT NULL,
+    baseline_commit TEXT,
+    candidate_commit TEXT,
+    job_id BLOB REFERENCES jobs(id),
+    created_utc TEXT NOT NULL,
+    finished_utc TEXT,
+    verdict TEXT,
+    result TEXT,
+    error TEXT
+) STRICT;
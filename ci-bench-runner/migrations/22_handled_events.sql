@@ -0,0 +1,10 @@
+CREATE TABLE handled_events(
+    id BLOB PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    handled_utc TEXT NOT NULL,
+    event TEXT NOT NULL,
+    payload BLOB NOT NULL,
+    priority INTEGER NOT NULL
+) STRICT;
+
+CREATE INDEX idx_handled_events_handled_utc ON handled_events(handled_utc);
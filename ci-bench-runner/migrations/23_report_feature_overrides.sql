@@ -0,0 +1,5 @@
+CREATE TABLE report_feature_overrides(
+    feature TEXT PRIMARY KEY,
+    rollout TEXT NOT NULL,
+    updated_utc TEXT NOT NULL
+) STRICT;
@@ -0,0 +1 @@
+ALTER TABLE event_queue ADD COLUMN not_before_utc TEXT;
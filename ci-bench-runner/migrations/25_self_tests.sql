@@ -0,0 +1,8 @@
+CREATE TABLE self_tests(
+    id BLOB PRIMARY KEY,
+    job_id BLOB REFERENCES jobs(id),
+    created_utc TEXT NOT NULL,
+    finished_utc TEXT,
+    timings TEXT,
+    error TEXT
+) STRICT;
@@ -0,0 +1,10 @@
+CREATE TABLE dead_events(
+    id BLOB PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    died_utc TEXT NOT NULL,
+    event TEXT NOT NULL,
+    payload BLOB NOT NULL,
+    priority INTEGER NOT NULL,
+    job_id BLOB,
+    error TEXT NOT NULL
+) STRICT;
@@ -0,0 +1,14 @@
+CREATE TABLE annotations(
+    id BLOB PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    note TEXT NOT NULL,
+    -- Set for annotations of a single comparison
+    comparison_id BLOB REFERENCES comparison_runs(id),
+    -- Set for annotations of a window of a tracked branch's history, the times being those of the
+    -- bench runs of the window's first and last commits
+    branch TEXT,
+    from_commit TEXT,
+    to_commit TEXT,
+    from_utc TEXT,
+    to_utc TEXT
+) STRICT;
@@ -0,0 +1,8 @@
+CREATE TABLE audit_log(
+    id INTEGER PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    -- The GitHub login of the user who performed the action
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    details TEXT NOT NULL
+) STRICT;
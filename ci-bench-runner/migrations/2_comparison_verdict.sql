@@ -0,0 +1 @@
+ALTER TABLE comparison_runs ADD COLUMN verdict TEXT;
@@ -0,0 +1,9 @@
+-- The deliveries of GitHub webhooks (identified by their `X-GitHub-Delivery` header), so
+-- redelivered webhooks aren't handled twice
+CREATE TABLE webhook_deliveries(
+    guid TEXT PRIMARY KEY,
+    event_id BLOB NOT NULL,
+    received_utc TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX idx_webhook_deliveries_received_utc ON webhook_deliveries(received_utc);
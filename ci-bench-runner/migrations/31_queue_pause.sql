@@ -0,0 +1,6 @@
+-- Holds a single row while event processing is paused through the admin API
+CREATE TABLE queue_pause(
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    paused_utc TEXT NOT NULL,
+    reason TEXT
+) STRICT;
@@ -0,0 +1,8 @@
+-- The app instance processing the event queue. During blue/green deploys, the new instance waits
+-- until the old one releases the queue (or stops sending heartbeats) before taking it over
+CREATE TABLE queue_owner(
+    id INTEGER PRIMARY KEY CHECK (id = 0),
+    instance_id BLOB NOT NULL,
+    heartbeat_utc TEXT NOT NULL,
+    released_utc TEXT
+) STRICT;
@@ -0,0 +1,14 @@
+-- The status of each job, updated as it goes through its lifecycle (see `JobStatus`), so failed
+-- jobs can be told apart from running ones without guessing
+ALTER TABLE jobs ADD COLUMN status TEXT NOT NULL DEFAULT 'queued';
+
+-- Unfinished jobs whose event is still queued get dead-lettered (and marked as failed) upon
+-- startup, while the rest must have crashed before this column existed
+UPDATE jobs SET status = CASE
+    WHEN cancelled = 1 THEN 'cancelled'
+    WHEN interrupted = 1 THEN 'interrupted'
+    WHEN success = 1 THEN 'success'
+    WHEN success = 0 THEN 'failure'
+    WHEN id IN (SELECT job_id FROM event_queue WHERE job_id IS NOT NULL) THEN 'running'
+    ELSE 'failure'
+END;
@@ -0,0 +1,5 @@
+-- The aggregate scenarios used to be stored as icount results
+UPDATE bench_results
+SET scenario_kind = 11
+WHERE scenario_kind = 0
+    AND scenario_name IN ('geometric_mean', 'geometric_mean_tls12', 'geometric_mean_tls13');
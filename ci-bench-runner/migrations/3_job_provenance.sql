@@ -0,0 +1,10 @@
+ALTER TABLE jobs ADD COLUMN event_id BLOB;
+ALTER TABLE jobs ADD COLUMN event TEXT;
+ALTER TABLE jobs ADD COLUMN bench_run_id BLOB REFERENCES bench_runs(id);
+ALTER TABLE jobs ADD COLUMN comparison_run_id BLOB REFERENCES comparison_runs(id);
+ALTER TABLE jobs ADD COLUMN pr_number INTEGER;
+ALTER TABLE jobs ADD COLUMN comment_id INTEGER;
+
+CREATE INDEX idx_jobs_event_id ON jobs(event_id);
+CREATE INDEX idx_jobs_bench_run_id ON jobs(bench_run_id);
+CREATE INDEX idx_jobs_comparison_run_id ON jobs(comparison_run_id);
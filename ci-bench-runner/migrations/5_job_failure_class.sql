@@ -0,0 +1 @@
+ALTER TABLE jobs ADD COLUMN failure_class TEXT;
@@ -0,0 +1,4 @@
+ALTER TABLE event_queue ADD COLUMN retry_of BLOB;
+ALTER TABLE jobs ADD COLUMN retry_of BLOB REFERENCES jobs(id);
+
+CREATE INDEX idx_jobs_retry_of ON jobs(retry_of);
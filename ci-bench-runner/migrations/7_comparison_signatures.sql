@@ -0,0 +1,2 @@
+ALTER TABLE comparison_runs ADD COLUMN signed_summary TEXT;
+ALTER TABLE comparison_runs ADD COLUMN signature TEXT;
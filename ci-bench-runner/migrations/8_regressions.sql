@@ -0,0 +1,11 @@
+CREATE TABLE regressions(
+    id BLOB PRIMARY KEY,
+    created_utc TEXT NOT NULL,
+    updated_utc TEXT NOT NULL,
+    last_good_commit TEXT NOT NULL,
+    first_bad_commit TEXT NOT NULL,
+    state TEXT NOT NULL,
+    scenarios TEXT NOT NULL
+) STRICT;
+
+CREATE INDEX idx_regressions_state ON regressions(state);
@@ -0,0 +1 @@
+ALTER TABLE regressions ADD COLUMN pr_numbers TEXT NOT NULL DEFAULT '[]';
@@ -0,0 +1,495 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use askama::Template;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use crate::db::{ComparisonResult, Db};
+use crate::runner::{write_logs_for_run, Log};
+use crate::scrub::Scrubber;
+use crate::BenchSuiteConfig;
+
+/// The name of the file where a job's command logs are stored, in its output dir
+pub static LOGS_FILE: &str = "logs.md";
+
+/// The directory where the [`ArtifactStore`] keeps its files, in the job output dir
+static ARTIFACT_STORE_DIR: &str = "artifacts";
+
+/// The name of the file where a job's command logs are stored in structured form, in its output
+/// dir (used to render the logs as HTML)
+static LOGS_JSON_FILE: &str = "logs.json";
+
+/// The name of the file where a job's markdown report is stored, in its output dir
+pub static REPORT_FILE: &str = "report.md";
+
+/// The subdirectories of a job's output dir holding the results of each benchmarked commit (the
+/// root is used by jobs that benchmark a single commit)
+static RUN_DIRS: &[&str] = &["", "base", "candidate"];
+
+/// Packages the job's logs, results and report as a gzipped tarball, for offline analysis
+///
+/// Only the artifacts that are still stored are included (e.g. nothing is left if the job's output
+/// dir was cleaned up), so `None` is returned if there are no artifacts at all. The artifacts are
+/// scrubbed if a scrubber is provided.
+pub fn job_logs_tarball(
+    job_output_dir: &Path,
+    bench_suite: &BenchSuiteConfig,
+    scrubber: Option<&Scrubber>,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut artifacts = vec![PathBuf::from(LOGS_FILE), PathBuf::from(REPORT_FILE)];
+    for run_dir in RUN_DIRS {
+        let run_dir = Path::new(run_dir);
+        artifacts.push(bench_suite.icounts_path(run_dir));
+        artifacts.push(bench_suite.walltimes_path(run_dir));
+    }
+
+    let mut builder = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    let mut empty = true;
+    for artifact in artifacts {
+        let Ok(contents) = fs::read(job_output_dir.join(&artifact)) else {
+            continue;
+        };
+
+        let contents = match scrubber {
+            Some(scrubber) => scrubber
+                .scrub_text(&String::from_utf8_lossy(&contents))
+                .into_bytes(),
+            None => contents,
+        };
+
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(OffsetDateTime::now_utc().unix_timestamp() as u64);
+        builder
+            .append_data(&mut header, &artifact, contents.as_slice())
+            .context("unable to add artifact to tarball")?;
+        empty = false;
+    }
+
+    if empty {
+        return Ok(None);
+    }
+
+    let tarball = builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .context("unable to finish tarball")?;
+    Ok(Some(tarball))
+}
+
+/// Deletes the output dirs of the jobs that were last modified before the provided date, returning
+/// how many were deleted
+///
+/// Only the dirs named after a job id are considered, so the [`ArtifactStore`] is kept
+pub fn prune_job_output_dirs(
+    job_output_dir: &Path,
+    before: OffsetDateTime,
+) -> anyhow::Result<usize> {
+    let entries = fs::read_dir(job_output_dir).context("unable to list the job output dir")?;
+
+    let mut pruned = 0;
+    for entry in entries {
+        let entry = entry.context("unable to list the job output dir")?;
+        let is_job_dir = entry.file_type().is_ok_and(|file_type| file_type.is_dir())
+            && entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| Uuid::parse_str(name).is_ok());
+        if !is_job_dir {
+            continue;
+        }
+
+        let modified = entry
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .context("unable to get the modification time of a job output dir")?;
+        if OffsetDateTime::from(modified) < before {
+            fs::remove_dir_all(entry.path()).with_context(|| {
+                format!("unable to delete job output dir {}", entry.path().display())
+            })?;
+            pruned += 1;
+        }
+    }
+
+    Ok(pruned)
+}
+
+/// The logs of the commands run to benchmark one of a job's commits
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunLogs {
+    /// The name of the run (e.g. `Candidate`)
+    pub name: String,
+    pub commands: Vec<CommandLog>,
+}
+
+impl RunLogs {
+    /// Returns the HTML anchor of the run's n-th command (starting at 1)
+    ///
+    /// Takes the index by reference, since that is how askama passes it from templates
+    pub fn anchor(&self, n: &usize) -> String {
+        let slug: String = self
+            .name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_lowercase()
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+        format!("{slug}-{n}")
+    }
+}
+
+/// The output of a command, as stored in [`RunLogs`]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct CommandLog {
+    pub command: String,
+    pub cwd: String,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// Writes the logs of a job's runs to its output dir, as markdown and in structured form
+pub fn write_job_logs(job_output_dir: &Path, runs: &[(&str, &[Log])]) -> anyhow::Result<()> {
+    let mut markdown = String::new();
+    let mut structured = Vec::new();
+    for &(name, logs) in runs {
+        writeln!(markdown, "### {name}").ok();
+        write_logs_for_run(&mut markdown, logs);
+
+        structured.push(RunLogs {
+            name: name.to_string(),
+            commands: logs
+                .iter()
+                .map(|log| CommandLog {
+                    command: log.command.clone(),
+                    cwd: log.cwd.clone(),
+                    stdout: String::from_utf8_lossy(&log.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&log.stderr).into_owned(),
+                })
+                .collect(),
+        });
+    }
+
+    fs::create_dir_all(job_output_dir).context("unable to create job output dir")?;
+    fs::write(job_output_dir.join(LOGS_FILE), markdown).context("unable to write job logs")?;
+    let json = serde_json::to_vec(&structured).expect("unreachable code");
+    fs::write(job_output_dir.join(LOGS_JSON_FILE), json).context("unable to write job logs")?;
+    Ok(())
+}
+
+/// A simple HTML page showing the output of each command run by a job
+#[derive(Template)]
+#[template(path = "job_logs.html")]
+struct JobLogsPage<'a> {
+    job_id: Uuid,
+    runs: &'a [RunLogs],
+    /// The markdown logs, for jobs that predate the structured logs
+    markdown_logs: Option<&'a str>,
+}
+
+/// Renders the logs of a job as HTML, with an anchor per command
+///
+/// Returns `None` if the job's logs are no longer available
+pub fn render_job_logs(job_id: Uuid, job_output_dir: &Path) -> anyhow::Result<Option<String>> {
+    let runs: Vec<RunLogs> = match fs::read(job_output_dir.join(LOGS_JSON_FILE)) {
+        Ok(json) => serde_json::from_slice(&json).context("invalid structured job logs")?,
+        Err(_) => Vec::new(),
+    };
+    let markdown_logs = if runs.is_empty() {
+        let Ok(markdown_logs) = fs::read_to_string(job_output_dir.join(LOGS_FILE)) else {
+            return Ok(None);
+        };
+        Some(markdown_logs)
+    } else {
+        None
+    };
+
+    let page = JobLogsPage {
+        job_id,
+        runs: &runs,
+        markdown_logs: markdown_logs.as_deref(),
+    }
+    .render()
+    .context("unable to render job logs")?;
+    Ok(Some(page))
+}
+
+/// Stores artifacts that are too big for the database (e.g. the end of huge cachegrind diffs)
+///
+/// Unlike the per-job output dirs, the store's contents are not tied to a job, so they should be
+/// kept around for as long as the database
+#[derive(Debug, Clone)]
+pub struct ArtifactStore {
+    root: PathBuf,
+}
+
+impl ArtifactStore {
+    /// Creates a store rooted in the provided job output dir
+    pub fn new(job_output_dir: &Path) -> Self {
+        Self {
+            root: job_output_dir.join(ARTIFACT_STORE_DIR),
+        }
+    }
+
+    fn cachegrind_diff_overflow_path(&self, comparison_id: Uuid, scenario_name: &str) -> PathBuf {
+        // Scenario names are hex-encoded, since they might contain characters that are not valid in
+        // file names
+        self.root
+            .join("cachegrind-diffs")
+            .join(comparison_id.to_string())
+            .join(hex::encode(scenario_name))
+    }
+
+    /// Stores the end of a scenario's cachegrind diff
+    pub fn store_cachegrind_diff_overflow(
+        &self,
+        comparison_id: Uuid,
+        scenario_name: &str,
+        overflow: &str,
+    ) -> anyhow::Result<()> {
+        let path = self.cachegrind_diff_overflow_path(comparison_id, scenario_name);
+        fs::create_dir_all(path.parent().unwrap())
+            .context("unable to create artifact store dir")?;
+        fs::write(&path, overflow)
+            .with_context(|| format!("unable to write artifact to {}", path.display()))
+    }
+
+    /// Returns the end of a scenario's cachegrind diff, if it is still stored
+    pub fn cachegrind_diff_overflow(
+        &self,
+        comparison_id: Uuid,
+        scenario_name: &str,
+    ) -> Option<String> {
+        fs::read_to_string(self.cachegrind_diff_overflow_path(comparison_id, scenario_name)).ok()
+    }
+}
+
+/// Splits off the end of a cachegrind diff exceeding `max_bytes`, at the last line break that
+/// fits, returning the end (or `None` if the diff fits)
+pub fn split_cachegrind_diff(diff: &mut String, max_bytes: usize) -> Option<String> {
+    if diff.len() <= max_bytes {
+        return None;
+    }
+
+    let mut split_at = max_bytes;
+    while !diff.is_char_boundary(split_at) {
+        split_at -= 1;
+    }
+    if let Some(line_end) = diff[..split_at].rfind('\n') {
+        split_at = line_end + 1;
+    }
+
+    Some(diff.split_off(split_at))
+}
+
+/// Stores the result of a comparison, moving the end of cachegrind diffs exceeding `max_bytes` to
+/// the artifact store (the truncation is recorded in the database)
+pub async fn store_comparison_result_with_overflow(
+    db: &Db,
+    store: &ArtifactStore,
+    max_bytes: usize,
+    baseline_commit: String,
+    candidate_commit: String,
+    mut result: ComparisonResult,
+) -> anyhow::Result<Uuid> {
+    let mut overflows = Vec::new();
+    for sub_result in result.metrics.values_mut() {
+        for diff in &mut sub_result.diffs {
+            let Some(cachegrind_diff) = &mut diff.cachegrind_diff else {
+                continue;
+            };
+            if let Some(overflow) = split_cachegrind_diff(cachegrind_diff, max_bytes) {
+                overflows.push((diff.scenario_name.clone(), overflow));
+            }
+        }
+    }
+
+    let comparison_id = db
+        .store_comparison_result(baseline_commit, candidate_commit, result)
+        .await?;
+    for (scenario_name, overflow) in overflows {
+        store.store_cachegrind_diff_overflow(comparison_id, &scenario_name, &overflow)?;
+        db.record_cachegrind_diff_overflow(comparison_id, &scenario_name, overflow.len() as u64)
+            .await?;
+    }
+
+    Ok(comparison_id)
+}
+
+/// Returns the full cachegrind diff for the specified comparison and scenario, if available
+///
+/// Diffs that exceeded the size limit are stitched back together from the database and the
+/// artifact store. If the end of the diff is no longer stored, a note takes its place.
+pub async fn full_cachegrind_diff(
+    db: &Db,
+    store: &ArtifactStore,
+    baseline_commit: &str,
+    candidate_commit: &str,
+    scenario_name: &str,
+) -> anyhow::Result<Option<String>> {
+    let Some(stored) = db
+        .cachegrind_diff(baseline_commit, candidate_commit, scenario_name)
+        .await?
+    else {
+        return Ok(None);
+    };
+
+    let mut diff = stored.diff;
+    if let Some(overflow_bytes) = stored.overflow_bytes {
+        match store.cachegrind_diff_overflow(stored.comparison_id, scenario_name) {
+            Some(overflow) => diff.push_str(&overflow),
+            None => {
+                writeln!(
+                    diff,
+                    "[the remaining {overflow_bytes} bytes of the diff are no longer available]"
+                )
+                .ok();
+            }
+        }
+    }
+
+    Ok(Some(diff))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Read;
+
+    use flate2::read::GzDecoder;
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn tarball_contains_stored_artifacts() {
+        let bench_suite = BenchSuiteConfig::default();
+        let job_output_dir = TempDir::new().unwrap();
+        assert_eq!(
+            job_logs_tarball(job_output_dir.path(), &bench_suite, None).unwrap(),
+            None
+        );
+
+        let icounts_path = bench_suite.icounts_path(&job_output_dir.path().join("base"));
+        fs::create_dir_all(icounts_path.parent().unwrap()).unwrap();
+        fs::write(&icounts_path, "fake_bench,12345").unwrap();
+        fs::write(job_output_dir.path().join(LOGS_FILE), "### Base").unwrap();
+
+        let tarball = job_logs_tarball(job_output_dir.path(), &bench_suite, None)
+            .unwrap()
+            .unwrap();
+        let mut archive = tar::Archive::new(GzDecoder::new(tarball.as_slice()));
+        let mut entries = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            entries.push((entry.path().unwrap().display().to_string(), contents));
+        }
+
+        assert_eq!(
+            entries,
+            [
+                ("logs.md".to_string(), "### Base".to_string()),
+                (
+                    "base/results/icounts.csv".to_string(),
+                    "fake_bench,12345".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn prune_only_old_job_output_dirs() {
+        let root = TempDir::new().unwrap();
+        let job_dir = root.path().join(Uuid::new_v4().to_string());
+        fs::create_dir_all(&job_dir).unwrap();
+        fs::write(job_dir.join(LOGS_FILE), "### Base").unwrap();
+        let store = ArtifactStore::new(root.path());
+        store
+            .store_cachegrind_diff_overflow(Uuid::new_v4(), "fake_bench", "overflow")
+            .unwrap();
+
+        // The job's dir is too recent to be pruned
+        let now = OffsetDateTime::now_utc();
+        let before = now - time::Duration::days(1);
+        assert_eq!(prune_job_output_dirs(root.path(), before).unwrap(), 0);
+        assert!(job_dir.exists());
+
+        // Once old enough, it is pruned, but the artifact store is kept
+        let before = now + time::Duration::days(1);
+        assert_eq!(prune_job_output_dirs(root.path(), before).unwrap(), 1);
+        assert!(!job_dir.exists());
+        assert!(root.path().join(ARTIFACT_STORE_DIR).exists());
+    }
+
+    #[test]
+    fn split_cachegrind_diff_at_line_breaks() {
+        let mut diff = "first line\nsecond line\n".to_string();
+        assert_eq!(split_cachegrind_diff(&mut diff, 100), None);
+        assert_eq!(split_cachegrind_diff(&mut diff, 23), None);
+
+        assert_eq!(
+            split_cachegrind_diff(&mut diff, 15),
+            Some("second line\n".to_string())
+        );
+        assert_eq!(diff, "first line\n");
+
+        // Without line breaks, the diff is split at the limit (respecting char boundaries)
+        let mut diff = "ééé".to_string();
+        assert_eq!(split_cachegrind_diff(&mut diff, 3), Some("éé".to_string()));
+        assert_eq!(diff, "é");
+    }
+
+    #[test]
+    fn job_logs_roundtrip() {
+        let job_id = Uuid::new_v4();
+        let job_output_dir = TempDir::new().unwrap();
+        assert_eq!(
+            render_job_logs(job_id, job_output_dir.path()).unwrap(),
+            None
+        );
+
+        let candidate = [Log {
+            command: "cargo build --release".to_string(),
+            cwd: "/tmp/candidate".to_string(),
+            stdout: Vec::new(),
+            stderr: b"error[E0425]: cannot find value `x` <here>".to_vec(),
+        }];
+        write_job_logs(
+            job_output_dir.path(),
+            &[("Candidate", candidate.as_slice()), ("Base", &[] as &[Log])],
+        )
+        .unwrap();
+
+        let markdown = fs::read_to_string(job_output_dir.path().join(LOGS_FILE)).unwrap();
+        assert!(markdown.starts_with("### Candidate\ncommand:"));
+        assert!(markdown.contains("### Base\n_Not available_"));
+
+        let page = render_job_logs(job_id, job_output_dir.path())
+            .unwrap()
+            .unwrap();
+        assert!(page.contains(r#"id="candidate-1""#));
+        assert!(page.contains("cannot find value `x` &lt;here&gt;"));
+    }
+
+    #[test]
+    fn job_logs_fall_back_to_markdown() {
+        let job_output_dir = TempDir::new().unwrap();
+        fs::write(job_output_dir.path().join(LOGS_FILE), "### Base\n<old>").unwrap();
+
+        let page = render_job_logs(Uuid::new_v4(), job_output_dir.path())
+            .unwrap()
+            .unwrap();
+        assert!(page.contains("### Base\n&lt;old&gt;"));
+    }
+}
@@ -5,13 +5,34 @@ use std::sync::Arc;
 use bencher_client::json::project::measure::{INSTRUCTIONS_SLUG_STR, LATENCY_SLUG_STR};
 use bencher_client::{
     json::{DateTime, JsonMetric, JsonMetricsMap, JsonResultsMap, Measure},
-    types::{Adapter, JsonNewReport, JsonReportSettings},
+    types::{Adapter, JsonNewReport, JsonNewTestbed, JsonReportSettings},
     BencherClient,
 };
-use tracing::error;
+use serde_json::json;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tracing::{error, warn};
 
+use crate::db::ScenarioKind;
+use crate::job::is_aggregate_scenario;
 use crate::BencherConfig;
 
+/// How many reports are requested per page when importing history (the maximum allowed by
+/// bencher.dev)
+const REPORTS_PER_PAGE: u32 = 255;
+
+/// The measure under which the instruction count index is reported (bencher.dev creates it on
+/// first use), so the index doesn't show up as one more benchmark next to the real scenarios
+const ICOUNT_INDEX_SLUG_STR: &str = "icount-index";
+
+/// The results of a bench run, as reported to bencher.dev
+#[derive(Debug, Clone, PartialEq)]
+pub struct BencherRun {
+    pub commit_sha: String,
+    pub start_time: OffsetDateTime,
+    pub results: Vec<(String, ScenarioKind, f64)>,
+}
+
 /// The Bencher.dev client along with its configuration
 #[derive(Clone)]
 pub struct BencherDev {
@@ -32,7 +53,9 @@ impl BencherDev {
         }
     }
 
-    /// Sends the icount and walltime results to bencher.dev for visualization
+    /// Sends the icount, instruction count index and walltime results to bencher.dev for
+    /// visualization
+    #[allow(clippy::too_many_arguments)]
     pub async fn track_results(
         &self,
         branch: &str,
@@ -40,10 +63,17 @@ impl BencherDev {
         start_time: DateTime,
         end_time: DateTime,
         icounts: HashMap<String, f64>,
+        icount_index: HashMap<String, f64>,
         walltimes: HashMap<String, f64>,
     ) -> anyhow::Result<()> {
         let mut bmf_map = results_to_bmf(icounts, INSTRUCTIONS_SLUG_STR.parse().unwrap());
         bmf_map.extend(results_to_bmf(walltimes, LATENCY_SLUG_STR.parse().unwrap()));
+        // The index scenarios don't share names with real scenarios, so they don't clash with
+        // the entries above
+        bmf_map.extend(results_to_bmf(
+            icount_index,
+            ICOUNT_INDEX_SLUG_STR.parse().unwrap(),
+        ));
 
         let testbed = self.config.testbed_id.clone();
         let report = JsonNewReport {
@@ -76,6 +106,95 @@ impl BencherDev {
 
         Ok(())
     }
+
+    /// Retrieves the runs of the branch that were reported to the configured testbed, in the order
+    /// returned by bencher.dev
+    ///
+    /// Reports without a commit hash or without results we know of are skipped
+    pub async fn fetch_runs(&self, branch: &str) -> anyhow::Result<Vec<BencherRun>> {
+        let project_id = &self.config.project_id;
+        let testbed_id = &self.config.testbed_id;
+
+        let mut runs = Vec::new();
+        for page in 1.. {
+            let reports = self
+                .client
+                .send(|client| async move {
+                    client
+                        .proj_reports_get()
+                        .project(project_id.clone())
+                        .branch(branch.to_string())
+                        .testbed(testbed_id.clone())
+                        .per_page(REPORTS_PER_PAGE)
+                        .page(page)
+                        .send()
+                        .await
+                })
+                .await?;
+
+            // The reports are inspected as plain JSON, to stay robust against changes in the
+            // client's types
+            let reports = serde_json::to_value(reports)?;
+            let reports = reports.as_array().map(Vec::as_slice).unwrap_or_default();
+            for report in reports {
+                match parse_report(report) {
+                    Some(run) => runs.push(run),
+                    None => warn!(
+                        report = report["uuid"].to_string(),
+                        "skipping bencher.dev report without usable results"
+                    ),
+                }
+            }
+
+            if reports.len() < REPORTS_PER_PAGE as usize {
+                break;
+            }
+        }
+
+        Ok(runs)
+    }
+
+    /// Ensures the configured testbed exists in the project, creating it if necessary
+    ///
+    /// Returns `true` if the testbed had to be created
+    pub async fn ensure_testbed(&self) -> anyhow::Result<bool> {
+        let project_id = &self.config.project_id;
+        let testbed_id = &self.config.testbed_id;
+
+        let existing = self
+            .client
+            .send(|client| async move {
+                client
+                    .proj_testbed_get()
+                    .project(project_id.clone())
+                    .testbed(testbed_id.clone())
+                    .send()
+                    .await
+            })
+            .await;
+
+        if existing.is_ok() {
+            return Ok(false);
+        }
+
+        let testbed: JsonNewTestbed = serde_json::from_value(json!({
+            "name": testbed_id,
+            "slug": testbed_id,
+        }))?;
+        let testbed = &testbed;
+        self.client
+            .send(|client| async move {
+                client
+                    .proj_testbed_post()
+                    .project(project_id.clone())
+                    .body(testbed.clone())
+                    .send()
+                    .await
+            })
+            .await?;
+
+        Ok(true)
+    }
 }
 
 /// Converts the instruction counts map into Bencher Metric Format (BMF)
@@ -106,3 +225,119 @@ fn results_to_bmf(results: HashMap<String, f64>, measure: Measure) -> JsonResult
         })
         .collect()
 }
+
+/// Extracts the commit, start time and results of a bencher.dev report
+///
+/// Only the measures we report (instructions, instruction count index and latency) are taken into
+/// account
+fn parse_report(report: &serde_json::Value) -> Option<BencherRun> {
+    let commit_sha = ["/branch/version/hash", "/branch/head/version/hash"]
+        .into_iter()
+        .find_map(|pointer| report.pointer(pointer)?.as_str())?;
+    let start_time = report["start_time"].as_str()?;
+    let start_time = OffsetDateTime::parse(start_time, &Rfc3339).ok()?;
+
+    let mut results = Vec::new();
+    let iterations = report["results"].as_array()?;
+    for measure_results in iterations.iter().filter_map(|i| i.as_array()).flatten() {
+        let measure = measure_results.pointer("/measure/slug")?.as_str()?;
+        let scenario_kind = if measure == INSTRUCTIONS_SLUG_STR {
+            ScenarioKind::Icount
+        } else if measure == ICOUNT_INDEX_SLUG_STR {
+            ScenarioKind::IcountIndex
+        } else if measure == LATENCY_SLUG_STR {
+            ScenarioKind::Walltime
+        } else {
+            continue;
+        };
+
+        for benchmark in measure_results["benchmarks"].as_array()? {
+            let name = benchmark["name"].as_str()?;
+            let value = benchmark.pointer("/metric/value")?.as_f64()?;
+            // The index used to be reported next to the real scenarios
+            let scenario_kind = match scenario_kind {
+                ScenarioKind::Icount if is_aggregate_scenario(name) => ScenarioKind::IcountIndex,
+                kind => kind,
+            };
+            results.push((name.to_string(), scenario_kind, value));
+        }
+    }
+
+    if results.is_empty() {
+        return None;
+    }
+
+    Some(BencherRun {
+        commit_sha: commit_sha.to_string(),
+        start_time,
+        results,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_report_with_known_measures() {
+        let report = json!({
+            "uuid": "00000000-0000-0000-0000-000000000000",
+            "branch": { "name": "main", "version": { "number": 3, "hash": "abcdef" } },
+            "start_time": "2023-10-11T12:00:00Z",
+            "results": [[
+                {
+                    "measure": { "slug": "instructions" },
+                    "benchmarks": [{ "name": "handshake", "metric": { "value": 1000.0 } }]
+                },
+                {
+                    "measure": { "slug": "latency" },
+                    "benchmarks": [{ "name": "handshake", "metric": { "value": 2.5 } }]
+                },
+                {
+                    "measure": { "slug": "icount-index" },
+                    "benchmarks": [{ "name": "geometric_mean", "metric": { "value": 900.0 } }]
+                },
+                {
+                    "measure": { "slug": "throughput" },
+                    "benchmarks": [{ "name": "handshake", "metric": { "value": 7.0 } }]
+                }
+            ]]
+        });
+
+        let run = parse_report(&report).unwrap();
+        assert_eq!(run.commit_sha, "abcdef");
+        assert_eq!(
+            run.start_time,
+            OffsetDateTime::parse("2023-10-11T12:00:00Z", &Rfc3339).unwrap()
+        );
+        assert_eq!(
+            run.results,
+            [
+                ("handshake".to_string(), ScenarioKind::Icount, 1000.0),
+                ("handshake".to_string(), ScenarioKind::Walltime, 2.5),
+                (
+                    "geometric_mean".to_string(),
+                    ScenarioKind::IcountIndex,
+                    900.0
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_report_without_hash_or_results() {
+        let report = json!({
+            "branch": { "name": "main", "version": { "number": 3 } },
+            "start_time": "2023-10-11T12:00:00Z",
+            "results": []
+        });
+        assert!(parse_report(&report).is_none());
+
+        let report = json!({
+            "branch": { "name": "main", "head": { "version": { "hash": "abcdef" } } },
+            "start_time": "2023-10-11T12:00:00Z",
+            "results": []
+        });
+        assert!(parse_report(&report).is_none());
+    }
+}
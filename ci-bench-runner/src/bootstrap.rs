@@ -0,0 +1,128 @@
+use std::fmt::Display;
+use std::fs;
+
+use anyhow::{bail, Context};
+
+use crate::bencher_dev::BencherDev;
+use crate::db;
+use crate::github::CachedOctocrab;
+use crate::runner::missing_executables;
+use crate::{AppConfig, MIGRATOR};
+
+/// Prepares a fresh deployment and prints a summary of its readiness to stdout
+///
+/// Creates the database (if necessary), runs migrations, checks the host environment, registers
+/// the bencher.dev testbed (if configured) and validates the GitHub App credentials. All steps are
+/// attempted even if a previous one fails, so the summary shows everything that needs fixing.
+pub async fn bootstrap(config: &AppConfig) -> anyhow::Result<()> {
+    let mut summary = ReadinessSummary::default();
+
+    summary.record("database", prepare_db(config).await);
+    summary.record("environment", check_environment(config));
+    summary.record("bencher.dev", register_bencher_testbed(config).await);
+    summary.record("GitHub App", validate_github_credentials(config).await);
+
+    println!("{summary}");
+    if !summary.is_ready() {
+        bail!("the application is not ready to run, see the summary above for details");
+    }
+
+    Ok(())
+}
+
+/// Creates the database if it doesn't exist yet and applies any pending migrations
+async fn prepare_db(config: &AppConfig) -> anyhow::Result<String> {
+    let mut sqlite = db::connect_creating_db(&config.path_to_db).await?;
+    MIGRATOR
+        .run(&mut sqlite)
+        .await
+        .context("failed to apply DB migration")?;
+
+    Ok(format!("migrations applied to {}", config.path_to_db))
+}
+
+/// Checks that the host has everything we need to run benchmarks
+fn check_environment(config: &AppConfig) -> anyhow::Result<String> {
+    let missing = missing_executables();
+    if !missing.is_empty() {
+        bail!("missing executables in PATH: {}", missing.join(", "));
+    }
+
+    fs::create_dir_all(&config.job_output_dir).with_context(|| {
+        format!(
+            "unable to create job output dir at {}",
+            config.job_output_dir.display()
+        )
+    })?;
+    tempfile::tempfile_in(&config.job_output_dir).with_context(|| {
+        format!(
+            "job output dir at {} is not writable",
+            config.job_output_dir.display()
+        )
+    })?;
+
+    Ok("required executables found and job output dir is writable".to_string())
+}
+
+/// Ensures the configured bencher.dev testbed exists, if bencher.dev has been configured
+async fn register_bencher_testbed(config: &AppConfig) -> anyhow::Result<String> {
+    let Some(bencher_config) = config.bencher.clone() else {
+        return Ok("not configured, skipped".to_string());
+    };
+
+    let testbed_id = bencher_config.testbed_id.clone();
+    let created = BencherDev::new(bencher_config)
+        .ensure_testbed()
+        .await
+        .context("unable to register testbed")?;
+
+    if created {
+        Ok(format!("testbed `{testbed_id}` created"))
+    } else {
+        Ok(format!("testbed `{testbed_id}` already registered"))
+    }
+}
+
+/// Obtains an installation token for the GitHub App, which only succeeds if the credentials are
+/// valid and the app has been installed on the configured repository
+async fn validate_github_credentials(config: &AppConfig) -> anyhow::Result<String> {
+    CachedOctocrab::new(config).await?;
+    Ok(format!(
+        "app {} is installed on {}/{}",
+        config.github_app_id, config.github_repo_owner, config.github_repo_name
+    ))
+}
+
+/// The outcome of each bootstrap step
+#[derive(Default)]
+struct ReadinessSummary {
+    steps: Vec<(&'static str, anyhow::Result<String>)>,
+}
+
+impl ReadinessSummary {
+    fn record(&mut self, step: &'static str, outcome: anyhow::Result<String>) {
+        self.steps.push((step, outcome));
+    }
+
+    fn is_ready(&self) -> bool {
+        self.steps.iter().all(|(_, outcome)| outcome.is_ok())
+    }
+}
+
+impl Display for ReadinessSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Readiness summary:")?;
+        for (step, outcome) in &self.steps {
+            match outcome {
+                Ok(details) => writeln!(f, "  [ok]     {step}: {details}")?,
+                Err(e) => writeln!(f, "  [failed] {step}: {e:#}")?,
+            }
+        }
+
+        if self.is_ready() {
+            write!(f, "The application is ready to run")
+        } else {
+            write!(f, "The application is NOT ready to run")
+        }
+    }
+}
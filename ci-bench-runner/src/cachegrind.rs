@@ -0,0 +1,207 @@
+use std::collections::BTreeMap;
+
+use askama::Template;
+
+use crate::db::ScenarioDiff;
+use crate::AppConfig;
+
+/// The header of the per-function section of `callgrind_annotate`'s output
+static FUNCTIONS_HEADER: &str = "file:function";
+
+/// The instructions executed by a function, in the baseline and in the candidate
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDiff {
+    /// The function, prefixed by its source file (e.g. `???:rustls::conn::foo`)
+    pub function: String,
+    /// Instructions executed in the baseline (0 if the function didn't show up)
+    pub baseline: u64,
+    /// Instructions executed in the candidate (0 if the function didn't show up)
+    pub candidate: u64,
+    /// The lines of `callgrind_annotate`'s output mentioning the function
+    pub baseline_line: Option<String>,
+    pub candidate_line: Option<String>,
+}
+
+impl FunctionDiff {
+    fn new(function: &str) -> Self {
+        Self {
+            function: function.to_string(),
+            baseline: 0,
+            candidate: 0,
+            baseline_line: None,
+            candidate_line: None,
+        }
+    }
+
+    pub fn diff(&self) -> i64 {
+        self.candidate as i64 - self.baseline as i64
+    }
+
+    /// Returns the ratio of change, if the function showed up in the baseline
+    pub fn diff_ratio(&self) -> Option<f64> {
+        (self.baseline > 0).then(|| self.diff() as f64 / self.baseline as f64)
+    }
+}
+
+/// Splits a cachegrind diff into the per-function instruction counts of both sides, sorted by
+/// the size of their change (biggest first)
+///
+/// Returns an empty list if the diff doesn't have the expected format
+pub fn function_diffs(diff: &str) -> Vec<FunctionDiff> {
+    let Some((baseline, candidate)) = diff.split_once("\n=====\n") else {
+        return Vec::new();
+    };
+
+    let mut functions: BTreeMap<&str, FunctionDiff> = BTreeMap::new();
+    for (name, instructions, line) in parse_annotated_functions(baseline) {
+        let function = functions
+            .entry(name)
+            .or_insert_with(|| FunctionDiff::new(name));
+        function.baseline += instructions;
+        function.baseline_line = Some(line.trim().to_string());
+    }
+    for (name, instructions, line) in parse_annotated_functions(candidate) {
+        let function = functions
+            .entry(name)
+            .or_insert_with(|| FunctionDiff::new(name));
+        function.candidate += instructions;
+        function.candidate_line = Some(line.trim().to_string());
+    }
+
+    let mut functions: Vec<_> = functions.into_values().collect();
+    functions.sort_by_key(|f| (std::cmp::Reverse(f.diff().abs()), f.function.clone()));
+    functions
+}
+
+/// Parses the per-function section of `callgrind_annotate`'s output, returning the function, its
+/// instruction count and the line it was found in
+///
+/// Lines look like `1,234 (12.34%)  ???:rustls::conn::foo [/path/to/binary]`
+fn parse_annotated_functions(output: &str) -> Vec<(&str, u64, &str)> {
+    let mut lines = output.lines();
+    if !lines
+        .by_ref()
+        .any(|line| line.trim_end().ends_with(FUNCTIONS_HEADER))
+    {
+        return Vec::new();
+    }
+
+    let mut functions = Vec::new();
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.starts_with("--") {
+            // The separator right after the header, or the end of the section
+            if functions.is_empty() {
+                continue;
+            }
+            break;
+        }
+
+        let Some((count, rest)) = trimmed.split_once(char::is_whitespace) else {
+            continue;
+        };
+        let Ok(count) = count.replace(',', "").parse() else {
+            continue;
+        };
+
+        // Skip the percentage, if present, and the binary the function belongs to
+        let mut rest = rest.trim_start();
+        if rest.starts_with('(') {
+            rest = rest.split_once(')').map(|(_, rest)| rest).unwrap_or(rest);
+        }
+        let name = match rest.trim().rsplit_once(" [") {
+            Some((name, _)) => name.trim(),
+            None => rest.trim(),
+        };
+        if !name.is_empty() {
+            functions.push((name, count, line));
+        }
+    }
+
+    functions
+}
+
+/// An HTML page showing a scenario's cachegrind diff, function by function
+#[derive(Template)]
+#[template(path = "cachegrind_diff.html")]
+struct CachegrindDiffPage<'a> {
+    scenario_name: &'a str,
+    comparison_url: &'a str,
+    /// The scenario's row in the comparison, if found
+    scenario_diff: Option<&'a ScenarioDiff>,
+    functions: &'a [FunctionDiff],
+    raw_diff: &'a str,
+}
+
+/// Renders the HTML page of a scenario's cachegrind diff
+pub fn render_diff_page(
+    config: &AppConfig,
+    compared_commits: &str,
+    scenario_name: &str,
+    scenario_diff: Option<&ScenarioDiff>,
+    raw_diff: &str,
+) -> String {
+    let comparison_url = format!("{}/comparisons/{compared_commits}", config.app_base_url);
+    CachegrindDiffPage {
+        scenario_name,
+        comparison_url: &comparison_url,
+        scenario_diff,
+        functions: &function_diffs(raw_diff),
+        raw_diff,
+    }
+    .render()
+    .expect("failed to render askama template")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn function_diffs_of_annotated_outputs() {
+        let diff = "\
+Base output:
+--------------------------------------------------------------------------------
+Ir
+--------------------------------------------------------------------------------
+1,300 (100.0%)  PROGRAM TOTALS
+
+--------------------------------------------------------------------------------
+Ir                  file:function
+--------------------------------------------------------------------------------
+1,000 (76.92%)  ???:rustls::foo [/bench]
+  300 (23.08%)  ???:rustls::bar [/bench]
+
+=====
+
+Candidate output:
+--------------------------------------------------------------------------------
+Ir                  file:function
+--------------------------------------------------------------------------------
+1,100 (78.57%)  ???:rustls::foo [/bench]
+  300 (21.43%)  ???:rustls::baz [/bench]
+";
+
+        let functions = function_diffs(diff);
+        let summary: Vec<_> = functions
+            .iter()
+            .map(|f| (f.function.as_str(), f.baseline, f.candidate))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("???:rustls::bar", 300, 0),
+                ("???:rustls::baz", 0, 300),
+                ("???:rustls::foo", 1000, 1100),
+            ]
+        );
+        assert_eq!(functions[2].diff_ratio(), Some(0.1));
+        assert_eq!(functions[1].diff_ratio(), None);
+        assert_eq!(
+            functions[2].candidate_line.as_deref(),
+            Some("1,100 (78.57%)  ???:rustls::foo [/bench]")
+        );
+
+        assert!(function_diffs("dummy cachegrind diff").is_empty());
+    }
+}
@@ -0,0 +1,220 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use crate::job::is_aggregate_scenario;
+
+/// How far back the runs used to correlate scenarios go
+pub const CORRELATION_WINDOW: time::Duration = time::Duration::days(90);
+
+/// The correlation above which two scenarios are considered to move together
+const MIN_CORRELATION: f64 = 0.95;
+
+/// The number of run-to-run movements two scenarios must share (i.e. runs where both of them moved)
+/// before they are correlated (fewer movements make coincidences too likely)
+const MIN_SHARED_MOVEMENTS: usize = 10;
+
+/// Scenarios whose results always move together, which suggests they share a noise source or
+/// measure the same code
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CorrelatedGroup {
+    /// The scenarios of the group, sorted by name
+    pub scenarios: Vec<String>,
+    /// The lowest correlation among the correlated pairs of the group
+    pub min_correlation: f64,
+}
+
+/// Groups the scenarios whose run-to-run movements are strongly correlated, biggest group first
+///
+/// Takes the results of each run, oldest first. Scenarios belong to the same group when they are
+/// linked by a chain of correlated pairs, so not every pair in a group needs to be correlated.
+/// Aggregate scenarios are left out, since they move along with the scenarios they aggregate.
+pub fn correlated_groups(runs: &[BTreeMap<String, f64>]) -> Vec<CorrelatedGroup> {
+    let movements = movements(runs);
+    let scenarios: Vec<_> = movements.keys().collect();
+
+    // Union-find over the indexes of the scenarios, with the lowest correlation of each root
+    let mut parents: Vec<usize> = (0..scenarios.len()).collect();
+    let mut min_correlations = vec![f64::INFINITY; scenarios.len()];
+    for (i, a) in scenarios.iter().enumerate() {
+        for (j, b) in scenarios.iter().enumerate().skip(i + 1) {
+            let Some(correlation) = correlation(&movements[*a], &movements[*b]) else {
+                continue;
+            };
+            if correlation < MIN_CORRELATION {
+                continue;
+            }
+
+            let (root_i, root_j) = (root(&mut parents, i), root(&mut parents, j));
+            let min_correlation = min_correlations[root_i]
+                .min(min_correlations[root_j])
+                .min(correlation);
+            parents[root_j] = root_i;
+            min_correlations[root_i] = min_correlation;
+        }
+    }
+
+    let mut groups: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (i, scenario) in scenarios.iter().enumerate() {
+        let root = root(&mut parents, i);
+        groups.entry(root).or_default().push(scenario.to_string());
+    }
+
+    let mut groups: Vec<_> = groups
+        .into_iter()
+        .filter(|(_, scenarios)| scenarios.len() > 1)
+        .map(|(root, scenarios)| CorrelatedGroup {
+            scenarios,
+            min_correlation: min_correlations[root],
+        })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.scenarios
+            .len()
+            .cmp(&a.scenarios.len())
+            .then_with(|| a.scenarios.cmp(&b.scenarios))
+    });
+    groups
+}
+
+/// Returns the root of the union-find tree containing `i`, compressing the path along the way
+fn root(parents: &mut [usize], mut i: usize) -> usize {
+    while parents[i] != i {
+        parents[i] = parents[parents[i]];
+        i = parents[i];
+    }
+    i
+}
+
+/// Returns the relative change of each scenario between consecutive runs, by scenario
+///
+/// Movements are indexed by the run they lead to, and missing if the scenario is absent from
+/// either run
+fn movements(runs: &[BTreeMap<String, f64>]) -> BTreeMap<String, Vec<Option<f64>>> {
+    let mut movements: BTreeMap<String, Vec<Option<f64>>> = BTreeMap::new();
+    for (i, pair) in runs.windows(2).enumerate() {
+        for (scenario, &result) in &pair[1] {
+            if is_aggregate_scenario(scenario) {
+                continue;
+            }
+            let Some(&previous) = pair[0].get(scenario) else {
+                continue;
+            };
+            if previous == 0.0 {
+                continue;
+            }
+
+            let scenario_movements = movements
+                .entry(scenario.clone())
+                .or_insert_with(|| vec![None; runs.len() - 1]);
+            scenario_movements[i] = Some((result - previous) / previous);
+        }
+    }
+
+    movements
+}
+
+/// Returns the Pearson correlation of the movements of two scenarios, or `None` if they share too
+/// few movements or either of them never moved
+///
+/// Runs where neither scenario moved are left out, since deterministic scenarios are flat most of
+/// the time and a single shared step change would otherwise look like a perfect correlation
+fn correlation(a: &[Option<f64>], b: &[Option<f64>]) -> Option<f64> {
+    let shared: Vec<(f64, f64)> = a
+        .iter()
+        .zip(b)
+        .filter_map(|(a, b)| Some(((*a)?, (*b)?)))
+        .filter(|&(a, b)| a != 0.0 || b != 0.0)
+        .collect();
+    let co_movements = shared
+        .iter()
+        .filter(|&&(a, b)| a != 0.0 && b != 0.0)
+        .count();
+    if co_movements < MIN_SHARED_MOVEMENTS {
+        return None;
+    }
+
+    let n = shared.len() as f64;
+    let mean_a = shared.iter().map(|(a, _)| a).sum::<f64>() / n;
+    let mean_b = shared.iter().map(|(_, b)| b).sum::<f64>() / n;
+    let (mut covariance, mut variance_a, mut variance_b) = (0.0, 0.0, 0.0);
+    for (a, b) in &shared {
+        covariance += (a - mean_a) * (b - mean_b);
+        variance_a += (a - mean_a).powi(2);
+        variance_b += (b - mean_b).powi(2);
+    }
+    if variance_a == 0.0 || variance_b == 0.0 {
+        return None;
+    }
+
+    Some(covariance / (variance_a * variance_b).sqrt())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::job::GEOMETRIC_MEAN_SCENARIO;
+
+    fn runs(series: &[(&str, &[f64])]) -> Vec<BTreeMap<String, f64>> {
+        let run_count = series[0].1.len();
+        (0..run_count)
+            .map(|i| {
+                series
+                    .iter()
+                    .map(|(scenario, results)| (scenario.to_string(), results[i]))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn scenarios_moving_together_are_grouped() {
+        let base = [
+            100.0, 101.0, 99.0, 102.0, 100.0, 103.0, 98.0, 100.0, 104.0, 101.0, 99.0, 100.0,
+        ];
+        let scaled: Vec<_> = base.iter().map(|r| r * 10.0).collect();
+        let noisy: Vec<_> = base.iter().map(|r| r + 0.01).collect();
+        let unrelated = [
+            100.0, 100.0, 103.0, 99.0, 99.0, 101.0, 101.0, 97.0, 100.0, 100.0, 104.0, 102.0,
+        ];
+
+        let runs = runs(&[
+            ("handshake", &base),
+            ("resumption", &scaled),
+            ("transfer", &noisy),
+            ("unrelated", &unrelated),
+            (GEOMETRIC_MEAN_SCENARIO, &base),
+        ]);
+        let groups = correlated_groups(&runs);
+
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].scenarios, ["handshake", "resumption", "transfer"]);
+        assert!(groups[0].min_correlation > 0.99);
+    }
+
+    #[test]
+    fn short_or_flat_histories_are_not_correlated() {
+        // Too few runs
+        let runs_short = runs(&[("a", &[1.0, 2.0, 3.0]), ("b", &[1.0, 2.0, 3.0])]);
+        assert!(correlated_groups(&runs_short).is_empty());
+
+        // Scenarios that never move
+        let flat = [5.0; 12];
+        let runs_flat = runs(&[("a", &flat), ("b", &flat)]);
+        assert!(correlated_groups(&runs_flat).is_empty());
+    }
+
+    #[test]
+    fn a_single_shared_step_change_is_not_correlated() {
+        // Flat histories with a step change in the same run
+        let a = [
+            100.0, 100.0, 100.0, 100.0, 100.0, 100.0, 110.0, 110.0, 110.0, 110.0, 110.0, 110.0,
+        ];
+        let b = [
+            500.0, 500.0, 500.0, 500.0, 500.0, 500.0, 520.0, 520.0, 520.0, 520.0, 520.0, 520.0,
+        ];
+
+        let runs = runs(&[("a", &a), ("b", &b)]);
+        assert!(correlated_groups(&runs).is_empty());
+    }
+}
@@ -0,0 +1,75 @@
+use std::time::Duration;
+
+use askama::Template;
+
+use crate::db::{CostGrouping, JobCostSummary};
+
+/// How far back the jobs accounted for in the cost overviews go
+pub const COSTS_WINDOW: time::Duration = time::Duration::days(30);
+
+/// A simple HTML page showing the resources consumed by recent jobs
+#[derive(Template)]
+#[template(path = "costs_dashboard.html")]
+struct CostsDashboard<'a> {
+    costs: &'a [JobCostSummary],
+    /// The ways the costs can be grouped by
+    groupings: [CostGrouping; 3],
+    /// The way the costs are grouped by
+    selected_grouping: CostGrouping,
+    window_days: i64,
+}
+
+/// Renders the costs dashboard
+pub fn render_dashboard(grouping: CostGrouping, costs: &[JobCostSummary]) -> String {
+    CostsDashboard {
+        costs,
+        groupings: [
+            CostGrouping::Requester,
+            CostGrouping::Pr,
+            CostGrouping::Event,
+        ],
+        selected_grouping: grouping,
+        window_days: COSTS_WINDOW.whole_days(),
+    }
+    .render()
+    .expect("failed to render askama template")
+}
+
+/// Returns the CPU time (user and system) consumed so far by the child processes of the server
+/// that have terminated and been waited for (their own descendants included)
+///
+/// The usage covers the children of all jobs, so the difference between the values before and
+/// after a job is only the CPU time consumed by the processes it spawned if no other job ran in the
+/// meantime (i.e. with a single queue worker)
+pub fn children_cpu_time() -> Duration {
+    fn to_duration(time: libc::timeval) -> Duration {
+        Duration::from_secs(time.tv_sec as u64) + Duration::from_micros(time.tv_usec as u64)
+    }
+
+    // Safety: `getrusage` only writes to the provided struct, which is valid when zeroed
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return Duration::ZERO;
+    }
+
+    to_duration(usage.ru_utime) + to_duration(usage.ru_stime)
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+
+    use super::*;
+
+    #[test]
+    fn children_cpu_time_accounts_for_waited_children() {
+        let before = children_cpu_time();
+        let status = Command::new("sh")
+            .args(["-c", "i=0; while [ $i -lt 100000 ]; do i=$((i+1)); done"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+
+        assert!(children_cpu_time() > before);
+    }
+}
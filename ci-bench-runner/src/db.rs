@@ -1,15 +1,24 @@
+use std::collections::{BTreeMap, HashSet};
 use std::ops::DerefMut;
 use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context};
 use octocrab::models::CommentId;
-use serde::Serialize;
-use sqlx::sqlite::SqliteRow;
-use sqlx::{Connection, Error, FromRow, Row, SqliteConnection};
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqliteRow};
+use sqlx::{ConnectOptions, Connection, Error, FromRow, Row, SqliteConnection};
 use time::OffsetDateTime;
 use tokio::sync::Mutex;
 use uuid::Uuid;
 
+use crate::event_queue::JobStatus;
+use crate::feature_flags::{FeatureRollout, ReportFeature};
+use crate::job::SelfTestTimings;
+use crate::notifications::WeeklyDigest;
+use crate::runner::FailureClass;
+use crate::signing::SignedSummary;
+use crate::units::{DisplayScale, ResultUnit};
+
 /// An enqueued GitHub event
 #[derive(Debug)]
 pub struct QueuedEvent {
@@ -23,6 +32,31 @@ pub struct QueuedEvent {
     pub payload: Vec<u8>,
     /// The moment at which the event was persisted
     pub created_utc: OffsetDateTime,
+    /// Events with a higher priority are handled first
+    pub priority: i64,
+    /// The moment before which the event must not be handled, for retries that are backing off
+    pub not_before_utc: Option<OffsetDateTime>,
+}
+
+/// The priority of PR events received through webhooks, and of events without a more specific
+/// priority
+pub const DEFAULT_EVENT_PRIORITY: i64 = 0;
+
+/// How long events are kept around after being removed from the queue, to allow replaying them
+pub const HANDLED_EVENT_RETENTION: time::Duration = time::Duration::days(30);
+
+impl QueuedEvent {
+    /// Returns the event's priority, boosted by one level for every `aging_interval` the event has
+    /// been waiting in the queue (no boost is applied if the interval is zero)
+    pub fn effective_priority(&self, now: OffsetDateTime, aging_interval: time::Duration) -> i64 {
+        let aging_seconds = aging_interval.whole_seconds();
+        if aging_seconds <= 0 {
+            return self.priority;
+        }
+
+        let waited_seconds = (now - self.created_utc).whole_seconds().max(0);
+        self.priority + waited_seconds / aging_seconds
+    }
 }
 
 impl FromRow<'_, SqliteRow> for QueuedEvent {
@@ -42,6 +76,52 @@ impl FromRow<'_, SqliteRow> for QueuedEvent {
             event: row.try_get("event")?,
             payload: row.try_get("payload")?,
             created_utc: row.try_get("created_utc")?,
+            priority: row.try_get("priority")?,
+            not_before_utc: row.try_get("not_before_utc")?,
+        })
+    }
+}
+
+/// An event that was taken out of the queue because handling it failed in a way retrying wouldn't
+/// fix (e.g. its payload is invalid, or its job crashed), kept around for inspection
+#[derive(Debug)]
+pub struct DeadEvent {
+    /// The id the event had in the queue
+    pub id: Uuid,
+    /// The event kind
+    pub event: String,
+    pub payload: Vec<u8>,
+    pub priority: i64,
+    /// The moment at which the event was enqueued
+    pub created_utc: OffsetDateTime,
+    /// The moment at which the event was taken out of the queue
+    pub died_utc: OffsetDateTime,
+    /// The job that was handling the event, if any
+    pub job_id: Option<Uuid>,
+    /// Why the event was taken out of the queue
+    pub error: String,
+}
+
+impl FromRow<'_, SqliteRow> for DeadEvent {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let id = row.try_get::<Vec<u8>, _>("id")?;
+        let id = Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let job_id = row.try_get::<Option<Vec<u8>>, _>("job_id")?;
+        let job_id = match job_id {
+            None => None,
+            Some(id) => Some(Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?),
+        };
+
+        Ok(Self {
+            id,
+            event: row.try_get("event")?,
+            payload: row.try_get("payload")?,
+            priority: row.try_get("priority")?,
+            created_utc: row.try_get("created_utc")?,
+            died_utc: row.try_get("died_utc")?,
+            job_id,
+            error: row.try_get("error")?,
         })
     }
 }
@@ -60,6 +140,439 @@ pub struct BenchJob {
     pub finished_utc: Option<OffsetDateTime>,
     /// Whether the job finished without errors
     pub success: Option<bool>,
+    /// Where the job is in its lifecycle (see [`crate::event_queue::JobStatus`])
+    pub status: String,
+    /// The class of the failure that prevented the job from producing results, if any (see
+    /// [`crate::runner::FailureClass`])
+    pub failure_class: Option<String>,
+    /// Whether the job was cancelled by a user before it finished
+    pub cancelled: bool,
+    /// The phase the job was in when it last reported progress (see
+    /// [`crate::event_queue::JobPhase`])
+    pub phase: Option<String>,
+    /// Whether the job was interrupted by a shutdown of the application, in which case its event
+    /// is handled again by a new job after restarting (its partial outputs are kept in its output
+    /// dir)
+    pub interrupted: bool,
+    /// The GitHub user whose action triggered the job, if known
+    pub requester: Option<String>,
+    /// The CPU time consumed by the processes the job spawned, once the job has finished (only
+    /// tracked with a single queue worker)
+    pub cpu_seconds: Option<f64>,
+    /// The time it took to run the job, once the job has finished
+    pub wall_seconds: Option<f64>,
+}
+
+/// A comparison requested through the HTTP API by an external CI system
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiComparison {
+    /// The id handed out to the client, to poll for the comparison's outcome
+    pub id: Uuid,
+    /// The name of the API client that requested the comparison
+    pub client: String,
+    /// The baseline, as requested (a commit hash, branch or tag of the repository)
+    pub baseline_ref: String,
+    /// The candidate, as requested (a commit hash, branch or tag of the repository)
+    pub candidate_ref: String,
+    /// The baseline commit, once the job has resolved it
+    pub baseline_commit: Option<String>,
+    /// The candidate commit, once the job has resolved it
+    pub candidate_commit: Option<String>,
+    /// The latest job that handled the comparison, if any
+    pub job_id: Option<Uuid>,
+    pub created_utc: OffsetDateTime,
+    /// The moment at which the latest job finished, successfully or not
+    pub finished_utc: Option<OffsetDateTime>,
+    /// The comparison's verdict, once it succeeded
+    pub verdict: Option<String>,
+    /// The comparison's results, as JSON, once it succeeded
+    pub result: Option<String>,
+    /// The reason the comparison failed, if it did
+    pub error: Option<String>,
+}
+
+impl FromRow<'_, SqliteRow> for ApiComparison {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let id = row.try_get::<Vec<u8>, _>("id")?;
+        let id = Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let job_id = row.try_get::<Option<Vec<u8>>, _>("job_id")?;
+        let job_id = match job_id {
+            None => None,
+            Some(id) => Some(Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?),
+        };
+
+        Ok(Self {
+            id,
+            client: row.try_get("client")?,
+            baseline_ref: row.try_get("baseline_ref")?,
+            candidate_ref: row.try_get("candidate_ref")?,
+            baseline_commit: row.try_get("baseline_commit")?,
+            candidate_commit: row.try_get("candidate_commit")?,
+            job_id,
+            created_utc: row.try_get("created_utc")?,
+            finished_utc: row.try_get("finished_utc")?,
+            verdict: row.try_get("verdict")?,
+            result: row.try_get("result")?,
+            error: row.try_get("error")?,
+        })
+    }
+}
+
+/// A self-test requested through the admin API (see [`crate::job::handle_self_test`])
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelfTest {
+    pub id: Uuid,
+    /// The latest job that handled the self-test, if any
+    pub job_id: Option<Uuid>,
+    pub created_utc: OffsetDateTime,
+    /// The moment at which the latest job finished, successfully or not
+    pub finished_utc: Option<OffsetDateTime>,
+    /// How long each stage took, as JSON, once the self-test succeeded
+    pub timings: Option<String>,
+    /// The reason the self-test failed, if it did
+    pub error: Option<String>,
+}
+
+impl FromRow<'_, SqliteRow> for SelfTest {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let id = row.try_get::<Vec<u8>, _>("id")?;
+        let id = Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let job_id = row.try_get::<Option<Vec<u8>>, _>("job_id")?;
+        let job_id = match job_id {
+            None => None,
+            Some(id) => Some(Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?),
+        };
+
+        Ok(Self {
+            id,
+            job_id,
+            created_utc: row.try_get("created_utc")?,
+            finished_utc: row.try_get("finished_utc")?,
+            timings: row.try_get("timings")?,
+            error: row.try_get("error")?,
+        })
+    }
+}
+
+/// A note attached by a maintainer to explain results with a known upstream cause (e.g. a
+/// dependency upgrade), shown wherever the annotated results show up
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Annotation {
+    pub id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    pub note: String,
+    /// The annotated comparison, for annotations of a single comparison
+    pub comparison_id: Option<Uuid>,
+    /// The annotated window, for annotations of a tracked branch's history
+    pub window: Option<AnnotatedWindow>,
+}
+
+/// A window of a tracked branch's history, from the bench run of a commit to that of a later one
+/// (both included)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AnnotatedWindow {
+    pub branch: String,
+    pub from_commit: String,
+    pub to_commit: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub from_utc: OffsetDateTime,
+    #[serde(with = "time::serde::rfc3339")]
+    pub to_utc: OffsetDateTime,
+}
+
+impl FromRow<'_, SqliteRow> for Annotation {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let id = row.try_get::<Vec<u8>, _>("id")?;
+        let id = Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let comparison_id = row.try_get::<Option<Vec<u8>>, _>("comparison_id")?;
+        let comparison_id = match comparison_id {
+            None => None,
+            Some(id) => Some(Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?),
+        };
+
+        let window = match row.try_get::<Option<String>, _>("branch")? {
+            None => None,
+            Some(branch) => Some(AnnotatedWindow {
+                branch,
+                from_commit: row.try_get("from_commit")?,
+                to_commit: row.try_get("to_commit")?,
+                from_utc: row.try_get("from_utc")?,
+                to_utc: row.try_get("to_utc")?,
+            }),
+        };
+
+        Ok(Self {
+            id,
+            created_utc: row.try_get("created_utc")?,
+            note: row.try_get("note")?,
+            comparison_id,
+            window,
+        })
+    }
+}
+
+/// What an annotation is attached to
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnnotationTarget {
+    /// A single comparison
+    Comparison(Uuid),
+    /// The window of the branch's history between the commits (in any order), which must have
+    /// been benchmarked on the branch
+    Window {
+        branch: String,
+        from_commit: String,
+        to_commit: String,
+    },
+}
+
+/// The provenance of a job's results, from the event that triggered it to the GitHub comment
+/// where the results were reported
+#[derive(Debug, Serialize)]
+pub struct JobTrace {
+    /// The job itself
+    pub job: BenchJob,
+    /// Id of the event that triggered the job (the event is archived once handled, and can be
+    /// replayed while it is)
+    pub event_id: Option<Uuid>,
+    /// Kind of the event that triggered the job
+    pub event: Option<String>,
+    /// The bench run produced by the job, for pushes to main
+    pub bench_run: Option<TracedBenchRun>,
+    /// The comparison reported by the job, for PRs
+    pub comparison: Option<TracedComparison>,
+    /// The PR where the results were reported
+    pub pr_number: Option<u64>,
+    /// The comment where the results were reported
+    pub comment_id: Option<u64>,
+}
+
+/// A bench run, as part of a [`JobTrace`]
+#[derive(Debug, PartialEq, sqlx::FromRow, Serialize)]
+pub struct TracedBenchRun {
+    #[sqlx(try_from = "Vec<u8>")]
+    pub id: Uuid,
+    pub created_utc: OffsetDateTime,
+    pub commit_sha: Option<String>,
+}
+
+/// A comparison run, as part of a [`JobTrace`]
+#[derive(Debug, PartialEq, sqlx::FromRow, Serialize)]
+pub struct TracedComparison {
+    #[sqlx(try_from = "Vec<u8>")]
+    pub id: Uuid,
+    pub created_utc: OffsetDateTime,
+    pub baseline_commit: String,
+    pub candidate_commit: String,
+    pub verdict: Option<String>,
+}
+
+/// The triage state of a regression detected on main
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegressionState {
+    /// Nobody has looked at the regression yet
+    New,
+    /// Someone is looking into the regression
+    Triaged,
+    /// The regression is a deliberate trade-off (e.g. a security fix)
+    Accepted,
+    /// The regression has been fixed
+    Fixed,
+}
+
+impl RegressionState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegressionState::New => "new",
+            RegressionState::Triaged => "triaged",
+            RegressionState::Accepted => "accepted",
+            RegressionState::Fixed => "fixed",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        Some(match state {
+            "new" => Self::New,
+            "triaged" => Self::Triaged,
+            "accepted" => Self::Accepted,
+            "fixed" => Self::Fixed,
+            _ => return None,
+        })
+    }
+}
+
+/// The ways in which job costs can be aggregated
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CostGrouping {
+    /// By the GitHub user whose action triggered the jobs
+    #[default]
+    Requester,
+    /// By the PR the jobs belong to
+    Pr,
+    /// By the kind of the event that triggered the jobs
+    Event,
+}
+
+impl CostGrouping {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CostGrouping::Requester => "requester",
+            CostGrouping::Pr => "pr",
+            CostGrouping::Event => "event",
+        }
+    }
+
+    /// The expression of the `jobs` table that jobs are grouped by
+    fn group_by_expr(&self) -> &'static str {
+        match self {
+            CostGrouping::Requester => "requester",
+            CostGrouping::Pr => "CAST(pr_number AS TEXT)",
+            CostGrouping::Event => "event",
+        }
+    }
+}
+
+/// The resources consumed by a group of jobs (see [`CostGrouping`])
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct JobCostSummary {
+    /// The requester, PR number or event kind shared by the jobs (unknown if `None`)
+    pub key: Option<String>,
+    pub jobs: i64,
+    pub cpu_seconds: f64,
+    pub wall_seconds: f64,
+}
+
+/// A webhook event that was dropped because it came from a repository we don't serve
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct DroppedWebhook {
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    pub event: String,
+    /// The repository the event came from (as `owner/name`), if the payload mentioned one
+    pub repository: Option<String>,
+}
+
+/// A pause of event processing requested through the admin API
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct QueuePause {
+    #[serde(with = "time::serde::rfc3339")]
+    pub paused_utc: OffsetDateTime,
+    /// Why processing was paused (e.g. host maintenance), if provided
+    pub reason: Option<String>,
+}
+
+/// An action performed by a maintainer that affects other people's jobs (e.g. reprioritizing the
+/// queue), recorded for accountability
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow, Serialize)]
+pub struct AuditLogEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    /// The GitHub login of the user who performed the action
+    pub actor: String,
+    pub action: String,
+    /// A human-readable description of what the action changed
+    pub details: String,
+}
+
+/// A scenario that regressed significantly on main
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RegressedScenario {
+    pub scenario_name: String,
+    /// The result of the last commit before the regression
+    pub previous_result: f64,
+    /// The result of the commit that introduced the regression
+    pub current_result: f64,
+    /// The significance threshold that was exceeded
+    pub significance_threshold: f64,
+    /// The unit of the results (regressions recorded before units were tracked are always
+    /// instruction counts)
+    #[serde(default)]
+    pub result_unit: ResultUnit,
+}
+
+impl RegressedScenario {
+    /// Returns the scale used to render the scenario's results, which fits both of them
+    pub fn display_scale(&self) -> DisplayScale {
+        self.result_unit
+            .common_scale(self.previous_result, self.current_result)
+    }
+}
+
+impl From<&ScenarioDiff> for RegressedScenario {
+    fn from(diff: &ScenarioDiff) -> Self {
+        Self {
+            scenario_name: diff.scenario_name.clone(),
+            previous_result: diff.baseline_result,
+            current_result: diff.candidate_result,
+            significance_threshold: diff.significance_threshold,
+            result_unit: diff.result_unit,
+        }
+    }
+}
+
+/// A performance regression detected on main, tracked separately from functional issues
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Regression {
+    pub id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    /// The last time the regression's state changed
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_utc: OffsetDateTime,
+    /// The tracked branch on which the regression was detected
+    pub branch: String,
+    /// The last benchmarked commit of the branch before the regression
+    pub last_good_commit: String,
+    /// The first benchmarked commit of the branch exhibiting the regression
+    pub first_bad_commit: String,
+    pub state: RegressionState,
+    pub scenarios: Vec<RegressedScenario>,
+    /// The merged PRs that introduced the commit range, if they could be determined
+    pub pr_numbers: Vec<u64>,
+}
+
+impl FromRow<'_, SqliteRow> for Regression {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let id = row.try_get::<Vec<u8>, _>("id")?;
+        let id = Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let state = row.try_get::<String, _>("state")?;
+        let state = RegressionState::parse(&state)
+            .ok_or_else(|| Error::Decode(format!("invalid regression state: {state}").into()))?;
+
+        let scenarios = row.try_get::<String, _>("scenarios")?;
+        let scenarios = serde_json::from_str(&scenarios).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let pr_numbers = row.try_get::<String, _>("pr_numbers")?;
+        let pr_numbers =
+            serde_json::from_str(&pr_numbers).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        Ok(Self {
+            id,
+            created_utc: row.try_get("created_utc")?,
+            updated_utc: row.try_get("updated_utc")?,
+            branch: row.try_get("branch")?,
+            last_good_commit: row.try_get("last_good_commit")?,
+            first_bad_commit: row.try_get("first_bad_commit")?,
+            state,
+            scenarios,
+            pr_numbers,
+        })
+    }
+}
+
+/// A PR comment where a comparison was reported
+#[derive(Debug)]
+pub struct ReportComment {
+    pub pr_number: u64,
+    pub comment_id: CommentId,
+    /// Whether the comment has since been updated to report a different comparison
+    pub superseded: bool,
 }
 
 /// A result for a specific benchmark scenario
@@ -75,18 +588,118 @@ pub struct BenchResult {
     /// We use f64 here to support multiple kinds of measurement (i.e. not only instruction counts,
     /// which are integers)
     pub result: f64,
+    /// The unit of the result
+    #[sqlx(try_from = "i64")]
+    pub result_unit: ResultUnit,
 }
 
 /// The results of a comparison between two branches of rustls
-#[derive(Debug, Clone)]
+///
+/// A scenario can be measured through several metrics (e.g. instruction counts and cache misses),
+/// each with its own unit and significance thresholds, so results are grouped by metric. Metrics
+/// that weren't measured are absent.
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ComparisonResult {
-    /// Result for the icount benchmarks
-    pub icount: ComparisonSubResult,
-    /// Result for the walltime benchmarks
-    pub walltime: ComparisonSubResult,
+    #[serde(flatten)]
+    pub metrics: BTreeMap<ScenarioKind, ComparisonSubResult>,
+}
+
+/// The result of a metric that wasn't measured
+static NOT_MEASURED: ComparisonSubResult = ComparisonSubResult {
+    diffs: Vec::new(),
+    scenarios_missing_in_baseline: Vec::new(),
+};
+
+impl ComparisonResult {
+    /// Returns the overall verdict of the comparison
+    ///
+    /// Only instruction counts are taken into account, because wall-time measurements are too
+    /// noisy to make automated decisions based on them
+    pub fn verdict(&self) -> Verdict {
+        self.metric(ScenarioKind::Icount).verdict()
+    }
+
+    /// Returns the result for the metric (empty if it wasn't measured)
+    pub fn metric(&self, kind: ScenarioKind) -> &ComparisonSubResult {
+        self.metrics.get(&kind).unwrap_or(&NOT_MEASURED)
+    }
+
+    /// Returns the result for the metric, for modification
+    pub fn metric_mut(&mut self, kind: ScenarioKind) -> &mut ComparisonSubResult {
+        self.metrics.entry(kind).or_default()
+    }
+
+    /// Removes the result for the metric, returning it (empty if it wasn't measured)
+    pub fn take_metric(&mut self, kind: ScenarioKind) -> ComparisonSubResult {
+        self.metrics.remove(&kind).unwrap_or_default()
+    }
+}
+
+impl FromIterator<(ScenarioKind, ComparisonSubResult)> for ComparisonResult {
+    fn from_iter<T: IntoIterator<Item = (ScenarioKind, ComparisonSubResult)>>(iter: T) -> Self {
+        Self {
+            metrics: iter.into_iter().collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl ComparisonResult {
+    /// Returns a result with the provided icount and walltime diffs
+    pub fn from_diffs(
+        icount_diffs: Vec<ScenarioDiff>,
+        icount_missing_in_baseline: &[&str],
+        walltime_diffs: Vec<ScenarioDiff>,
+    ) -> Self {
+        ComparisonResult::from_iter([
+            (
+                ScenarioKind::Icount,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: icount_missing_in_baseline
+                        .iter()
+                        .map(|s| s.to_string())
+                        .collect(),
+                    diffs: icount_diffs,
+                },
+            ),
+            (
+                ScenarioKind::Walltime,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: Vec::new(),
+                    diffs: walltime_diffs,
+                },
+            ),
+        ])
+    }
+}
+
+/// A stable, machine-readable summary of a comparison, meant for automation
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Verdict {
+    /// At least one scenario improved significantly, and none regressed
+    Improved,
+    /// At least one scenario regressed significantly
+    Regressed,
+    /// No significant differences
+    Neutral,
+    /// The comparison could not be performed
+    Error,
+}
+
+impl Verdict {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Verdict::Improved => "improved",
+            Verdict::Regressed => "regressed",
+            Verdict::Neutral => "neutral",
+            Verdict::Error => "error",
+        }
+    }
 }
 
-#[derive(Debug, Clone)]
+/// The result of a comparison for a single metric
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct ComparisonSubResult {
     /// The diffs, per scenario
     pub diffs: Vec<ScenarioDiff>,
@@ -94,9 +707,27 @@ pub struct ComparisonSubResult {
     pub scenarios_missing_in_baseline: Vec<String>,
 }
 
+impl ComparisonSubResult {
+    /// Returns the verdict of the comparison for this metric
+    pub fn verdict(&self) -> Verdict {
+        let significant = self.diffs.iter().filter(|d| d.is_significant());
+
+        let mut verdict = Verdict::Neutral;
+        for diff in significant {
+            if diff.diff() > 0.0 {
+                return Verdict::Regressed;
+            } else if diff.diff() < 0.0 {
+                verdict = Verdict::Improved;
+            }
+        }
+
+        verdict
+    }
+}
+
 /// A diff for a particular scenario, obtained by comparing benchmark results between two versions
 /// of rustls
-#[derive(Clone, Debug, PartialEq, sqlx::FromRow)]
+#[derive(Clone, Debug, PartialEq, sqlx::FromRow, Serialize)]
 pub struct ScenarioDiff {
     /// The scenario's name
     pub scenario_name: String,
@@ -109,10 +740,49 @@ pub struct ScenarioDiff {
     pub candidate_result: f64,
     /// Significance threshold derived from history, when the diff was created
     pub significance_threshold: f64,
+    /// The unit of the baseline and candidate results
+    #[sqlx(try_from = "i64")]
+    pub result_unit: ResultUnit,
     /// Instruction-level cachegrind diff, for icount scenarios
+    ///
+    /// Not serialized, because it is big and there is a dedicated endpoint to retrieve it
+    #[serde(skip)]
     pub cachegrind_diff: Option<String>,
 }
 
+/// A cachegrind diff, as stored in the database
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredCachegrindDiff {
+    /// The comparison the diff belongs to
+    pub comparison_id: Uuid,
+    /// The diff itself, or its beginning if it exceeded the size limit
+    pub diff: String,
+    /// The size of the diff's end, if it exceeded the size limit (it is then stored in the
+    /// [`crate::artifacts::ArtifactStore`])
+    pub overflow_bytes: Option<u64>,
+}
+
+#[cfg(test)]
+impl ScenarioDiff {
+    /// Returns an icount diff (without cachegrind diff) for the scenario
+    pub fn icount(
+        scenario_name: &str,
+        baseline_result: f64,
+        candidate_result: f64,
+        significance_threshold: f64,
+    ) -> Self {
+        ScenarioDiff {
+            scenario_name: scenario_name.to_string(),
+            scenario_kind: ScenarioKind::Icount,
+            baseline_result,
+            candidate_result,
+            significance_threshold,
+            result_unit: ResultUnit::Instructions,
+            cachegrind_diff: None,
+        }
+    }
+}
+
 impl ScenarioDiff {
     /// Returns the measured difference between the candidate and the baseline results
     pub fn diff(&self) -> f64 {
@@ -123,75 +793,386 @@ impl ScenarioDiff {
     pub fn diff_ratio(&self) -> f64 {
         self.diff() / self.baseline_result
     }
-}
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-pub enum ScenarioKind {
-    Icount = 0,
-    Walltime = 1,
-}
 
-impl TryFrom<i64> for ScenarioKind {
-    type Error = anyhow::Error;
+    /// Returns true if the change exceeds the significance threshold
+    pub fn is_significant(&self) -> bool {
+        self.diff_ratio().abs() >= self.significance_threshold
+    }
 
-    fn try_from(value: i64) -> Result<Self, Self::Error> {
-        match value {
-            0 => Ok(Self::Icount),
-            1 => Ok(Self::Walltime),
-            kind => bail!("invalid scenario kind: {kind}"),
-        }
+    /// Returns the scale used to render the diff's results, which fits both of them
+    pub fn display_scale(&self) -> DisplayScale {
+        self.result_unit
+            .common_scale(self.baseline_result, self.candidate_result)
     }
 }
 
-/// Strongly-typed interface to the database
-#[derive(Clone)]
-pub struct Db {
-    sqlite: Arc<Mutex<SqliteConnection>>,
+/// How many jobs succeeded and failed on a given day
+#[derive(Debug, Clone, PartialEq, Eq, FromRow, Serialize)]
+pub struct DailyJobOutcomes {
+    /// The day, formatted as `YYYY-MM-DD` (UTC)
+    pub day: String,
+    pub succeeded: i64,
+    pub failed: i64,
 }
 
-impl Db {
-    /// Creates a new [`Db`] wrapping the provided SQLite connection
-    pub fn with_connection(sqlite: Arc<Mutex<SqliteConnection>>) -> Self {
+/// A scenario that appeared in or disappeared from the results of a tracked branch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenarioSetChange {
+    pub scenario_name: String,
+    pub scenario_kind: ScenarioKind,
+    /// Whether the scenario was added (otherwise, it was removed)
+    pub added: bool,
+    /// The first benchmarked commit reflecting the change
+    pub commit_sha: String,
+    /// The benchmarked commit right before it
+    pub previous_commit_sha: String,
+}
+
+/// A scenario's result in a bench run of a tracked branch
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScenarioRunResult {
+    pub bench_run_id: Uuid,
+    /// The job that produced the bench run, if known
+    #[serde(skip)]
+    pub job_id: Option<Uuid>,
+    pub commit_sha: String,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    pub result: f64,
+    pub result_unit: ResultUnit,
+}
+
+impl FromRow<'_, SqliteRow> for ScenarioRunResult {
+    fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+        let bench_run_id = row.try_get::<Vec<u8>, _>("bench_run_id")?;
+        let bench_run_id =
+            Uuid::from_slice(&bench_run_id).map_err(|e| Error::Decode(Box::new(e)))?;
+
+        let job_id = row.try_get::<Option<Vec<u8>>, _>("job_id")?;
+        let job_id = match job_id {
+            None => None,
+            Some(id) => Some(Uuid::from_slice(&id).map_err(|e| Error::Decode(Box::new(e)))?),
+        };
+
+        let result_unit = row.try_get::<i64, _>("result_unit")?;
+        let result_unit = ResultUnit::try_from(result_unit).map_err(|e| Error::Decode(e.into()))?;
+
+        Ok(Self {
+            bench_run_id,
+            job_id,
+            commit_sha: row.try_get("commit_sha")?,
+            created_utc: row.try_get("created_utc")?,
+            result: row.try_get("result")?,
+            result_unit,
+        })
+    }
+}
+
+/// The metric through which a scenario was measured
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScenarioKind {
+    Icount = 0,
+    Walltime = 1,
+    /// Peak resident set size of the benchmarked process
+    MaxRss = 2,
+    /// First-level data cache misses, as simulated by cachegrind
+    D1Misses = 3,
+    /// Last-level cache misses, as simulated by cachegrind
+    LlMisses = 4,
+    /// Wall time of a clean release build of the benchmarks (including rustls)
+    BuildTime = 5,
+    /// CPU cycles estimated from the instruction count and cache misses (see
+    /// [`crate::job::CacheMisses::estimated_cycles`])
+    EstimatedCycles = 6,
+    /// CPU cycles, as counted by `perf stat`
+    PerfCycles = 7,
+    /// Retired instructions, as counted by `perf stat`
+    PerfInstructions = 8,
+    /// Mispredicted branches, as counted by `perf stat`
+    PerfBranchMisses = 9,
+    /// Package energy consumed while running the walltime benchmarks, as measured by RAPL
+    Energy = 10,
+    /// Geometric mean of the instruction counts of a run's scenarios, stored under the names of
+    /// the aggregate scenarios (see [`crate::job::is_aggregate_scenario`])
+    IcountIndex = 11,
+}
+
+impl ScenarioKind {
+    /// All metrics, in the order in which they are reported
+    pub const ALL: [ScenarioKind; 12] = [
+        ScenarioKind::Icount,
+        ScenarioKind::Walltime,
+        ScenarioKind::MaxRss,
+        ScenarioKind::D1Misses,
+        ScenarioKind::LlMisses,
+        ScenarioKind::BuildTime,
+        ScenarioKind::EstimatedCycles,
+        ScenarioKind::PerfCycles,
+        ScenarioKind::PerfInstructions,
+        ScenarioKind::PerfBranchMisses,
+        ScenarioKind::Energy,
+        ScenarioKind::IcountIndex,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScenarioKind::Icount => "icount",
+            ScenarioKind::Walltime => "walltime",
+            ScenarioKind::MaxRss => "max_rss",
+            ScenarioKind::D1Misses => "d1_misses",
+            ScenarioKind::LlMisses => "ll_misses",
+            ScenarioKind::BuildTime => "build_time",
+            ScenarioKind::EstimatedCycles => "estimated_cycles",
+            ScenarioKind::PerfCycles => "perf_cycles",
+            ScenarioKind::PerfInstructions => "perf_instructions",
+            ScenarioKind::PerfBranchMisses => "perf_branch_misses",
+            ScenarioKind::Energy => "energy",
+            ScenarioKind::IcountIndex => "icount_index",
+        }
+    }
+
+    /// Returns a human-readable name of the metric, for reports
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScenarioKind::Icount => "Instruction counts",
+            ScenarioKind::Walltime => "Wall-time",
+            ScenarioKind::MaxRss => "Memory usage",
+            ScenarioKind::D1Misses => "D1 cache misses",
+            ScenarioKind::LlMisses => "LL cache misses",
+            ScenarioKind::BuildTime => "Build time",
+            ScenarioKind::EstimatedCycles => "Estimated cycles",
+            ScenarioKind::PerfCycles => "Cycles (perf stat)",
+            ScenarioKind::PerfInstructions => "Instructions (perf stat)",
+            ScenarioKind::PerfBranchMisses => "Branch misses (perf stat)",
+            ScenarioKind::Energy => "Energy (RAPL)",
+            ScenarioKind::IcountIndex => "Instruction count index",
+        }
+    }
+
+    /// Returns the unit in which the scenario's results are measured
+    pub fn unit(&self) -> ResultUnit {
+        match self {
+            ScenarioKind::Icount | ScenarioKind::PerfInstructions | ScenarioKind::IcountIndex => {
+                ResultUnit::Instructions
+            }
+            ScenarioKind::Walltime | ScenarioKind::BuildTime => ResultUnit::Nanoseconds,
+            ScenarioKind::MaxRss => ResultUnit::Bytes,
+            ScenarioKind::D1Misses | ScenarioKind::LlMisses | ScenarioKind::PerfBranchMisses => {
+                ResultUnit::Misses
+            }
+            ScenarioKind::EstimatedCycles | ScenarioKind::PerfCycles => ResultUnit::Cycles,
+            ScenarioKind::Energy => ResultUnit::Joules,
+        }
+    }
+}
+
+impl TryFrom<i64> for ScenarioKind {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Icount),
+            1 => Ok(Self::Walltime),
+            2 => Ok(Self::MaxRss),
+            3 => Ok(Self::D1Misses),
+            4 => Ok(Self::LlMisses),
+            5 => Ok(Self::BuildTime),
+            6 => Ok(Self::EstimatedCycles),
+            7 => Ok(Self::PerfCycles),
+            8 => Ok(Self::PerfInstructions),
+            9 => Ok(Self::PerfBranchMisses),
+            10 => Ok(Self::Energy),
+            11 => Ok(Self::IcountIndex),
+            kind => bail!("invalid scenario kind: {kind}"),
+        }
+    }
+}
+
+/// Tables that are included in anonymized exports of the database
+///
+/// Tables containing raw webhook payloads or references to GitHub comments are deliberately left
+/// out.
+static EXPORTED_TABLES: &[&str] = &[
+    "bench_runs",
+    "bench_results",
+    "comparison_runs",
+    "scenario_diffs",
+    "comparison_missing_scenarios",
+    "artifact_sizes",
+];
+
+/// Opens a connection to the SQLite database at the provided path, creating it if necessary
+pub async fn connect_creating_db(path_to_db: &str) -> anyhow::Result<SqliteConnection> {
+    let options: SqliteConnectOptions = format!("sqlite:{path_to_db}")
+        .parse()
+        .context("invalid path to database")?;
+    options
+        .create_if_missing(true)
+        .connect()
+        .await
+        .context("unable to open database")
+}
+
+/// Strongly-typed interface to the database
+#[derive(Clone)]
+pub struct Db {
+    sqlite: Arc<Mutex<SqliteConnection>>,
+}
+
+impl Db {
+    /// Creates a new [`Db`] wrapping the provided SQLite connection
+    pub fn with_connection(sqlite: Arc<Mutex<SqliteConnection>>) -> Self {
         Self { sqlite }
     }
 
     /// Enqueues an incoming event to the database
     #[tracing::instrument(skip(self, payload), ret)]
     pub async fn enqueue_event(&self, event: &str, payload: &[u8]) -> anyhow::Result<Uuid> {
+        self.enqueue_event_with_priority(event, payload, DEFAULT_EVENT_PRIORITY)
+            .await
+    }
+
+    /// Enqueues an event, which will be handled after all queued events of higher priority
+    pub async fn enqueue_event_with_priority(
+        &self,
+        event: &str,
+        payload: &[u8],
+        priority: i64,
+    ) -> anyhow::Result<Uuid> {
         let id = Uuid::new_v4();
         let now = OffsetDateTime::now_utc();
 
         let mut conn = self.sqlite.lock().await;
         sqlx::query(
-            "INSERT INTO event_queue (id, created_utc, event, payload) VALUES (?, ?, ?, ?)",
+            "INSERT INTO event_queue (id, created_utc, event, payload, priority) VALUES (?, ?, ?, ?, ?)",
         )
         .bind(id.as_bytes().as_slice())
         .bind(now)
         .bind(event)
         .bind(payload)
+        .bind(priority)
         .execute(conn.deref_mut())
         .await?;
 
         Ok(id)
     }
 
-    /// Retrieves the next event we should handle
+    /// Enqueues a webhook event, recording its delivery so redeliveries of the same event are
+    /// skipped
+    ///
+    /// Returns the id of the queued event, or `Err` with the id of the event that was queued when
+    /// the delivery was first received
+    pub async fn enqueue_webhook_delivery(
+        &self,
+        delivery: &str,
+        event: &str,
+        payload: &[u8],
+        priority: i64,
+    ) -> anyhow::Result<Result<Uuid, Uuid>> {
+        let delivery = delivery.to_string();
+        let event = event.to_string();
+        let payload = payload.to_vec();
+        let id = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+
+        let mut conn = self.sqlite.lock().await;
+        let id = conn
+            .transaction(|t| {
+                Box::pin(async move {
+                    let previous: Option<Vec<u8>> = sqlx::query_scalar(
+                        "SELECT event_id FROM webhook_deliveries WHERE guid = ?",
+                    )
+                    .bind(delivery.as_str())
+                    .fetch_optional(t.deref_mut())
+                    .await?;
+                    if let Some(previous) = previous {
+                        let previous =
+                            Uuid::from_slice(&previous).map_err(|e| Error::Decode(Box::new(e)))?;
+                        return Ok(Err(previous));
+                    }
+
+                    sqlx::query(
+                        "INSERT INTO webhook_deliveries (guid, event_id, received_utc) VALUES (?, ?, ?)",
+                    )
+                    .bind(delivery.as_str())
+                    .bind(id.as_bytes().as_slice())
+                    .bind(now)
+                    .execute(t.deref_mut())
+                    .await?;
+                    sqlx::query(
+                        "INSERT INTO event_queue (id, created_utc, event, payload, priority) VALUES (?, ?, ?, ?, ?)",
+                    )
+                    .bind(id.as_bytes().as_slice())
+                    .bind(now)
+                    .bind(event)
+                    .bind(payload)
+                    .bind(priority)
+                    .execute(t.deref_mut())
+                    .await?;
+
+                    Ok::<_, Error>(Ok(id))
+                })
+            })
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Records the delivery of a webhook that is handled right away instead of enqueued
+    ///
+    /// Returns `false` if the delivery had been recorded before (i.e. this is a redelivery)
+    #[tracing::instrument(skip(self))]
+    pub async fn record_webhook_delivery(&self, delivery: &str) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let result = sqlx::query(
+            "INSERT OR IGNORE INTO webhook_deliveries (guid, event_id, received_utc) VALUES (?, ?, ?)",
+        )
+        .bind(delivery)
+        .bind(Uuid::new_v4().as_bytes().as_slice())
+        .bind(OffsetDateTime::now_utc())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Retrieves the next event we should handle, if any
     #[tracing::instrument(skip(self))]
-    pub async fn next_queued_event(&self) -> anyhow::Result<QueuedEvent> {
+    pub async fn next_queued_event(&self) -> anyhow::Result<Option<QueuedEvent>> {
         let mut conn = self.sqlite.lock().await;
         let event = sqlx::query_as(
             r"
             SELECT *
             FROM event_queue
-            ORDER BY created_utc
+            ORDER BY priority DESC, created_utc
             LIMIT 1",
         )
-        .fetch_one(conn.deref_mut())
+        .fetch_optional(conn.deref_mut())
         .await?;
 
         Ok(event)
     }
 
+    /// Retrieves the next event we should handle, if any, taking into account how long events have
+    /// been waiting (see [`QueuedEvent::effective_priority`]), so low-priority events can't starve
+    #[tracing::instrument(skip(self))]
+    pub async fn next_queued_event_with_aging(
+        &self,
+        now: OffsetDateTime,
+        aging_interval: time::Duration,
+    ) -> anyhow::Result<Option<QueuedEvent>> {
+        let events = self.queued_events().await?;
+        let event = events.into_iter().min_by_key(|event| {
+            (
+                std::cmp::Reverse(event.effective_priority(now, aging_interval)),
+                event.created_utc,
+            )
+        });
+
+        Ok(event)
+    }
+
     /// Returns the count of currently queued events
     #[tracing::instrument(skip(self), ret)]
     pub async fn queued_event_count(&self) -> anyhow::Result<i64> {
@@ -207,47 +1188,31 @@ impl Db {
         Ok(row.try_get("count")?)
     }
 
-    /// Deletes the event from the database
+    /// Deletes the event from the queue
     ///
-    /// Used to get rid of events once they have been successfully handled
+    /// Used to get rid of events once they have been successfully handled. The event is archived
+    /// for [`HANDLED_EVENT_RETENTION`], so it can be replayed (see [`Db::handled_event`])
     #[tracing::instrument(skip(self))]
     pub async fn delete_event(&self, id: Uuid) -> anyhow::Result<()> {
-        let mut conn = self.sqlite.lock().await;
-        sqlx::query("DELETE FROM event_queue WHERE id = ?")
-            .bind(id.as_bytes().as_slice())
-            .execute(conn.deref_mut())
-            .await?;
-
-        Ok(())
-    }
-
-    /// Creates a job associated to the provided event
-    #[tracing::instrument(skip(self), ret)]
-    pub async fn new_job_for_event(
-        &self,
-        event_id: Uuid,
-        event_created_utc: OffsetDateTime,
-    ) -> anyhow::Result<Uuid> {
-        let id = Uuid::new_v4();
-
         let mut conn = self.sqlite.lock().await;
         conn.transaction(|t| {
             Box::pin(async move {
-                // Create job
                 let now = OffsetDateTime::now_utc();
                 sqlx::query(
-                    "INSERT INTO jobs (id, event_queued_utc, created_utc) VALUES (?, ?, ?)",
+                    r"
+                    INSERT OR REPLACE INTO handled_events (id, created_utc, handled_utc, event, payload, priority)
+                    SELECT id, created_utc, ?, event, payload, priority FROM event_queue WHERE id = ?",
                 )
-                .bind(id.as_bytes().as_slice())
-                .bind(event_created_utc)
                 .bind(now)
+                .bind(id.as_bytes().as_slice())
                 .execute(t.deref_mut())
                 .await?;
-
-                // Associate the event to this job
-                sqlx::query("UPDATE event_queue SET job_id = ? WHERE id = ?")
+                sqlx::query("DELETE FROM event_queue WHERE id = ?")
                     .bind(id.as_bytes().as_slice())
-                    .bind(event_id.as_bytes().as_slice())
+                    .execute(t.deref_mut())
+                    .await?;
+                sqlx::query("DELETE FROM handled_events WHERE handled_utc < ?")
+                    .bind(now - HANDLED_EVENT_RETENTION)
                     .execute(t.deref_mut())
                     .await?;
 
@@ -256,406 +1221,3120 @@ impl Db {
         })
         .await?;
 
-        Ok(id)
+        Ok(())
     }
 
-    /// Marks the job as finished
+    /// Moves the event from the queue to the dead events, recording why it couldn't be handled
+    ///
+    /// Returns `false` if the event was no longer queued
     #[tracing::instrument(skip(self))]
-    pub async fn job_finished(&self, id: Uuid, success: bool) -> anyhow::Result<()> {
-        let finished_utc = OffsetDateTime::now_utc();
-
+    pub async fn dead_letter_event(&self, id: Uuid, error: &str) -> anyhow::Result<bool> {
+        let error = error.to_string();
         let mut conn = self.sqlite.lock().await;
-        sqlx::query("UPDATE jobs SET finished_utc = ?, success = ? WHERE id = ?")
-            .bind(Some(finished_utc))
-            .bind(Some(success))
-            .bind(id.as_bytes().as_slice())
-            .execute(conn.deref_mut())
+        let moved = conn
+            .transaction(|t| {
+                Box::pin(async move {
+                    let moved = sqlx::query(
+                        r"
+                        INSERT OR REPLACE INTO dead_events (id, created_utc, died_utc, event, payload, priority, job_id, error)
+                        SELECT id, created_utc, ?, event, payload, priority, job_id, ? FROM event_queue WHERE id = ?",
+                    )
+                    .bind(OffsetDateTime::now_utc())
+                    .bind(error)
+                    .bind(id.as_bytes().as_slice())
+                    .execute(t.deref_mut())
+                    .await?
+                    .rows_affected()
+                        > 0;
+                    sqlx::query("DELETE FROM event_queue WHERE id = ?")
+                        .bind(id.as_bytes().as_slice())
+                        .execute(t.deref_mut())
+                        .await?;
+
+                    Ok::<_, Error>(moved)
+                })
+            })
             .await?;
 
-        Ok(())
+        Ok(moved)
     }
 
-    /// Retrieves a job by its id
-    pub async fn job(&self, id: Uuid) -> anyhow::Result<BenchJob> {
-        let job = self
-            .maybe_job(id)
-            .await?
-            .ok_or(anyhow!("job not found: {id}"))?;
+    /// Returns the dead events, most recent first
+    pub async fn dead_events(&self) -> anyhow::Result<Vec<DeadEvent>> {
+        let mut conn = self.sqlite.lock().await;
+        let events = sqlx::query_as("SELECT * FROM dead_events ORDER BY died_utc DESC")
+            .fetch_all(conn.deref_mut())
+            .await?;
 
-        Ok(job)
+        Ok(events)
     }
 
-    /// Optionally retrieve a job by its id
+    /// Moves a dead event back to the queue, under a new id
+    ///
+    /// Returns the id of the enqueued event, or `None` if the dead event doesn't exist
     #[tracing::instrument(skip(self), ret)]
-    pub async fn maybe_job(&self, id: Uuid) -> anyhow::Result<Option<BenchJob>> {
+    pub async fn requeue_dead_event(&self, id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let new_id = Uuid::new_v4();
         let mut conn = self.sqlite.lock().await;
-        let job = sqlx::query_as(
+        let requeued = conn
+            .transaction(|t| {
+                Box::pin(async move {
+                    let requeued = sqlx::query(
+                        r"
+                        INSERT INTO event_queue (id, created_utc, event, payload, priority)
+                        SELECT ?, ?, event, payload, priority FROM dead_events WHERE id = ?",
+                    )
+                    .bind(new_id.as_bytes().as_slice())
+                    .bind(OffsetDateTime::now_utc())
+                    .bind(id.as_bytes().as_slice())
+                    .execute(t.deref_mut())
+                    .await?
+                    .rows_affected()
+                        > 0;
+                    sqlx::query("DELETE FROM dead_events WHERE id = ?")
+                        .bind(id.as_bytes().as_slice())
+                        .execute(t.deref_mut())
+                        .await?;
+
+                    Ok::<_, Error>(requeued)
+                })
+            })
+            .await?;
+
+        Ok(requeued.then_some(new_id))
+    }
+
+    /// Retrieves an event that was removed from the queue, if it is still archived
+    #[tracing::instrument(skip(self))]
+    pub async fn handled_event(&self, id: Uuid) -> anyhow::Result<Option<QueuedEvent>> {
+        let mut conn = self.sqlite.lock().await;
+        let event = sqlx::query_as(
             r"
-            SELECT *
-            FROM jobs
+            SELECT id, NULL AS job_id, event, payload, created_utc, priority, NULL AS not_before_utc
+            FROM handled_events
             WHERE id = ?",
         )
         .bind(id.as_bytes().as_slice())
         .fetch_optional(conn.deref_mut())
         .await?;
 
-        Ok(job)
+        Ok(event)
     }
 
-    /// Stores the results of a bench run to the database
-    #[tracing::instrument(skip(self, results), ret)]
-    pub async fn store_run_results(
+    /// Creates a job associated to the provided event
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn new_job_for_event(
         &self,
-        results: Vec<(String, ScenarioKind, f64)>,
+        event_id: Uuid,
+        event_created_utc: OffsetDateTime,
     ) -> anyhow::Result<Uuid> {
-        let bench_run_id = Uuid::new_v4();
+        let id = Uuid::new_v4();
 
         let mut conn = self.sqlite.lock().await;
         conn.transaction(|t| {
             Box::pin(async move {
-                // Create bench run
+                // Create job, remembering the event that triggered it
                 let now = OffsetDateTime::now_utc();
-                sqlx::query("INSERT INTO bench_runs (id, created_utc) VALUES (?, ?)")
-                    .bind(bench_run_id.as_bytes().as_slice())
-                    .bind(now)
-                    .execute(t.deref_mut())
-                    .await?;
+                let inserted = sqlx::query(
+                    r"
+                    INSERT INTO jobs
+                        (id, event_queued_utc, created_utc, event_id, event, retry_of, status)
+                    SELECT ?, ?, ?, id, event, retry_of, ? FROM event_queue WHERE id = ?",
+                )
+                .bind(id.as_bytes().as_slice())
+                .bind(event_created_utc)
+                .bind(now)
+                .bind(JobStatus::Queued.as_str())
+                .bind(event_id.as_bytes().as_slice())
+                .execute(t.deref_mut())
+                .await?;
+                if inserted.rows_affected() == 0 {
+                    return Err(Error::RowNotFound);
+                }
 
-                // Add benchmark results
-                for (scenario_name, scenario_kind, result) in results {
-                    sqlx::query(
-                        "INSERT INTO bench_results (bench_run_id, scenario_name, scenario_kind, result) VALUES (?, ?, ?, ?)",
-                    )
-                    .bind(bench_run_id.as_bytes().as_slice())
-                    .bind(scenario_name)
-                    .bind(scenario_kind as i64)
-                    .bind(result)
+                // Associate the event to this job
+                sqlx::query("UPDATE event_queue SET job_id = ? WHERE id = ?")
+                    .bind(id.as_bytes().as_slice())
+                    .bind(event_id.as_bytes().as_slice())
                     .execute(t.deref_mut())
                     .await?;
-                }
 
                 Ok::<_, Error>(())
             })
         })
         .await?;
 
-        Ok(bench_run_id)
+        Ok(id)
     }
 
-    /// Retrieve the results since the provided cutoff date
-    #[tracing::instrument(skip(self))]
-    pub async fn result_history(
+    /// Enqueues the event again, to retry handling it after the provided job failed
+    ///
+    /// The retry won't be handled before `not_before`
+    #[tracing::instrument(skip(self, event), ret)]
+    pub async fn enqueue_retry(
         &self,
-        cutoff_date: OffsetDateTime,
-    ) -> anyhow::Result<Vec<BenchResult>> {
+        event: &QueuedEvent,
+        failed_job_id: Uuid,
+        not_before: OffsetDateTime,
+    ) -> anyhow::Result<Uuid> {
+        let id = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+
         let mut conn = self.sqlite.lock().await;
-        let results = sqlx::query_as(
+        sqlx::query(
             r"
-            SELECT scenario_name, scenario_kind, result
-            FROM bench_results JOIN
-                (SELECT id FROM bench_runs WHERE created_utc > ? ORDER BY created_utc)
-            ON id = bench_run_id",
+            INSERT INTO event_queue (id, created_utc, event, payload, priority, retry_of, not_before_utc)
+            VALUES (?, ?, ?, ?, ?, ?, ?)",
         )
-        .bind(cutoff_date)
-        .fetch_all(conn.deref_mut())
+        .bind(id.as_bytes().as_slice())
+        .bind(now)
+        .bind(&event.event)
+        .bind(&event.payload)
+        .bind(event.priority)
+        .bind(failed_job_id.as_bytes().as_slice())
+        .bind(not_before)
+        .execute(conn.deref_mut())
         .await?;
 
-        Ok(results)
+        Ok(id)
     }
 
-    /// Stores the result of a comparison between two branches of rustls
-    #[tracing::instrument(skip(self, result))]
-    pub async fn store_comparison_result(
-        &self,
-        baseline_commit: String,
-        candidate_commit: String,
-        result: ComparisonResult,
+    /// Changes the priority of a queued event
+    #[tracing::instrument(skip(self))]
+    pub async fn set_event_priority(&self, id: Uuid, priority: i64) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE event_queue SET priority = ? WHERE id = ?")
+            .bind(priority)
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns all attempts at handling the event of the provided job, from the original job to its
+    /// latest retry (the job itself included)
+    pub async fn retry_history(&self, job_id: Uuid) -> anyhow::Result<Vec<BenchJob>> {
+        let mut conn = self.sqlite.lock().await;
+        let jobs = sqlx::query_as(
+            r"
+            WITH RECURSIVE
+                earlier_attempts(id, retry_of) AS (
+                    SELECT id, retry_of FROM jobs WHERE id = ?
+                    UNION ALL
+                    SELECT jobs.id, jobs.retry_of
+                    FROM jobs JOIN earlier_attempts ON jobs.id = earlier_attempts.retry_of
+                ),
+                attempts(id) AS (
+                    SELECT id FROM earlier_attempts WHERE retry_of IS NULL
+                    UNION ALL
+                    SELECT jobs.id FROM jobs JOIN attempts ON jobs.retry_of = attempts.id
+                )
+            SELECT *
+            FROM jobs
+            WHERE id IN (SELECT id FROM attempts)
+            ORDER BY created_utc",
+        )
+        .bind(job_id.as_bytes().as_slice())
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(jobs)
+    }
+
+    /// Marks the job as running, once its handler has started
+    #[tracing::instrument(skip(self))]
+    pub async fn job_started(&self, id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE jobs SET status = ? WHERE id = ? AND status = ?")
+            .bind(JobStatus::Running.as_str())
+            .bind(id.as_bytes().as_slice())
+            .bind(JobStatus::Queued.as_str())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Marks the job as finished
+    ///
+    /// Jobs with a recorded failure class (see [`Db::record_job_failure`]) are marked as failed,
+    /// even if their handler succeeded (e.g. because it reported the failure on the PR)
+    #[tracing::instrument(skip(self))]
+    pub async fn job_finished(&self, id: Uuid, success: bool) -> anyhow::Result<()> {
+        let finished_utc = OffsetDateTime::now_utc();
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs SET
+                finished_utc = ?,
+                success = ?,
+                status = CASE WHEN ? AND failure_class IS NULL THEN ? ELSE ? END
+            WHERE id = ?",
+        )
+        .bind(Some(finished_utc))
+        .bind(Some(success))
+        .bind(success)
+        .bind(JobStatus::Success.as_str())
+        .bind(JobStatus::Failure.as_str())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a job as finished because it was cancelled by a user
+    #[tracing::instrument(skip(self))]
+    pub async fn job_cancelled(&self, id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs SET finished_utc = ?, success = 0, cancelled = 1, status = ?
+            WHERE id = ?",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(JobStatus::Cancelled.as_str())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a job as finished because the application shut down while it was running
+    ///
+    /// The job's event is left in the queue, so it is handled again after restarting
+    #[tracing::instrument(skip(self))]
+    pub async fn job_interrupted(&self, id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs SET finished_utc = ?, success = 0, interrupted = 1, status = ?
+            WHERE id = ?",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(JobStatus::Interrupted.as_str())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Marks a job as failed because it stopped without finishing (i.e. it crashed, or the
+    /// application stopped without shutting down gracefully)
+    ///
+    /// Jobs that already finished are left alone
+    #[tracing::instrument(skip(self))]
+    pub async fn job_crashed(&self, id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs SET finished_utc = ?, success = 0, status = ?
+            WHERE id = ? AND finished_utc IS NULL",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(JobStatus::Failure.as_str())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the phase a running job has entered
+    pub async fn set_job_phase(&self, id: Uuid, phase: &str) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE jobs SET phase = ? WHERE id = ?")
+            .bind(phase)
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Records the resources consumed by a finished job, and who or what it was consumed for
+    ///
+    /// The PR number is only recorded if the job didn't report its results to a PR already
+    #[tracing::instrument(skip(self))]
+    pub async fn record_job_cost(
+        &self,
+        id: Uuid,
+        requester: Option<&str>,
+        pr_number: Option<u64>,
+        cpu_seconds: Option<f64>,
+        wall_seconds: f64,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs
+            SET requester = ?,
+                pr_number = COALESCE(pr_number, ?),
+                cpu_seconds = ?,
+                wall_seconds = ?
+            WHERE id = ?",
+        )
+        .bind(requester)
+        .bind(pr_number.map(|pr_number| pr_number as i64))
+        .bind(cpu_seconds)
+        .bind(wall_seconds)
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Aggregates the resources consumed by the jobs created since the provided moment, most
+    /// expensive first
+    pub async fn job_costs(
+        &self,
+        grouping: CostGrouping,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<JobCostSummary>> {
+        let mut conn = self.sqlite.lock().await;
+        let costs = sqlx::query_as(&format!(
+            r"
+            SELECT {} AS key,
+                   COUNT(*) AS jobs,
+                   COALESCE(SUM(cpu_seconds), 0.0) AS cpu_seconds,
+                   SUM(wall_seconds) AS wall_seconds
+            FROM jobs
+            WHERE created_utc > ? AND wall_seconds IS NOT NULL
+            GROUP BY key
+            ORDER BY cpu_seconds DESC, wall_seconds DESC",
+            grouping.group_by_expr()
+        ))
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(costs)
+    }
+
+    /// Records the class of the failure that prevented a job from producing results
+    pub async fn record_job_failure(&self, id: Uuid, class: FailureClass) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE jobs SET failure_class = ? WHERE id = ?")
+            .bind(class.as_str())
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves a job by its id
+    pub async fn job(&self, id: Uuid) -> anyhow::Result<BenchJob> {
+        let job = self
+            .maybe_job(id)
+            .await?
+            .ok_or(anyhow!("job not found: {id}"))?;
+
+        Ok(job)
+    }
+
+    /// Optionally retrieve a job by its id
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn maybe_job(&self, id: Uuid) -> anyhow::Result<Option<BenchJob>> {
+        let mut conn = self.sqlite.lock().await;
+        let job = sqlx::query_as(
+            r"
+            SELECT *
+            FROM jobs
+            WHERE id = ?",
+        )
+        .bind(id.as_bytes().as_slice())
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(job)
+    }
+
+    /// Stores the results of a bench run for the provided commit of a tracked branch to the
+    /// database
+    pub async fn store_run_results(
+        &self,
+        branch: &str,
+        commit_sha: &str,
+        results: Vec<(String, ScenarioKind, f64)>,
     ) -> anyhow::Result<Uuid> {
-        fn to_json_array(values: &[String]) -> Option<String> {
-            if values.is_empty() {
-                None
-            } else {
-                Some(serde_json::to_string(&values).expect("unreachable code"))
-            }
-        }
+        self.store_run_results_at(branch, commit_sha, OffsetDateTime::now_utc(), results)
+            .await
+    }
 
-        let icount_scenarios_missing = to_json_array(&result.icount.scenarios_missing_in_baseline);
-        let walltime_scenarios_missing =
-            to_json_array(&result.walltime.scenarios_missing_in_baseline);
+    /// Stores the results of a bench run that happened at the provided time (e.g. one imported
+    /// from bencher.dev)
+    #[tracing::instrument(skip(self, results), ret)]
+    pub async fn store_run_results_at(
+        &self,
+        branch: &str,
+        commit_sha: &str,
+        created_utc: OffsetDateTime,
+        results: Vec<(String, ScenarioKind, f64)>,
+    ) -> anyhow::Result<Uuid> {
+        let branch = branch.to_string();
+        let commit_sha = commit_sha.to_string();
+        let bench_run_id = Uuid::new_v4();
 
         let mut conn = self.sqlite.lock().await;
-        let id = conn.transaction(|t| {
+        conn.transaction(|t| {
             Box::pin(async move {
-                // Create comparison run
-                let id = Uuid::new_v4();
-                let now = OffsetDateTime::now_utc();
+                // Create bench run
                 sqlx::query(
-                    "INSERT INTO comparison_runs (id, created_utc, baseline_commit, candidate_commit, icount_scenarios_missing_in_baseline, walltime_scenarios_missing_in_baseline) VALUES (?, ?, ?, ?, ?, ?)",
+                    "INSERT INTO bench_runs (id, created_utc, commit_sha, branch) VALUES (?, ?, ?, ?)",
                 )
-                    .bind(id.as_bytes().as_slice())
-                    .bind(now)
-                    .bind(baseline_commit)
-                    .bind(candidate_commit)
-                    .bind(icount_scenarios_missing)
-                    .bind(walltime_scenarios_missing)
-                    .execute(t.deref_mut())
-                    .await?;
+                .bind(bench_run_id.as_bytes().as_slice())
+                .bind(created_utc)
+                .bind(commit_sha)
+                .bind(branch)
+                .execute(t.deref_mut())
+                .await?;
 
-                // Insert the associated diffs
-                for diff in result.icount.diffs.into_iter().chain(result.walltime.diffs) {
+                // Add benchmark results
+                for (scenario_name, scenario_kind, result) in results {
                     sqlx::query(
-                        "INSERT INTO scenario_diffs (comparison_run_id, scenario_name, scenario_kind, baseline_result, candidate_result, significance_threshold, cachegrind_diff) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                        "INSERT INTO bench_results (bench_run_id, scenario_name, scenario_kind, result, result_unit) VALUES (?, ?, ?, ?, ?)",
                     )
-                        .bind(id.as_bytes().as_slice())
-                        .bind(diff.scenario_name)
-                        .bind(diff.scenario_kind as i64)
-                        .bind(diff.baseline_result)
-                        .bind(diff.candidate_result)
-                        .bind(diff.significance_threshold)
-                        .bind(diff.cachegrind_diff)
-                        .execute(t.deref_mut())
-                        .await?;
+                    .bind(bench_run_id.as_bytes().as_slice())
+                    .bind(scenario_name)
+                    .bind(scenario_kind as i64)
+                    .bind(result)
+                    .bind(scenario_kind.unit() as i64)
+                    .execute(t.deref_mut())
+                    .await?;
                 }
 
-                Ok::<_, Error>(id)
-            })
-        })
+                Ok::<_, Error>(())
+            })
+        })
+        .await?;
+
+        Ok(bench_run_id)
+    }
+
+    /// Returns true if the commit of the tracked branch has been benchmarked
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn bench_run_exists(&self, branch: &str, commit_sha: &str) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let count: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM bench_runs WHERE branch = ? AND commit_sha = ?",
+        )
+        .bind(branch)
+        .bind(commit_sha)
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Returns the latest benchmarked commit of the tracked branch, if any
+    ///
+    /// Bench runs are only created for pushes to tracked branches
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn latest_commit(&self, branch: &str) -> anyhow::Result<Option<String>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT commit_sha
+            FROM bench_runs
+            WHERE commit_sha IS NOT NULL AND branch = ?
+            ORDER BY created_utc DESC
+            LIMIT 1",
+        )
+        .bind(branch)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(row.try_get("commit_sha")?))
+    }
+
+    /// Returns the commit and results of the most recent bench run of the tracked branch, if any
+    #[tracing::instrument(skip(self))]
+    pub async fn latest_results(
+        &self,
+        branch: &str,
+    ) -> anyhow::Result<Option<(String, Vec<BenchResult>)>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT id, commit_sha
+            FROM bench_runs
+            WHERE commit_sha IS NOT NULL AND branch = ?
+            ORDER BY created_utc DESC
+            LIMIT 1",
+        )
+        .bind(branch)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let bench_run_id: Vec<u8> = row.try_get("id")?;
+        let results = sqlx::query_as(
+            r"
+            SELECT scenario_name, scenario_kind, result, result_unit
+            FROM bench_results
+            WHERE bench_run_id = ?",
+        )
+        .bind(bench_run_id)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(Some((row.try_get("commit_sha")?, results)))
+    }
+
+    /// Summarizes the benchmarking activity since the provided date
+    ///
+    /// Runs and the instruction count index (tracked through the results of `index_scenario`) are
+    /// those of the provided branch
+    #[tracing::instrument(skip(self))]
+    pub async fn digest(
+        &self,
+        since: OffsetDateTime,
+        branch: &str,
+        index_scenario: &str,
+    ) -> anyhow::Result<WeeklyDigest> {
+        let mut conn = self.sqlite.lock().await;
+        let main_runs = sqlx::query_scalar(
+            r"
+            SELECT COUNT(*)
+            FROM bench_runs
+            WHERE created_utc > ? AND commit_sha IS NOT NULL AND branch = ?",
+        )
+        .bind(since)
+        .bind(branch)
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        let index: Vec<f64> = sqlx::query_scalar(
+            r"
+            SELECT result
+            FROM bench_results JOIN bench_runs ON id = bench_run_id
+            WHERE created_utc > ? AND branch = ? AND scenario_name = ? AND scenario_kind = ?
+            ORDER BY created_utc",
+        )
+        .bind(since)
+        .bind(branch)
+        .bind(index_scenario)
+        .bind(ScenarioKind::IcountIndex as i64)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let comparisons = sqlx::query_as(
+            r"
+            SELECT COALESCE(verdict, 'unknown'), COUNT(*)
+            FROM comparison_runs
+            WHERE created_utc > ?
+            GROUP BY verdict
+            ORDER BY verdict",
+        )
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        // Interrupted jobs are handled again after restarting, so they don't count as failures
+        let failed_jobs = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM jobs WHERE created_utc > ? AND success = 0 AND interrupted = 0",
+        )
+        .bind(since)
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(WeeklyDigest {
+            main_runs,
+            index_range: index.first().copied().zip(index.last().copied()),
+            comparisons,
+            failed_jobs,
+            // They cover a longer period, so they are retrieved separately
+            scenario_changes: Vec::new(),
+            annotations: Vec::new(),
+            correlated_groups: Vec::new(),
+        })
+    }
+
+    /// Returns how many jobs succeeded and failed on each day since the provided date, oldest first
+    ///
+    /// Cancelled, interrupted and unfinished jobs are not counted
+    pub async fn job_outcomes_by_day(
+        &self,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<DailyJobOutcomes>> {
+        let mut conn = self.sqlite.lock().await;
+        let outcomes = sqlx::query_as(
+            r"
+            SELECT substr(created_utc, 1, 10) AS day,
+                   SUM(success = 1) AS succeeded,
+                   SUM(success = 0) AS failed
+            FROM jobs
+            WHERE created_utc > ? AND cancelled = 0 AND interrupted = 0 AND success IS NOT NULL
+            GROUP BY day
+            ORDER BY day",
+        )
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(outcomes)
+    }
+
+    /// Returns the scenarios that appeared in or disappeared from the results of the tracked branch
+    /// since the provided date, comparing each run to the previous one (oldest first)
+    #[tracing::instrument(skip(self))]
+    pub async fn scenario_set_changes(
+        &self,
+        branch: &str,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<ScenarioSetChange>> {
+        let mut conn = self.sqlite.lock().await;
+        let rows = sqlx::query(
+            r"
+            SELECT bench_runs.id AS run_id, commit_sha, scenario_name, scenario_kind
+            FROM bench_results JOIN bench_runs ON id = bench_run_id
+            WHERE created_utc > ? AND branch = ? AND commit_sha IS NOT NULL AND scenario_kind != ?
+            ORDER BY created_utc, bench_runs.id",
+        )
+        .bind(since)
+        .bind(branch)
+        // The index follows the real scenarios, so its changes are not worth reporting
+        .bind(ScenarioKind::IcountIndex as i64)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        // The id, commit and scenarios of each run, oldest first
+        type Scenarios = HashSet<(String, ScenarioKind)>;
+        let mut runs: Vec<(Vec<u8>, String, Scenarios)> = Vec::new();
+        for row in rows {
+            let run_id: Vec<u8> = row.try_get("run_id")?;
+            let scenario = (
+                row.try_get("scenario_name")?,
+                ScenarioKind::try_from(row.try_get::<i64, _>("scenario_kind")?)?,
+            );
+            match runs.last_mut() {
+                Some((id, _, scenarios)) if *id == run_id => {
+                    scenarios.insert(scenario);
+                }
+                _ => runs.push((
+                    run_id,
+                    row.try_get("commit_sha")?,
+                    HashSet::from([scenario]),
+                )),
+            }
+        }
+
+        let mut changes = Vec::new();
+        for pair in runs.windows(2) {
+            let (_, previous_commit_sha, previous) = &pair[0];
+            let (_, commit_sha, current) = &pair[1];
+            let added = current.difference(previous).map(|s| (s, true));
+            let removed = previous.difference(current).map(|s| (s, false));
+            let mut run_changes: Vec<_> = added
+                .chain(removed)
+                .map(
+                    |((scenario_name, scenario_kind), added)| ScenarioSetChange {
+                        scenario_name: scenario_name.clone(),
+                        scenario_kind: *scenario_kind,
+                        added,
+                        commit_sha: commit_sha.clone(),
+                        previous_commit_sha: previous_commit_sha.clone(),
+                    },
+                )
+                .collect();
+            run_changes.sort_by(|a, b| a.scenario_name.cmp(&b.scenario_name));
+            changes.extend(run_changes);
+        }
+
+        Ok(changes)
+    }
+
+    /// Returns the scenario's result of the provided kind in the bench run of the tracked branch that
+    /// is closest in time to the provided date, among the runs that include the scenario
+    #[tracing::instrument(skip(self))]
+    pub async fn closest_scenario_result(
+        &self,
+        branch: &str,
+        scenario_name: &str,
+        scenario_kind: ScenarioKind,
+        date: OffsetDateTime,
+    ) -> anyhow::Result<Option<ScenarioRunResult>> {
+        let mut conn = self.sqlite.lock().await;
+        let query = |comparison: &str, order: &str| {
+            format!(
+                r"
+                SELECT bench_runs.id AS bench_run_id, jobs.id AS job_id, bench_runs.commit_sha,
+                    bench_runs.created_utc, result, result_unit
+                FROM bench_results
+                    JOIN bench_runs ON bench_runs.id = bench_results.bench_run_id
+                    LEFT JOIN jobs ON jobs.bench_run_id = bench_runs.id
+                WHERE bench_runs.branch = ? AND bench_runs.commit_sha IS NOT NULL
+                    AND scenario_name = ? AND scenario_kind = ?
+                    AND bench_runs.created_utc {comparison} ?
+                ORDER BY bench_runs.created_utc {order}
+                LIMIT 1"
+            )
+        };
+
+        let before: Option<ScenarioRunResult> = sqlx::query_as(&query("<=", "DESC"))
+            .bind(branch)
+            .bind(scenario_name)
+            .bind(scenario_kind as i64)
+            .bind(date)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+        let after: Option<ScenarioRunResult> = sqlx::query_as(&query(">", "ASC"))
+            .bind(branch)
+            .bind(scenario_name)
+            .bind(scenario_kind as i64)
+            .bind(date)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(match (before, after) {
+            (Some(before), Some(after)) => {
+                if date - before.created_utc <= after.created_utc - date {
+                    Some(before)
+                } else {
+                    Some(after)
+                }
+            }
+            (before, after) => before.or(after),
+        })
+    }
+
+    /// Returns the scenario's results, as measured through the metric, in the bench runs of the
+    /// tracked branch since the provided date, oldest first
+    #[tracing::instrument(skip(self))]
+    pub async fn scenario_history(
+        &self,
+        branch: &str,
+        scenario_name: &str,
+        scenario_kind: ScenarioKind,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<ScenarioRunResult>> {
+        let mut conn = self.sqlite.lock().await;
+        let results = sqlx::query_as(
+            r"
+            SELECT bench_runs.id AS bench_run_id, jobs.id AS job_id, bench_runs.commit_sha,
+                bench_runs.created_utc, result, result_unit
+            FROM bench_results
+                JOIN bench_runs ON bench_runs.id = bench_results.bench_run_id
+                LEFT JOIN jobs ON jobs.bench_run_id = bench_runs.id
+            WHERE bench_runs.branch = ? AND bench_runs.commit_sha IS NOT NULL
+                AND scenario_name = ? AND scenario_kind = ? AND bench_runs.created_utc > ?
+            ORDER BY bench_runs.created_utc",
+        )
+        .bind(branch)
+        .bind(scenario_name)
+        .bind(scenario_kind as i64)
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(results)
+    }
+
+    /// Returns the results of each bench run of the tracked branch since the provided date, as
+    /// measured through the metric, by scenario (oldest run first)
+    #[tracing::instrument(skip(self))]
+    pub async fn results_by_run(
+        &self,
+        branch: &str,
+        scenario_kind: ScenarioKind,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<BTreeMap<String, f64>>> {
+        let mut conn = self.sqlite.lock().await;
+        let rows = sqlx::query(
+            r"
+            SELECT bench_run_id, scenario_name, result
+            FROM bench_results JOIN bench_runs ON bench_runs.id = bench_results.bench_run_id
+            WHERE bench_runs.branch = ? AND bench_runs.commit_sha IS NOT NULL
+                AND scenario_kind = ? AND bench_runs.created_utc > ?
+            ORDER BY bench_runs.created_utc, bench_run_id",
+        )
+        .bind(branch)
+        .bind(scenario_kind as i64)
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut runs: Vec<BTreeMap<String, f64>> = Vec::new();
+        let mut current_run = None;
+        for row in rows {
+            let bench_run_id: Vec<u8> = row.try_get("bench_run_id")?;
+            if current_run.as_ref() != Some(&bench_run_id) {
+                runs.push(BTreeMap::new());
+                current_run = Some(bench_run_id);
+            }
+
+            let results = runs.last_mut().expect("unreachable code");
+            results.insert(row.try_get("scenario_name")?, row.try_get("result")?);
+        }
+
+        Ok(runs)
+    }
+
+    /// Retrieve the results of the tracked branch since the provided cutoff date
+    #[tracing::instrument(skip(self))]
+    pub async fn result_history(
+        &self,
+        branch: &str,
+        cutoff_date: OffsetDateTime,
+    ) -> anyhow::Result<Vec<BenchResult>> {
+        let mut conn = self.sqlite.lock().await;
+        let results = sqlx::query_as(
+            r"
+            SELECT scenario_name, scenario_kind, result, result_unit
+            FROM bench_results JOIN
+                (SELECT id FROM bench_runs WHERE created_utc > ? AND branch = ? ORDER BY created_utc)
+            ON id = bench_run_id",
+        )
+        .bind(cutoff_date)
+        .bind(branch)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(results)
+    }
+
+    /// Stores the result of a comparison between two branches of rustls
+    #[tracing::instrument(skip(self, result))]
+    pub async fn store_comparison_result(
+        &self,
+        baseline_commit: String,
+        candidate_commit: String,
+        result: ComparisonResult,
+    ) -> anyhow::Result<Uuid> {
+        let verdict = result.verdict();
+
+        let mut conn = self.sqlite.lock().await;
+        let id = conn.transaction(|t| {
+            Box::pin(async move {
+                // Create comparison run
+                let id = Uuid::new_v4();
+                let now = OffsetDateTime::now_utc();
+                sqlx::query(
+                    "INSERT INTO comparison_runs (id, created_utc, baseline_commit, candidate_commit, verdict) VALUES (?, ?, ?, ?, ?)",
+                )
+                    .bind(id.as_bytes().as_slice())
+                    .bind(now)
+                    .bind(baseline_commit)
+                    .bind(candidate_commit)
+                    .bind(verdict.as_str())
+                    .execute(t.deref_mut())
+                    .await?;
+
+                // Insert the scenarios missing in the baseline, per metric
+                for (kind, sub_result) in &result.metrics {
+                    for scenario in &sub_result.scenarios_missing_in_baseline {
+                        sqlx::query(
+                            "INSERT OR IGNORE INTO comparison_missing_scenarios (comparison_run_id, scenario_kind, scenario_name) VALUES (?, ?, ?)",
+                        )
+                            .bind(id.as_bytes().as_slice())
+                            .bind(*kind as i64)
+                            .bind(scenario)
+                            .execute(t.deref_mut())
+                            .await?;
+                    }
+                }
+
+                // Insert the associated diffs
+                let diffs = result
+                    .metrics
+                    .into_values()
+                    .flat_map(|sub_result| sub_result.diffs);
+                for diff in diffs {
+                    sqlx::query(
+                        "INSERT INTO scenario_diffs (comparison_run_id, scenario_name, scenario_kind, baseline_result, candidate_result, significance_threshold, result_unit, cachegrind_diff) VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    )
+                        .bind(id.as_bytes().as_slice())
+                        .bind(diff.scenario_name)
+                        .bind(diff.scenario_kind as i64)
+                        .bind(diff.baseline_result)
+                        .bind(diff.candidate_result)
+                        .bind(diff.significance_threshold)
+                        .bind(diff.result_unit as i64)
+                        .bind(diff.cachegrind_diff)
+                        .execute(t.deref_mut())
+                        .await?;
+                }
+
+                Ok::<_, Error>(id)
+            })
+        })
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Stores a comparison requested through the HTTP API, which has yet to be handled
+    pub async fn store_api_comparison(
+        &self,
+        client: &str,
+        baseline_ref: &str,
+        candidate_ref: &str,
+    ) -> anyhow::Result<Uuid> {
+        let id = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            "INSERT INTO api_comparisons (id, client, baseline_ref, candidate_ref, created_utc) VALUES (?, ?, ?, ?, ?)",
+        )
+            .bind(id.as_bytes().as_slice())
+            .bind(client)
+            .bind(baseline_ref)
+            .bind(candidate_ref)
+            .bind(now)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Returns the comparison requested through the HTTP API with the provided id, if any
+    pub async fn api_comparison(&self, id: Uuid) -> anyhow::Result<Option<ApiComparison>> {
+        let mut conn = self.sqlite.lock().await;
+        let comparison = sqlx::query_as("SELECT * FROM api_comparisons WHERE id = ?")
+            .bind(id.as_bytes().as_slice())
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(comparison)
+    }
+
+    /// Records that a job started handling a comparison requested through the HTTP API, clearing
+    /// the outcome of previous attempts (if it is a retry)
+    pub async fn api_comparison_started(&self, id: Uuid, job_id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE api_comparisons
+            SET job_id = ?, finished_utc = NULL, verdict = NULL, result = NULL, error = NULL
+            WHERE id = ?",
+        )
+        .bind(job_id.as_bytes().as_slice())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records the commits a comparison requested through the HTTP API resolved to
+    pub async fn api_comparison_resolved(
+        &self,
+        id: Uuid,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            "UPDATE api_comparisons SET baseline_commit = ?, candidate_commit = ? WHERE id = ?",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the outcome of a comparison requested through the HTTP API
+    pub async fn api_comparison_finished(
+        &self,
+        id: Uuid,
+        outcome: Result<&ComparisonResult, String>,
+    ) -> anyhow::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let (verdict, result, error) = match outcome {
+            Ok(result) => (
+                Some(result.verdict().as_str()),
+                Some(serde_json::to_string(result)?),
+                None,
+            ),
+            Err(error) => (None, None, Some(error)),
+        };
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE api_comparisons
+            SET finished_utc = ?, verdict = ?, result = ?, error = ?
+            WHERE id = ?",
+        )
+        .bind(now)
+        .bind(verdict)
+        .bind(result)
+        .bind(error)
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores a self-test requested through the admin API, which has yet to be handled
+    pub async fn store_self_test(&self) -> anyhow::Result<Uuid> {
+        let id = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("INSERT INTO self_tests (id, created_utc) VALUES (?, ?)")
+            .bind(id.as_bytes().as_slice())
+            .bind(now)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(id)
+    }
+
+    /// Returns the self-test with the provided id, if any
+    pub async fn self_test(&self, id: Uuid) -> anyhow::Result<Option<SelfTest>> {
+        let mut conn = self.sqlite.lock().await;
+        let self_test = sqlx::query_as("SELECT * FROM self_tests WHERE id = ?")
+            .bind(id.as_bytes().as_slice())
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(self_test)
+    }
+
+    /// Records that a job started handling a self-test, clearing the outcome of previous attempts
+    /// (if it is a retry)
+    pub async fn self_test_started(&self, id: Uuid, job_id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE self_tests
+            SET job_id = ?, finished_utc = NULL, timings = NULL, error = NULL
+            WHERE id = ?",
+        )
+        .bind(job_id.as_bytes().as_slice())
+        .bind(id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the outcome of a self-test
+    pub async fn self_test_finished(
+        &self,
+        id: Uuid,
+        outcome: Result<&SelfTestTimings, String>,
+    ) -> anyhow::Result<()> {
+        let now = OffsetDateTime::now_utc();
+        let (timings, error) = match outcome {
+            Ok(timings) => (Some(serde_json::to_string(timings)?), None),
+            Err(error) => (None, Some(error)),
+        };
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE self_tests SET finished_utc = ?, timings = ?, error = ? WHERE id = ?")
+            .bind(now)
+            .bind(timings)
+            .bind(error)
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Attaches an annotation to a comparison or to a window of a tracked branch's history
+    ///
+    /// Returns `None` if the comparison doesn't exist, or if one of the window's commits hasn't
+    /// been benchmarked on the branch
+    #[tracing::instrument(skip(self))]
+    pub async fn store_annotation(
+        &self,
+        note: &str,
+        target: &AnnotationTarget,
+    ) -> anyhow::Result<Option<Annotation>> {
+        let mut conn = self.sqlite.lock().await;
+        let (comparison_id, window) = match target {
+            AnnotationTarget::Comparison(id) => {
+                let count: i64 =
+                    sqlx::query_scalar("SELECT COUNT(*) FROM comparison_runs WHERE id = ?")
+                        .bind(id.as_bytes().as_slice())
+                        .fetch_one(conn.deref_mut())
+                        .await?;
+                if count == 0 {
+                    return Ok(None);
+                }
+
+                (Some(*id), None)
+            }
+            AnnotationTarget::Window {
+                branch,
+                from_commit,
+                to_commit,
+            } => {
+                let mut run_times = Vec::new();
+                for commit_sha in [from_commit, to_commit] {
+                    let created_utc: Option<OffsetDateTime> = sqlx::query_scalar(
+                        r"
+                        SELECT created_utc
+                        FROM bench_runs
+                        WHERE branch = ? AND commit_sha = ?
+                        ORDER BY created_utc
+                        LIMIT 1",
+                    )
+                    .bind(branch)
+                    .bind(commit_sha)
+                    .fetch_optional(conn.deref_mut())
+                    .await?;
+                    let Some(created_utc) = created_utc else {
+                        return Ok(None);
+                    };
+                    run_times.push((created_utc, commit_sha.clone()));
+                }
+
+                run_times.sort();
+                let [(from_utc, from_commit), (to_utc, to_commit)]: [_; 2] =
+                    run_times.try_into().expect("unreachable code");
+                let window = AnnotatedWindow {
+                    branch: branch.clone(),
+                    from_commit,
+                    to_commit,
+                    from_utc,
+                    to_utc,
+                };
+                (None, Some(window))
+            }
+        };
+
+        let annotation = Annotation {
+            id: Uuid::new_v4(),
+            created_utc: OffsetDateTime::now_utc(),
+            note: note.to_string(),
+            comparison_id,
+            window,
+        };
+        let window = annotation.window.as_ref();
+        sqlx::query(
+            r"
+            INSERT INTO annotations (id, created_utc, note, comparison_id, branch, from_commit, to_commit, from_utc, to_utc)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(annotation.id.as_bytes().as_slice())
+        .bind(annotation.created_utc)
+        .bind(&annotation.note)
+        .bind(annotation.comparison_id.map(|id| id.as_bytes().to_vec()))
+        .bind(window.map(|w| w.branch.clone()))
+        .bind(window.map(|w| w.from_commit.clone()))
+        .bind(window.map(|w| w.to_commit.clone()))
+        .bind(window.map(|w| w.from_utc))
+        .bind(window.map(|w| w.to_utc))
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(Some(annotation))
+    }
+
+    /// Returns all annotations, most recent first
+    pub async fn annotations(&self) -> anyhow::Result<Vec<Annotation>> {
+        let mut conn = self.sqlite.lock().await;
+        let annotations = sqlx::query_as("SELECT * FROM annotations ORDER BY created_utc DESC")
+            .fetch_all(conn.deref_mut())
+            .await?;
+
+        Ok(annotations)
+    }
+
+    /// Deletes an annotation, returning false if it doesn't exist
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn delete_annotation(&self, id: Uuid) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let deleted = sqlx::query("DELETE FROM annotations WHERE id = ?")
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// Returns the annotations of windows of the tracked branch's history that end after the
+    /// provided date, oldest first
+    #[tracing::instrument(skip(self))]
+    pub async fn branch_annotations(
+        &self,
+        branch: &str,
+        since: OffsetDateTime,
+    ) -> anyhow::Result<Vec<Annotation>> {
+        let mut conn = self.sqlite.lock().await;
+        let annotations = sqlx::query_as(
+            r"
+            SELECT *
+            FROM annotations
+            WHERE branch = ? AND to_utc > ?
+            ORDER BY from_utc",
+        )
+        .bind(branch)
+        .bind(since)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(annotations)
+    }
+
+    /// Returns the annotations that apply to the comparison between the commits, oldest first
+    ///
+    /// These are the annotations attached to the comparison itself, and those of the windows it
+    /// spans (i.e. one side was benchmarked on the window's branch before the window starts, and
+    /// the other one when it ends or later)
+    #[tracing::instrument(skip(self))]
+    pub async fn comparison_annotations(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<Vec<Annotation>> {
+        let mut conn = self.sqlite.lock().await;
+        let annotations = sqlx::query_as(
+            r"
+            SELECT *
+            FROM (
+                SELECT annotations.*,
+                    (SELECT MIN(created_utc) FROM bench_runs
+                     WHERE bench_runs.branch = annotations.branch AND commit_sha = ?) AS baseline_utc,
+                    (SELECT MIN(created_utc) FROM bench_runs
+                     WHERE bench_runs.branch = annotations.branch AND commit_sha = ?) AS candidate_utc
+                FROM annotations
+            )
+            WHERE comparison_id IN (
+                    SELECT id FROM comparison_runs WHERE baseline_commit = ? AND candidate_commit = ?
+                )
+                OR (MIN(baseline_utc, candidate_utc) < from_utc
+                    AND MAX(baseline_utc, candidate_utc) >= to_utc)
+            ORDER BY created_utc",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(annotations)
+    }
+
+    /// Stores the signed summary of a comparison
+    pub async fn store_comparison_signature(
+        &self,
+        comparison_id: Uuid,
+        signed: &SignedSummary,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE comparison_runs SET signed_summary = ?, signature = ? WHERE id = ?")
+            .bind(&signed.summary)
+            .bind(&signed.signature)
+            .bind(comparison_id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves the signed summary of the comparison between the commits, if it was signed
+    pub async fn comparison_signature(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<Option<SignedSummary>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT signed_summary, signature
+            FROM comparison_runs
+            WHERE baseline_commit = ? AND candidate_commit = ? AND signature IS NOT NULL
+                AND NOT invalidated",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some(SignedSummary {
+            summary: row.try_get("signed_summary")?,
+            signature: row.try_get("signature")?,
+        }))
+    }
+
+    /// Invalidates the cached result of a comparison between two branches of rustls, so the
+    /// branches get compared again next time
+    ///
+    /// The result is kept around (e.g. for the traces of the jobs that reported it), but it is no
+    /// longer returned when looking up comparisons by their commits. Returns whether there was a
+    /// cached result.
+    #[tracing::instrument(skip(self))]
+    pub async fn invalidate_comparison_result(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let invalidated = sqlx::query(
+            r"
+            UPDATE comparison_runs
+            SET invalidated = 1
+            WHERE baseline_commit = ? AND candidate_commit = ? AND NOT invalidated",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(invalidated.rows_affected() > 0)
+    }
+
+    /// Returns the id of the (non-invalidated) comparison between the commits, if any
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn comparison_id(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<Option<Uuid>> {
+        let mut conn = self.sqlite.lock().await;
+        let id: Option<Vec<u8>> = sqlx::query_scalar(
+            r"
+            SELECT id
+            FROM comparison_runs
+            WHERE baseline_commit = ? AND candidate_commit = ? AND NOT invalidated",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        id.map(|id| Uuid::from_slice(&id).context("invalid uuid in db"))
+            .transpose()
+    }
+
+    /// Retrieves the result of a comparison between two branches of rustls
+    #[tracing::instrument(skip(self))]
+    pub async fn comparison_result(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<Option<ComparisonResult>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT id
+            FROM comparison_runs
+            WHERE baseline_commit = ? AND candidate_commit = ? AND NOT invalidated",
+        )
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let id: Vec<u8> = row.try_get("id")?;
+        let diffs: Vec<ScenarioDiff> = sqlx::query_as(
+            r"
+            SELECT *
+            FROM scenario_diffs
+            WHERE comparison_run_id = ?",
+        )
+        .bind(&id)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let missing: Vec<(i64, String)> = sqlx::query_as(
+            r"
+            SELECT scenario_kind, scenario_name
+            FROM comparison_missing_scenarios
+            WHERE comparison_run_id = ?
+            ORDER BY rowid",
+        )
+        .bind(id)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        let mut result = ComparisonResult::default();
+        for diff in diffs {
+            result.metric_mut(diff.scenario_kind).diffs.push(diff);
+        }
+        for (kind, scenario) in missing {
+            result
+                .metric_mut(ScenarioKind::try_from(kind)?)
+                .scenarios_missing_in_baseline
+                .push(scenario);
+        }
+
+        Ok(Some(result))
+    }
+
+    /// Returns the baseline and candidate commits of the comparison with the provided id
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn comparison_commits(&self, id: Uuid) -> anyhow::Result<Option<(String, String)>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT baseline_commit, candidate_commit
+            FROM comparison_runs
+            WHERE id = ?",
+        )
+        .bind(id.as_bytes().as_slice())
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        Ok(Some((
+            row.try_get("baseline_commit")?,
+            row.try_get("candidate_commit")?,
+        )))
+    }
+
+    /// Returns the cachegrind diff for the specified comparison and scenario, if available
+    ///
+    /// Only icount scenarios have a diff, so the scenario's rows of other metrics (which share its
+    /// name) are skipped. Diffs exceeding the size limit are stored partially (see
+    /// [`StoredCachegrindDiff`])
+    #[tracing::instrument(skip(self))]
+    pub async fn cachegrind_diff(
+        &self,
+        baseline_commit: &str,
+        candidate_commit: &str,
+        scenario_name: &str,
+    ) -> anyhow::Result<Option<StoredCachegrindDiff>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT comparison_run_id, cachegrind_diff, cachegrind_diff_overflow_bytes
+            FROM comparison_runs JOIN scenario_diffs ON comparison_runs.id = scenario_diffs.comparison_run_id
+            WHERE baseline_commit = ? AND candidate_commit = ? AND scenario_name = ?
+                AND cachegrind_diff IS NOT NULL AND NOT invalidated",
+        )
+            .bind(baseline_commit)
+            .bind(candidate_commit)
+            .bind(scenario_name)
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let Some(diff) = row.try_get("cachegrind_diff")? else {
+            return Ok(None);
+        };
+
+        let comparison_id: Vec<u8> = row.try_get("comparison_run_id")?;
+        let overflow_bytes: Option<i64> = row.try_get("cachegrind_diff_overflow_bytes")?;
+        Ok(Some(StoredCachegrindDiff {
+            comparison_id: Uuid::from_slice(&comparison_id)?,
+            diff,
+            overflow_bytes: overflow_bytes.map(|bytes| bytes as u64),
+        }))
+    }
+
+    /// Records that the end of a scenario's cachegrind diff didn't fit in the database, and was
+    /// stored elsewhere
+    #[tracing::instrument(skip(self))]
+    pub async fn record_cachegrind_diff_overflow(
+        &self,
+        comparison_id: Uuid,
+        scenario_name: &str,
+        overflow_bytes: u64,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE scenario_diffs
+            SET cachegrind_diff_overflow_bytes = ?
+            WHERE comparison_run_id = ? AND scenario_name = ? AND cachegrind_diff IS NOT NULL",
+        )
+        .bind(overflow_bytes as i64)
+        .bind(comparison_id.as_bytes().as_slice())
+        .bind(scenario_name)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Starts tracking a regression detected on a tracked branch, in the `new` state
+    #[tracing::instrument(skip(self, scenarios))]
+    pub async fn store_regression(
+        &self,
+        branch: &str,
+        last_good_commit: &str,
+        first_bad_commit: &str,
+        scenarios: &[RegressedScenario],
+    ) -> anyhow::Result<Uuid> {
+        let id = Uuid::new_v4();
+        let now = OffsetDateTime::now_utc();
+        let scenarios = serde_json::to_string(scenarios)?;
+
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            INSERT INTO regressions (id, created_utc, updated_utc, branch, last_good_commit, first_bad_commit, state, scenarios)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(id.as_bytes().as_slice())
+        .bind(now)
+        .bind(now)
+        .bind(branch)
+        .bind(last_good_commit)
+        .bind(first_bad_commit)
+        .bind(RegressionState::New.as_str())
+        .bind(scenarios)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Links a regression to the merged PRs that introduced it
+    #[tracing::instrument(skip(self))]
+    pub async fn link_regression_prs(&self, id: Uuid, pr_numbers: &[u64]) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE regressions SET pr_numbers = ? WHERE id = ?")
+            .bind(serde_json::to_string(pr_numbers)?)
+            .bind(id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves the tracked regressions, most recent first, optionally filtered by state and
+    /// branch
+    #[tracing::instrument(skip(self))]
+    pub async fn regressions(
+        &self,
+        state: Option<RegressionState>,
+        branch: Option<&str>,
+    ) -> anyhow::Result<Vec<Regression>> {
+        let mut conn = self.sqlite.lock().await;
+        let regressions = sqlx::query_as(
+            r"
+            SELECT *
+            FROM regressions
+            WHERE (?1 IS NULL OR state = ?1) AND (?2 IS NULL OR branch = ?2)
+            ORDER BY created_utc DESC",
+        )
+        .bind(state.map(|s| s.as_str()))
+        .bind(branch)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(regressions)
+    }
+
+    /// Moves a regression to a new triage state, returning the updated regression (or `None` if
+    /// it does not exist)
+    #[tracing::instrument(skip(self))]
+    pub async fn set_regression_state(
+        &self,
+        id: Uuid,
+        state: RegressionState,
+    ) -> anyhow::Result<Option<Regression>> {
+        let mut conn = self.sqlite.lock().await;
+        let regression = sqlx::query_as(
+            r"
+            UPDATE regressions
+            SET state = ?, updated_utc = ?
+            WHERE id = ?
+            RETURNING *",
+        )
+        .bind(state.as_str())
+        .bind(OffsetDateTime::now_utc())
+        .bind(id.as_bytes().as_slice())
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        Ok(regression)
+    }
+
+    /// Stores the id of the comment used to report results for a specific PR
+    #[tracing::instrument(skip(self))]
+    pub async fn store_result_comment_id(
+        &self,
+        pr_number: u64,
+        comment_id: CommentId,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            INSERT INTO result_comments (pr_number, comment_id)
+            VALUES (?, ?)
+            ON CONFLICT(pr_number) DO UPDATE SET comment_id = excluded.comment_id",
+        )
+        .bind(pr_number as i64)
+        .bind(comment_id.into_inner() as i64)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Records that a maintainer approved benchmarking a commit of a PR coming from a fork
+    #[tracing::instrument(skip(self))]
+    pub async fn store_fork_approval(
+        &self,
+        pr_number: u64,
+        commit_sha: &str,
+        approved_by: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            INSERT INTO fork_approvals (pr_number, commit_sha, approved_by, created_utc)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(pr_number, commit_sha) DO NOTHING",
+        )
+        .bind(pr_number as i64)
+        .bind(commit_sha)
+        .bind(approved_by)
+        .bind(OffsetDateTime::now_utc())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns true if a maintainer approved benchmarking the commit of a PR coming from a fork
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn is_fork_commit_approved(
+        &self,
+        pr_number: u64,
+        commit_sha: &str,
+    ) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let approved: bool = sqlx::query_scalar(
+            r"
+            SELECT EXISTS(
+                SELECT 1
+                FROM fork_approvals
+                WHERE pr_number = ? AND commit_sha = ?
+            )",
+        )
+        .bind(pr_number as i64)
+        .bind(commit_sha)
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(approved)
+    }
+
+    /// Stores the size in bytes of the artifacts built from a commit, identified by its comparison
+    /// key (see [`crate::CommitIdentifier::comparison_key`])
+    ///
+    /// Sizes that were already stored for the commit are replaced
+    #[tracing::instrument(skip(self))]
+    pub async fn store_artifact_sizes(
+        &self,
+        commit_key: &str,
+        sizes: &BTreeMap<String, u64>,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        let now = OffsetDateTime::now_utc();
+        for (artifact, &size) in sizes {
+            sqlx::query(
+                r"
+                INSERT INTO artifact_sizes (commit_key, artifact, size_bytes, created_utc)
+                VALUES (?, ?, ?, ?)
+                ON CONFLICT(commit_key, artifact) DO UPDATE
+                SET size_bytes = excluded.size_bytes, created_utc = excluded.created_utc",
+            )
+            .bind(commit_key)
+            .bind(artifact)
+            .bind(size as i64)
+            .bind(now)
+            .execute(conn.deref_mut())
+            .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns the size in bytes of the artifacts built from a commit, by artifact
+    #[tracing::instrument(skip(self))]
+    pub async fn artifact_sizes(&self, commit_key: &str) -> anyhow::Result<BTreeMap<String, u64>> {
+        let mut conn = self.sqlite.lock().await;
+        let rows =
+            sqlx::query("SELECT artifact, size_bytes FROM artifact_sizes WHERE commit_key = ?")
+                .bind(commit_key)
+                .fetch_all(conn.deref_mut())
+                .await?;
+
+        let mut sizes = BTreeMap::new();
+        for row in rows {
+            let size: i64 = row.try_get("size_bytes")?;
+            sizes.insert(row.try_get("artifact")?, size as u64);
+        }
+
+        Ok(sizes)
+    }
+
+    /// Retrieves the rollouts of report features set at runtime, which override the configured ones
+    #[tracing::instrument(skip(self))]
+    pub async fn report_feature_overrides(
+        &self,
+    ) -> anyhow::Result<BTreeMap<ReportFeature, FeatureRollout>> {
+        let mut conn = self.sqlite.lock().await;
+        let rows: Vec<(String, String)> =
+            sqlx::query_as("SELECT feature, rollout FROM report_feature_overrides")
+                .fetch_all(conn.deref_mut())
+                .await?;
+
+        let mut overrides = BTreeMap::new();
+        for (feature, rollout) in rows {
+            // Features that no longer exist are ignored
+            let Ok(feature) = serde_json::from_value(serde_json::Value::String(feature)) else {
+                continue;
+            };
+            let rollout = serde_json::from_str(&rollout).context("invalid rollout in db")?;
+            overrides.insert(feature, rollout);
+        }
+
+        Ok(overrides)
+    }
+
+    /// Overrides the configured rollout of a report feature, or goes back to the configured one if
+    /// `rollout` is `None`
+    #[tracing::instrument(skip(self))]
+    pub async fn set_report_feature_override(
+        &self,
+        feature: ReportFeature,
+        rollout: Option<&FeatureRollout>,
+    ) -> anyhow::Result<()> {
+        let feature = serde_json::to_value(feature)?;
+        let feature = feature.as_str().context("invalid feature name")?;
+
+        let mut conn = self.sqlite.lock().await;
+        match rollout {
+            Some(rollout) => {
+                sqlx::query(
+                    r"
+                    INSERT INTO report_feature_overrides (feature, rollout, updated_utc)
+                    VALUES (?, ?, ?)
+                    ON CONFLICT(feature) DO UPDATE
+                    SET rollout = excluded.rollout, updated_utc = excluded.updated_utc",
+                )
+                .bind(feature)
+                .bind(serde_json::to_string(rollout)?)
+                .bind(OffsetDateTime::now_utc())
+                .execute(conn.deref_mut())
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM report_feature_overrides WHERE feature = ?")
+                    .bind(feature)
+                    .execute(conn.deref_mut())
+                    .await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Records a webhook event that was dropped because it came from a repository we don't serve
+    #[tracing::instrument(skip(self))]
+    pub async fn record_dropped_webhook(
+        &self,
+        event: &str,
+        repository: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            "INSERT INTO dropped_webhooks (created_utc, event, repository) VALUES (?, ?, ?)",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(event)
+        .bind(repository)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the most recently dropped webhook events, newest first
+    pub async fn dropped_webhooks(&self, limit: u32) -> anyhow::Result<Vec<DroppedWebhook>> {
+        let mut conn = self.sqlite.lock().await;
+        let dropped = sqlx::query_as(
+            r"
+            SELECT created_utc, event, repository
+            FROM dropped_webhooks
+            ORDER BY id DESC
+            LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(dropped)
+    }
+
+    /// Deletes the dropped webhooks, finished self-tests and webhook deliveries recorded before the
+    /// provided date, and the handled events past their retention, returning how many rows were
+    /// deleted
+    ///
+    /// Handled events are usually pruned when another event is handled, but nothing else prunes
+    /// them if the queue stays idle
+    #[tracing::instrument(skip(self))]
+    pub async fn prune(&self, before: OffsetDateTime) -> anyhow::Result<u64> {
+        let mut conn = self.sqlite.lock().await;
+        let deleted = conn
+            .transaction(|t| {
+                Box::pin(async move {
+                    let mut deleted = 0;
+                    deleted += sqlx::query("DELETE FROM dropped_webhooks WHERE created_utc < ?")
+                        .bind(before)
+                        .execute(t.deref_mut())
+                        .await?
+                        .rows_affected();
+                    deleted += sqlx::query(
+                        "DELETE FROM self_tests WHERE finished_utc IS NOT NULL AND created_utc < ?",
+                    )
+                    .bind(before)
+                    .execute(t.deref_mut())
+                    .await?
+                    .rows_affected();
+                    deleted += sqlx::query("DELETE FROM handled_events WHERE handled_utc < ?")
+                        .bind(OffsetDateTime::now_utc() - HANDLED_EVENT_RETENTION)
+                        .execute(t.deref_mut())
+                        .await?
+                        .rows_affected();
+                    deleted += sqlx::query("DELETE FROM webhook_deliveries WHERE received_utc < ?")
+                        .bind(before)
+                        .execute(t.deref_mut())
+                        .await?
+                        .rows_affected();
+
+                    Ok::<_, Error>(deleted)
+                })
+            })
+            .await?;
+
+        Ok(deleted)
+    }
+
+    /// Records a maintainer's action in the audit log
+    pub async fn record_audit_entry(
+        &self,
+        actor: &str,
+        action: &str,
+        details: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            "INSERT INTO audit_log (created_utc, actor, action, details) VALUES (?, ?, ?, ?)",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(actor)
+        .bind(action)
+        .bind(details)
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the most recent entries of the audit log, newest first
+    pub async fn audit_log(&self, limit: u32) -> anyhow::Result<Vec<AuditLogEntry>> {
+        let mut conn = self.sqlite.lock().await;
+        let entries = sqlx::query_as(
+            r"
+            SELECT created_utc, actor, action, details
+            FROM audit_log
+            ORDER BY id DESC
+            LIMIT ?",
+        )
+        .bind(limit)
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(entries)
+    }
+
+    /// Retrieves the pause of event processing requested through the admin API, if any
+    pub async fn queue_pause(&self) -> anyhow::Result<Option<QueuePause>> {
+        let mut conn = self.sqlite.lock().await;
+        let pause = sqlx::query_as("SELECT paused_utc, reason FROM queue_pause")
+            .fetch_optional(conn.deref_mut())
+            .await?;
+
+        Ok(pause)
+    }
+
+    /// Records that event processing is paused, returning the resulting pause
+    ///
+    /// Pausing an already paused queue keeps the original pause time, but updates the reason
+    pub async fn pause_queue(&self, reason: Option<&str>) -> anyhow::Result<QueuePause> {
+        let mut conn = self.sqlite.lock().await;
+        let pause = sqlx::query_as(
+            r"
+            INSERT INTO queue_pause (id, paused_utc, reason)
+            VALUES (0, ?, ?)
+            ON CONFLICT(id) DO UPDATE SET reason = excluded.reason
+            RETURNING paused_utc, reason",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(reason)
+        .fetch_one(conn.deref_mut())
+        .await?;
+
+        Ok(pause)
+    }
+
+    /// Records that event processing is no longer paused
+    pub async fn resume_queue(&self) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("DELETE FROM queue_pause")
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Makes the instance the owner of the event queue, unless another instance owns it, returning
+    /// whether the instance owns the queue
+    ///
+    /// Ownership is taken over if it was released, or if the owner's last heartbeat is older than
+    /// `stale_before` (i.e. it crashed)
+    pub async fn try_acquire_queue(
+        &self,
+        instance_id: Uuid,
+        stale_before: OffsetDateTime,
+    ) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let acquired = sqlx::query(
+            r"
+            INSERT INTO queue_owner (id, instance_id, heartbeat_utc)
+            VALUES (0, ?, ?)
+            ON CONFLICT(id) DO UPDATE
+            SET instance_id = excluded.instance_id,
+                heartbeat_utc = excluded.heartbeat_utc,
+                released_utc = NULL
+            WHERE queue_owner.instance_id = excluded.instance_id
+                OR queue_owner.released_utc IS NOT NULL
+                OR queue_owner.heartbeat_utc < ?",
+        )
+        .bind(instance_id.as_bytes().as_slice())
+        .bind(OffsetDateTime::now_utc())
+        .bind(stale_before)
+        .execute(conn.deref_mut())
+        .await?
+        .rows_affected();
+
+        Ok(acquired == 1)
+    }
+
+    /// Records that the owner of the event queue is still alive, returning `false` if the instance
+    /// no longer owns the queue
+    pub async fn queue_heartbeat(&self, instance_id: Uuid) -> anyhow::Result<bool> {
+        let mut conn = self.sqlite.lock().await;
+        let updated = sqlx::query(
+            r"
+            UPDATE queue_owner
+            SET heartbeat_utc = ?
+            WHERE instance_id = ? AND released_utc IS NULL",
+        )
+        .bind(OffsetDateTime::now_utc())
+        .bind(instance_id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?
+        .rows_affected();
+
+        Ok(updated == 1)
+    }
+
+    /// Releases the ownership of the event queue, so another instance can take it over right away
+    pub async fn release_queue(&self, instance_id: Uuid) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE queue_owner SET released_utc = ? WHERE instance_id = ?")
+            .bind(OffsetDateTime::now_utc())
+            .bind(instance_id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Retrieves the id of the comment used to report results for a specific PR, if available
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn result_comment_id(&self, pr_number: u64) -> anyhow::Result<Option<CommentId>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT comment_id
+            FROM result_comments
+            WHERE pr_number = ?",
+        )
+        .bind(pr_number as i64)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let comment_id: i64 = row.try_get("comment_id")?;
+        Ok(Some((comment_id as u64).into()))
+    }
+
+    /// Retrieves the stored result comments, as pairs of PR number and comment id
+    #[tracing::instrument(skip(self))]
+    pub async fn result_comments(&self) -> anyhow::Result<Vec<(u64, CommentId)>> {
+        let mut conn = self.sqlite.lock().await;
+        let rows: Vec<(i64, i64)> =
+            sqlx::query_as("SELECT pr_number, comment_id FROM result_comments ORDER BY pr_number")
+                .fetch_all(conn.deref_mut())
+                .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|(pr_number, comment_id)| (pr_number as u64, (comment_id as u64).into()))
+            .collect())
+    }
+
+    /// Replaces the PR's result comment (e.g. because the old one was deleted), moving the reports
+    /// linked to the old comment to the new one
+    #[tracing::instrument(skip(self))]
+    pub async fn replace_result_comment(
+        &self,
+        pr_number: u64,
+        old_comment_id: CommentId,
+        new_comment_id: CommentId,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        conn.transaction(|t| {
+            Box::pin(async move {
+                sqlx::query("UPDATE result_comments SET comment_id = ? WHERE pr_number = ?")
+                    .bind(new_comment_id.into_inner() as i64)
+                    .bind(pr_number as i64)
+                    .execute(t.deref_mut())
+                    .await?;
+                sqlx::query("UPDATE jobs SET comment_id = ? WHERE comment_id = ?")
+                    .bind(new_comment_id.into_inner() as i64)
+                    .bind(old_comment_id.into_inner() as i64)
+                    .execute(t.deref_mut())
+                    .await?;
+
+                Ok::<_, Error>(())
+            })
+        })
+        .await?;
+
+        Ok(())
+    }
+
+    /// Forgets the PR's result comment, so the next report is posted in a new one
+    #[tracing::instrument(skip(self))]
+    pub async fn forget_result_comment(&self, pr_number: u64) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("DELETE FROM result_comments WHERE pr_number = ?")
+            .bind(pr_number as i64)
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Links a job to the bench run it produced
+    #[tracing::instrument(skip(self))]
+    pub async fn record_job_bench_run(
+        &self,
+        job_id: Uuid,
+        bench_run_id: Uuid,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("UPDATE jobs SET bench_run_id = ? WHERE id = ?")
+            .bind(bench_run_id.as_bytes().as_slice())
+            .bind(job_id.as_bytes().as_slice())
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(())
+    }
+
+    /// Links a job to the comparison it reported and to the PR comment where it was reported
+    ///
+    /// The comparison might have been produced by an earlier job, in which case it is shared
+    #[tracing::instrument(skip(self))]
+    pub async fn record_job_report(
+        &self,
+        job_id: Uuid,
+        pr_number: u64,
+        comment_id: CommentId,
+        baseline_commit: &str,
+        candidate_commit: &str,
+    ) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query(
+            r"
+            UPDATE jobs
+            SET pr_number = ?,
+                comment_id = ?,
+                comparison_run_id = (
+                    SELECT id
+                    FROM comparison_runs
+                    WHERE baseline_commit = ? AND candidate_commit = ? AND NOT invalidated
+                )
+            WHERE id = ?",
+        )
+        .bind(pr_number as i64)
+        .bind(comment_id.into_inner() as i64)
+        .bind(baseline_commit)
+        .bind(candidate_commit)
+        .bind(job_id.as_bytes().as_slice())
+        .execute(conn.deref_mut())
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns the PR comment where the comparison with the provided id was last reported, if any
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn report_comment(
+        &self,
+        comparison_id: Uuid,
+    ) -> anyhow::Result<Option<ReportComment>> {
+        let mut conn = self.sqlite.lock().await;
+        let row = sqlx::query(
+            r"
+            SELECT pr_number, comment_id, (
+                SELECT comparison_run_id
+                FROM jobs AS later_jobs
+                WHERE later_jobs.comment_id = jobs.comment_id
+                ORDER BY created_utc DESC
+                LIMIT 1
+            ) AS latest_comparison_run_id
+            FROM jobs
+            WHERE comparison_run_id = ? AND comment_id IS NOT NULL
+            ORDER BY created_utc DESC
+            LIMIT 1",
+        )
+        .bind(comparison_id.as_bytes().as_slice())
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let pr_number: i64 = row.try_get("pr_number")?;
+        let comment_id: i64 = row.try_get("comment_id")?;
+        let latest_comparison_run_id: Option<Vec<u8>> = row.try_get("latest_comparison_run_id")?;
+        Ok(Some(ReportComment {
+            pr_number: pr_number as u64,
+            comment_id: (comment_id as u64).into(),
+            superseded: latest_comparison_run_id.as_deref() != Some(comparison_id.as_bytes()),
+        }))
+    }
+
+    /// Returns the id of the comparison last reported in the PR's results comment, if any
+    ///
+    /// Standalone comparisons (e.g. of arbitrary commits), which are reported in comments of their
+    /// own, are not taken into account
+    #[tracing::instrument(skip(self), ret)]
+    pub async fn latest_pr_comparison(&self, pr_number: u64) -> anyhow::Result<Option<Uuid>> {
+        let mut conn = self.sqlite.lock().await;
+        let id: Option<Vec<u8>> = sqlx::query_scalar(
+            r"
+            SELECT comparison_run_id
+            FROM jobs
+            WHERE comparison_run_id IS NOT NULL
+                AND comment_id = (SELECT comment_id FROM result_comments WHERE pr_number = ?)
+            ORDER BY created_utc DESC
+            LIMIT 1",
+        )
+        .bind(pr_number as i64)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        id.map(|id| Uuid::from_slice(&id).context("invalid uuid in db"))
+            .transpose()
+    }
+
+    /// Retrieves the provenance of the job identified by `id`, which can be the id of the job
+    /// itself, of the event that triggered it, or of the bench run or comparison it produced
+    ///
+    /// Comparisons can be reported by multiple jobs (e.g. when a PR is benchmarked again without
+    /// changes), in which case the job that produced the comparison is returned
+    #[tracing::instrument(skip(self))]
+    pub async fn trace(&self, id: Uuid) -> anyhow::Result<Option<JobTrace>> {
+        fn to_uuid(bytes: Option<Vec<u8>>) -> anyhow::Result<Option<Uuid>> {
+            bytes
+                .map(|bytes| Uuid::from_slice(&bytes).context("invalid uuid in db"))
+                .transpose()
+        }
+
+        let mut conn = self.sqlite.lock().await;
+        let id = id.as_bytes().as_slice();
+        let row = sqlx::query(
+            r"
+            SELECT *
+            FROM jobs
+            WHERE id = ? OR event_id = ? OR bench_run_id = ? OR comparison_run_id = ?
+            ORDER BY created_utc
+            LIMIT 1",
+        )
+        .bind(id)
+        .bind(id)
+        .bind(id)
+        .bind(id)
+        .fetch_optional(conn.deref_mut())
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+
+        let bench_run_id: Option<Vec<u8>> = row.try_get("bench_run_id")?;
+        let bench_run = match bench_run_id {
+            None => None,
+            Some(bench_run_id) => {
+                sqlx::query_as("SELECT id, created_utc, commit_sha FROM bench_runs WHERE id = ?")
+                    .bind(bench_run_id)
+                    .fetch_optional(conn.deref_mut())
+                    .await?
+            }
+        };
+
+        let comparison_run_id: Option<Vec<u8>> = row.try_get("comparison_run_id")?;
+        let comparison = match comparison_run_id {
+            None => None,
+            Some(comparison_run_id) => {
+                sqlx::query_as(
+                    r"
+                    SELECT id, created_utc, baseline_commit, candidate_commit, verdict
+                    FROM comparison_runs
+                    WHERE id = ?",
+                )
+                .bind(comparison_run_id)
+                .fetch_optional(conn.deref_mut())
+                .await?
+            }
+        };
+
+        let pr_number: Option<i64> = row.try_get("pr_number")?;
+        let comment_id: Option<i64> = row.try_get("comment_id")?;
+        Ok(Some(JobTrace {
+            job: BenchJob::from_row(&row)?,
+            event_id: to_uuid(row.try_get("event_id")?)?,
+            event: row.try_get("event")?,
+            bench_run,
+            comparison,
+            pr_number: pr_number.map(|n| n as u64),
+            comment_id: comment_id.map(|id| id as u64),
+        }))
+    }
+
+    /// Copies the benchmark runs, results and comparisons to the (already migrated) database at the
+    /// provided path, leaving out anything that could identify users or GitHub resources
+    #[tracing::instrument(skip(self))]
+    pub async fn export_anonymized(&self, export_db_path: &str) -> anyhow::Result<()> {
+        let mut conn = self.sqlite.lock().await;
+        sqlx::query("ATTACH DATABASE ? AS export")
+            .bind(export_db_path)
+            .execute(conn.deref_mut())
+            .await?;
+
+        let result = conn
+            .transaction(|t| {
+                Box::pin(async move {
+                    for table in EXPORTED_TABLES {
+                        sqlx::query(&format!(
+                            "INSERT INTO export.{table} SELECT * FROM main.{table}"
+                        ))
+                        .execute(t.deref_mut())
+                        .await?;
+                    }
+
+                    Ok::<_, Error>(())
+                })
+            })
+            .await;
+
+        sqlx::query("DETACH DATABASE export")
+            .execute(conn.deref_mut())
+            .await?;
+
+        Ok(result?)
+    }
+
+    /// Returns all jobs, oldest first
+    #[tracing::instrument(skip(self))]
+    pub async fn jobs(&self) -> anyhow::Result<Vec<BenchJob>> {
+        let mut conn = self.sqlite.lock().await;
+        let jobs = sqlx::query_as(
+            r"
+            SELECT *
+            FROM jobs
+            ORDER BY created_utc",
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(jobs)
+    }
+
+    /// Returns all queued events, in the order in which they will be handled
+    #[tracing::instrument(skip(self))]
+    pub async fn queued_events(&self) -> anyhow::Result<Vec<QueuedEvent>> {
+        let mut conn = self.sqlite.lock().await;
+        let events = sqlx::query_as(
+            r"
+            SELECT *
+            FROM event_queue
+            ORDER BY priority DESC, created_utc",
+        )
+        .fetch_all(conn.deref_mut())
+        .await?;
+
+        Ok(events)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::MIGRATOR;
+    use time::Duration;
+
+    #[tokio::test]
+    async fn test_store_load_results_round_trips_and_orders_by_time() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        db.store_run_results(
+            "main",
+            "commit",
+            vec![("foo".to_string(), ScenarioKind::Icount, 42.0)],
+        )
+        .await?;
+        db.store_run_results(
+            "main",
+            "commit",
+            vec![("foo".to_string(), ScenarioKind::Icount, 41.0)],
+        )
+        .await?;
+        db.store_run_results(
+            "main",
+            "commit",
+            vec![("foo".to_string(), ScenarioKind::Walltime, 43.0)],
+        )
+        .await?;
+
+        let history = db
+            .result_history("main", OffsetDateTime::now_utc() - Duration::minutes(1))
+            .await?;
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history[0].result, 42.0);
+        assert_eq!(history[0].scenario_kind, ScenarioKind::Icount);
+        assert_eq!(history[1].result, 41.0);
+        assert_eq!(history[1].scenario_kind, ScenarioKind::Icount);
+        assert_eq!(history[2].result, 43.0);
+        assert_eq!(history[2].scenario_kind, ScenarioKind::Walltime);
+        assert_eq!(history[1].result_unit, ResultUnit::Instructions);
+        assert_eq!(history[2].result_unit, ResultUnit::Nanoseconds);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_latest_commit_per_branch() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        assert_eq!(db.latest_commit("main").await?, None);
+
+        db.store_run_results("main", "first", Vec::new()).await?;
+        db.store_run_results("main", "second", Vec::new()).await?;
+        db.store_run_results("rel-0.23", "third", Vec::new())
+            .await?;
+        assert_eq!(db.latest_commit("main").await?, Some("second".to_string()));
+        assert_eq!(
+            db.latest_commit("rel-0.23").await?,
+            Some("third".to_string())
+        );
+        assert_eq!(db.latest_commit("rel-0.22").await?, None);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_latest_results_and_digest() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let since = OffsetDateTime::now_utc() - Duration::weeks(1);
+        assert!(db.latest_results("main").await?.is_none());
+
+        db.store_run_results(
+            "main",
+            "first",
+            vec![("index".to_string(), ScenarioKind::IcountIndex, 100.0)],
+        )
+        .await?;
+        db.store_run_results(
+            "main",
+            "second",
+            vec![("index".to_string(), ScenarioKind::IcountIndex, 98.0)],
+        )
+        .await?;
+        let (commit, results) = db.latest_results("main").await?.unwrap();
+        assert_eq!(commit, "second");
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].result, 98.0);
+
+        db.store_comparison_result(
+            "first".to_string(),
+            "second".to_string(),
+            ComparisonResult::from_iter([
+                (
+                    ScenarioKind::Icount,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: Vec::new(),
+                    },
+                ),
+                (
+                    ScenarioKind::Walltime,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: Vec::new(),
+                    },
+                ),
+            ]),
+        )
+        .await?;
+
+        let digest = db.digest(since, "main", "index").await?;
+        assert_eq!(digest.main_runs, 2);
+        assert_eq!(digest.index_range, Some((100.0, 98.0)));
+        assert_eq!(digest.comparisons, vec![("neutral".to_string(), 1)]);
+        assert_eq!(digest.failed_jobs, 0);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_scenario_set_changes() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let since = OffsetDateTime::now_utc() - Duration::days(30);
+        let results = |scenarios: &[&str]| {
+            scenarios
+                .iter()
+                .map(|s| (s.to_string(), ScenarioKind::Icount, 100.0))
+                .collect::<Vec<_>>()
+        };
+
+        db.store_run_results("main", "first", results(&["a", "b"]))
+            .await?;
+        db.store_run_results("main", "second", results(&["a", "b"]))
+            .await?;
+        db.store_run_results("main", "third", results(&["a", "c"]))
+            .await?;
+        db.store_run_results("rel-0.23", "other", results(&["d"]))
+            .await?;
+
+        let changes = db.scenario_set_changes("main", since).await?;
+        let summary: Vec<_> = changes
+            .iter()
+            .map(|c| (c.scenario_name.as_str(), c.added, c.commit_sha.as_str()))
+            .collect();
+        assert_eq!(summary, [("b", false, "third"), ("c", true, "third")]);
+        assert_eq!(changes[0].previous_commit_sha, "second");
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_regression_triage() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let scenarios = vec![RegressedScenario {
+            scenario_name: "handshake".to_string(),
+            previous_result: 100.0,
+            current_result: 110.0,
+            significance_threshold: 0.01,
+            result_unit: ResultUnit::Instructions,
+        }];
+        let first = db.store_regression("main", "a", "b", &scenarios).await?;
+        let second = db.store_regression("main", "b", "c", &scenarios).await?;
+
+        let regressions = db.regressions(None, None).await?;
+        assert_eq!(regressions.len(), 2);
+        assert_eq!(regressions[0].id, second);
+        assert_eq!(regressions[1].scenarios, scenarios);
+        assert!(regressions.iter().all(|r| r.state == RegressionState::New));
+
+        db.link_regression_prs(first, &[42]).await?;
+        let triaged = db
+            .set_regression_state(first, RegressionState::Triaged)
+            .await?
+            .unwrap();
+        assert_eq!(triaged.state, RegressionState::Triaged);
+        assert_eq!(triaged.first_bad_commit, "b");
+        assert_eq!(triaged.pr_numbers, vec![42]);
+
+        let new = db.regressions(Some(RegressionState::New), None).await?;
+        assert_eq!(new.len(), 1);
+        assert_eq!(new[0].id, second);
+        assert!(db
+            .set_regression_state(Uuid::new_v4(), RegressionState::Fixed)
+            .await?
+            .is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_comparison_verdict() {
+        let diff = |baseline, candidate| ScenarioDiff::icount("foo", baseline, candidate, 0.01);
+        // Wall-time regressions are ignored
+        let result = |icount_diffs| {
+            ComparisonResult::from_diffs(icount_diffs, &[], vec![diff(100.0, 200.0)])
+        };
+
+        assert_eq!(result(vec![]).verdict(), Verdict::Neutral);
+        assert_eq!(result(vec![diff(100.0, 100.5)]).verdict(), Verdict::Neutral);
+        assert_eq!(
+            result(vec![diff(100.0, 90.0), diff(100.0, 100.5)]).verdict(),
+            Verdict::Improved
+        );
+        assert_eq!(
+            result(vec![diff(100.0, 90.0), diff(100.0, 110.0)]).verdict(),
+            Verdict::Regressed
+        );
+    }
+
+    #[tokio::test]
+    async fn test_store_load_event_round_trips_and_orders_by_time() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        let id1 = db.enqueue_event("foo", &[1, 2, 3, 4]).await?;
+        let id2 = db.enqueue_event("bar", &[1, 2, 3, 4]).await?;
+
+        let event = db.next_queued_event().await?.unwrap();
+        assert_eq!(id1, event.id);
+        assert_eq!(event.payload, [1, 2, 3, 4]);
+
+        db.delete_event(id1).await?;
+
+        let event = db.next_queued_event().await?.unwrap();
+        assert_eq!(id2, event.id);
+
+        let job_id = db.new_job_for_event(event.id, event.created_utc).await?;
+        let job = db.job(job_id).await?;
+        assert_eq!(job.id, job_id);
+        assert_eq!(job.finished_utc, None);
+
+        db.job_finished(job_id, true).await?;
+        let job = db.job(job_id).await?;
+
+        assert!(job.finished_utc.is_some());
+        assert_eq!(job.success, Some(true));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_next_queued_event_orders_by_priority() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        let low = db.enqueue_event_with_priority("foo", &[], -1).await?;
+        let normal = db.enqueue_event("bar", &[]).await?;
+
+        assert_eq!(db.next_queued_event().await?.unwrap().id, normal);
+        db.delete_event(normal).await?;
+        assert_eq!(db.next_queued_event().await?.unwrap().id, low);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_next_queued_event_with_aging() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        let low = db.enqueue_event_with_priority("foo", &[], -1).await?;
+        let normal = db.enqueue_event("bar", &[]).await?;
+        let aging = Duration::minutes(30);
+
+        // The low-priority event hasn't waited long enough to be boosted
+        let now = OffsetDateTime::now_utc();
+        let next = db.next_queued_event_with_aging(now, aging).await?.unwrap();
+        assert_eq!(next.id, normal);
+
+        // Once boosted, it ties with the normal event and wins because it was enqueued earlier (the
+        // normal event ages too, so the low-priority one needs to have waited an interval longer)
+        sqlx::query("UPDATE event_queue SET created_utc = ? WHERE id = ?")
+            .bind(now - Duration::minutes(31))
+            .bind(low.as_bytes().as_slice())
+            .execute(db.sqlite.lock().await.deref_mut())
+            .await?;
+        let next = db.next_queued_event_with_aging(now, aging).await?.unwrap();
+        assert_eq!(next.id, low);
+        assert_eq!(next.effective_priority(now, aging), DEFAULT_EVENT_PRIORITY);
+
+        // A zero interval disables aging
+        let next = db
+            .next_queued_event_with_aging(now, Duration::ZERO)
+            .await?
+            .unwrap();
+        assert_eq!(next.id, normal);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_comparison_result() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let result = |baseline_result| {
+            ComparisonResult::from_iter([
+                (
+                    ScenarioKind::Icount,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: vec![ScenarioDiff {
+                            scenario_name: "foo".to_string(),
+                            scenario_kind: ScenarioKind::Icount,
+                            baseline_result,
+                            candidate_result: 100.0,
+                            significance_threshold: 0.35,
+                            result_unit: ResultUnit::Instructions,
+                            cachegrind_diff: None,
+                        }],
+                    },
+                ),
+                (
+                    ScenarioKind::Walltime,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: Vec::new(),
+                    },
+                ),
+            ])
+        };
+
+        let first_id = db
+            .store_comparison_result("base".to_string(), "head".to_string(), result(90.0))
+            .await?;
+        assert!(db.invalidate_comparison_result("base", "head").await?);
+        assert!(db.comparison_result("base", "head").await?.is_none());
+        assert!(!db.invalidate_comparison_result("base", "head").await?);
+
+        // A fresh result replaces the invalidated one, which can still be found by id
+        db.store_comparison_result("base".to_string(), "head".to_string(), result(95.0))
+            .await?;
+        let comparison = db.comparison_result("base", "head").await?.unwrap();
+        assert_eq!(
+            comparison.metric(ScenarioKind::Icount).diffs[0].baseline_result,
+            95.0
+        );
+        assert_eq!(
+            db.comparison_commits(first_id).await?,
+            Some(("base".to_string(), "head".to_string()))
+        );
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_load_artifact_sizes() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        assert!(db.artifact_sizes("abc").await?.is_empty());
+
+        let mut sizes = BTreeMap::new();
+        sizes.insert("librustls.rlib".to_string(), 1000);
+        sizes.insert("bench".to_string(), 5000);
+        db.store_artifact_sizes("abc", &sizes).await?;
+
+        // Storing again overwrites previous sizes
+        sizes.insert("bench".to_string(), 6000);
+        db.store_artifact_sizes("abc", &sizes).await?;
+
+        assert_eq!(db.artifact_sizes("abc").await?, sizes);
+        assert!(db.artifact_sizes("def").await?.is_empty());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_store_load_comparison_with_extra_metrics() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let diff = |scenario_kind: ScenarioKind| ScenarioDiff {
+            scenario_name: "handshake".to_string(),
+            scenario_kind,
+            baseline_result: 1000.0,
+            candidate_result: 1100.0,
+            significance_threshold: 0.01,
+            result_unit: scenario_kind.unit(),
+            cachegrind_diff: None,
+        };
+        let result = ComparisonResult::from_iter([
+            (
+                ScenarioKind::MaxRss,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: vec!["new_scenario".to_string()],
+                    diffs: vec![diff(ScenarioKind::MaxRss)],
+                },
+            ),
+            (
+                ScenarioKind::D1Misses,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: Vec::new(),
+                    diffs: vec![diff(ScenarioKind::D1Misses)],
+                },
+            ),
+            (
+                ScenarioKind::LlMisses,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: Vec::new(),
+                    diffs: vec![diff(ScenarioKind::LlMisses)],
+                },
+            ),
+            (
+                ScenarioKind::BuildTime,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: Vec::new(),
+                    diffs: vec![diff(ScenarioKind::BuildTime)],
+                },
+            ),
+            (
+                ScenarioKind::EstimatedCycles,
+                ComparisonSubResult {
+                    scenarios_missing_in_baseline: Vec::new(),
+                    diffs: vec![diff(ScenarioKind::EstimatedCycles)],
+                },
+            ),
+        ]);
+
+        db.store_comparison_result("base".to_string(), "head".to_string(), result)
             .await?;
+        let comparison = db.comparison_result("base", "head").await?.unwrap();
 
-        Ok(id)
+        // Metrics without results are absent
+        assert!(!comparison.metrics.contains_key(&ScenarioKind::Icount));
+        assert!(comparison.metric(ScenarioKind::Walltime).diffs.is_empty());
+
+        assert_eq!(
+            comparison
+                .metric(ScenarioKind::MaxRss)
+                .scenarios_missing_in_baseline,
+            ["new_scenario"]
+        );
+        for kind in [
+            ScenarioKind::MaxRss,
+            ScenarioKind::D1Misses,
+            ScenarioKind::LlMisses,
+            ScenarioKind::BuildTime,
+            ScenarioKind::EstimatedCycles,
+        ] {
+            assert_eq!(comparison.metric(kind).diffs, [diff(kind)]);
+        }
+
+        Ok(())
     }
 
-    /// Retrieves the result of a comparison between two branches of rustls
-    #[tracing::instrument(skip(self))]
-    pub async fn comparison_result(
-        &self,
-        baseline_commit: &str,
-        candidate_commit: &str,
-    ) -> anyhow::Result<Option<ComparisonResult>> {
-        fn from_json_array(values: Option<String>) -> anyhow::Result<Vec<String>> {
-            match values {
-                None => Ok(Vec::new()),
-                Some(missing) => serde_json::from_str(&missing).context("invalid JSON in db"),
-            }
+    #[tokio::test]
+    async fn test_job_costs() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        let costs = [
+            ("issue_comment", Some("alice"), Some(1), 10.0),
+            ("pull_request", Some("bob"), Some(1), 20.0),
+            ("issue_comment", Some("alice"), Some(2), 30.0),
+            ("push", None, None, 5.0),
+        ];
+        for (event, requester, pr_number, cpu_seconds) in costs {
+            db.enqueue_event(event, &[]).await?;
+            let event = db.next_queued_event().await?.unwrap();
+            let job_id = db.new_job_for_event(event.id, event.created_utc).await?;
+            db.job_finished(job_id, true).await?;
+            db.record_job_cost(job_id, requester, pr_number, Some(cpu_seconds), 60.0)
+                .await?;
+            db.delete_event(event.id).await?;
         }
 
-        let mut conn = self.sqlite.lock().await;
-        let row = sqlx::query(
-            r"
-            SELECT id, created_utc, icount_scenarios_missing_in_baseline, walltime_scenarios_missing_in_baseline
-            FROM comparison_runs
-            WHERE baseline_commit = ? AND candidate_commit = ?",
-        )
-        .bind(baseline_commit)
-        .bind(candidate_commit)
-        .fetch_optional(conn.deref_mut())
-        .await?;
+        // Jobs that are still running are not accounted for
+        db.enqueue_event("push", &[]).await?;
+        let event = db.next_queued_event().await?.unwrap();
+        db.new_job_for_event(event.id, event.created_utc).await?;
 
-        let Some(row) = row else {
-            return Ok(None);
+        let since = OffsetDateTime::now_utc() - Duration::minutes(1);
+        let summary = |key: Option<&str>, jobs, cpu_seconds, wall_seconds| JobCostSummary {
+            key: key.map(|key| key.to_string()),
+            jobs,
+            cpu_seconds,
+            wall_seconds,
         };
+        assert_eq!(
+            db.job_costs(CostGrouping::Requester, since).await?,
+            [
+                summary(Some("alice"), 2, 40.0, 120.0),
+                summary(Some("bob"), 1, 20.0, 60.0),
+                summary(None, 1, 5.0, 60.0),
+            ]
+        );
+        assert_eq!(
+            db.job_costs(CostGrouping::Pr, since).await?,
+            [
+                summary(Some("1"), 2, 30.0, 120.0),
+                summary(Some("2"), 1, 30.0, 60.0),
+                summary(None, 1, 5.0, 60.0),
+            ]
+        );
+        assert_eq!(
+            db.job_costs(CostGrouping::Event, since).await?,
+            [
+                summary(Some("issue_comment"), 2, 40.0, 120.0),
+                summary(Some("pull_request"), 1, 20.0, 60.0),
+                summary(Some("push"), 1, 5.0, 60.0),
+            ]
+        );
 
-        let id: Vec<u8> = row.try_get("id")?;
-        let icount_scenarios_missing_in_baseline =
-            from_json_array(row.try_get("icount_scenarios_missing_in_baseline")?)?;
-        let walltime_scenarios_missing_in_baseline =
-            from_json_array(row.try_get("walltime_scenarios_missing_in_baseline")?)?;
+        let later = OffsetDateTime::now_utc() + Duration::minutes(1);
+        assert!(db.job_costs(CostGrouping::Event, later).await?.is_empty());
 
-        let icount_diffs = sqlx::query_as(
-            r"
-            SELECT *
-            FROM scenario_diffs
-            WHERE comparison_run_id = ? AND scenario_kind = ?",
-        )
-        .bind(&id)
-        .bind(ScenarioKind::Icount as i32)
-        .fetch_all(conn.deref_mut())
-        .await?;
+        Ok(())
+    }
 
-        let walltime_diffs = sqlx::query_as(
-            r"
-            SELECT *
-            FROM scenario_diffs
-            WHERE comparison_run_id = ? AND scenario_kind = ?",
-        )
-        .bind(id)
-        .bind(ScenarioKind::Walltime as i64)
-        .fetch_all(conn.deref_mut())
-        .await?;
+    #[tokio::test]
+    async fn test_dead_letter_and_requeue_event() -> anyhow::Result<()> {
+        let db = empty_db().await;
 
-        Ok(Some(ComparisonResult {
-            icount: ComparisonSubResult {
-                scenarios_missing_in_baseline: icount_scenarios_missing_in_baseline,
-                diffs: icount_diffs,
-            },
-            walltime: ComparisonSubResult {
-                scenarios_missing_in_baseline: walltime_scenarios_missing_in_baseline,
-                diffs: walltime_diffs,
-            },
-        }))
-    }
+        let event_id = db.enqueue_event("push", &[1, 2, 3]).await?;
+        assert!(db.dead_letter_event(event_id, "invalid payload").await?);
+        assert_eq!(db.queued_event_count().await?, 0);
+        assert!(!db.dead_letter_event(event_id, "invalid payload").await?);
 
-    /// Returns the cachegrind diff for the specified comparison and scenario, if available
-    #[tracing::instrument(skip(self))]
-    pub async fn cachegrind_diff(
-        &self,
-        baseline_commit: &str,
-        candidate_commit: &str,
-        scenario_name: &str,
-    ) -> anyhow::Result<Option<String>> {
-        let mut conn = self.sqlite.lock().await;
-        let row = sqlx::query(
-            r"
-            SELECT cachegrind_diff
-            FROM comparison_runs JOIN scenario_diffs ON comparison_runs.id = scenario_diffs.comparison_run_id
-            WHERE baseline_commit = ? AND candidate_commit = ? AND scenario_name = ?",
-        )
-            .bind(baseline_commit)
-            .bind(candidate_commit)
-            .bind(scenario_name)
-            .fetch_optional(conn.deref_mut())
-            .await?;
+        let dead_events = db.dead_events().await?;
+        assert_eq!(dead_events.len(), 1);
+        assert_eq!(dead_events[0].id, event_id);
+        assert_eq!(dead_events[0].payload, [1, 2, 3]);
+        assert_eq!(dead_events[0].error, "invalid payload");
 
-        let Some(row) = row else {
-            return Ok(None);
-        };
+        let requeued_id = db.requeue_dead_event(event_id).await?.unwrap();
+        assert_ne!(requeued_id, event_id);
+        assert!(db.dead_events().await?.is_empty());
+        let requeued = db.next_queued_event().await?.unwrap();
+        assert_eq!(requeued.id, requeued_id);
+        assert_eq!(requeued.payload, [1, 2, 3]);
+
+        assert_eq!(db.requeue_dead_event(event_id).await?, None);
 
-        Ok(row.try_get("cachegrind_diff")?)
+        Ok(())
     }
 
-    /// Stores the id of the comment used to report results for a specific PR
-    #[tracing::instrument(skip(self))]
-    pub async fn store_result_comment_id(
-        &self,
-        pr_number: u64,
-        comment_id: CommentId,
-    ) -> anyhow::Result<()> {
-        let mut conn = self.sqlite.lock().await;
-        sqlx::query(
-            r"
-            INSERT INTO result_comments (pr_number, comment_id)
-            VALUES (?, ?)
-            ON CONFLICT(pr_number) DO UPDATE SET comment_id = excluded.comment_id",
-        )
-        .bind(pr_number as i64)
-        .bind(comment_id.into_inner() as i64)
-        .execute(conn.deref_mut())
-        .await?;
+    #[tokio::test]
+    async fn test_job_status_lifecycle() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let new_job = || async {
+            let event_id = db.enqueue_event("push", &[]).await?;
+            db.new_job_for_event(event_id, OffsetDateTime::now_utc())
+                .await
+        };
+
+        let crashed = new_job().await?;
+        assert_eq!(db.job(crashed).await?.status, "queued");
+        db.job_started(crashed).await?;
+        assert_eq!(db.job(crashed).await?.status, "running");
+        db.job_crashed(crashed).await?;
+        let job = db.job(crashed).await?;
+        assert_eq!(job.status, "failure");
+        assert!(job.finished_utc.is_some());
+
+        // Handlers may succeed after reporting a failure
+        let reported_failure = new_job().await?;
+        db.job_started(reported_failure).await?;
+        db.record_job_failure(reported_failure, FailureClass::Build)
+            .await?;
+        db.job_finished(reported_failure, true).await?;
+        assert_eq!(db.job(reported_failure).await?.status, "failure");
+
+        // Finished jobs are left alone if their handler crashes afterwards
+        let succeeded = new_job().await?;
+        db.job_started(succeeded).await?;
+        db.job_finished(succeeded, true).await?;
+        db.job_crashed(succeeded).await?;
+        assert_eq!(db.job(succeeded).await?.status, "success");
+
+        let cancelled = new_job().await?;
+        db.job_cancelled(cancelled).await?;
+        assert_eq!(db.job(cancelled).await?.status, "cancelled");
 
         Ok(())
     }
 
-    /// Retrieves the id of the comment used to report results for a specific PR, if available
-    #[tracing::instrument(skip(self), ret)]
-    pub async fn result_comment_id(&self, pr_number: u64) -> anyhow::Result<Option<CommentId>> {
-        let mut conn = self.sqlite.lock().await;
-        let row = sqlx::query(
-            r"
-            SELECT comment_id
-            FROM result_comments
-            WHERE pr_number = ?",
-        )
-        .bind(pr_number as i64)
-        .fetch_optional(conn.deref_mut())
-        .await?;
-
-        let Some(row) = row else {
-            return Ok(None);
+    #[tokio::test]
+    async fn test_interrupted_jobs_are_not_failures() -> anyhow::Result<()> {
+        let db = empty_db().await;
+        let since = OffsetDateTime::now_utc() - Duration::days(1);
+        let new_job = || async {
+            let event_id = db.enqueue_event("push", &[]).await?;
+            db.new_job_for_event(event_id, OffsetDateTime::now_utc())
+                .await
         };
 
-        let comment_id: i64 = row.try_get("comment_id")?;
-        Ok(Some((comment_id as u64).into()))
-    }
+        let failed = new_job().await?;
+        db.job_started(failed).await?;
+        db.job_finished(failed, false).await?;
 
-    #[cfg(test)]
-    pub async fn jobs(&self) -> anyhow::Result<Vec<BenchJob>> {
-        let mut conn = self.sqlite.lock().await;
-        let jobs = sqlx::query_as(
-            r"
-            SELECT *
-            FROM jobs
-            ORDER BY created_utc",
-        )
-        .fetch_all(conn.deref_mut())
-        .await?;
+        let interrupted = new_job().await?;
+        db.job_started(interrupted).await?;
+        db.job_interrupted(interrupted).await?;
 
-        Ok(jobs)
-    }
+        let digest = db.digest(since, "main", "index").await?;
+        assert_eq!(digest.failed_jobs, 1);
 
-    #[cfg(test)]
-    pub async fn queued_events(&self) -> anyhow::Result<Vec<QueuedEvent>> {
-        let mut conn = self.sqlite.lock().await;
-        let jobs = sqlx::query_as(
-            r"
-            SELECT *
-            FROM event_queue
-            ORDER BY created_utc",
-        )
-        .fetch_all(conn.deref_mut())
-        .await?;
+        let outcomes = db.job_outcomes_by_day(since).await?;
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].succeeded, 0);
+        assert_eq!(outcomes[0].failed, 1);
 
-        Ok(jobs)
+        Ok(())
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
-    use crate::MIGRATOR;
-    use time::Duration;
 
     #[tokio::test]
-    async fn test_store_load_results_round_trips_and_orders_by_time() -> anyhow::Result<()> {
+    async fn test_annotations() -> anyhow::Result<()> {
         let db = empty_db().await;
 
-        db.store_run_results(vec![("foo".to_string(), ScenarioKind::Icount, 42.0)])
-            .await?;
-        db.store_run_results(vec![("foo".to_string(), ScenarioKind::Icount, 41.0)])
-            .await?;
-        db.store_run_results(vec![("foo".to_string(), ScenarioKind::Walltime, 43.0)])
-            .await?;
+        let start = OffsetDateTime::now_utc() - Duration::days(4);
+        for (i, commit) in ["a", "b", "c", "d"].into_iter().enumerate() {
+            let created_utc = start + Duration::days(i as i64);
+            db.store_run_results_at("main", commit, created_utc, Vec::new())
+                .await?;
+        }
 
-        let history = db
-            .result_history(OffsetDateTime::now_utc() - Duration::minutes(1))
+        // The commits of a window can be provided in any order
+        let target = AnnotationTarget::Window {
+            branch: "main".to_string(),
+            from_commit: "c".to_string(),
+            to_commit: "b".to_string(),
+        };
+        let window_annotation = db.store_annotation("ring upgrade", &target).await?.unwrap();
+        let window = window_annotation.window.as_ref().unwrap();
+        assert_eq!(
+            (window.from_commit.as_str(), window.to_commit.as_str()),
+            ("b", "c")
+        );
+
+        let unknown_commit = AnnotationTarget::Window {
+            branch: "main".to_string(),
+            from_commit: "b".to_string(),
+            to_commit: "unknown".to_string(),
+        };
+        assert_eq!(db.store_annotation("note", &unknown_commit).await?, None);
+        let unknown_comparison = AnnotationTarget::Comparison(Uuid::new_v4());
+        assert_eq!(
+            db.store_annotation("note", &unknown_comparison).await?,
+            None
+        );
+
+        let comparison_id = db
+            .store_comparison_result("a".to_string(), "b".to_string(), Default::default())
             .await?;
+        let comparison_annotation = db
+            .store_annotation("noisy host", &AnnotationTarget::Comparison(comparison_id))
+            .await?
+            .unwrap();
 
-        assert_eq!(history.len(), 3);
-        assert_eq!(history[0].result, 42.0);
-        assert_eq!(history[0].scenario_kind, ScenarioKind::Icount);
-        assert_eq!(history[1].result, 41.0);
-        assert_eq!(history[1].scenario_kind, ScenarioKind::Icount);
-        assert_eq!(history[2].result, 43.0);
-        assert_eq!(history[2].scenario_kind, ScenarioKind::Walltime);
+        // Only comparisons spanning the whole window get its annotation, in either direction
+        let annotations = db.comparison_annotations("a", "d").await?;
+        assert_eq!(annotations, vec![window_annotation.clone()]);
+        let annotations = db.comparison_annotations("d", "a").await?;
+        assert_eq!(annotations, vec![window_annotation.clone()]);
+        let annotations = db.comparison_annotations("b", "d").await?;
+        assert!(annotations.is_empty());
+        let annotations = db.comparison_annotations("a", "b").await?;
+        assert_eq!(annotations, vec![comparison_annotation.clone()]);
+
+        let annotations = db.branch_annotations("main", start).await?;
+        assert_eq!(annotations, vec![window_annotation.clone()]);
+        let since = start + Duration::days(3);
+        assert!(db.branch_annotations("main", since).await?.is_empty());
+
+        assert_eq!(db.annotations().await?.len(), 2);
+        assert!(db.delete_annotation(window_annotation.id).await?);
+        assert!(!db.delete_annotation(window_annotation.id).await?);
+        assert_eq!(db.annotations().await?, [comparison_annotation]);
 
         Ok(())
     }
 
     #[tokio::test]
-    async fn test_store_load_event_round_trips_and_orders_by_time() -> anyhow::Result<()> {
+    async fn test_retry_history() -> anyhow::Result<()> {
         let db = empty_db().await;
 
-        let id1 = db.enqueue_event("foo", &[1, 2, 3, 4]).await?;
-        let id2 = db.enqueue_event("bar", &[1, 2, 3, 4]).await?;
+        db.enqueue_event("push", &[1, 2, 3]).await?;
+        let event = db.next_queued_event().await?.unwrap();
+        let first_job = db.new_job_for_event(event.id, event.created_utc).await?;
+        db.delete_event(event.id).await?;
 
-        let event = db.next_queued_event().await?;
-        assert_eq!(id1, event.id);
-        assert_eq!(event.payload, [1, 2, 3, 4]);
+        let not_before = OffsetDateTime::now_utc() + Duration::minutes(5);
+        db.enqueue_retry(&event, first_job, not_before).await?;
+        let retry_event = db.next_queued_event().await?.unwrap();
+        assert_eq!(retry_event.payload, [1, 2, 3]);
+        assert!(retry_event.not_before_utc.unwrap() > event.created_utc);
+        let second_job = db
+            .new_job_for_event(retry_event.id, retry_event.created_utc)
+            .await?;
 
-        db.delete_event(id1).await?;
+        // The history is the same regardless of the attempt it is requested for
+        for job_id in [first_job, second_job] {
+            let history: Vec<_> = db
+                .retry_history(job_id)
+                .await?
+                .into_iter()
+                .map(|job| job.id)
+                .collect();
+            assert_eq!(history, [first_job, second_job]);
+        }
 
-        let event = db.next_queued_event().await?;
-        assert_eq!(id2, event.id);
+        Ok(())
+    }
 
+    #[tokio::test]
+    async fn test_trace_main_job() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        let event_id = db.enqueue_event("push", &[]).await?;
+        let event = db.next_queued_event().await?.unwrap();
         let job_id = db.new_job_for_event(event.id, event.created_utc).await?;
-        let job = db.job(job_id).await?;
-        assert_eq!(job.id, job_id);
-        assert_eq!(job.finished_utc, None);
+        let bench_run_id = db
+            .store_run_results(
+                "main",
+                "abc",
+                vec![("foo".to_string(), ScenarioKind::Icount, 1.0)],
+            )
+            .await?;
+        db.record_job_bench_run(job_id, bench_run_id).await?;
+        db.delete_event(event_id).await?;
 
-        db.job_finished(job_id, true).await?;
-        let job = db.job(job_id).await?;
+        for id in [job_id, event_id, bench_run_id] {
+            let trace = db.trace(id).await?.unwrap();
+            assert_eq!(trace.job.id, job_id);
+            assert_eq!(trace.event_id, Some(event_id));
+            assert_eq!(trace.event.as_deref(), Some("push"));
+            assert_eq!(trace.bench_run.unwrap().commit_sha.as_deref(), Some("abc"));
+            assert!(trace.comparison.is_none());
+            assert_eq!(trace.comment_id, None);
+        }
 
-        assert!(job.finished_utc.is_some());
-        assert_eq!(job.success, Some(true));
+        assert!(db.trace(Uuid::new_v4()).await?.is_none());
 
         Ok(())
     }
@@ -678,6 +4357,7 @@ mod test {
                     candidate_result: 42.0,
                     baseline_result: 42.5,
                     significance_threshold: 0.3,
+                    result_unit: scenario_kind.unit(),
                     cachegrind_diff: cachegrind_diff.clone(),
                 },
                 ScenarioDiff {
@@ -686,6 +4366,7 @@ mod test {
                     candidate_result: 100.0,
                     baseline_result: 104.0,
                     significance_threshold: 5.0,
+                    result_unit: scenario_kind.unit(),
                     cachegrind_diff,
                 },
             ]
@@ -699,16 +4380,22 @@ mod test {
         db.store_comparison_result(
             baseline_commit.to_string(),
             candidate_commit.to_string(),
-            ComparisonResult {
-                icount: ComparisonSubResult {
-                    scenarios_missing_in_baseline: Vec::new(),
-                    diffs: icount_diffs.clone(),
-                },
-                walltime: ComparisonSubResult {
-                    scenarios_missing_in_baseline: Vec::new(),
-                    diffs: walltime_diffs.clone(),
-                },
-            },
+            ComparisonResult::from_iter([
+                (
+                    ScenarioKind::Icount,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: icount_diffs.clone(),
+                    },
+                ),
+                (
+                    ScenarioKind::Walltime,
+                    ComparisonSubResult {
+                        scenarios_missing_in_baseline: Vec::new(),
+                        diffs: walltime_diffs.clone(),
+                    },
+                ),
+            ]),
         )
         .await?;
         let comparison = db
@@ -719,26 +4406,42 @@ mod test {
             bail!("no comparison results found for the provided commits");
         };
 
-        assert!(comparison.icount.scenarios_missing_in_baseline.is_empty());
-        assert!(comparison.walltime.scenarios_missing_in_baseline.is_empty());
-        assert_eq!(comparison.icount.diffs.len(), 2);
-        assert_eq!(comparison.walltime.diffs.len(), 2);
+        assert!(comparison
+            .metric(ScenarioKind::Icount)
+            .scenarios_missing_in_baseline
+            .is_empty());
+        assert!(comparison
+            .metric(ScenarioKind::Walltime)
+            .scenarios_missing_in_baseline
+            .is_empty());
+        assert_eq!(comparison.metric(ScenarioKind::Icount).diffs.len(), 2);
+        assert_eq!(comparison.metric(ScenarioKind::Walltime).diffs.len(), 2);
 
         comparison
-            .icount
+            .metric_mut(ScenarioKind::Icount)
             .diffs
             .sort_by(|d1, d2| d1.scenario_name.cmp(&d2.scenario_name));
         comparison
-            .walltime
+            .metric_mut(ScenarioKind::Walltime)
             .diffs
             .sort_by(|d1, d2| d1.scenario_name.cmp(&d2.scenario_name));
-        assert_eq!(comparison.icount.diffs[0], icount_diffs[1]);
-        assert_eq!(comparison.walltime.diffs[0], walltime_diffs[1]);
+        assert_eq!(
+            comparison.metric(ScenarioKind::Icount).diffs[0],
+            icount_diffs[1]
+        );
+        assert_eq!(
+            comparison.metric(ScenarioKind::Walltime).diffs[0],
+            walltime_diffs[1]
+        );
 
+        // The walltime diff of the scenario, which has no cachegrind diff, is skipped
         let cachegrind_diff = db
             .cachegrind_diff(baseline_commit, candidate_commit, "foo")
             .await?;
-        assert_eq!(cachegrind_diff, Some("fake cachegrind diff".to_string()));
+        assert_eq!(
+            cachegrind_diff.map(|stored| stored.diff),
+            Some("fake cachegrind diff".to_string())
+        );
 
         let cachegrind_diff = db
             .cachegrind_diff(baseline_commit, candidate_commit, "non-existent")
@@ -761,22 +4464,29 @@ mod test {
             candidate_result: 42.0,
             baseline_result: 42.5,
             significance_threshold: 0.3,
+            result_unit: ResultUnit::Instructions,
             cachegrind_diff: Some("fake cachegrind diff".to_string()),
         }];
 
         db.store_comparison_result(
             baseline_commit.to_string(),
             candidate_commit.to_string(),
-            ComparisonResult {
-                icount: ComparisonSubResult {
-                    diffs: diffs.clone(),
-                    scenarios_missing_in_baseline: vec!["bar".to_string()],
-                },
-                walltime: ComparisonSubResult {
-                    diffs: Vec::new(),
-                    scenarios_missing_in_baseline: vec!["baz".to_string()],
-                },
-            },
+            ComparisonResult::from_iter([
+                (
+                    ScenarioKind::Icount,
+                    ComparisonSubResult {
+                        diffs: diffs.clone(),
+                        scenarios_missing_in_baseline: vec!["bar".to_string()],
+                    },
+                ),
+                (
+                    ScenarioKind::Walltime,
+                    ComparisonSubResult {
+                        diffs: Vec::new(),
+                        scenarios_missing_in_baseline: vec!["baz".to_string()],
+                    },
+                ),
+            ]),
         )
         .await?;
         let comparison = db
@@ -788,16 +4498,20 @@ mod test {
         };
 
         assert_eq!(
-            comparison.icount.scenarios_missing_in_baseline,
+            comparison
+                .metric(ScenarioKind::Icount)
+                .scenarios_missing_in_baseline,
             ["bar".to_string()]
         );
         assert_eq!(
-            comparison.walltime.scenarios_missing_in_baseline,
+            comparison
+                .metric(ScenarioKind::Walltime)
+                .scenarios_missing_in_baseline,
             ["baz".to_string()]
         );
-        assert_eq!(comparison.icount.diffs.len(), 1);
-        assert_eq!(comparison.icount.diffs[0], diffs[0]);
-        assert!(comparison.walltime.diffs.is_empty());
+        assert_eq!(comparison.metric(ScenarioKind::Icount).diffs.len(), 1);
+        assert_eq!(comparison.metric(ScenarioKind::Icount).diffs[0], diffs[0]);
+        assert!(comparison.metric(ScenarioKind::Walltime).diffs.is_empty());
 
         Ok(())
     }
@@ -825,6 +4539,56 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_fork_approvals_are_per_commit() -> anyhow::Result<()> {
+        let db = empty_db().await;
+
+        db.store_fork_approval(42, "abc", "maintainer").await?;
+        db.store_fork_approval(42, "abc", "other-maintainer")
+            .await?;
+        assert!(db.is_fork_commit_approved(42, "abc").await?);
+        assert!(!db.is_fork_commit_approved(42, "def").await?);
+        assert!(!db.is_fork_commit_approved(43, "abc").await?);
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_export_anonymized_skips_sensitive_tables() -> anyhow::Result<()> {
+        // Attaching a file to an in-memory database doesn't work, so we use files instead
+        let tmp = tempfile::tempdir()?;
+        let db_path = tmp.path().join("db.sqlite3");
+        let mut sqlite = connect_creating_db(db_path.to_str().unwrap()).await?;
+        MIGRATOR.run(&mut sqlite).await?;
+        let db = Db::with_connection(Arc::new(Mutex::new(sqlite)));
+
+        db.store_run_results(
+            "main",
+            "commit",
+            vec![("foo".to_string(), ScenarioKind::Icount, 42.0)],
+        )
+        .await?;
+        db.store_result_comment_id(42, 100.into()).await?;
+        db.enqueue_event("push", &[1, 2, 3, 4]).await?;
+
+        let export_path = tmp.path().join("export.sqlite3");
+        let export_path = export_path.to_str().unwrap();
+        let mut export_conn = connect_creating_db(export_path).await?;
+        MIGRATOR.run(&mut export_conn).await?;
+        db.export_anonymized(export_path).await?;
+
+        let export = Db::with_connection(Arc::new(Mutex::new(export_conn)));
+        let history = export
+            .result_history("main", OffsetDateTime::now_utc() - Duration::minutes(1))
+            .await?;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].result, 42.0);
+        assert_eq!(export.result_comment_id(42).await?, None);
+        assert_eq!(export.queued_event_count().await?, 0);
+
+        Ok(())
+    }
+
     async fn empty_db() -> Db {
         let mut sqlite = SqliteConnection::connect("sqlite::memory:").await.unwrap();
         MIGRATOR.run(&mut sqlite).await.unwrap();
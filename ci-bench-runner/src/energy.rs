@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context};
+use tracing::{trace, warn};
+
+use crate::job::read_walltime_results;
+use crate::runner::{run_command, BenchSuiteConfig, CancellationToken, FailureClass, Log};
+
+/// The file of a powercap zone holding its energy counter, in microjoules
+static ENERGY_COUNTER_FILE: &str = "energy_uj";
+
+/// The file of a powercap zone holding the value at which its energy counter wraps around
+static MAX_ENERGY_RANGE_FILE: &str = "max_energy_range_uj";
+
+/// Measures the package energy consumed by each walltime scenario, storing the results (in joules)
+/// in the job's output dir
+///
+/// Each scenario is run on its own (through `filter_walltime_args`), with the energy counter of
+/// the configured RAPL zone read right before and after. The counter covers the whole package, so
+/// the measurements are only meaningful on an otherwise idle host.
+pub(crate) fn run_energy_benchmarks(
+    bench_suite: &BenchSuiteConfig,
+    rapl_zone: &Path,
+    bench_exe_path: &Path,
+    bench_path: &Path,
+    job_output_dir: &Path,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    if bench_suite.filter_walltime_args.is_empty() {
+        warn!("skipping energy benchmarks, because the walltime scenarios can't be filtered");
+        return Ok(());
+    }
+
+    // The energy counter is only readable by root unless granted otherwise (see the ansible
+    // setup), which shouldn't make the whole job fail
+    let counter_path = rapl_zone.join(ENERGY_COUNTER_FILE);
+    let max_energy_range = match read_counter(&rapl_zone.join(MAX_ENERGY_RANGE_FILE))
+        .and_then(|max_energy_range| read_counter(&counter_path).map(|_| max_energy_range))
+    {
+        Ok(max_energy_range) => max_energy_range,
+        Err(e) => {
+            warn!("skipping energy benchmarks, because the RAPL counters can't be read: {e:#}");
+            return Ok(());
+        }
+    };
+    let mut scenarios: Vec<_> = read_walltime_results(&bench_suite.walltimes_path(job_output_dir))
+        .context("failed to read walltimes from file")?
+        .into_keys()
+        .collect();
+    scenarios.sort();
+
+    let mut csv = String::new();
+    for scenario in scenarios {
+        let mut command = bench_suite.command("setarch");
+        command
+            .arg("-R")
+            .arg(bench_exe_path)
+            .args(&bench_suite.walltime_args)
+            .args(
+                bench_suite
+                    .filter_walltime_args
+                    .iter()
+                    .map(|arg| arg.replace("{filter}", &scenario)),
+            )
+            .current_dir(bench_path);
+
+        let before =
+            read_counter(&counter_path).map_err(|e| e.context(FailureClass::Infrastructure))?;
+        run_command(
+            command,
+            command_logs,
+            FailureClass::HarnessCrash,
+            cancellation,
+        )?;
+        let after =
+            read_counter(&counter_path).map_err(|e| e.context(FailureClass::Infrastructure))?;
+
+        let joules = energy_delta_uj(before, after, max_energy_range) as f64 / 1_000_000.0;
+        trace!("scenario `{scenario}` consumed {joules:.3} J");
+        writeln!(csv, "{scenario},{joules}").unwrap();
+    }
+
+    fs::write(bench_suite.energy_path(job_output_dir), csv)
+        .context("failed to write energy results to disk")
+}
+
+/// Reads a counter of a powercap zone
+fn read_counter(path: &Path) -> anyhow::Result<u64> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("unable to read RAPL counter at {}", path.display()))?;
+    contents
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid RAPL counter at {}: {contents}", path.display()))
+}
+
+/// Returns the energy consumed between two readings of a counter, taking into account that the
+/// counter wraps around after reaching `max_energy_range`
+fn energy_delta_uj(before: u64, after: u64, max_energy_range: u64) -> u64 {
+    if after >= before {
+        after - before
+    } else {
+        max_energy_range - before + after
+    }
+}
+
+/// Reads the (benchmark, energy in joules) pairs from previous CSV output
+///
+/// Returns no results if the file is missing, because energy is only measured when a RAPL zone
+/// is configured
+pub fn read_energy_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
+    if !path.is_file() {
+        return Ok(HashMap::new());
+    }
+
+    trace!(
+        path = path.display().to_string(),
+        "reading energy results from CSV file"
+    );
+    let csv = fs::read_to_string(path)
+        .with_context(|| format!("unable to read energy results at {}", path.display()))?;
+    let mut results = HashMap::new();
+    for line in csv.lines().filter(|line| !line.trim().is_empty()) {
+        let Some((scenario, joules)) = line.trim().split_once(',') else {
+            bail!("invalid energy results row: {line}");
+        };
+        let joules = joules
+            .parse()
+            .map_err(|_| anyhow!("invalid energy results row: {line}"))?;
+        results.insert(scenario.to_string(), joules);
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn energy_delta_handles_wraparound() {
+        assert_eq!(energy_delta_uj(1_000, 3_500, 10_000), 2_500);
+        assert_eq!(energy_delta_uj(9_000, 500, 10_000), 1_500);
+        assert_eq!(energy_delta_uj(42, 42, 10_000), 0);
+    }
+
+    #[test]
+    fn energy_results_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("energy.csv");
+        assert!(read_energy_results(&path).unwrap().is_empty());
+
+        fs::write(&path, "handshake_tls13,1.5\ntransfer,0.25\n").unwrap();
+        let results = read_energy_results(&path).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results["handshake_tls13"], 1.5);
+        assert_eq!(results["transfer"], 0.25);
+
+        fs::write(&path, "handshake_tls13;1.5\n").unwrap();
+        assert!(read_energy_results(&path).is_err());
+    }
+}
@@ -1,30 +1,48 @@
-use std::fmt::{Debug, Formatter};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Debug, Display, Formatter};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
+use anyhow::{anyhow, bail, Context};
 use axum::body::Bytes;
 use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
 use serde::{Deserialize, Serialize};
-use time::OffsetDateTime;
+use time::{OffsetDateTime, Time};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
 use tokio::task::JoinHandle;
-use tracing::{error, info, trace_span, Instrument};
+use tracing::{error, info, trace, trace_span, warn, Instrument};
 use uuid::Uuid;
 
 use crate::bencher_dev::BencherDev;
-use crate::db::{BenchJob, Db};
+use crate::costs::children_cpu_time;
+use crate::db::{BenchJob, Db, QueuePause, QueuedEvent, DEFAULT_EVENT_PRIORITY};
 use crate::github::CachedOctocrab;
-use crate::job::{bench_main, handle_issue_comment, handle_pr_review, handle_pr_update};
-use crate::runner::BenchRunner;
+use crate::gitlab;
+use crate::job::{
+    bench_main, handle_api_compare, handle_backport_original, handle_full_run,
+    handle_gitlab_merge_request, handle_gitlab_note, handle_issue_comment, handle_pr_review,
+    handle_pr_update, handle_scheduled_job, handle_self_test, is_urgent_command, ApiCompareRequest,
+    BackportOriginalRequest, FullRunRequest, SelfTestRequest,
+};
+use crate::metrics::Metrics;
+use crate::notifications::Notifier;
+use crate::runner::{BenchRunner, CancellationToken, FailureClass};
+use crate::scheduler::{ScheduledJobConfig, ScheduledJobRequest, ScheduledTask};
+use crate::signing::Signer;
 use crate::AppConfig;
 
-/// A queue that keeps track of GitHub events and handles them sequentially in the background
+/// A queue that keeps track of GitHub events and handles them in the background
+///
+/// Up to [`AppConfig::queue_workers`] events are handled at the same time, but events of the same
+/// PR (or pushes to the same branch) and events of the same resource class (see
+/// [`AppConfig::queue_resource_classes`]) are always handled one after the other
 #[derive(Clone)]
 pub struct EventQueue {
-    /// The job that is currently running (if any)
-    active_job_id: Arc<Mutex<Option<Uuid>>>,
+    /// The jobs that are currently running, oldest first
+    active_jobs: Arc<Mutex<Vec<ActiveJob>>>,
     /// A sender indicating that a new event has been enqueued
     event_enqueued_tx: UnboundedSender<()>,
     /// Keeps track of whether incoming events should be processed.
@@ -36,6 +54,20 @@ pub struct EventQueue {
     db: Db,
     /// Bencher.dev client
     bencher_dev: Option<BencherDev>,
+    /// Signs benchmark data, if signing is enabled
+    signer: Option<Arc<Signer>>,
+    /// Sends notifications about the benchmark results
+    notifier: Notifier,
+    /// Application metrics, to which queue starvation statistics are reported
+    metrics: Arc<Metrics>,
+    /// Set once the application starts shutting down or hands off the queue to another instance,
+    /// so no more jobs are started
+    shutting_down: Arc<AtomicBool>,
+    /// Identifies this instance of the application when coordinating with other instances (see
+    /// [`Self::hand_off`])
+    instance_id: Uuid,
+    /// Whether this instance currently owns the queue, i.e. is the one handling its events
+    owns_queue: Arc<AtomicBool>,
 }
 
 impl EventQueue {
@@ -49,17 +81,31 @@ impl EventQueue {
         db: Db,
         bench_runner: Arc<dyn BenchRunner>,
         octocrab: CachedOctocrab,
+        signer: Option<Arc<Signer>>,
+        notifier: Notifier,
+        metrics: Arc<Metrics>,
     ) -> anyhow::Result<Self> {
+        if let Some(window) = &config.nightly_window {
+            window.validate()?;
+        }
+
         let (worker_tx, event_enqueued_rx) = tokio::sync::mpsc::unbounded_channel();
 
         let queue = Self {
-            active_job_id: Arc::new(Mutex::new(None)),
+            active_jobs: Arc::new(Mutex::new(Vec::new())),
             event_enqueued_tx: worker_tx,
             process_events_toggler: ProcessEventsToggler::new()
                 .context("failed to initialize ProcessEventsToggler")?,
             db,
             bencher_dev: config.bencher.clone().map(BencherDev::new),
+            signer,
+            notifier,
+            metrics,
+            shutting_down: Arc::new(AtomicBool::new(false)),
+            instance_id: Uuid::new_v4(),
+            owns_queue: Arc::new(AtomicBool::new(false)),
         };
+        queue.spawn_queue_heartbeat();
 
         Ok(queue.start_and_supervise_queue_processing(
             event_enqueued_rx,
@@ -77,7 +123,6 @@ impl EventQueue {
         bench_runner: Arc<dyn BenchRunner>,
         octocrab: CachedOctocrab,
     ) -> Self {
-        let active_job_id = self.active_job_id.clone();
         let queue = self.clone();
         let event_enqueued_rx = Arc::new(tokio::sync::Mutex::new(event_enqueued_rx));
         let toggler = self.process_events_toggler.clone();
@@ -102,19 +147,18 @@ impl EventQueue {
                         // The task finished with an error
                         error!(
                             cause = e.to_string(),
-                            "job queue background task {active_job_id:?} errored, restarting in 1s"
+                            "job queue background task errored, restarting in 1s"
                         );
                     }
                     Err(e) => {
                         // The task panicked or was cancelled
                         error!(
                             cause = e.to_string(),
-                            "job queue background task {active_job_id:?} crashed, restarting in 1s"
+                            "job queue background task crashed, restarting in 1s"
                         );
                     }
                 }
 
-                *active_job_id.lock().unwrap() = None;
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
         });
@@ -124,8 +168,9 @@ impl EventQueue {
 
     /// Spawns a tokio task to process queued events in the background.
     ///
-    /// The task might unexpectedly crash. Therefore, it should be supervised and restarted upon
-    /// need.
+    /// The task dispatches events to jobs, each running in a task of its own. It might
+    /// unexpectedly crash. Therefore, it should be supervised and restarted upon need (jobs that
+    /// are already running are not affected).
     fn process_queued_events_in_background(
         &self,
         event_enqueued_rx: Arc<tokio::sync::Mutex<UnboundedReceiver<()>>>,
@@ -134,12 +179,18 @@ impl EventQueue {
         bench_runner: Arc<dyn BenchRunner>,
         octocrab: CachedOctocrab,
     ) -> JoinHandle<anyhow::Result<()>> {
-        let active_job_id = self.active_job_id.clone();
         let db = self.db.clone();
         let event_enqueued_tx = self.event_enqueued_tx.clone();
-        let bencher_dev = self.bencher_dev.clone();
+        let metrics = self.metrics.clone();
+        let queue = self.clone();
 
         tokio::spawn(async move {
+            // Another instance might still be handling events (e.g. during a blue/green deploy)
+            queue.acquire_queue().await?;
+
+            // Pauses requested through the admin API survive restarts
+            toggler.set_paused_by_admin(db.queue_pause().await?.is_some());
+
             // When starting up, we need to make sure we will process queued events that are already
             // in the db
             let events = db.queued_event_count().await?;
@@ -148,7 +199,8 @@ impl EventQueue {
             }
 
             loop {
-                // Wait until the next event arrives
+                // Wait until the next event arrives (or a job finishes, which might unblock
+                // events of the same PR or resource class)
                 if event_enqueued_rx.lock().await.recv().await.is_none() {
                     break;
                 }
@@ -156,91 +208,763 @@ impl EventQueue {
                 // Postpone event processing if requested
                 toggler.wait_for_processing_enabled().await;
 
-                // Get the next event from the database
-                let event = db.next_queued_event().await?;
+                // Start as many jobs as allowed (none if we are shutting down, since interrupted
+                // events must stay in the queue until the application restarts)
+                while !queue.shutting_down.load(Ordering::SeqCst)
+                    && queue.active_jobs.lock().unwrap().len() < config.queue_workers.max(1)
+                {
+                    // Keep the nightly window free of PR jobs (only pushes are handled if PR jobs
+                    // are deferred)
+                    let now = OffsetDateTime::now_utc();
+                    let deferred_until = config
+                        .nightly_window
+                        .as_ref()
+                        .and_then(|window| window.pr_jobs_deferred_until(now));
+                    let aging_interval = time::Duration::minutes(config.queue_aging_minutes.into());
 
-                let Some(github_event) = AllowedEvent::from_event_string(&event.event) else {
-                    error!(
-                        event = event.event,
-                        "found and discarded forbidden event in the queue"
+                    let queued = db.queued_events().await?;
+                    let next_retry_utc = queued
+                        .iter()
+                        .filter_map(|event| event.not_before_utc)
+                        .filter(|&not_before| not_before > now)
+                        .min();
+                    let busy = queue.busy_slots();
+                    let next = next_dispatchable_event(
+                        queued,
+                        &busy,
+                        &config.queue_resource_classes,
+                        deferred_until.is_some(),
+                        now,
+                        aging_interval,
                     );
+                    let Some(event) = next else {
+                        if let Some(next_retry_utc) = next_retry_utc {
+                            // Wake up once the earliest retry is done backing off
+                            let wait = Duration::try_from(next_retry_utc - now).unwrap_or_default();
+                            let event_enqueued_tx = event_enqueued_tx.clone();
+                            tokio::spawn(async move {
+                                tokio::time::sleep(wait).await;
+                                let _ = event_enqueued_tx.send(());
+                            });
+                        }
 
-                    db.delete_event(event.id).await?;
-                    continue;
-                };
+                        if let Some(deferred_until) = deferred_until {
+                            info!("PR jobs deferred until {deferred_until} (nightly window)");
 
-                if event.job_id.is_some() {
-                    // It looks like we crashed while handling this event. Let's remove it from the
-                    // queue to avoid an infinite crash loop.
-                    db.delete_event(event.id).await?;
-                }
+                            // Check again in a while, in case a deferred event is waiting
+                            let remaining = deferred_until - OffsetDateTime::now_utc();
+                            let wait = Duration::try_from(remaining)
+                                .unwrap_or_default()
+                                .min(DEFERRED_JOBS_POLL_INTERVAL);
+                            tokio::time::sleep(wait).await;
+                            event_enqueued_tx.send(())?;
+                        }
 
-                let job_id = db.new_job_for_event(event.id, event.created_utc).await?;
-                *active_job_id.lock().unwrap() = Some(job_id);
+                        break;
+                    };
 
-                let span = trace_span!(
-                    "handle event",
-                    job_id = job_id.to_string(),
-                    event = event.event
-                );
-                async {
-                    let job_output_dir = config.job_output_dir.join(job_id.to_string());
-                    let ctx = JobContext {
-                        event: &event.event,
-                        job_id,
-                        job_output_dir,
-                        octocrab: &octocrab,
-                        event_payload: &event.payload,
-                        config: &config,
-                        bench_runner: bench_runner.clone(),
-                        db: db.clone(),
-                        bencher_dev: bencher_dev.as_ref(),
+                    let Some(github_event) = AllowedEvent::from_queued_event_string(&event.event)
+                    else {
+                        error!(
+                            event = event.event,
+                            "found and dead-lettered forbidden event in the queue"
+                        );
+
+                        db.dead_letter_event(
+                            event.id,
+                            &format!("forbidden event: {}", event.event),
+                        )
+                        .await?;
+                        continue;
                     };
 
-                    let result = match github_event {
+                    if let Some(job_id) = event.job_id {
+                        // The application stopped while handling this event. If it was shut down
+                        // gracefully, the event is handled again by a new job. Otherwise, it looks
+                        // like we crashed, so let's take it out of the queue to avoid an infinite
+                        // crash loop.
+                        let interrupted =
+                            db.maybe_job(job_id).await?.is_some_and(|j| j.interrupted);
+                        if interrupted {
+                            let resumed_id = db.enqueue_retry(&event, job_id, now).await?;
+                            db.delete_event(event.id).await?;
+                            info!(
+                                resumed_event_id = resumed_id.to_string(),
+                                "resuming event {} after its job was interrupted by a shutdown",
+                                event.id
+                            );
+                        } else {
+                            db.job_crashed(job_id).await?;
+                            db.dead_letter_event(
+                                event.id,
+                                "the application stopped while handling the event",
+                            )
+                            .await?;
+                        }
+                        continue;
+                    }
+
+                    let wait_seconds = (now - event.created_utc).whole_seconds().max(0) as u64;
+                    let aged = event.effective_priority(now, aging_interval) > event.priority;
+                    metrics.record_queue_wait(&event.event, wait_seconds, aged);
+
+                    queue
+                        .start_job(
+                            event,
+                            github_event,
+                            config.clone(),
+                            bench_runner.clone(),
+                            octocrab.clone(),
+                        )
+                        .await?;
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Returns the events and the serialization slots taken by the running jobs
+    fn busy_slots(&self) -> BusySlots {
+        let active_jobs = self.active_jobs.lock().unwrap();
+        BusySlots {
+            event_ids: active_jobs.iter().map(|job| job.event_id).collect(),
+            serialization_keys: active_jobs
+                .iter()
+                .filter_map(|job| job.serialization_key.clone())
+                .collect(),
+            resource_classes: active_jobs
+                .iter()
+                .filter_map(|job| job.resource_class.clone())
+                .collect(),
+        }
+    }
+
+    /// Creates a job for the event and handles it in a task of its own, which wakes up the
+    /// dispatcher once the job is done
+    async fn start_job(
+        &self,
+        event: QueuedEvent,
+        github_event: AllowedEvent,
+        config: Arc<AppConfig>,
+        bench_runner: Arc<dyn BenchRunner>,
+        octocrab: CachedOctocrab,
+    ) -> anyhow::Result<()> {
+        let db = self.db.clone();
+        let job_id = db.new_job_for_event(event.id, event.created_utc).await?;
+        let (cancel_tx, cancel_rx) = oneshot::channel();
+        self.active_jobs.lock().unwrap().push(ActiveJob {
+            id: job_id,
+            event_id: event.id,
+            pr_number: event_pr_number(&event),
+            serialization_key: event_serialization_key(&event),
+            resource_class: config.queue_resource_classes.get(&event.event).cloned(),
+            pending_commit_sha: None,
+            cancel_tx: Some(cancel_tx),
+        });
+
+        let event_id = event.id;
+        let queue = self.clone();
+        let job = async move {
+            let cost_tracker = CostTracker::start(config.queue_workers <= 1);
+            let span = trace_span!(
+                "handle event",
+                job_id = job_id.to_string(),
+                event = event.event
+            );
+            async {
+                db.job_started(job_id).await?;
+                let job_output_dir = config.job_output_dir.join(job_id.to_string());
+                let cancellation = CancellationToken::default();
+                let ctx = JobContext {
+                    event: &event.event,
+                    job_id,
+                    job_output_dir,
+                    octocrab: &octocrab,
+                    event_payload: &event.payload,
+                    config: &config,
+                    bench_runner: bench_runner.clone(),
+                    db: db.clone(),
+                    bencher_dev: queue.bencher_dev.as_ref(),
+                    signer: queue.signer.as_deref(),
+                    notifier: &queue.notifier,
+                    event_queue: &queue,
+                    cancellation: cancellation.clone(),
+                };
+
+                let handler = async {
+                    match github_event {
                         AllowedEvent::IssueComment => handle_issue_comment(ctx).await,
                         AllowedEvent::PullRequest => handle_pr_update(ctx).await,
                         AllowedEvent::PullRequestReview => handle_pr_review(ctx).await,
                         AllowedEvent::Push => bench_main(ctx).await,
-                    };
+                        AllowedEvent::FullRun => handle_full_run(ctx).await,
+                        AllowedEvent::BackportOriginal => handle_backport_original(ctx).await,
+                        AllowedEvent::ApiCompare => handle_api_compare(ctx).await,
+                        AllowedEvent::SelfTest => handle_self_test(ctx).await,
+                        AllowedEvent::ScheduledJob => handle_scheduled_job(ctx).await,
+                        AllowedEvent::GitLabMergeRequest => handle_gitlab_merge_request(ctx).await,
+                        AllowedEvent::GitLabNote => handle_gitlab_note(ctx).await,
+                    }
+                };
 
-                    if let Err(e) = &result {
-                        error!(
-                            cause = e.to_string(),
-                            "error handling event: {github_event:?}"
-                        );
+                // Jobs exceeding their time limit get their processes killed, so they fail and
+                // report the failure instead of blocking the queue indefinitely (e.g. on a hung
+                // build)
+                let time_limit = config.job_timeout_minutes.map(|minutes| {
+                    let cancellation = cancellation.clone();
+                    tokio::spawn(async move {
+                        tokio::time::sleep(Duration::from_secs(minutes * 60)).await;
+                        warn!("job exceeded its time limit of {minutes} minutes, killing it");
+                        cancellation.time_out();
+                    })
+                });
+
+                // Dropping the handler aborts the job, but the processes it spawned need to be
+                // killed explicitly
+                let result = tokio::select! {
+                    result = handler => result,
+                    Ok(reason) = cancel_rx => {
+                        cancellation.cancel();
+                        if let Some(time_limit) = &time_limit {
+                            time_limit.abort();
+                        }
+                        match reason {
+                            StopReason::Cancelled => {
+                                info!("job cancelled");
+                                db.job_cancelled(job_id).await?;
+                                cost_tracker.record(&db, job_id, &event).await?;
+                                db.delete_event(event.id).await?;
+                            }
+                            StopReason::Shutdown => {
+                                // The event stays in the queue, linked to this job, so it gets
+                                // handled again after restarting
+                                info!("job interrupted by shutdown");
+                                db.job_interrupted(job_id).await?;
+                                cost_tracker.record(&db, job_id, &event).await?;
+                            }
+                        }
+                        return Ok(());
                     }
+                };
+                if let Some(time_limit) = &time_limit {
+                    time_limit.abort();
+                }
 
-                    db.job_finished(job_id, result.is_ok()).await?;
-                    db.delete_event(event.id).await?;
+                // Handlers report comparison failures themselves, so a timed out job might still
+                // have succeeded from their point of view
+                let result = match result {
+                    Ok(()) if cancellation.is_timed_out() => {
+                        Err(anyhow!("the job exceeded its time limit")
+                            .context(FailureClass::Timeout))
+                    }
+                    result => result,
+                };
 
-                    Ok::<_, anyhow::Error>(())
+                if let Err(e) = &result {
+                    error!(
+                        cause = e.to_string(),
+                        "error handling event: {github_event:?}"
+                    );
+                    db.record_job_failure(job_id, FailureClass::of(e)).await?;
                 }
-                .instrument(span)
-                .await?
+
+                db.job_finished(job_id, result.is_ok()).await?;
+                cost_tracker.record(&db, job_id, &event).await?;
+                if let Err(e) = &result {
+                    if e.is::<PoisonEvent>() {
+                        // Handling the event again would fail the same way
+                        db.dead_letter_event(event.id, &format!("{e:#}")).await?;
+                        return Ok(());
+                    }
+                }
+                if retry_if_allowed(&db, &config.retry_policy, &event, job_id).await? {
+                    queue.event_enqueued_tx.send(())?;
+                }
+                db.delete_event(event.id).await?;
+
+                Ok::<_, anyhow::Error>(())
             }
+            .instrument(span)
+            .await
+        };
 
-            Ok(())
-        })
+        // The job runs in a task of its own, so a crash doesn't leave it registered as active
+        let job = tokio::spawn(job);
+        let queue = self.clone();
+        tokio::spawn(async move {
+            let error = match job.await {
+                Ok(Ok(())) => None,
+                Ok(Err(e)) => {
+                    error!(cause = e.to_string(), "job {job_id} errored");
+                    Some(format!("the job errored: {e:#}"))
+                }
+                Err(e) => {
+                    error!(cause = e.to_string(), "job {job_id} crashed");
+                    Some(format!("the job crashed: {e}"))
+                }
+            };
+
+            // Events are removed from the queue by their job, so an event that is still queued
+            // would crash the job again
+            if let Some(error) = error {
+                if let Err(e) = queue.db.job_crashed(job_id).await {
+                    error!(
+                        cause = format!("{e:#}"),
+                        "unable to mark job {job_id} as failed"
+                    );
+                }
+                if let Err(e) = queue.db.dead_letter_event(event_id, &error).await {
+                    error!(
+                        cause = format!("{e:#}"),
+                        "unable to dead-letter event {event_id}"
+                    );
+                }
+            }
+
+            queue
+                .active_jobs
+                .lock()
+                .unwrap()
+                .retain(|active_job| active_job.id != job_id);
+            // Sending only fails if the dispatcher is gone, in which case there is nobody to wake
+            let _ = queue.event_enqueued_tx.send(());
+        });
+
+        Ok(())
     }
 
-    /// Enqueue an event.
+    /// Enqueue an event, with the priority resulting from its kind and payload (see
+    /// [`webhook_event_priority`]).
     ///
-    /// Returns `None` if the event kind is not allowed.
-    pub async fn enqueue(&self, event: &str, webhook_body: Bytes) -> anyhow::Result<Option<Uuid>> {
-        if AllowedEvent::from_event_string(event).is_none() {
-            return Ok(None);
+    /// Pushes to a PR supersede the queued events that would benchmark an older commit of the PR
+    /// (see [`Self::supersede_stale_pr_events`]).
+    ///
+    /// Deliveries (identified by GitHub's `X-GitHub-Delivery` header) that were already received
+    /// are skipped, since GitHub redelivers webhooks whose delivery timed out.
+    pub async fn enqueue(
+        &self,
+        config: &AppConfig,
+        event: &str,
+        delivery: Option<&str>,
+        webhook_body: Bytes,
+    ) -> anyhow::Result<EnqueuedWebhook> {
+        let Some(priority) = webhook_event_priority(config, event, &webhook_body) else {
+            return Ok(EnqueuedWebhook::Unsupported);
+        };
+
+        let event_id = match delivery {
+            Some(delivery) => {
+                let enqueued = self
+                    .db
+                    .enqueue_webhook_delivery(delivery, event, &webhook_body, priority)
+                    .await?;
+                match enqueued {
+                    Ok(event_id) => event_id,
+                    Err(previous) => return Ok(EnqueuedWebhook::Duplicate(previous)),
+                }
+            }
+            None => {
+                self.db
+                    .enqueue_event_with_priority(event, &webhook_body, priority)
+                    .await?
+            }
+        };
+        if let Some((pr_number, head_sha)) = pr_synchronize_head(event, &webhook_body) {
+            self.supersede_stale_pr_events(event_id, pr_number, &head_sha)
+                .await?;
+        }
+        self.event_enqueued_tx.send(())?;
+
+        Ok(EnqueuedWebhook::Enqueued(event_id))
+    }
+
+    /// Enqueues a GitLab webhook event, under its queued event name (see [`gitlab::queued_event`])
+    ///
+    /// Like pushes to a PR, pushes to a merge request supersede the queued (not yet started) events
+    /// of the merge request that benchmark an older commit
+    pub async fn enqueue_gitlab(&self, event: &str, webhook_body: Bytes) -> anyhow::Result<Uuid> {
+        let event_id = self
+            .db
+            .enqueue_event_with_priority(event, &webhook_body, DEFAULT_EVENT_PRIORITY)
+            .await?;
+
+        if let Some(merge_request) = merge_request_push(event, &webhook_body) {
+            for queued in self.db.queued_events().await? {
+                let is_stale = queued.id != event_id
+                    && queued.job_id.is_none()
+                    && queued.event == gitlab::MERGE_REQUEST_EVENT
+                    && event_merge_request(&queued) == Some(merge_request);
+                if is_stale {
+                    self.db.delete_event(queued.id).await?;
+                    info!(
+                        superseded_event_id = queued.id.to_string(),
+                        "event for merge request !{} superseded by a push", merge_request.1
+                    );
+                }
+            }
+        }
+        self.event_enqueued_tx.send(())?;
+
+        Ok(event_id)
+    }
+
+    /// Removes the queued (not yet started) events of the PR that would benchmark another commit
+    /// than its current head, which the provided event benchmarks instead
+    ///
+    /// Events that fetch the PR's head when handled (e.g. comments) are left alone. Removed events
+    /// are archived like handled ones, so they can still be replayed.
+    async fn supersede_stale_pr_events(
+        &self,
+        superseding_event_id: Uuid,
+        pr_number: u64,
+        head_sha: &str,
+    ) -> anyhow::Result<()> {
+        for event in self.db.queued_events().await? {
+            let is_stale = event.id != superseding_event_id
+                && event.job_id.is_none()
+                && event_pr_number(&event) == Some(pr_number)
+                && event_candidate_sha(&event).is_some_and(|sha| sha != head_sha);
+            if is_stale {
+                self.db.delete_event(event.id).await?;
+                info!(
+                    superseded_event_id = event.id.to_string(),
+                    "event for PR #{pr_number} superseded by push of {head_sha}"
+                );
+            }
         }
 
-        let event_id = self.db.enqueue_event(event, &webhook_body).await.unwrap();
+        Ok(())
+    }
+
+    /// Enqueues a full run of a PR's benchmarks, following up on a quick run.
+    ///
+    /// The run has a lower priority than events received through webhooks, so it doesn't delay
+    /// them.
+    pub async fn enqueue_full_run(&self, request: &FullRunRequest) -> anyhow::Result<Uuid> {
+        let payload = serde_json::to_vec(request)?;
+        let event_id = self
+            .db
+            .enqueue_event_with_priority(FULL_RUN_EVENT, &payload, FULL_RUN_EVENT_PRIORITY)
+            .await?;
         self.event_enqueued_tx.send(())?;
 
-        Ok(Some(event_id))
+        Ok(event_id)
     }
 
-    /// Returns the active job's id, if there is an active job
-    pub fn active_job_id(&self) -> Option<Uuid> {
-        *self.active_job_id.lock().unwrap()
+    /// Enqueues the benchmarking of the change backported by a PR, to complete its backport check
+    ///
+    /// It has the lowest priority, since the backport's own results have already been reported
+    pub async fn enqueue_backport_original(
+        &self,
+        request: &BackportOriginalRequest,
+    ) -> anyhow::Result<Uuid> {
+        let payload = serde_json::to_vec(request)?;
+        let event_id = self
+            .db
+            .enqueue_event_with_priority(
+                BACKPORT_ORIGINAL_EVENT,
+                &payload,
+                BACKPORT_ORIGINAL_EVENT_PRIORITY,
+            )
+            .await?;
+        self.event_enqueued_tx.send(())?;
+
+        Ok(event_id)
+    }
+
+    /// Enqueues a comparison requested through the HTTP API, which is handled like events received
+    /// through webhooks
+    pub async fn enqueue_api_compare(&self, request: &ApiCompareRequest) -> anyhow::Result<Uuid> {
+        let payload = serde_json::to_vec(request)?;
+        let event_id = self.db.enqueue_event(API_COMPARE_EVENT, &payload).await?;
+        self.event_enqueued_tx.send(())?;
+
+        Ok(event_id)
+    }
+
+    /// Enqueues a self-test requested through the admin API
+    ///
+    /// Self-tests have a higher priority than regular events received through webhooks, so a
+    /// post-deploy check isn't stuck behind a long queue
+    pub async fn enqueue_self_test(&self, request: &SelfTestRequest) -> anyhow::Result<Uuid> {
+        let payload = serde_json::to_vec(request)?;
+        let event_id = self
+            .db
+            .enqueue_event_with_priority(SELF_TEST_EVENT, &payload, SELF_TEST_EVENT_PRIORITY)
+            .await?;
+        self.event_enqueued_tx.send(())?;
+
+        Ok(event_id)
+    }
+
+    /// Enqueues a job whose schedule is due
+    ///
+    /// Scheduled jobs are background work, so they have the same priority as pushes to tracked
+    /// branches
+    pub async fn enqueue_scheduled_job(
+        &self,
+        request: &ScheduledJobRequest,
+    ) -> anyhow::Result<Uuid> {
+        let payload = serde_json::to_vec(request)?;
+        let event_id = self
+            .db
+            .enqueue_event_with_priority(SCHEDULED_JOB_EVENT, &payload, PUSH_EVENT_PRIORITY)
+            .await?;
+        self.event_enqueued_tx.send(())?;
+
+        Ok(event_id)
+    }
+
+    /// Enqueues a copy of an event that was already handled (or cancelled), so operators can
+    /// recover from transient failures without redelivering the event from GitHub
+    pub async fn replay_event(&self, event_id: Uuid) -> anyhow::Result<ReplayOutcome> {
+        let queued = self.db.queued_events().await?;
+        if queued.iter().any(|event| event.id == event_id) {
+            return Ok(ReplayOutcome::StillQueued);
+        }
+
+        let Some(event) = self.db.handled_event(event_id).await? else {
+            return Ok(ReplayOutcome::NotFound);
+        };
+        let replay_id = self
+            .db
+            .enqueue_event_with_priority(&event.event, &event.payload, event.priority)
+            .await?;
+        self.event_enqueued_tx.send(())?;
+
+        info!(
+            replayed_event_id = event_id.to_string(),
+            "replaying event as {replay_id}"
+        );
+        Ok(ReplayOutcome::Enqueued(replay_id))
+    }
+
+    /// Enqueues a dead event again (e.g. after deploying a fix for the bug that made its job crash),
+    /// returning the id under which it was enqueued, or `None` if the dead event doesn't exist
+    pub async fn replay_dead_event(&self, dead_event_id: Uuid) -> anyhow::Result<Option<Uuid>> {
+        let Some(replay_id) = self.db.requeue_dead_event(dead_event_id).await? else {
+            return Ok(None);
+        };
+        self.event_enqueued_tx.send(())?;
+
+        info!(
+            dead_event_id = dead_event_id.to_string(),
+            "replaying dead event as {replay_id}"
+        );
+        Ok(Some(replay_id))
+    }
+
+    /// Returns the ids of the jobs that are currently running, oldest first
+    pub fn active_job_ids(&self) -> Vec<Uuid> {
+        self.active_jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| job.id)
+            .collect()
+    }
+
+    /// Cancels the queued and running jobs of the PR
+    ///
+    /// Queued events are removed right away, while the running job (if any) is aborted in the
+    /// background, along with the processes it spawned. Jobs of the same PR never run at the same
+    /// time, so there is at most one running job to cancel
+    pub async fn cancel_pr_jobs(&self, pr_number: u64) -> anyhow::Result<CancelledJobs> {
+        let mut cancelled = CancelledJobs::default();
+        for event in self.db.queued_events().await? {
+            if event.job_id.is_none() && event_pr_number(&event) == Some(pr_number) {
+                self.db.delete_event(event.id).await?;
+                cancelled.queued_events += 1;
+            }
+        }
+
+        let mut active_jobs = self.active_jobs.lock().unwrap();
+        for job in active_jobs.iter_mut() {
+            if job.pr_number == Some(pr_number) {
+                // Sending fails if the job already finished
+                let cancel_tx = job.cancel_tx.take();
+                if cancel_tx.is_some_and(|cancel_tx| cancel_tx.send(StopReason::Cancelled).is_ok())
+                {
+                    cancelled.running_job_id = Some(job.id);
+                    cancelled.pending_commit_sha = job.pending_commit_sha.clone();
+                }
+            }
+        }
+
+        Ok(cancelled)
+    }
+
+    /// Records the commit a running job set pending commit statuses on, so they can be updated if
+    /// the job gets cancelled
+    pub fn set_pending_commit(&self, job_id: Uuid, commit_sha: &str) {
+        let mut active_jobs = self.active_jobs.lock().unwrap();
+        if let Some(job) = active_jobs.iter_mut().find(|job| job.id == job_id) {
+            job.pending_commit_sha = Some(commit_sha.to_string());
+        }
+    }
+
+    /// Moves the PR's queued events to the front of the queue, returning how many were moved
+    ///
+    /// Events that are already being handled are left alone
+    pub async fn prioritize_pr_events(&self, pr_number: u64) -> anyhow::Result<usize> {
+        let mut prioritized = 0;
+        for event in self.db.queued_events().await? {
+            if event.job_id.is_none()
+                && event.priority < PRIORITIZED_EVENT_PRIORITY
+                && event_pr_number(&event) == Some(pr_number)
+            {
+                self.db
+                    .set_event_priority(event.id, PRIORITIZED_EVENT_PRIORITY)
+                    .await?;
+                prioritized += 1;
+            }
+        }
+
+        Ok(prioritized)
+    }
+
+    /// Waits until this instance owns the queue, which happens right away unless another instance
+    /// owns it and is still alive
+    ///
+    /// Returns without acquiring the queue if the instance has already stopped starting jobs
+    async fn acquire_queue(&self) -> anyhow::Result<()> {
+        let mut waiting = false;
+        while !self.shutting_down.load(Ordering::SeqCst) {
+            let stale_before = OffsetDateTime::now_utc() - QUEUE_OWNER_STALE_AFTER;
+            if self
+                .db
+                .try_acquire_queue(self.instance_id, stale_before)
+                .await?
+            {
+                self.owns_queue.store(true, Ordering::SeqCst);
+                info!(instance_id = %self.instance_id, "acquired the event queue");
+                return Ok(());
+            }
+
+            if !waiting {
+                info!("waiting for another instance to hand off the event queue");
+                waiting = true;
+            }
+            tokio::time::sleep(QUEUE_OWNER_POLL_INTERVAL).await;
+        }
+
+        Ok(())
+    }
+
+    /// Spawns a background task that regularly signals that this instance still owns the queue, so
+    /// other instances don't take it over
+    ///
+    /// Each heartbeat also wakes up the dispatcher, so events enqueued by other instances (e.g. the
+    /// old instance of a blue/green deploy, which still receives webhooks) are picked up too
+    fn spawn_queue_heartbeat(&self) {
+        let queue = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(QUEUE_HEARTBEAT_INTERVAL).await;
+                if !queue.owns_queue.load(Ordering::SeqCst) {
+                    continue;
+                }
+
+                match queue.db.queue_heartbeat(queue.instance_id).await {
+                    Ok(true) => {
+                        // Sending only fails if the dispatcher is gone, in which case there is
+                        // nobody to wake
+                        let _ = queue.event_enqueued_tx.send(());
+                    }
+                    Ok(false) => {
+                        error!(
+                            "another instance took over the event queue, no longer starting jobs"
+                        );
+                        queue.owns_queue.store(false, Ordering::SeqCst);
+                        queue.shutting_down.store(true, Ordering::SeqCst);
+                    }
+                    Err(e) => error!(cause = format!("{e:#}"), "unable to record queue heartbeat"),
+                }
+            }
+        });
+    }
+
+    /// Returns whether this instance currently owns the queue (see [`Self::hand_off`])
+    pub fn owns_queue(&self) -> bool {
+        self.owns_queue.load(Ordering::SeqCst)
+    }
+
+    /// Hands the queue off to another instance of the application, which takes it over as soon as
+    /// it is released (see [`Self::acquire_queue`])
+    ///
+    /// No more jobs are started. Depending on the mode, running jobs are either left to finish or
+    /// interrupted (see [`Self::shutdown`]), in which case the new instance handles their events
+    /// again. The instance keeps accepting webhooks, whose events are handled by the new instance.
+    pub async fn hand_off(&self, mode: HandoffMode, timeout: Duration) -> anyhow::Result<()> {
+        info!(?mode, "handing off the event queue");
+        match mode {
+            HandoffMode::Finish => {
+                self.shutting_down.store(true, Ordering::SeqCst);
+                while !self.active_jobs.lock().unwrap().is_empty() {
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+            HandoffMode::Checkpoint => {
+                if !self.shutdown(timeout).await {
+                    bail!("the running jobs did not stop in time, so the queue was not released");
+                }
+            }
+        }
+
+        // No-op if the queue was already released when shutting down
+        self.release_queue().await
+    }
+
+    /// Releases the queue, if this instance owns it, so another instance can take it over
+    async fn release_queue(&self) -> anyhow::Result<()> {
+        if self.owns_queue.swap(false, Ordering::SeqCst) {
+            self.db.release_queue(self.instance_id).await?;
+            info!(instance_id = %self.instance_id, "released the event queue");
+        }
+
+        Ok(())
+    }
+
+    /// Stops starting new jobs and interrupts the running ones, waiting (up to the provided timeout)
+    /// until they have recorded their interruption, and returns whether they did
+    ///
+    /// The queue is released once the jobs have stopped, so another instance can take it over.
+    ///
+    /// Interrupted jobs leave their events in the queue, so they are handled again by new jobs
+    /// once the application restarts, instead of leaving their PRs with pending commit statuses
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.shutting_down.store(true, Ordering::SeqCst);
+
+        let mut interrupted = 0;
+        for job in self.active_jobs.lock().unwrap().iter_mut() {
+            // Sending fails if the job already finished
+            let cancel_tx = job.cancel_tx.take();
+            if cancel_tx.is_some_and(|cancel_tx| cancel_tx.send(StopReason::Shutdown).is_ok()) {
+                interrupted += 1;
+            }
+        }
+        info!("shutting down, {interrupted} running job(s) interrupted");
+
+        let start = Instant::now();
+        while !self.active_jobs.lock().unwrap().is_empty() {
+            if start.elapsed() > timeout {
+                error!("timed out waiting for interrupted jobs to stop");
+                return false;
+            }
+
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        // Another instance can take over right away, instead of waiting for our heartbeats to stop
+        if let Err(e) = self.release_queue().await {
+            error!(
+                cause = format!("{e:#}"),
+                "unable to release the event queue"
+            );
+        }
+
+        true
     }
 
     /// Returns whether event processing is currently enabled
@@ -248,28 +972,657 @@ impl EventQueue {
         self.process_events_toggler.processing_enabled()
     }
 
+    /// Stops starting jobs until [`Self::resume`] is called, even across restarts
+    ///
+    /// Webhooks are still accepted and queued in the meantime. Running jobs are left to finish.
+    pub async fn pause(&self, reason: Option<&str>) -> anyhow::Result<QueuePause> {
+        let pause = self.db.pause_queue(reason).await?;
+        self.process_events_toggler.set_paused_by_admin(true);
+        info!(?reason, "event processing paused through the admin API");
+        Ok(pause)
+    }
+
+    /// Lifts a pause requested through [`Self::pause`], after which queued events are handled in
+    /// the usual order
+    ///
+    /// Processing stays disabled while the `pause` file exists.
+    pub async fn resume(&self) -> anyhow::Result<()> {
+        self.db.resume_queue().await?;
+        self.process_events_toggler.set_paused_by_admin(false);
+        info!("event processing resumed through the admin API");
+        Ok(())
+    }
+
     /// Returns a user-facing view of the given job id, or `None` if the job could not be found
     pub async fn job_view(&self, job_id: Uuid) -> anyhow::Result<Option<JobView>> {
         let Some(job) = self.db.maybe_job(job_id).await? else {
             return Ok(None);
         };
 
-        let active = *self.active_job_id.lock().unwrap() == Some(job_id);
-        Ok(Some(JobView::from_job(job, active)))
+        let retry_history = self
+            .db
+            .retry_history(job_id)
+            .await?
+            .into_iter()
+            .map(JobAttemptView::from_job)
+            .collect();
+        Ok(Some(JobView::from_job(job, retry_history)))
     }
+
+    /// Returns a page of user-facing views of the jobs, newest first, optionally only including
+    /// those with the provided status
+    ///
+    /// Pages are numbered from 1
+    pub async fn job_views(
+        &self,
+        status: Option<JobStatus>,
+        page: usize,
+        per_page: usize,
+    ) -> anyhow::Result<JobsPage> {
+        let mut jobs: Vec<_> = self
+            .db
+            .jobs()
+            .await?
+            .into_iter()
+            .map(|job| JobView::from_job(job, Vec::new()))
+            .filter(|job| status.is_none() || status.as_ref() == Some(&job.status))
+            .collect();
+        jobs.reverse();
+
+        let total = jobs.len();
+        let jobs = jobs
+            .into_iter()
+            .skip(page.saturating_sub(1) * per_page)
+            .take(per_page)
+            .collect();
+
+        Ok(JobsPage {
+            jobs,
+            page,
+            per_page,
+            total,
+        })
+    }
+}
+
+/// A job that is currently being handled by the queue
+struct ActiveJob {
+    id: Uuid,
+    /// The event the job is handling
+    event_id: Uuid,
+    /// The PR the job belongs to, if any
+    pr_number: Option<u64>,
+    /// See [`event_serialization_key`]
+    serialization_key: Option<String>,
+    /// The resource class of the job's event, if any
+    resource_class: Option<String>,
+    /// The commit the job set pending commit statuses on, if any
+    pending_commit_sha: Option<String>,
+    /// Aborts the job when used (it is taken once the job has been cancelled or interrupted)
+    cancel_tx: Option<oneshot::Sender<StopReason>>,
+}
+
+/// Why a running job is being stopped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum StopReason {
+    /// The job was cancelled by a user, so its event is removed from the queue
+    Cancelled,
+    /// The application is shutting down, so its event is handled again after restarting
+    Shutdown,
 }
 
-/// Allowed GitHub events that we process
+/// The phases of a job, recorded as it goes through them so it's clear how far an interrupted job
+/// got
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobPhase {
+    /// Checking out, building and running the benchmarks
+    Benchmarking,
+    /// Storing the results in the database (and in bencher.dev, if configured)
+    StoringResults,
+    /// Posting the results (e.g. to a PR)
+    Reporting,
+}
+
+impl JobPhase {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobPhase::Benchmarking => "benchmarking",
+            JobPhase::StoringResults => "storing_results",
+            JobPhase::Reporting => "reporting",
+        }
+    }
+}
+
+/// What happens to the running jobs when handing off the queue (see [`EventQueue::hand_off`])
+#[derive(Debug, Copy, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandoffMode {
+    /// The jobs are interrupted, and handled again by the new instance
+    #[default]
+    Checkpoint,
+    /// The jobs are left to finish before releasing the queue
+    Finish,
+}
+
+/// The outcome of [`EventQueue::enqueue`]
+#[derive(Debug, PartialEq)]
+pub enum EnqueuedWebhook {
+    /// The event was enqueued, under the provided id
+    Enqueued(Uuid),
+    /// The delivery was already received, and enqueued under the provided id
+    Duplicate(Uuid),
+    /// The event kind is not allowed
+    Unsupported,
+}
+
+/// The outcome of [`EventQueue::replay_event`]
+#[derive(Debug, PartialEq)]
+pub enum ReplayOutcome {
+    /// The event was enqueued again, under the provided id
+    Enqueued(Uuid),
+    /// The event hasn't been handled yet, so there is nothing to replay
+    StillQueued,
+    /// The event is unknown, or is no longer archived
+    NotFound,
+}
+
+/// Marks errors caused by the event itself (e.g. an invalid payload), which would fail again if the
+/// event were handled again
+///
+/// Events whose job fails with this error are dead-lettered instead of retried (see
+/// [`Db::dead_letter_event`])
+#[derive(Debug, Clone, Copy)]
+pub struct PoisonEvent;
+
+impl Display for PoisonEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the event can't be handled")
+    }
+}
+
+/// The jobs that were cancelled through [`EventQueue::cancel_pr_jobs`]
+#[derive(Debug, Default)]
+pub struct CancelledJobs {
+    /// The running job that was aborted, if any
+    pub running_job_id: Option<Uuid>,
+    /// The commit the running job set pending commit statuses on, if any
+    pub pending_commit_sha: Option<String>,
+    /// How many queued events were removed before being handled
+    pub queued_events: usize,
+}
+
+impl CancelledJobs {
+    pub fn is_empty(&self) -> bool {
+        self.running_job_id.is_none() && self.queued_events == 0
+    }
+}
+
+/// The slots taken by the running jobs, which queued events have to wait for
+#[derive(Debug, Default)]
+struct BusySlots {
+    event_ids: HashSet<Uuid>,
+    serialization_keys: HashSet<String>,
+    resource_classes: HashSet<String>,
+}
+
+/// Returns the queued event that should be handled next, if any, skipping events that have to wait
+/// for a running job (because they are of the same PR, branch or resource class)
+///
+/// Events are considered in order of effective priority (see [`QueuedEvent::effective_priority`]),
+/// so low-priority events can't starve. Only pushes and scheduled jobs are considered if PR jobs are
+/// deferred.
+fn next_dispatchable_event(
+    queued: Vec<QueuedEvent>,
+    busy: &BusySlots,
+    resource_classes: &BTreeMap<String, String>,
+    pr_jobs_deferred: bool,
+    now: OffsetDateTime,
+    aging_interval: time::Duration,
+) -> Option<QueuedEvent> {
+    queued
+        .into_iter()
+        .filter(|event| !busy.event_ids.contains(&event.id))
+        .filter(|event| {
+            event
+                .not_before_utc
+                .is_none_or(|not_before| not_before <= now)
+        })
+        .filter(|event| {
+            !pr_jobs_deferred || event.event == "push" || event.event == SCHEDULED_JOB_EVENT
+        })
+        .filter(|event| {
+            event_serialization_key(event).is_none_or(|key| !busy.serialization_keys.contains(&key))
+        })
+        .filter(|event| {
+            resource_classes
+                .get(&event.event)
+                .is_none_or(|class| !busy.resource_classes.contains(class))
+        })
+        .min_by_key(|event| {
+            (
+                std::cmp::Reverse(event.effective_priority(now, aging_interval)),
+                event.created_utc,
+            )
+        })
+}
+
+/// Returns the key of the events that must be handled one after the other, if any: events of the
+/// same PR (or GitLab merge request) share a key, and so do pushes to the same branch and runs of
+/// the same scheduled job
+fn event_serialization_key(event: &QueuedEvent) -> Option<String> {
+    if let Some(pr_number) = event_pr_number(event) {
+        return Some(format!("pr:{pr_number}"));
+    }
+
+    if let Some((project_id, iid)) = event_merge_request(event) {
+        return Some(format!("gitlab:{project_id}!{iid}"));
+    }
+
+    if event.event == SCHEDULED_JOB_EVENT {
+        let payload: serde_json::Value = serde_json::from_slice(&event.payload).ok()?;
+        return payload["name"]
+            .as_str()
+            .map(|name| format!("scheduled:{name}"));
+    }
+
+    if event.event == "push" {
+        let payload: serde_json::Value = serde_json::from_slice(&event.payload).ok()?;
+        return payload["ref"]
+            .as_str()
+            .map(|git_ref| format!("push:{git_ref}"));
+    }
+
+    None
+}
+
+/// Returns the number of the PR the event belongs to, if any
+fn event_pr_number(event: &QueuedEvent) -> Option<u64> {
+    let payload: serde_json::Value = serde_json::from_slice(&event.payload).ok()?;
+    let pr_number = match event.event.as_str() {
+        "issue_comment" => &payload["issue"]["number"],
+        "pull_request" | "pull_request_review" => &payload["pull_request"]["number"],
+        FULL_RUN_EVENT => &payload["pr_number"],
+        _ => return None,
+    };
+
+    pr_number.as_u64()
+}
+
+/// Returns the project id and the iid of the GitLab merge request the event belongs to, if any
+fn event_merge_request(event: &QueuedEvent) -> Option<(u64, u64)> {
+    let payload: serde_json::Value = serde_json::from_slice(&event.payload).ok()?;
+    let iid = match event.event.as_str() {
+        gitlab::MERGE_REQUEST_EVENT => &payload["object_attributes"]["iid"],
+        gitlab::NOTE_EVENT => &payload["merge_request"]["iid"],
+        _ => return None,
+    };
+
+    Some((payload["project"]["id"].as_u64()?, iid.as_u64()?))
+}
+
+/// Returns the commit of the PR that the event benchmarks, for events that carry it in their payload
+///
+/// Events that fetch the PR's head when handled (e.g. comments) return `None`
+fn event_candidate_sha(event: &QueuedEvent) -> Option<String> {
+    let payload: serde_json::Value = serde_json::from_slice(&event.payload).ok()?;
+    let sha = match event.event.as_str() {
+        "pull_request" | "pull_request_review" => &payload["pull_request"]["head"]["sha"],
+        FULL_RUN_EVENT => &payload["candidate_commit"],
+        _ => return None,
+    };
+
+    sha.as_str().map(|sha| sha.to_string())
+}
+
+/// Returns the PR number and new head commit of a webhook event signaling a push to a PR (i.e. a
+/// `pull_request` event with the `synchronize` action)
+fn pr_synchronize_head(event: &str, body: &[u8]) -> Option<(u64, String)> {
+    if event != "pull_request" {
+        return None;
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(body).ok()?;
+    if payload["action"] != "synchronize" {
+        return None;
+    }
+
+    let pr_number = payload["pull_request"]["number"].as_u64()?;
+    let head_sha = payload["pull_request"]["head"]["sha"].as_str()?;
+    Some((pr_number, head_sha.to_string()))
+}
+
+/// Returns the project id and the iid of the merge request of a GitLab event signaling a push to a
+/// merge request (i.e. an `update` merge request event with the previous head in `oldrev`)
+fn merge_request_push(event: &str, body: &[u8]) -> Option<(u64, u64)> {
+    if event != gitlab::MERGE_REQUEST_EVENT {
+        return None;
+    }
+
+    let payload: serde_json::Value = serde_json::from_slice(body).ok()?;
+    let attributes = &payload["object_attributes"];
+    if attributes["action"] != "update" || !attributes["oldrev"].is_string() {
+        return None;
+    }
+
+    Some((
+        payload["project"]["id"].as_u64()?,
+        attributes["iid"].as_u64()?,
+    ))
+}
+
+/// Returns the login of the GitHub user whose action triggered the event, if known
+///
+/// Full runs (and the benchmarking of backported changes) are attributed to the user who requested
+/// the run they follow up on, and comparisons requested through the HTTP API to the API client
+pub fn event_requester(event: &str, payload: &[u8]) -> Option<String> {
+    let payload: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    let requester = match event {
+        FULL_RUN_EVENT => &payload["requester"],
+        BACKPORT_ORIGINAL_EVENT => &payload["backport"]["requester"],
+        API_COMPARE_EVENT => &payload["client"],
+        _ => &payload["sender"]["login"],
+    };
+
+    requester.as_str().map(|requester| requester.to_string())
+}
+
+/// Measures the resources consumed by a job, from the moment it starts
+struct CostTracker {
+    started: Instant,
+    /// The CPU time of the server's children when the job started, if it is tracked
+    children_cpu_time: Option<Duration>,
+}
+
+impl CostTracker {
+    /// Starts measuring, including the CPU time only if requested
+    ///
+    /// The CPU time can't be told apart between jobs running at the same time (see
+    /// [`children_cpu_time`]), so it should only be tracked when jobs run one at a time
+    fn start(track_cpu_time: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            children_cpu_time: track_cpu_time.then(children_cpu_time),
+        }
+    }
+
+    /// Records the resources consumed by the job so far, attributing them to the event's
+    /// requester and PR
+    async fn record(&self, db: &Db, job_id: Uuid, event: &QueuedEvent) -> anyhow::Result<()> {
+        let cpu_time = self
+            .children_cpu_time
+            .map(|before| children_cpu_time().saturating_sub(before));
+        db.record_job_cost(
+            job_id,
+            event_requester(&event.event, &event.payload).as_deref(),
+            event_pr_number(event),
+            cpu_time.map(|cpu_time| cpu_time.as_secs_f64()),
+            self.started.elapsed().as_secs_f64(),
+        )
+        .await
+    }
+}
+
+/// Enqueues the job's event again if the job failed, as long as the retry policy allows retrying
+/// failures of its class
+///
+/// Returns whether the event was enqueued again
+async fn retry_if_allowed(
+    db: &Db,
+    policy: &RetryPolicyConfig,
+    event: &QueuedEvent,
+    job_id: Uuid,
+) -> anyhow::Result<bool> {
+    let job = db.job(job_id).await?;
+    let Some(class) = job.failure_class.as_deref().and_then(FailureClass::parse) else {
+        return Ok(false);
+    };
+
+    // Attempts interrupted by a shutdown didn't fail, so they don't count against the policy
+    let attempts = db.retry_history(job_id).await?;
+    let retries = attempts
+        .iter()
+        .filter(|attempt| !attempt.interrupted)
+        .count()
+        - 1;
+    let max_retries = policy.max_retries(class);
+    if retries >= max_retries as usize {
+        info!("not retrying job after {class} ({retries} of {max_retries} retries used)");
+        return Ok(false);
+    }
+
+    let backoff = policy.backoff(retries as u32);
+    let not_before = OffsetDateTime::now_utc() + backoff;
+    let retry_event_id = db.enqueue_retry(event, job_id, not_before).await?;
+    info!(
+        retry_event_id = retry_event_id.to_string(),
+        "retrying job after {class} in {} s (retry {} of {max_retries})",
+        backoff.whole_seconds(),
+        retries + 1
+    );
+
+    Ok(true)
+}
+
+/// How many times a failed job is retried, depending on the class of its failure
+///
+/// Failures caused by the code under test (e.g. build failures) are deterministic, so retrying them
+/// would only delay other jobs. Retries are enqueued behind the events that are already queued, and
+/// back off exponentially so transient failures (e.g. GitHub being down) have time to go away.
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicyConfig {
+    /// Retries after failing to check out the code (e.g. due to network issues)
+    pub clone: u32,
+    /// Retries after failing to build the benchmarks
+    pub build: u32,
+    /// Retries after a crash of the benchmark harness (e.g. valgrind crashing)
+    pub harness_crash: u32,
+    /// Retries after a command timed out
+    pub timeout: u32,
+    /// Retries after any other failure (e.g. a full disk)
+    pub infrastructure: u32,
+    /// How long the first retry waits before being handled, doubled for every subsequent retry
+    pub backoff_seconds: u64,
+    /// The longest a retry waits before being handled
+    pub max_backoff_seconds: u64,
+}
+
+impl RetryPolicyConfig {
+    fn max_retries(&self, class: FailureClass) -> u32 {
+        match class {
+            FailureClass::Clone => self.clone,
+            FailureClass::Build => self.build,
+            FailureClass::HarnessCrash => self.harness_crash,
+            FailureClass::Timeout => self.timeout,
+            FailureClass::Infrastructure => self.infrastructure,
+        }
+    }
+
+    /// Returns how long to wait before handling a retry, given the number of earlier retries
+    fn backoff(&self, earlier_retries: u32) -> time::Duration {
+        let seconds = self
+            .backoff_seconds
+            .saturating_mul(2u64.saturating_pow(earlier_retries))
+            .min(self.max_backoff_seconds);
+        time::Duration::seconds(seconds as i64)
+    }
+}
+
+impl Default for RetryPolicyConfig {
+    fn default() -> Self {
+        Self {
+            clone: 1,
+            build: 0,
+            harness_crash: 1,
+            timeout: 0,
+            infrastructure: 2,
+            backoff_seconds: 60,
+            max_backoff_seconds: 60 * 60,
+        }
+    }
+}
+
+/// The priority of pushes to tracked branches, whose runs are background work that shouldn't delay
+/// feedback on PRs
+const PUSH_EVENT_PRIORITY: i64 = DEFAULT_EVENT_PRIORITY - 1;
+
+/// The priority of benchmarking commands flagged as urgent by maintainers (see
+/// [`is_urgent_command`]), which are handled before PR events and pushes
+const URGENT_EVENT_PRIORITY: i64 = DEFAULT_EVENT_PRIORITY + 1;
+
+/// The priority of events moved to the front of the queue by maintainers, through
+/// `@bot_name prioritize` (see [`EventQueue::prioritize_pr_events`])
+const PRIORITIZED_EVENT_PRIORITY: i64 = URGENT_EVENT_PRIORITY + 1;
+
+/// Returns the priority of a webhook event, or `None` if the event kind is not allowed
+///
+/// PR events come before pushes to tracked branches, so interactive feedback isn't stuck behind
+/// background work, and urgent commands come before both
+fn webhook_event_priority(config: &AppConfig, event: &str, body: &[u8]) -> Option<i64> {
+    let priority = match AllowedEvent::from_event_string(event)? {
+        AllowedEvent::Push => PUSH_EVENT_PRIORITY,
+        AllowedEvent::IssueComment if is_urgent_command(config, body) => URGENT_EVENT_PRIORITY,
+        _ => DEFAULT_EVENT_PRIORITY,
+    };
+
+    Some(priority)
+}
+
+/// The kind of the events enqueued by the application to request a full run of a PR's benchmarks
+const FULL_RUN_EVENT: &str = "full_run";
+
+/// The priority of full runs, which are handled after events received through webhooks
+const FULL_RUN_EVENT_PRIORITY: i64 = DEFAULT_EVENT_PRIORITY - 1;
+
+/// The kind of the events enqueued by the application to benchmark the change backported by a PR
+const BACKPORT_ORIGINAL_EVENT: &str = "backport_original";
+
+/// The priority of benchmarking the change backported by a PR, which only completes a check of a
+/// report that was already posted, so it is handled after all other events
+const BACKPORT_ORIGINAL_EVENT_PRIORITY: i64 = PUSH_EVENT_PRIORITY - 1;
+
+/// The kind of the events enqueued by the application for comparisons requested through the HTTP
+/// API
+const API_COMPARE_EVENT: &str = "api_compare";
+
+/// The kind of the events enqueued by the application for self-tests requested through the admin
+/// API
+const SELF_TEST_EVENT: &str = "self_test";
+
+/// The priority of self-tests, which are handled before events received through webhooks
+const SELF_TEST_EVENT_PRIORITY: i64 = DEFAULT_EVENT_PRIORITY + 1;
+
+/// The name of the scheduled job benchmarking `main` at the start of the nightly window
+const NIGHTLY_WINDOW_JOB: &str = "nightly_window";
+
+/// The kind of the events enqueued by the application for scheduled jobs (see
+/// [`crate::scheduler`])
+const SCHEDULED_JOB_EVENT: &str = "scheduled_job";
+
+/// How often deferred PR jobs are checked while the nightly window is active
+const DEFERRED_JOBS_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the owner of the queue records that it is still alive
+const QUEUE_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+
+/// How long without heartbeats until the owner of the queue is considered dead, so another
+/// instance can take the queue over
+const QUEUE_OWNER_STALE_AFTER: time::Duration = time::Duration::minutes(1);
+
+/// How often an instance waiting for the queue checks whether it was released
+const QUEUE_OWNER_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A daily time window reserved for benchmarking `main`
+///
+/// The head of `main` is benchmarked as the window starts, and PR jobs that would collide with the
+/// window (based on their estimated duration) are deferred until after it, so the main history
+/// keeps a consistent daily cadence
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct NightlyWindowConfig {
+    /// The hour (0-23, in UTC) at which the window starts
+    pub start_hour_utc: u8,
+    /// How long the window lasts
+    pub duration_minutes: u16,
+    /// How long a PR job is expected to take, at most
+    pub pr_job_estimate_minutes: u16,
+}
+
+impl NightlyWindowConfig {
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.start_hour_utc >= 24 {
+            bail!(
+                "invalid nightly window start hour: {} (must be between 0 and 23)",
+                self.start_hour_utc
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Returns the scheduled job benchmarking the head of `main` at the start of the window
+    pub fn bench_main_job(&self) -> ScheduledJobConfig {
+        ScheduledJobConfig {
+            name: NIGHTLY_WINDOW_JOB.to_string(),
+            cron: format!("0 {} * * *", self.start_hour_utc),
+            task: ScheduledTask::BenchMain,
+        }
+    }
+
+    /// Returns the end of the window, if a PR job started at `now` would collide with it
+    fn pr_jobs_deferred_until(&self, now: OffsetDateTime) -> Option<OffsetDateTime> {
+        let start_time = Time::from_hms(self.start_hour_utc, 0, 0).ok()?;
+        let window_duration = time::Duration::minutes(self.duration_minutes.into());
+        let job_end = now + time::Duration::minutes(self.pr_job_estimate_minutes.into());
+
+        // The windows of yesterday, today and tomorrow are the only ones that could be relevant
+        (-1..=1)
+            .map(|days| now.replace_time(start_time) + time::Duration::days(days))
+            .find(|&window_start| now < window_start + window_duration && job_end > window_start)
+            .map(|window_start| window_start + window_duration)
+    }
+}
+
+/// Allowed events that we process
 #[derive(Copy, Clone, Debug)]
 pub enum AllowedEvent {
     IssueComment,
     PullRequest,
     PullRequestReview,
     Push,
+    /// A full run of a PR's benchmarks, enqueued by the application itself (never by GitHub)
+    FullRun,
+    /// The benchmarking of the change backported by a PR, enqueued by the application itself
+    /// (never by GitHub)
+    BackportOriginal,
+    /// A comparison requested through the HTTP API (never by GitHub)
+    ApiCompare,
+    /// A self-test requested through the admin API (never by GitHub)
+    SelfTest,
+    /// A job whose schedule is due (never enqueued by GitHub)
+    ScheduledJob,
+    /// A merge request event of a project hosted on GitLab
+    GitLabMergeRequest,
+    /// A note (i.e. comment) event of a project hosted on GitLab
+    GitLabNote,
 }
 
 impl AllowedEvent {
-    fn from_event_string(event: &str) -> Option<Self> {
+    /// Parses the event of a queued event, including events enqueued by the application itself
+    fn from_queued_event_string(event: &str) -> Option<Self> {
+        match event {
+            FULL_RUN_EVENT => Some(Self::FullRun),
+            BACKPORT_ORIGINAL_EVENT => Some(Self::BackportOriginal),
+            API_COMPARE_EVENT => Some(Self::ApiCompare),
+            SELF_TEST_EVENT => Some(Self::SelfTest),
+            SCHEDULED_JOB_EVENT => Some(Self::ScheduledJob),
+            gitlab::MERGE_REQUEST_EVENT => Some(Self::GitLabMergeRequest),
+            gitlab::NOTE_EVENT => Some(Self::GitLabNote),
+            _ => Self::from_event_string(event),
+        }
+    }
+
+    /// Parses the event of a GitHub webhook
+    pub fn from_event_string(event: &str) -> Option<Self> {
         Some(match event {
             "issue_comment" => Self::IssueComment,
             "push" => Self::Push,
@@ -294,8 +1647,25 @@ pub struct JobContext<'a> {
     pub config: &'a AppConfig,
     pub octocrab: &'a CachedOctocrab,
     pub bencher_dev: Option<&'a BencherDev>,
+    pub signer: Option<&'a Signer>,
+    pub notifier: &'a Notifier,
     pub bench_runner: Arc<dyn BenchRunner>,
     pub db: Db,
+    pub event_queue: &'a EventQueue,
+    /// Kills the processes spawned by the job when the job gets cancelled
+    pub cancellation: CancellationToken,
+}
+
+impl JobContext<'_> {
+    /// Records that the job entered a new phase
+    ///
+    /// Phases are informative, so failures are logged instead of returned
+    pub async fn enter_phase(&self, phase: JobPhase) {
+        trace!("job entered phase {}", phase.as_str());
+        if let Err(e) = self.db.set_job_phase(self.job_id, phase.as_str()).await {
+            error!(cause = format!("{e:#}"), "unable to record job phase");
+        }
+    }
 }
 
 impl<'a> Debug for JobContext<'a> {
@@ -310,6 +1680,11 @@ impl<'a> Debug for JobContext<'a> {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct JobView {
+    pub id: Uuid,
+    /// The GitHub user whose action triggered the job, if known
+    pub requester: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub event_queued_utc: OffsetDateTime,
     #[serde(with = "time::serde::rfc3339")]
     pub created_utc: OffsetDateTime,
     #[serde(
@@ -318,54 +1693,170 @@ pub struct JobView {
     )]
     pub finished_utc: Option<OffsetDateTime>,
     pub status: JobStatus,
+    /// The class of the failure that prevented the job from producing results, if any
+    pub failure_class: Option<String>,
+    /// The CPU time consumed by the processes the job spawned, once the job has finished (only
+    /// tracked with a single queue worker)
+    pub cpu_seconds: Option<f64>,
+    /// The time it took to run the job, once the job has finished
+    pub wall_seconds: Option<f64>,
+    /// How long the job's event waited in the queue before the job was created
+    pub queue_wait_seconds: f64,
+    /// How long the job has been running so far, while it is running
+    pub running_seconds: Option<f64>,
+    /// The phase the job was in when it last reported progress (e.g. how far an interrupted job
+    /// got)
+    pub phase: Option<String>,
+    /// All attempts at handling the job's event, from the original job to its latest retry (the job
+    /// itself included). Left empty in job listings
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub retry_history: Vec<JobAttemptView>,
 }
 
 impl JobView {
-    fn from_job(job: BenchJob, active: bool) -> Self {
-        let status = match job.success {
-            None => match &job.finished_utc {
-                None if active => JobStatus::Pending,
-                None => JobStatus::Failure,
-                Some(_) => unreachable!("if the job finished, it's success field will be set"),
-            },
-            Some(true) => JobStatus::Success,
-            Some(false) => JobStatus::Failure,
-        };
+    fn from_job(job: BenchJob, retry_history: Vec<JobAttemptView>) -> Self {
+        let status = JobStatus::of(&job);
+        let running_seconds = (status == JobStatus::Running)
+            .then(|| (OffsetDateTime::now_utc() - job.created_utc).as_seconds_f64());
 
         Self {
+            id: job.id,
+            requester: job.requester,
+            event_queued_utc: job.event_queued_utc,
+            queue_wait_seconds: (job.created_utc - job.event_queued_utc).as_seconds_f64(),
+            running_seconds,
+            phase: job.phase,
+            status,
             created_utc: job.created_utc,
             finished_utc: job.finished_utc,
-            status,
+            failure_class: job.failure_class,
+            cpu_seconds: job.cpu_seconds,
+            wall_seconds: job.wall_seconds,
+            retry_history,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Debug, PartialEq)]
+/// A page of jobs, as returned by [`EventQueue::job_views`]
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobsPage {
+    pub jobs: Vec<JobView>,
+    pub page: usize,
+    pub per_page: usize,
+    /// The amount of jobs across all pages
+    pub total: usize,
+}
+
+/// A summary of one of the attempts at handling an event
+#[derive(Debug, Serialize, Deserialize)]
+pub struct JobAttemptView {
+    pub job_id: Uuid,
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_utc: OffsetDateTime,
+    pub status: JobStatus,
+    pub failure_class: Option<String>,
+}
+
+impl JobAttemptView {
+    fn from_job(job: BenchJob) -> Self {
+        Self {
+            status: JobStatus::of(&job),
+            job_id: job.id,
+            created_utc: job.created_utc,
+            failure_class: job.failure_class,
+        }
+    }
+}
+
+/// Where a job is in its lifecycle, as stored in the database
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
 pub enum JobStatus {
-    Pending,
+    /// The job was created for its event, but its handler hasn't started yet
+    Queued,
+    Running,
     Success,
+    /// The job finished with an error, or stopped without finishing (e.g. because it crashed)
     Failure,
+    Cancelled,
+    /// The application shut down while the job was running, so a new job handles its event
+    Interrupted,
+}
+
+impl JobStatus {
+    fn of(job: &BenchJob) -> Self {
+        // The status is only ever written by the application, so an unknown one means the job was
+        // left behind in an inconsistent state
+        JobStatus::parse(&job.status).unwrap_or(JobStatus::Failure)
+    }
+
+    /// Parses a status from its stable identifier (see [`JobStatus::as_str`])
+    pub fn parse(status: &str) -> Option<Self> {
+        Some(match status {
+            "queued" => JobStatus::Queued,
+            "running" => JobStatus::Running,
+            "success" => JobStatus::Success,
+            "failure" => JobStatus::Failure,
+            "cancelled" => JobStatus::Cancelled,
+            "interrupted" => JobStatus::Interrupted,
+            _ => return None,
+        })
+    }
+
+    /// The identifier under which the status is stored in the database
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Success => "success",
+            JobStatus::Failure => "failure",
+            JobStatus::Cancelled => "cancelled",
+            JobStatus::Interrupted => "interrupted",
+        }
+    }
+}
+
+/// The reasons why event processing is disabled (it is enabled if there are none)
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct PauseReasons {
+    /// The `pause` file exists
+    pause_file: bool,
+    /// The queue was paused through the admin API (see [`EventQueue::pause`])
+    admin: bool,
+}
+
+impl PauseReasons {
+    fn any(&self) -> bool {
+        self.pause_file || self.admin
+    }
 }
 
 /// Watches the filesystem to toggle event processing.
 ///
 /// Event processing is enabled by default, but can be disabled by creating a file called `pause`
 /// in the program's working directory. If the file is present upon startup or gets created while
-/// the application runs, processing will be disabled until the file is deleted.
+/// the application runs, processing will be disabled until the file is deleted. Processing can
+/// also be disabled through the admin API, in which case it stays disabled until resumed through
+/// the API too.
 struct ProcessEventsToggler {
     /// Filesystem watcher
     watcher: Arc<RecommendedWatcher>,
+    /// Sender used to pause and resume processing through the admin API
+    pause_reasons_tx: Arc<tokio::sync::watch::Sender<PauseReasons>>,
     /// Receiver tracking the current state of the toggle (enabled / disabled)
-    processing_enabled_rx: tokio::sync::watch::Receiver<bool>,
+    pause_reasons_rx: tokio::sync::watch::Receiver<PauseReasons>,
 }
 
 impl ProcessEventsToggler {
     fn new() -> anyhow::Result<Self> {
         let pause_file_path = Path::new("pause");
-        let process_events = pause_file_path.try_exists().ok() != Some(true);
-        let (processing_enabled_tx, processing_enabled_rx) =
-            tokio::sync::watch::channel(process_events);
+        let pause_reasons = PauseReasons {
+            pause_file: pause_file_path.try_exists().ok() == Some(true),
+            admin: false,
+        };
+        let (pause_reasons_tx, pause_reasons_rx) = tokio::sync::watch::channel(pause_reasons);
+        let pause_reasons_tx = Arc::new(pause_reasons_tx);
 
+        let watcher_tx = pause_reasons_tx.clone();
         let mut watcher =
             notify::recommended_watcher(move |r: notify::Result<notify::Event>| match r {
                 Ok(event) => {
@@ -377,11 +1868,11 @@ impl ProcessEventsToggler {
                         match event.kind {
                             EventKind::Create(_) => {
                                 info!("event processing disabled");
-                                processing_enabled_tx.send_replace(false);
+                                watcher_tx.send_modify(|reasons| reasons.pause_file = true);
                             }
                             EventKind::Remove(_) => {
                                 info!("event processing enabled");
-                                processing_enabled_tx.send_replace(true);
+                                watcher_tx.send_modify(|reasons| reasons.pause_file = false);
                             }
                             _ => {}
                         }
@@ -399,12 +1890,18 @@ impl ProcessEventsToggler {
 
         Ok(ProcessEventsToggler {
             watcher: Arc::new(watcher),
-            processing_enabled_rx,
+            pause_reasons_tx,
+            pause_reasons_rx,
         })
     }
 
     fn processing_enabled(&self) -> bool {
-        *self.processing_enabled_rx.borrow()
+        !self.pause_reasons_rx.borrow().any()
+    }
+
+    fn set_paused_by_admin(&self, paused: bool) {
+        self.pause_reasons_tx
+            .send_modify(|reasons| reasons.admin = paused);
     }
 
     fn processing_disabled(&self) -> bool {
@@ -416,8 +1913,11 @@ impl ProcessEventsToggler {
             info!("event handling postponed until processing gets enabled");
         }
 
-        self.processing_enabled_rx.wait_for(|&b| b).await.unwrap();
-        assert!(*self.processing_enabled_rx.borrow());
+        self.pause_reasons_rx
+            .wait_for(|reasons| !reasons.any())
+            .await
+            .unwrap();
+        assert!(self.processing_enabled());
     }
 }
 
@@ -425,7 +1925,273 @@ impl Clone for ProcessEventsToggler {
     fn clone(&self) -> Self {
         Self {
             watcher: self.watcher.clone(),
-            processing_enabled_rx: self.processing_enabled_rx.clone(),
+            pause_reasons_tx: self.pause_reasons_tx.clone(),
+            pause_reasons_rx: self.pause_reasons_rx.clone(),
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+    use time::{Date, Month};
+
+    use super::*;
+    use crate::scheduler::CronSchedule;
+
+    fn datetime(day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        Date::from_calendar_date(2023, Month::October, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    fn queued_event(event: &str, payload: serde_json::Value, minute: u8) -> QueuedEvent {
+        QueuedEvent {
+            id: Uuid::new_v4(),
+            job_id: None,
+            event: event.to_string(),
+            payload: serde_json::to_vec(&payload).unwrap(),
+            created_utc: datetime(10, 12, minute),
+            priority: DEFAULT_EVENT_PRIORITY,
+            not_before_utc: None,
+        }
+    }
+
+    #[test]
+    fn dispatch_serializes_events_of_the_same_pr() {
+        let pr_7 = queued_event(
+            "pull_request",
+            json!({ "pull_request": { "number": 7 } }),
+            0,
+        );
+        let pr_7_comment = queued_event("issue_comment", json!({ "issue": { "number": 7 } }), 1);
+        let pr_8 = queued_event(
+            "pull_request",
+            json!({ "pull_request": { "number": 8 } }),
+            2,
+        );
+        let pr_8_id = pr_8.id;
+
+        let busy = BusySlots {
+            event_ids: HashSet::from([pr_7.id]),
+            serialization_keys: HashSet::from(["pr:7".to_string()]),
+            resource_classes: HashSet::new(),
+        };
+        let next = |busy: &BusySlots, queued| {
+            next_dispatchable_event(
+                queued,
+                busy,
+                &BTreeMap::new(),
+                false,
+                datetime(10, 12, 30),
+                time::Duration::ZERO,
+            )
+            .map(|event| event.id)
+        };
+
+        // The comment has to wait for the job of its PR, but the other PR doesn't
+        assert_eq!(next(&busy, vec![pr_7, pr_7_comment, pr_8]), Some(pr_8_id));
+
+        // Once nothing is running, events are handled in order
+        let pr_7_comment = queued_event("issue_comment", json!({ "issue": { "number": 7 } }), 1);
+        let pr_8 = queued_event(
+            "pull_request",
+            json!({ "pull_request": { "number": 8 } }),
+            2,
+        );
+        let pr_7_comment_id = pr_7_comment.id;
+        let idle = BusySlots::default();
+        assert_eq!(next(&idle, vec![pr_8, pr_7_comment]), Some(pr_7_comment_id));
+    }
+
+    #[test]
+    fn dispatch_serializes_events_of_the_same_merge_request() {
+        let merge_request = |project_id: u64, iid: u64, minute| {
+            queued_event(
+                gitlab::MERGE_REQUEST_EVENT,
+                json!({ "project": { "id": project_id }, "object_attributes": { "iid": iid } }),
+                minute,
+            )
+        };
+        let mr_3 = merge_request(15, 3, 0);
+        let mr_3_note = queued_event(
+            gitlab::NOTE_EVENT,
+            json!({ "project": { "id": 15 }, "merge_request": { "iid": 3 } }),
+            1,
+        );
+        // Same iid, but in another project
+        let other_project_mr_3 = merge_request(16, 3, 2);
+        let other_project_mr_3_id = other_project_mr_3.id;
+        assert_eq!(
+            event_serialization_key(&mr_3_note).as_deref(),
+            Some("gitlab:15!3")
+        );
+
+        let busy = BusySlots {
+            event_ids: HashSet::from([mr_3.id]),
+            serialization_keys: HashSet::from(["gitlab:15!3".to_string()]),
+            resource_classes: HashSet::new(),
+        };
+        let next = next_dispatchable_event(
+            vec![mr_3, mr_3_note, other_project_mr_3],
+            &busy,
+            &BTreeMap::new(),
+            false,
+            datetime(10, 12, 30),
+            time::Duration::ZERO,
+        )
+        .map(|event| event.id);
+
+        // The note has to wait for the job of its merge request
+        assert_eq!(next, Some(other_project_mr_3_id));
+    }
+
+    #[test]
+    fn dispatch_serializes_events_of_the_same_resource_class() {
+        let push = queued_event("push", json!({ "ref": "refs/heads/main" }), 0);
+        let pr = queued_event(
+            "pull_request",
+            json!({ "pull_request": { "number": 7 } }),
+            1,
+        );
+        let api = queued_event("api_compare", json!({ "client": "ci" }), 2);
+        let api_id = api.id;
+        let resource_classes = BTreeMap::from([
+            ("push".to_string(), "bench-host".to_string()),
+            ("pull_request".to_string(), "bench-host".to_string()),
+        ]);
+
+        let busy = BusySlots {
+            event_ids: HashSet::new(),
+            serialization_keys: HashSet::new(),
+            resource_classes: HashSet::from(["bench-host".to_string()]),
+        };
+        let next = next_dispatchable_event(
+            vec![push, pr, api],
+            &busy,
+            &resource_classes,
+            false,
+            datetime(10, 12, 30),
+            time::Duration::ZERO,
+        );
+        assert_eq!(next.map(|event| event.id), Some(api_id));
+    }
+
+    #[test]
+    fn dispatch_skips_retries_that_are_backing_off() {
+        let retry_id = Uuid::new_v4();
+        let pr_id = Uuid::new_v4();
+        let queued = || {
+            let mut retry = queued_event("push", json!({ "ref": "refs/heads/main" }), 0);
+            retry.id = retry_id;
+            retry.not_before_utc = Some(datetime(10, 12, 45));
+            let mut pr = queued_event(
+                "pull_request",
+                json!({ "pull_request": { "number": 7 } }),
+                1,
+            );
+            pr.id = pr_id;
+            vec![retry, pr]
+        };
+
+        let busy = BusySlots {
+            event_ids: HashSet::new(),
+            serialization_keys: HashSet::new(),
+            resource_classes: HashSet::new(),
+        };
+        let dispatch = |minute| {
+            next_dispatchable_event(
+                queued(),
+                &busy,
+                &BTreeMap::new(),
+                false,
+                datetime(10, 12, minute),
+                time::Duration::ZERO,
+            )
+            .map(|event| event.id)
+        };
+
+        // The retry is older, but it is only handled once it is done backing off
+        assert_eq!(dispatch(30), Some(pr_id));
+        assert_eq!(dispatch(45), Some(retry_id));
+    }
+
+    #[test]
+    fn retries_back_off_exponentially() {
+        let policy = RetryPolicyConfig::default();
+        assert_eq!(policy.backoff(0), time::Duration::minutes(1));
+        assert_eq!(policy.backoff(1), time::Duration::minutes(2));
+        assert_eq!(policy.backoff(3), time::Duration::minutes(8));
+        assert_eq!(policy.backoff(10), time::Duration::hours(1));
+        assert_eq!(policy.backoff(100), time::Duration::hours(1));
+    }
+
+    #[test]
+    fn nightly_window_defers_colliding_pr_jobs() {
+        let window = NightlyWindowConfig {
+            start_hour_utc: 2,
+            duration_minutes: 60,
+            pr_job_estimate_minutes: 30,
+        };
+
+        // Far enough from the window
+        assert_eq!(window.pr_jobs_deferred_until(datetime(10, 1, 0)), None);
+        assert_eq!(window.pr_jobs_deferred_until(datetime(10, 3, 0)), None);
+
+        // The job would run into the window
+        assert_eq!(
+            window.pr_jobs_deferred_until(datetime(10, 1, 45)),
+            Some(datetime(10, 3, 0))
+        );
+
+        // Inside the window
+        assert_eq!(
+            window.pr_jobs_deferred_until(datetime(10, 2, 59)),
+            Some(datetime(10, 3, 0))
+        );
+    }
+
+    #[test]
+    fn nightly_window_schedules_main_run_at_its_start() {
+        let window = NightlyWindowConfig {
+            start_hour_utc: 2,
+            duration_minutes: 60,
+            pr_job_estimate_minutes: 30,
+        };
+
+        let job = window.bench_main_job();
+        assert_eq!(job.task, ScheduledTask::BenchMain);
+        let schedule: CronSchedule = job.cron.parse().unwrap();
+        assert_eq!(
+            schedule.next_after(datetime(10, 1, 0)),
+            Some(datetime(10, 2, 0))
+        );
+        assert_eq!(
+            schedule.next_after(datetime(10, 2, 0)),
+            Some(datetime(11, 2, 0))
+        );
+    }
+
+    #[test]
+    fn nightly_window_wraps_around_midnight() {
+        let window = NightlyWindowConfig {
+            start_hour_utc: 23,
+            duration_minutes: 120,
+            pr_job_estimate_minutes: 30,
+        };
+
+        // Yesterday's window is still active
+        assert_eq!(
+            window.pr_jobs_deferred_until(datetime(10, 0, 30)),
+            Some(datetime(10, 1, 0))
+        );
+
+        // Today's window is about to start
+        assert_eq!(
+            window.pr_jobs_deferred_until(datetime(10, 22, 45)),
+            Some(datetime(11, 1, 0))
+        );
+    }
+}
@@ -0,0 +1,65 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use sqlx::{Connection, SqliteConnection};
+use tokio::sync::Mutex;
+
+use crate::db::{self, Db};
+use crate::signing::Signer;
+use crate::{AppConfig, MIGRATOR};
+
+/// Exports an anonymized copy of the benchmark data to a new SQLite database
+///
+/// The resulting database has the same schema as the original one, but only contains benchmark
+/// runs, results and comparisons (i.e. no webhook payloads, jobs or comment ids), so it can be
+/// shared with researchers or used to seed a local development database.
+///
+/// If signing is enabled, the export's signature is written next to it (with a `.sig` suffix), so
+/// recipients can verify it against the deployment's public key.
+pub async fn export(config: &AppConfig, export_path: &Path) -> anyhow::Result<()> {
+    if export_path.try_exists()? {
+        bail!(
+            "refusing to overwrite existing file at {}",
+            export_path.display()
+        );
+    }
+
+    let export_path = export_path
+        .to_str()
+        .context("export path must be valid UTF-8")?;
+
+    // Create the export database with an up-to-date schema
+    let mut export_conn = db::connect_creating_db(export_path).await?;
+    MIGRATOR
+        .run(&mut export_conn)
+        .await
+        .context("failed to apply DB migration to export database")?;
+    export_conn.close().await?;
+
+    // The source database must be up-to-date too, so the tables match
+    let mut sqlite = SqliteConnection::connect(&format!("sqlite:{}", config.path_to_db))
+        .await
+        .context("unable to open database")?;
+    MIGRATOR
+        .run(&mut sqlite)
+        .await
+        .context("failed to apply DB migration")?;
+
+    Db::with_connection(Arc::new(Mutex::new(sqlite)))
+        .export_anonymized(export_path)
+        .await
+        .context("failed to export data")?;
+
+    println!("anonymized export written to {export_path}");
+
+    if let Some(signer) = Signer::from_config(config).context("invalid signing key")? {
+        let export = std::fs::read(export_path).context("unable to read export for signing")?;
+        let signature_path = format!("{export_path}.sig");
+        std::fs::write(&signature_path, signer.sign(&export))
+            .context("unable to write export signature")?;
+        println!("export signature written to {signature_path}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,170 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{AppConfig, Db};
+
+/// Report sections that can be rolled out gradually, instead of showing up in every PR at once
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFeature {
+    /// The overall instruction count index (geometric mean across scenarios)
+    IcountIndex,
+    /// The scenarios with the largest instruction count changes, at the top of the report
+    TopMovers,
+    /// The client/server scenario pairs whose instruction counts moved in opposite directions
+    DivergingPairs,
+    /// The sizes of the artifacts built from both sides
+    ArtifactSizes,
+    /// The stability of new scenarios, measured twice on the candidate
+    NewScenarioStability,
+}
+
+impl ReportFeature {
+    pub const ALL: [ReportFeature; 5] = [
+        ReportFeature::IcountIndex,
+        ReportFeature::TopMovers,
+        ReportFeature::DivergingPairs,
+        ReportFeature::ArtifactSizes,
+        ReportFeature::NewScenarioStability,
+    ];
+}
+
+/// The PRs whose reports include a feature
+///
+/// Features without a rollout are included in every report
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FeatureRollout {
+    /// The percentage of PRs (0 to 100) whose reports include the feature, picked by PR number so
+    /// each PR consistently gets the same sections
+    pub percent: u8,
+    /// PRs whose reports include the feature regardless of the percentage (e.g. to compare a report
+    /// side by side with the report of a PR without the feature)
+    #[serde(default)]
+    pub prs: Vec<u64>,
+}
+
+impl FeatureRollout {
+    pub fn includes(&self, pr_number: u64) -> bool {
+        self.prs.contains(&pr_number) || pr_number % 100 < self.percent as u64
+    }
+}
+
+/// The rollout of a feature currently in effect, and where it comes from
+#[derive(Debug, Clone, Eq, PartialEq, Serialize)]
+pub struct EffectiveRollout {
+    /// `None` if the feature is included in every report
+    pub rollout: Option<FeatureRollout>,
+    /// Whether the rollout was set at runtime, overriding the configuration
+    pub overridden: bool,
+}
+
+/// Returns the rollout in effect for each feature: the one set at runtime through the admin API,
+/// if any, or else the configured one
+pub async fn effective_rollouts(
+    config: &AppConfig,
+    db: &Db,
+) -> anyhow::Result<BTreeMap<ReportFeature, EffectiveRollout>> {
+    let overrides = db.report_feature_overrides().await?;
+    Ok(ReportFeature::ALL
+        .into_iter()
+        .map(|feature| {
+            let rollout = match overrides.get(&feature) {
+                Some(rollout) => EffectiveRollout {
+                    rollout: Some(rollout.clone()),
+                    overridden: true,
+                },
+                None => EffectiveRollout {
+                    rollout: config.report.feature_rollouts.get(&feature).cloned(),
+                    overridden: false,
+                },
+            };
+            (feature, rollout)
+        })
+        .collect())
+}
+
+/// The features included in a specific report
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ReportFeatures(BTreeSet<ReportFeature>);
+
+impl ReportFeatures {
+    /// Resolves the features included in the reports of the PR
+    pub async fn for_pr(config: &AppConfig, db: &Db, pr_number: u64) -> anyhow::Result<Self> {
+        let rollouts = effective_rollouts(config, db).await?;
+        Ok(Self::from_rollouts(&rollouts, pr_number))
+    }
+
+    fn from_rollouts(rollouts: &BTreeMap<ReportFeature, EffectiveRollout>, pr_number: u64) -> Self {
+        Self(
+            ReportFeature::ALL
+                .into_iter()
+                .filter(|feature| {
+                    rollouts
+                        .get(feature)
+                        .and_then(|effective| effective.rollout.as_ref())
+                        .is_none_or(|rollout| rollout.includes(pr_number))
+                })
+                .collect(),
+        )
+    }
+
+    pub fn includes(&self, feature: ReportFeature) -> bool {
+        self.0.contains(&feature)
+    }
+}
+
+impl Default for ReportFeatures {
+    /// All features, for reports that are not subject to rollouts
+    fn default() -> Self {
+        Self(ReportFeature::ALL.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rollouts_include_a_consistent_subset_of_prs() {
+        let rollout = FeatureRollout {
+            percent: 25,
+            prs: vec![1050],
+        };
+        assert!(rollout.includes(7));
+        assert!(rollout.includes(124));
+        assert!(!rollout.includes(25));
+        assert!(!rollout.includes(99));
+
+        // Explicitly listed PRs are always included
+        assert!(rollout.includes(1050));
+    }
+
+    #[test]
+    fn features_without_rollout_are_included() {
+        let rollouts = BTreeMap::from([
+            (
+                ReportFeature::TopMovers,
+                EffectiveRollout {
+                    rollout: Some(FeatureRollout {
+                        percent: 0,
+                        prs: Vec::new(),
+                    }),
+                    overridden: true,
+                },
+            ),
+            (
+                ReportFeature::ArtifactSizes,
+                EffectiveRollout {
+                    rollout: None,
+                    overridden: false,
+                },
+            ),
+        ]);
+
+        let features = ReportFeatures::from_rollouts(&rollouts, 7);
+        assert!(!features.includes(ReportFeature::TopMovers));
+        assert!(features.includes(ReportFeature::ArtifactSizes));
+        assert!(features.includes(ReportFeature::DivergingPairs));
+    }
+}
@@ -0,0 +1,76 @@
+use std::future::Future;
+use std::pin::Pin;
+
+use tracing::{error, trace};
+
+/// The future returned by [`Forge`] operations (boxed, so forges can be used as trait objects)
+pub type ForgeFuture<'a, T> = Pin<Box<dyn Future<Output = anyhow::Result<T>> + Send + 'a>>;
+
+/// The code hosting platform of a repository, through which benchmark results are reported
+///
+/// Each forge has its own webhooks, but once an event has been parsed reporting works the same
+/// way: results are posted as comments on the PR (called merge request on GitLab), and verdicts as
+/// commit statuses on its head
+pub trait Forge: Send + Sync {
+    /// Posts a new comment on the PR
+    fn post_comment<'a>(&'a self, pr_number: u64, body: &'a str) -> ForgeFuture<'a, ()>;
+
+    /// Sets the status of a commit for the status's context, replacing the previous one
+    fn set_commit_status<'a>(
+        &'a self,
+        commit_sha: &'a str,
+        status: &'a CommitStatus,
+    ) -> ForgeFuture<'a, ()>;
+}
+
+/// The commit status contexts reported on PRs, one per kind of benchmarks
+///
+/// Each context transitions independently, so branch protection can require only the stable
+/// instruction count check
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StatusContext {
+    Icount,
+    Walltime,
+}
+
+impl StatusContext {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StatusContext::Icount => "bench/icount",
+            StatusContext::Walltime => "bench/walltime",
+        }
+    }
+}
+
+/// The state of a commit status, mapped to the closest equivalent of each forge
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommitState {
+    Pending,
+    Success,
+    Error,
+}
+
+/// A commit status, as shown next to the commit on the forge
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CommitStatus {
+    pub context: StatusContext,
+    pub state: CommitState,
+    pub description: Option<String>,
+    /// The page the status links to (e.g. the job's page)
+    pub target_url: String,
+}
+
+/// Updates a commit's status and logs the result
+///
+/// Statuses are informative, so failures are logged instead of returned
+pub async fn update_commit_status(forge: &dyn Forge, commit_sha: &str, status: &CommitStatus) {
+    let context = status.context.as_str();
+    let state = status.state;
+    match forge.set_commit_status(commit_sha, status).await {
+        Ok(()) => trace!("commit status `{context}` updated to {state:?}"),
+        Err(e) => error!(
+            cause = format!("{e:#}"),
+            "error updating status `{context}` to {state:?}"
+        ),
+    }
+}
@@ -4,11 +4,16 @@ use std::time::Duration;
 use anyhow::Context;
 use hmac::{Hmac, Mac};
 use jsonwebtoken::EncodingKey;
-use octocrab::models::{InstallationId, StatusState};
+use octocrab::models::reactions::ReactionContent;
+use octocrab::models::{CommentId, InstallationId, StatusState};
 use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
 use sha2::Sha256;
-use tracing::{error, trace, warn};
+use tracing::{error, warn};
+use uuid::Uuid;
 
+use crate::db::Verdict;
+use crate::forge::{CommitState, CommitStatus, Forge, ForgeFuture};
 use crate::AppConfig;
 
 pub mod api {
@@ -27,6 +32,7 @@ pub mod api {
 
     #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
     pub struct Review {
+        pub user: GitHubUser,
         pub author_association: String,
         pub state: String,
         pub commit_id: String,
@@ -50,6 +56,7 @@ pub mod api {
 
     #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
     pub struct Comment {
+        pub id: u64,
         pub author_association: String,
         pub body: String,
         pub user: GitHubUser,
@@ -76,6 +83,51 @@ pub mod api {
     pub struct Repo {
         pub clone_url: String,
     }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct CommitComparison {
+        /// The commits in the range, oldest first
+        pub commits: Vec<CommitLite>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct CommitLite {
+        pub sha: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct RepositoryLite {
+        pub clone_url: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct PullRequestFile {
+        pub filename: String,
+        /// The file's diff, unless it is binary or too big
+        pub patch: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct AssociatedPullRequest {
+        pub number: u64,
+        pub merged_at: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct OpenPullRequest {
+        pub number: u64,
+        pub head: CommitLite,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct CombinedStatus {
+        pub statuses: Vec<CommitStatus>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct CommitStatus {
+        pub context: String,
+    }
 }
 
 /// Provides access to an authenticated `Octocrab` client
@@ -161,42 +213,453 @@ impl CachedOctocrab {
         });
     }
 
+    /// Returns a client authenticated as the GitHub App itself, as required by the `/app` endpoints
+    /// (e.g. to list webhook deliveries)
+    pub fn app(&self) -> Octocrab {
+        self.app_client.clone()
+    }
+
     /// Returns the cached and authenticated `Octocrab` client
     pub fn cached(&self) -> Octocrab {
         self.installation_client.lock().unwrap().clone()
     }
 }
 
-/// Updates a commit's status and logs the result
-pub async fn update_commit_status(
-    sha: String,
-    state: StatusState,
-    job_url: String,
+/// Adds a reaction to an issue comment and logs the result
+pub async fn add_comment_reaction(
+    comment_id: u64,
+    reaction: ReactionContent,
     config: &AppConfig,
     octocrab: &Octocrab,
 ) {
     let post_result = octocrab
-        .repos(&config.github_repo_owner, &config.github_repo_name)
-        .create_status(sha, state)
-        .context("icount benchmarks".to_string())
-        .target(job_url)
-        .send()
+        .issues(&config.github_repo_owner, &config.github_repo_name)
+        .create_comment_reaction(comment_id, reaction.clone())
+        .await;
+
+    if let Err(e) = post_result {
+        error!(
+            cause = e.to_string(),
+            comment_id, "unable to add {reaction:?} reaction to comment"
+        );
+    }
+}
+
+/// The configured GitHub repository, as a [`Forge`]
+pub struct GitHubForge<'a> {
+    config: &'a AppConfig,
+    octocrab: Octocrab,
+}
+
+impl<'a> GitHubForge<'a> {
+    pub fn new(config: &'a AppConfig, octocrab: Octocrab) -> Self {
+        Self { config, octocrab }
+    }
+}
+
+impl Forge for GitHubForge<'_> {
+    fn post_comment<'a>(&'a self, pr_number: u64, body: &'a str) -> ForgeFuture<'a, ()> {
+        Box::pin(async move {
+            self.octocrab
+                .issues(
+                    &self.config.github_repo_owner,
+                    &self.config.github_repo_name,
+                )
+                .create_comment(pr_number, body)
+                .await
+                .context("unable to post comment")?;
+            Ok(())
+        })
+    }
+
+    fn set_commit_status<'a>(
+        &'a self,
+        commit_sha: &'a str,
+        status: &'a CommitStatus,
+    ) -> ForgeFuture<'a, ()> {
+        Box::pin(async move {
+            let state = match status.state {
+                CommitState::Pending => StatusState::Pending,
+                CommitState::Success => StatusState::Success,
+                CommitState::Error => StatusState::Error,
+            };
+            let repos = self.octocrab.repos(
+                &self.config.github_repo_owner,
+                &self.config.github_repo_name,
+            );
+            let mut builder = repos
+                .create_status(commit_sha.to_string(), state)
+                .context(status.context.as_str().to_string())
+                .target(status.target_url.clone());
+
+            if let Some(description) = &status.description {
+                builder = builder.description(description.clone());
+            }
+
+            builder.send().await.context("unable to create status")?;
+            Ok(())
+        })
+    }
+}
+
+/// Returns the merged PRs that introduced the commits after `base` up to (and including) `head`
+///
+/// Only the most recent commits of the range are considered, to bound the number of API calls
+pub async fn merged_prs_between(
+    base: &str,
+    head: &str,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Vec<u64>> {
+    const MAX_COMMITS: usize = 50;
+
+    let repo = format!(
+        "/repos/{}/{}",
+        config.github_repo_owner, config.github_repo_name
+    );
+    let comparison: api::CommitComparison = octocrab
+        .get(format!("{repo}/compare/{base}...{head}"), None::<&()>)
+        .await
+        .context("failed to compare commits")?;
+
+    let mut pr_numbers = Vec::new();
+    for commit in comparison.commits.iter().rev().take(MAX_COMMITS) {
+        let prs: Vec<api::AssociatedPullRequest> = octocrab
+            .get(format!("{repo}/commits/{}/pulls", commit.sha), None::<&()>)
+            .await
+            .context("failed to list the PRs associated to a commit")?;
+
+        for pr in prs {
+            if pr.merged_at.is_some() && !pr_numbers.contains(&pr.number) {
+                pr_numbers.push(pr.number);
+            }
+        }
+    }
+
+    Ok(pr_numbers)
+}
+
+/// Resolves a commit hash, branch or tag of the repository to the full hash of its commit
+pub async fn resolve_commit(
+    git_ref: &str,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<String> {
+    let commit: api::CommitLite = octocrab
+        .get(
+            format!(
+                "/repos/{}/{}/commits/{git_ref}",
+                config.github_repo_owner, config.github_repo_name
+            ),
+            None::<&()>,
+        )
+        .await
+        .context("failed to get commit")?;
+
+    Ok(commit.sha)
+}
+
+/// Returns the URL from which the repository can be cloned
+pub async fn repo_clone_url(config: &AppConfig, octocrab: &Octocrab) -> anyhow::Result<String> {
+    let repo: api::RepositoryLite = octocrab
+        .get(
+            format!(
+                "/repos/{}/{}",
+                config.github_repo_owner, config.github_repo_name
+            ),
+            None::<&()>,
+        )
+        .await
+        .context("failed to get repository details")?;
+
+    Ok(repo.clone_url)
+}
+
+/// Returns the files changed by a PR
+///
+/// GitHub lists at most 3000 files per PR, which is more than enough for our purposes
+pub async fn pr_changed_files(
+    pr_number: u64,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Vec<api::PullRequestFile>> {
+    const PER_PAGE: usize = 100;
+    const MAX_PAGES: usize = 30;
+
+    let mut files = Vec::new();
+    for page in 1..=MAX_PAGES {
+        let page_files: Vec<api::PullRequestFile> = octocrab
+            .get(
+                format!(
+                    "/repos/{}/{}/pulls/{pr_number}/files?per_page={PER_PAGE}&page={page}",
+                    config.github_repo_owner, config.github_repo_name
+                ),
+                None::<&()>,
+            )
+            .await
+            .context("failed to list the PR's files")?;
+
+        let last_page = page_files.len() < PER_PAGE;
+        files.extend(page_files);
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(files)
+}
+
+/// Returns the repository's open PRs
+pub async fn open_prs(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Vec<api::OpenPullRequest>> {
+    const PER_PAGE: usize = 100;
+    const MAX_PAGES: usize = 10;
+
+    let mut prs = Vec::new();
+    for page in 1..=MAX_PAGES {
+        let page_prs: Vec<api::OpenPullRequest> = octocrab
+            .get(
+                format!(
+                    "/repos/{}/{}/pulls?state=open&per_page={PER_PAGE}&page={page}",
+                    config.github_repo_owner, config.github_repo_name
+                ),
+                None::<&()>,
+            )
+            .await
+            .context("failed to list open PRs")?;
+
+        let last_page = page_prs.len() < PER_PAGE;
+        prs.extend(page_prs);
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(prs)
+}
+
+/// Returns whether the issue comment still exists (i.e. it wasn't deleted)
+pub async fn comment_exists(
+    comment_id: CommentId,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<bool> {
+    let comment: Result<api::Comment, _> = octocrab
+        .get(
+            format!(
+                "/repos/{}/{}/issues/comments/{comment_id}",
+                config.github_repo_owner, config.github_repo_name
+            ),
+            None::<&()>,
+        )
         .await;
 
-    match post_result {
-        Ok(_) => trace!("commit status updated to {state:?}"),
-        Err(e) => error!(cause = e.to_string(), "error updating status to {state:?}"),
+    match comment {
+        Ok(_) => Ok(true),
+        Err(octocrab::Error::GitHub { source, .. }) if source.status_code.as_u16() == 404 => {
+            Ok(false)
+        }
+        Err(e) => Err(e).context("failed to get comment"),
+    }
+}
+
+/// Returns the contexts of the statuses reported on a commit
+pub async fn commit_status_contexts(
+    sha: &str,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Vec<String>> {
+    let status: api::CombinedStatus = octocrab
+        .get(
+            format!(
+                "/repos/{}/{}/commits/{sha}/status",
+                config.github_repo_owner, config.github_repo_name
+            ),
+            None::<&()>,
+        )
+        .await
+        .context("failed to get the commit's status")?;
+
+    Ok(status.statuses.into_iter().map(|s| s.context).collect())
+}
+
+/// A hidden marker included in the comment where a PR's results are reported, so the comment can
+/// be found again if its id was never stored (e.g. because the process crashed right after posting
+/// it)
+pub const RESULT_COMMENT_MARKER: &str = "<!-- rustls-bench-app:results -->";
+
+/// Marks the comment as the one where the PR's results are reported
+pub fn mark_result_comment(body: &mut String) {
+    body.insert_str(0, &format!("{RESULT_COMMENT_MARKER}\n"));
+}
+
+/// The prefix of the hidden HTML comment carrying a report's [`ReportMetadata`]
+const REPORT_METADATA_PREFIX: &str = "<!-- rustls-bench-app:metadata ";
+
+/// The version of the reports' format, to be bumped whenever the metadata or the report change in
+/// ways that tooling parsing them should know about
+pub const REPORT_VERSION: u32 = 1;
+
+/// Machine-readable metadata embedded in every report comment, so external tooling (and the app
+/// itself, after losing data) can parse results straight from GitHub
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ReportMetadata {
+    pub report_version: u32,
+    /// The id of the stored comparison, if the comparison succeeded
+    pub comparison_id: Option<Uuid>,
+    pub baseline_commit: String,
+    pub candidate_commit: String,
+    pub verdict: Verdict,
+}
+
+impl ReportMetadata {
+    /// Embeds the metadata at the top of the comment, as a hidden HTML comment
+    pub fn embed(&self, body: &mut String) {
+        let json = serde_json::to_string(self).expect("unreachable code");
+        body.insert_str(0, &format!("{REPORT_METADATA_PREFIX}{json} -->\n"));
+    }
+
+    /// Extracts the metadata embedded in the comment, if any
+    #[cfg(test)]
+    pub fn extract(body: &str) -> Option<Self> {
+        let start = body.find(REPORT_METADATA_PREFIX)? + REPORT_METADATA_PREFIX.len();
+        let end = start + body[start..].find(" -->")?;
+        serde_json::from_str(&body[start..end]).ok()
     }
 }
 
-/// Truncates a comment if it exceeds GitHub's size limit
-pub fn maybe_truncate_comment(body: &mut String) {
-    const GITHUB_COMMENT_MAX_LEN: usize = 65536;
+/// Returns the latest comment of the PR that carries the [`RESULT_COMMENT_MARKER`] and was
+/// posted by a user accepted by `is_own`
+pub async fn find_result_comment(
+    pr_number: u64,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    is_own: impl Fn(&api::GitHubUser) -> bool,
+) -> anyhow::Result<Option<CommentId>> {
+    const PER_PAGE: usize = 100;
+    const MAX_PAGES: usize = 10;
+
+    let mut found = None;
+    for page in 1..=MAX_PAGES {
+        let comments: Vec<api::Comment> = octocrab
+            .get(
+                format!(
+                    "/repos/{}/{}/issues/{pr_number}/comments?per_page={PER_PAGE}&page={page}",
+                    config.github_repo_owner, config.github_repo_name
+                ),
+                None::<&()>,
+            )
+            .await
+            .context("failed to list the PR's comments")?;
+
+        let last_page = comments.len() < PER_PAGE;
+        // Comments are listed oldest first, so later matches take precedence
+        if let Some(comment) = comments
+            .iter()
+            .rev()
+            .find(|c| is_own(&c.user) && c.body.contains(RESULT_COMMENT_MARKER))
+        {
+            found = Some(CommentId::from(comment.id));
+        }
+        if last_page {
+            break;
+        }
+    }
+
+    Ok(found)
+}
+
+/// GitHub's size limit for comment bodies, in bytes
+pub const GITHUB_COMMENT_MAX_LEN: usize = 65536;
+
+/// A section of a comment that can be omitted when the comment exceeds GitHub's size limit
+///
+/// Sections are delimited in the templates by `<!-- section:NAME -->` and `<!-- /section:NAME -->`
+/// markers, which GitHub doesn't render
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommentSection {
+    /// The tables of differences below the significance threshold
+    NegligibleDiffs,
+    /// The command logs of failed runs
+    Logs,
+}
 
-    if body.len() > GITHUB_COMMENT_MAX_LEN {
-        let prepend = format!("_Note: the comment has been truncated to respect GitHub's size limit of {GITHUB_COMMENT_MAX_LEN} bytes._");
-        body.truncate(GITHUB_COMMENT_MAX_LEN - prepend.len());
-        body.insert_str(0, &prepend);
+impl CommentSection {
+    fn name(self) -> &'static str {
+        match self {
+            CommentSection::NegligibleDiffs => "negligible_diffs",
+            CommentSection::Logs => "logs",
+        }
+    }
+
+    fn omitted_note(self) -> &'static str {
+        match self {
+            CommentSection::NegligibleDiffs => "_Omitted to respect GitHub's size limit_",
+            CommentSection::Logs => "_Logs omitted to respect GitHub's size limit_",
+        }
+    }
+}
+
+/// Shrinks a comment if it exceeds GitHub's size limit
+///
+/// The sections in `omit_order` are replaced by a short note, one kind at a time, until the
+/// comment fits. As a last resort, the end of the comment is cut off. A link to the full report is
+/// added to the comment, if available, when anything had to be left out.
+pub fn truncate_comment(
+    body: &mut String,
+    omit_order: &[CommentSection],
+    full_report_url: Option<&str>,
+) {
+    if body.len() <= GITHUB_COMMENT_MAX_LEN {
+        return;
+    }
+
+    let full_report = match full_report_url {
+        Some(url) => format!(" See the [full report]({url})."),
+        None => String::new(),
+    };
+
+    let omitted_prepend = format!("_Note: some sections have been omitted to respect GitHub's size limit of {GITHUB_COMMENT_MAX_LEN} bytes.{full_report}_\n\n");
+    for &section in omit_order {
+        omit_section(
+            body,
+            section,
+            &format!("{}{full_report}", section.omitted_note()),
+        );
+        if body.len() + omitted_prepend.len() <= GITHUB_COMMENT_MAX_LEN {
+            body.insert_str(0, &omitted_prepend);
+            return;
+        }
+    }
+
+    let prepend = format!("_Note: the comment has been truncated to respect GitHub's size limit of {GITHUB_COMMENT_MAX_LEN} bytes.{full_report}_\n\n");
+    let mut len = GITHUB_COMMENT_MAX_LEN.saturating_sub(prepend.len());
+    while !body.is_char_boundary(len) {
+        len -= 1;
+    }
+    body.truncate(len);
+    body.insert_str(0, &prepend);
+}
+
+/// Replaces the contents of every occurrence of the section by the note
+fn omit_section(body: &mut String, section: CommentSection, note: &str) {
+    let start_marker = format!("<!-- section:{} -->", section.name());
+    let end_marker = format!("<!-- /section:{} -->", section.name());
+
+    let mut search_from = 0;
+    while let Some(start) = body[search_from..].find(&start_marker) {
+        let contents_start = search_from + start + start_marker.len();
+        let Some(end) = body[contents_start..].find(&end_marker) else {
+            break;
+        };
+
+        let contents_end = contents_start + end;
+        let replacement = format!("\n\n{note}\n\n");
+        body.replace_range(contents_start..contents_end, &replacement);
+        search_from = contents_start + replacement.len() + end_marker.len();
     }
 }
 
@@ -217,6 +680,10 @@ pub fn verify_webhook_signature(body: &[u8], signature: &str, secret: &str) -> b
 #[cfg(test)]
 mod test {
     use super::api::*;
+    use super::{
+        truncate_comment, CommentSection, ReportMetadata, GITHUB_COMMENT_MAX_LEN, REPORT_VERSION,
+    };
+    use crate::db::Verdict;
 
     #[test]
     fn parse_comment_created_without_pr_event() {
@@ -243,4 +710,82 @@ mod test {
         assert_eq!(parsed.git_ref, "refs/heads/main");
         assert!(!parsed.deleted);
     }
+
+    fn section(name: &str, len: usize) -> String {
+        format!(
+            "<!-- section:{name} -->\n{}\n<!-- /section:{name} -->",
+            "x".repeat(len)
+        )
+    }
+
+    const OMIT_ORDER: &[CommentSection] = &[CommentSection::NegligibleDiffs, CommentSection::Logs];
+
+    #[test]
+    fn truncate_comment_within_limit() {
+        let original = format!("# Results\n\n{}", section("negligible_diffs", 60_000));
+        let mut body = original.clone();
+        truncate_comment(&mut body, OMIT_ORDER, Some("https://example.com/report.md"));
+        assert_eq!(body, original);
+    }
+
+    #[test]
+    fn truncate_comment_omits_sections_in_order() {
+        let mut body = format!(
+            "# Results\n\n{}\n\n{}\n\n## Additional information\n\nCheckout details",
+            section("negligible_diffs", 40_000),
+            section("logs", 40_000),
+        );
+        truncate_comment(&mut body, OMIT_ORDER, Some("https://example.com/report.md"));
+
+        assert!(body.len() <= GITHUB_COMMENT_MAX_LEN);
+        assert!(body.starts_with("_Note: some sections have been omitted"));
+        assert!(body.contains(
+            "_Omitted to respect GitHub's size limit_ See the [full report](https://example.com/report.md)."
+        ));
+        assert!(!body.contains("_Logs omitted"));
+        assert!(body.contains(&"x".repeat(40_000)));
+        assert!(body.ends_with("Checkout details"));
+
+        // Omitting the negligible diffs is not enough when the logs are too large
+        let mut body = format!(
+            "# Results\n\n{}\n\n{}\n\nCheckout details",
+            section("negligible_diffs", 1_000),
+            section("logs", GITHUB_COMMENT_MAX_LEN),
+        );
+        truncate_comment(&mut body, OMIT_ORDER, None);
+
+        assert!(body.len() <= GITHUB_COMMENT_MAX_LEN);
+        assert!(body.contains("_Logs omitted to respect GitHub's size limit_"));
+        assert!(!body.contains("full report"));
+        assert!(body.ends_with("Checkout details"));
+    }
+
+    #[test]
+    fn truncate_comment_cuts_off_the_end_as_last_resort() {
+        let mut body = format!("# Results\n\n{}", "é".repeat(GITHUB_COMMENT_MAX_LEN));
+        truncate_comment(&mut body, OMIT_ORDER, Some("https://example.com/report.md"));
+
+        assert!(body.len() <= GITHUB_COMMENT_MAX_LEN);
+        assert!(body.starts_with("_Note: the comment has been truncated to respect GitHub's size limit of 65536 bytes. See the [full report](https://example.com/report.md)._"));
+        assert!(body.contains("# Results"));
+    }
+
+    #[test]
+    fn report_metadata_roundtrip() {
+        let metadata = ReportMetadata {
+            report_version: REPORT_VERSION,
+            comparison_id: Some(uuid::Uuid::new_v4()),
+            baseline_commit: "7edbfb999b352aa09fe669e9103d8155d7e7d890".to_string(),
+            candidate_commit: "b0b69e925b2c9c6187cb16f361dd36e156f8e097".to_string(),
+            verdict: Verdict::Regressed,
+        };
+
+        let mut body = "# Benchmark results\n\nSome <!-- hidden --> text".to_string();
+        metadata.embed(&mut body);
+        assert!(body.starts_with("<!-- rustls-bench-app:metadata {"));
+        assert!(body.ends_with("# Benchmark results\n\nSome <!-- hidden --> text"));
+        assert_eq!(ReportMetadata::extract(&body), Some(metadata));
+
+        assert_eq!(ReportMetadata::extract("# Benchmark results"), None);
+    }
 }
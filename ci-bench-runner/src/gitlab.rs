@@ -0,0 +1,247 @@
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::forge::{CommitState, CommitStatus, Forge, ForgeFuture};
+
+/// The header GitLab uses to send the webhook's secret token
+pub static WEBHOOK_TOKEN_HEADER: &str = "X-Gitlab-Token";
+
+/// The header GitLab uses to identify the type of webhook event
+pub static WEBHOOK_EVENT_HEADER: &str = "X-Gitlab-Event";
+
+/// The queued event for merge request webhooks (prefixed, so it can't be confused with GitHub's)
+pub const MERGE_REQUEST_EVENT: &str = "gitlab_merge_request";
+
+/// The queued event for note (i.e. comment) webhooks
+pub const NOTE_EVENT: &str = "gitlab_note";
+
+/// The lowest access level allowed to use `@bot_name` commands (Developer)
+pub const COMMAND_ACCESS_LEVEL: u32 = 30;
+
+/// GitLab's configuration, for projects hosted on GitLab instead of GitHub
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct GitLabConfig {
+    /// Base URL of the GitLab API (defaults to `https://gitlab.com/api/v4`)
+    #[serde(default = "default_api_url")]
+    pub api_url: String,
+    /// Access token used to read merge requests and post notes and commit statuses (needs the
+    /// `api` scope)
+    pub token: String,
+    /// Secret token GitLab sends along with webhook payloads
+    pub webhook_secret: String,
+    /// Projects (as `namespace/name`) benchmarked through GitLab. Webhook events from other
+    /// projects are dropped
+    pub projects: Vec<String>,
+}
+
+fn default_api_url() -> String {
+    "https://gitlab.com/api/v4".to_string()
+}
+
+pub mod api {
+    //! Types used to deserialize GitLab's webhook payloads and API responses
+
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct MergeRequestEvent {
+        pub project: Project,
+        pub object_attributes: MergeRequestAttributes,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct NoteEvent {
+        pub project: Project,
+        pub user: User,
+        pub object_attributes: NoteAttributes,
+        /// The merge request the note was posted to, if any (notes can also be posted to issues,
+        /// commits and snippets)
+        pub merge_request: Option<MergeRequestAttributes>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct Project {
+        pub path_with_namespace: String,
+        pub git_http_url: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct User {
+        pub id: u64,
+        pub username: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct MergeRequestAttributes {
+        pub iid: u64,
+        pub source_branch: String,
+        pub target_branch: String,
+        pub source: Project,
+        pub target: Project,
+        /// Only present in merge request events (e.g. `open`, `update`)
+        pub action: Option<String>,
+        /// The previous head of the merge request, only present in `update` events caused by new
+        /// commits
+        pub oldrev: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct NoteAttributes {
+        pub note: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct MergeRequest {
+        /// Missing while GitLab is still computing the merge request's diff
+        pub diff_refs: Option<DiffRefs>,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct DiffRefs {
+        /// The merge base of the source and target branches
+        pub base_sha: String,
+        pub head_sha: String,
+    }
+
+    #[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+    pub struct Member {
+        pub access_level: u32,
+    }
+}
+
+/// Returns the queued event corresponding to a GitLab webhook event, if it is one we handle
+pub fn queued_event(webhook_event: &str) -> Option<&'static str> {
+    match webhook_event {
+        "Merge Request Hook" => Some(MERGE_REQUEST_EVENT),
+        "Note Hook" => Some(NOTE_EVENT),
+        _ => None,
+    }
+}
+
+/// Extracts the project (as `namespace/name`) from a webhook payload
+pub fn webhook_project(body: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Payload {
+        project: api::Project,
+    }
+
+    let payload: Payload = serde_json::from_slice(body).ok()?;
+    Some(payload.project.path_with_namespace)
+}
+
+/// A project hosted on GitLab, as a [`Forge`]
+pub struct GitLabForge<'a> {
+    config: &'a GitLabConfig,
+    /// The project's path, URL-encoded so it can be used as the project's id
+    project_id: String,
+    client: reqwest::Client,
+}
+
+impl<'a> GitLabForge<'a> {
+    pub fn new(config: &'a GitLabConfig, project: &str) -> Self {
+        Self {
+            config,
+            project_id: project.replace('/', "%2F"),
+            client: reqwest::Client::new(),
+        }
+    }
+
+    fn project_url(&self, path: &str) -> String {
+        format!(
+            "{}/projects/{}/{path}",
+            self.config.api_url.trim_end_matches('/'),
+            self.project_id
+        )
+    }
+
+    /// Sends a request to the GitLab API, parsing the JSON response
+    async fn send<T: DeserializeOwned>(
+        &self,
+        request: reqwest::RequestBuilder,
+    ) -> anyhow::Result<T> {
+        let response = request
+            .header("PRIVATE-TOKEN", &self.config.token)
+            .send()
+            .await
+            .context("failed to send request")?
+            .error_for_status()
+            .context("the GitLab API returned an error")?;
+        let body = response.bytes().await.context("failed to read response")?;
+        serde_json::from_slice(&body).context("invalid response from the GitLab API")
+    }
+
+    /// Fetches the current state of a merge request
+    pub async fn merge_request(&self, iid: u64) -> anyhow::Result<api::MergeRequest> {
+        let url = self.project_url(&format!("merge_requests/{iid}"));
+        self.send(self.client.get(url)).await
+    }
+
+    /// Returns the access level of a user in the project (including inherited memberships), or
+    /// `None` if the user is not a member
+    pub async fn access_level(&self, user_id: u64) -> anyhow::Result<Option<u32>> {
+        let url = self.project_url(&format!("members/all/{user_id}"));
+        let response = self
+            .client
+            .get(url)
+            .header("PRIVATE-TOKEN", &self.config.token)
+            .send()
+            .await
+            .context("failed to send request")?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let body = response
+            .error_for_status()
+            .context("the GitLab API returned an error")?
+            .bytes()
+            .await
+            .context("failed to read response")?;
+        let member: api::Member =
+            serde_json::from_slice(&body).context("invalid response from the GitLab API")?;
+        Ok(Some(member.access_level))
+    }
+}
+
+impl Forge for GitLabForge<'_> {
+    fn post_comment<'a>(&'a self, pr_number: u64, body: &'a str) -> ForgeFuture<'a, ()> {
+        Box::pin(async move {
+            let url = self.project_url(&format!("merge_requests/{pr_number}/notes"));
+            let _: serde_json::Value = self
+                .send(self.client.post(url).form(&[("body", body)]))
+                .await
+                .context("unable to post note")?;
+            Ok(())
+        })
+    }
+
+    fn set_commit_status<'a>(
+        &'a self,
+        commit_sha: &'a str,
+        status: &'a CommitStatus,
+    ) -> ForgeFuture<'a, ()> {
+        Box::pin(async move {
+            let state = match status.state {
+                CommitState::Pending => "running",
+                CommitState::Success => "success",
+                CommitState::Error => "failed",
+            };
+            let mut form = vec![
+                ("state", state),
+                ("name", status.context.as_str()),
+                ("target_url", status.target_url.as_str()),
+            ];
+            if let Some(description) = &status.description {
+                form.push(("description", description.as_str()));
+            }
+
+            let url = self.project_url(&format!("statuses/{commit_sha}"));
+            let _: serde_json::Value = self
+                .send(self.client.post(url).form(&form))
+                .await
+                .context("unable to create status")?;
+            Ok(())
+        })
+    }
+}
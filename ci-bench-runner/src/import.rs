@@ -0,0 +1,75 @@
+use std::sync::Arc;
+
+use anyhow::Context;
+use sqlx::{Connection, SqliteConnection};
+use tokio::sync::Mutex;
+
+use crate::bencher_dev::BencherDev;
+use crate::db::Db;
+use crate::job::{bencher_branch, MAIN_BRANCH};
+use crate::{AppConfig, MIGRATOR};
+
+/// Imports the historical results of the tracked branches from bencher.dev
+///
+/// Useful for deployments that tracked results in bencher.dev before this application existed (or
+/// after losing their database), so significance thresholds have history to be derived from right
+/// away. Runs of commits that are already in the database are skipped, so the import can safely be
+/// repeated.
+pub async fn import_bencher(config: &AppConfig) -> anyhow::Result<()> {
+    let bencher_config = config
+        .bencher
+        .clone()
+        .context("bencher.dev must be configured to import its data")?;
+    let bencher = BencherDev::new(bencher_config);
+
+    let mut sqlite = SqliteConnection::connect(&format!("sqlite:{}", config.path_to_db))
+        .await
+        .context("unable to open database")?;
+    MIGRATOR
+        .run(&mut sqlite)
+        .await
+        .context("failed to apply DB migration")?;
+    let db = Db::with_connection(Arc::new(Mutex::new(sqlite)));
+
+    for (branch, bencher_branch) in imported_branches(config) {
+        let runs = bencher
+            .fetch_runs(bencher_branch)
+            .await
+            .with_context(|| format!("unable to fetch the reports of `{bencher_branch}`"))?;
+
+        let mut imported = 0;
+        for run in &runs {
+            if db.bench_run_exists(branch, &run.commit_sha).await? {
+                continue;
+            }
+
+            db.store_run_results_at(branch, &run.commit_sha, run.start_time, run.results.clone())
+                .await
+                .with_context(|| format!("failed to store the results of {}", run.commit_sha))?;
+            imported += 1;
+        }
+
+        println!(
+            "imported {imported} of the {} runs of `{branch}` found in bencher.dev (as `{bencher_branch}`)",
+            runs.len()
+        );
+    }
+
+    Ok(())
+}
+
+/// Returns the branches whose history is imported, each along with the name under which its
+/// results are published to bencher.dev (see [`bencher_branch`])
+pub fn imported_branches(config: &AppConfig) -> Vec<(&str, &str)> {
+    let mut branches = vec![MAIN_BRANCH];
+    for tracked in &config.tracked_branches {
+        if !branches.contains(&tracked.name.as_str()) {
+            branches.push(&tracked.name);
+        }
+    }
+
+    branches
+        .into_iter()
+        .map(|branch| (branch, bencher_branch(config, branch).unwrap_or(branch)))
+        .collect()
+}
@@ -0,0 +1,146 @@
+use std::collections::HashMap;
+
+use crate::db::{ComparisonResult, ScenarioKind};
+
+/// The result of checking whether a backport performs like the change it backports
+#[derive(Debug)]
+pub struct BackportCheck {
+    /// The number of the PR that introduced the original change
+    pub original_pr: u64,
+    pub status: BackportStatus,
+}
+
+/// The status of a backport check
+#[derive(Debug)]
+pub enum BackportStatus {
+    /// The backport was compared against the original change
+    Compared(BackportOutcome),
+    /// The original change is still waiting to be benchmarked, in a job of its own
+    Pending,
+    /// The original change couldn't be compared, for the provided reason
+    Failed(String),
+}
+
+/// The comparison between the effect of a backport and the effect of the original change
+#[derive(Debug, PartialEq)]
+pub struct BackportOutcome {
+    /// The number of icount scenarios present in both comparisons
+    pub compared_scenarios: usize,
+    /// Scenarios where the backport's effect differs from the original change's effect
+    pub mismatches: Vec<BackportMismatch>,
+}
+
+/// An icount scenario where the backport's effect differs from the original change's effect
+#[derive(Debug, PartialEq)]
+pub struct BackportMismatch {
+    pub scenario_name: String,
+    /// The relative change caused by the original change
+    pub original_diff_ratio: f64,
+    /// The relative change caused by the backport
+    pub backport_diff_ratio: f64,
+}
+
+/// Returns the number of the backported PR, if the PR's body contains a `backport of #N` marker
+///
+/// The marker is case-insensitive
+pub fn backported_pr_number(pr_body: &str) -> Option<u64> {
+    static MARKER: &str = "backport of #";
+
+    let pr_body = pr_body.to_lowercase();
+    let start = pr_body.find(MARKER)? + MARKER.len();
+    let digits: String = pr_body[start..]
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+
+    digits.parse().ok()
+}
+
+/// Compares the instruction count effect of a backport against that of the original change
+///
+/// A scenario mismatches when the two relative changes differ by more than the scenario's
+/// significance threshold (the largest of both comparisons), so noise-level differences between
+/// branches don't get reported
+pub fn compare_backport(
+    original: &ComparisonResult,
+    backport: &ComparisonResult,
+) -> BackportOutcome {
+    let original_diffs: HashMap<_, _> = original
+        .metric(ScenarioKind::Icount)
+        .diffs
+        .iter()
+        .map(|diff| (diff.scenario_name.as_str(), diff))
+        .collect();
+
+    let mut compared_scenarios = 0;
+    let mut mismatches = Vec::new();
+    for backport_diff in &backport.metric(ScenarioKind::Icount).diffs {
+        let Some(original_diff) = original_diffs.get(backport_diff.scenario_name.as_str()) else {
+            continue;
+        };
+
+        compared_scenarios += 1;
+        let threshold = original_diff
+            .significance_threshold
+            .max(backport_diff.significance_threshold);
+        let original_diff_ratio = original_diff.diff_ratio();
+        let backport_diff_ratio = backport_diff.diff_ratio();
+        if (original_diff_ratio - backport_diff_ratio).abs() > threshold {
+            mismatches.push(BackportMismatch {
+                scenario_name: backport_diff.scenario_name.clone(),
+                original_diff_ratio,
+                backport_diff_ratio,
+            });
+        }
+    }
+
+    mismatches.sort_by(|m1, m2| m1.scenario_name.cmp(&m2.scenario_name));
+    BackportOutcome {
+        compared_scenarios,
+        mismatches,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::ScenarioDiff;
+
+    fn comparison(diffs: &[(&str, f64, f64)]) -> ComparisonResult {
+        let diffs = diffs
+            .iter()
+            .map(|&(scenario, baseline, candidate)| {
+                ScenarioDiff::icount(scenario, baseline, candidate, 0.01)
+            })
+            .collect();
+        ComparisonResult::from_diffs(diffs, &[], Vec::new())
+    }
+
+    #[test]
+    fn backported_pr_number_from_marker() {
+        assert_eq!(
+            backported_pr_number("Backport of #1234 to 0.21"),
+            Some(1234)
+        );
+        assert_eq!(backported_pr_number("This is a backport of #56."), Some(56));
+        assert_eq!(backported_pr_number("Backport of 1234"), None);
+        assert_eq!(backported_pr_number("Fixes #1234"), None);
+    }
+
+    #[test]
+    fn compare_backport_reports_mismatches_beyond_threshold() {
+        let original = comparison(&[("a", 100.0, 90.0), ("b", 100.0, 90.0), ("c", 1.0, 1.0)]);
+        let backport = comparison(&[("a", 200.0, 181.0), ("b", 100.0, 100.0), ("d", 1.0, 2.0)]);
+
+        let outcome = compare_backport(&original, &backport);
+        assert_eq!(outcome.compared_scenarios, 2);
+        assert_eq!(
+            outcome.mismatches,
+            vec![BackportMismatch {
+                scenario_name: "b".to_string(),
+                original_diff_ratio: -0.1,
+                backport_diff_ratio: 0.0,
+            }]
+        );
+    }
+}
@@ -1,46 +1,100 @@
+use std::collections::HashMap;
 use std::fs;
 
 use anyhow::{bail, Context};
+use askama::Template;
 use bencher_client::json::DateTime;
+use serde::Deserialize;
 use tempfile::TempDir;
 use tracing::{trace, warn};
 
-use super::{icounts_path, read_icount_results, read_walltime_results, walltimes_path};
-use crate::db::ScenarioKind;
-use crate::event_queue::JobContext;
+use super::bench_pr::{compare_main_runs, load_significance_thresholds};
+use super::{
+    aggregate_icounts, estimated_cycles, read_cache_misses, read_icount_results,
+    read_max_rss_results, read_walltime_results, BUILD_TIME_SCENARIO,
+};
+use crate::artifacts::write_job_logs;
+use crate::db::{RegressedScenario, ScenarioDiff, ScenarioKind};
+use crate::energy::read_energy_results;
+use crate::event_queue::{JobContext, JobPhase, PoisonEvent};
 use crate::github::api::PushEvent;
-use crate::runner::write_logs_for_run;
-use crate::CommitIdentifier;
+use crate::github::merged_prs_between;
+use crate::notifications::{scenario_owners, Notification};
+use crate::perf_stat::{perf_stat_path, read_perf_stat_results};
+use crate::runner::{read_artifact_sizes, read_build_time, read_determinism_discrepancy};
+use crate::{AppConfig, CommitIdentifier};
 
 pub static MAIN_BRANCH: &str = "main";
 
-/// Handle a push to main
+/// A long-lived branch that is benchmarked on push, besides main (e.g. a release branch)
+///
+/// Each tracked branch has its own history of results, from which its significance thresholds and
+/// regressions are derived
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct TrackedBranchConfig {
+    /// Name of the git branch (e.g. `rel-0.23`)
+    pub name: String,
+    /// Name of the branch in bencher.dev (defaults to the git branch's name)
+    pub bencher_branch: Option<String>,
+}
+
+/// Returns the name under which the branch's results are published to bencher.dev, or `None` if
+/// the branch isn't tracked
+///
+/// Main is always tracked, though its bencher.dev name can be configured like any other branch's
+pub fn bencher_branch<'a>(config: &'a AppConfig, branch: &'a str) -> Option<&'a str> {
+    match config.tracked_branches.iter().find(|b| b.name == branch) {
+        Some(tracked) => Some(tracked.bencher_branch.as_deref().unwrap_or(&tracked.name)),
+        None if branch == MAIN_BRANCH => Some(MAIN_BRANCH),
+        None => None,
+    }
+}
+
+/// Returns whether pushes to the branch are benchmarked
+pub fn is_tracked_branch(config: &AppConfig, branch: &str) -> bool {
+    bencher_branch(config, branch).is_some()
+}
+
+/// Handle a push to main or to another tracked branch
 ///
 /// Runs the benchmarks for the head commit and stores the results in the database so they can be
 /// used later (e.g. for deriving the significance threshold)
 pub async fn bench_main(ctx: JobContext<'_>) -> anyhow::Result<()> {
     // Ideally, we'd use WebhookEvent::try_from_header_and_body from `octocrab`, but it doesn't have
     // the `repository` field on the payload, which we need.
-    let Ok(payload) = serde_json::from_slice::<PushEvent>(ctx.event_payload) else {
-        bail!("invalid JSON payload, ignoring event");
-    };
+    let payload = serde_json::from_slice::<PushEvent>(ctx.event_payload)
+        .context("invalid JSON payload")
+        .map_err(|e| e.context(PoisonEvent))?;
+
+    bench_push(ctx, payload).await
+}
 
+/// Benchmarks the head commit of a push to a tracked branch, which might also be synthesized by
+/// the application (e.g. to benchmark main again on a schedule)
+pub(super) async fn bench_push(ctx: JobContext<'_>, payload: PushEvent) -> anyhow::Result<()> {
     if payload.deleted {
         trace!("ignoring push event for deleted ref");
         return Ok(());
     }
 
-    if payload.git_ref != "refs/heads/main" {
-        trace!("ignoring push event for non-main ref: {}", payload.git_ref);
-        return Ok(());
-    }
+    let branch = match payload.git_ref.strip_prefix("refs/heads/") {
+        Some(branch) if is_tracked_branch(ctx.config, branch) => branch.to_string(),
+        _ => {
+            trace!("ignoring push event for untracked ref: {}", payload.git_ref);
+            return Ok(());
+        }
+    };
 
     let benchmark_run_start = DateTime::now();
 
-    // Run the benchmarks on the main branch
+    // Run the benchmarks on the branch
+    ctx.enter_phase(JobPhase::Benchmarking).await;
     let job_output_dir = ctx.job_output_dir.clone();
     let bench_runner = ctx.bench_runner.clone();
+    let cancellation = ctx.cancellation.clone();
     let commit_sha = payload.after.clone();
+    let bench_suite = ctx.config.bench_suite.clone();
+    let branch_name = branch.clone();
     tokio::task::spawn_blocking(move || -> anyhow::Result<_> {
         fs::create_dir_all(&job_output_dir).context("unable to create job output dir")?;
         let base_repo = TempDir::new().context("unable to create temp dir")?;
@@ -50,21 +104,23 @@ pub async fn bench_main(ctx: JobContext<'_>) -> anyhow::Result<()> {
         let result = bench_runner.checkout_and_run_benchmarks(
             &CommitIdentifier {
                 clone_url: payload.repository.clone_url,
-                branch_name: MAIN_BRANCH.to_string(),
+                branch_name: branch_name.clone(),
                 commit_sha,
+                patch_series: None,
+                pinned_harness: None,
             },
             &base_repo_path,
             &job_output_dir,
+            &bench_suite,
             &mut logs,
+            &cancellation,
         );
 
-        let mut s = String::new();
-        write_logs_for_run(&mut s, &logs);
-        fs::write(job_output_dir.join("logs.md"), s).context("unable to write job logs")?;
+        write_job_logs(&job_output_dir, &[(branch_name.as_str(), logs.as_slice())])?;
 
         result.with_context(|| {
             format!(
-                "unable to run benchmarks for main branch. Check the logs at {} for more details.",
+                "unable to run benchmarks for {branch_name} branch. Check the logs at {} for more details.",
                 job_output_dir.display()
             )
         })
@@ -74,35 +130,164 @@ pub async fn bench_main(ctx: JobContext<'_>) -> anyhow::Result<()> {
 
     let benchmark_run_end = DateTime::now();
 
+    // Results measured in an unstable environment would pollute the branch's history (the job is
+    // retried according to the retry policy for infrastructure errors)
+    if let Some(discrepancy) = read_determinism_discrepancy(&ctx.job_output_dir)? {
+        bail!(
+            "discarded the results of an environmentally unstable run (`{}` measured {} and {} instructions)",
+            discrepancy.scenario,
+            discrepancy.first_icount,
+            discrepancy.second_icount
+        );
+    }
+
     // Get the benchmark results back from the filesystem
-    let icounts = read_icount_results(&icounts_path(&ctx.job_output_dir))
-        .context("failed to read instruction counts from file")?;
-    let walltimes = read_walltime_results(&walltimes_path(&ctx.job_output_dir))
+    let bench_suite = &ctx.config.bench_suite;
+    // The perf stat backend measures hardware counters instead of instruction counts
+    let icounts_path = bench_suite.icounts_path(&ctx.job_output_dir);
+    let icounts = if ctx.config.perf_stat.is_some() && !icounts_path.is_file() {
+        HashMap::new()
+    } else {
+        read_icount_results(&icounts_path).context("failed to read instruction counts from file")?
+    };
+
+    // Cache misses are only available if the icount benchmarks run with cache simulation
+    let cache_misses = read_cache_misses(bench_suite, &ctx.job_output_dir, icounts.keys())
+        .context("failed to read cache misses from callgrind outputs")?;
+    let estimated_cycles = estimated_cycles(&icounts, &cache_misses);
+
+    // Hardware counters are only available if the benchmarks run under `perf stat`
+    let perf_counters = read_perf_stat_results(&perf_stat_path(bench_suite, &ctx.job_output_dir))
+        .context("failed to read hardware counters from file")?;
+
+    // Track the overall index and the index of each TLS protocol version as synthetic scenarios,
+    // under a kind of their own so they don't feed thresholds or regression alerts (bencher.dev
+    // gets them under a measure of their own too)
+    let aggregates: HashMap<_, _> = aggregate_icounts(&icounts).into_iter().collect();
+    let walltimes = read_walltime_results(&bench_suite.walltimes_path(&ctx.job_output_dir))
         .context("failed to read walltimes from file")?;
+    let max_rss = if bench_suite.max_rss_args.is_empty() {
+        HashMap::new()
+    } else {
+        read_max_rss_results(&bench_suite.max_rss_path(&ctx.job_output_dir))
+            .context("failed to read memory usage results from file")?
+    };
+    // Energy is only available if the walltime benchmarks run with a RAPL zone configured
+    let energy = read_energy_results(&bench_suite.energy_path(&ctx.job_output_dir))
+        .context("failed to read energy results from file")?;
+    let build_time = read_build_time(&ctx.job_output_dir)?;
+
+    // Compare against the previous run of the branch, before the new results become part of its
+    // history
+    let previous_run = ctx
+        .db
+        .latest_results(&branch)
+        .await
+        .context("failed to load the results of the previous run")?;
+    let significance_thresholds = load_significance_thresholds(&ctx.db, &branch).await?;
 
     // Persist results in the DB and in bencher.dev
-    let results = icounts
-        .iter()
-        .map(|(scenario, result)| (scenario.clone(), ScenarioKind::Icount, *result))
-        .chain(
-            walltimes
-                .iter()
-                .map(|(scenario, result)| (scenario.clone(), ScenarioKind::Walltime, *result)),
-        )
-        .collect();
-    ctx.db
-        .store_run_results(results)
+    ctx.enter_phase(JobPhase::StoringResults).await;
+    let results =
+        icounts
+            .iter()
+            .map(|(scenario, result)| (scenario.clone(), ScenarioKind::Icount, *result))
+            .chain(
+                aggregates.iter().map(|(scenario, result)| {
+                    (scenario.clone(), ScenarioKind::IcountIndex, *result)
+                }),
+            )
+            .chain(
+                walltimes
+                    .iter()
+                    .map(|(scenario, result)| (scenario.clone(), ScenarioKind::Walltime, *result)),
+            )
+            .chain(
+                max_rss
+                    .iter()
+                    .map(|(scenario, result)| (scenario.clone(), ScenarioKind::MaxRss, *result)),
+            )
+            .chain(cache_misses.iter().flat_map(|(scenario, misses)| {
+                [
+                    (scenario.clone(), ScenarioKind::D1Misses, misses.d1),
+                    (scenario.clone(), ScenarioKind::LlMisses, misses.ll),
+                ]
+            }))
+            .chain(estimated_cycles.iter().map(|(scenario, result)| {
+                (scenario.clone(), ScenarioKind::EstimatedCycles, *result)
+            }))
+            .chain(perf_counters.iter().flat_map(|(scenario, counters)| {
+                [
+                    (scenario.clone(), ScenarioKind::PerfCycles, counters.cycles),
+                    (
+                        scenario.clone(),
+                        ScenarioKind::PerfInstructions,
+                        counters.instructions,
+                    ),
+                    (
+                        scenario.clone(),
+                        ScenarioKind::PerfBranchMisses,
+                        counters.branch_misses,
+                    ),
+                ]
+            }))
+            .chain(
+                energy
+                    .iter()
+                    .map(|(scenario, result)| (scenario.clone(), ScenarioKind::Energy, *result)),
+            )
+            .chain(build_time.map(|build_time| {
+                (
+                    BUILD_TIME_SCENARIO.to_string(),
+                    ScenarioKind::BuildTime,
+                    build_time,
+                )
+            }))
+            .collect();
+    let bench_run_id = ctx
+        .db
+        .store_run_results(&branch, &payload.after, results)
         .await
         .context("failed to store benchmark results")?;
+    ctx.db
+        .record_job_bench_run(ctx.job_id, bench_run_id)
+        .await
+        .context("failed to link bench run to job")?;
+    if let Some(sizes) = read_artifact_sizes(&ctx.job_output_dir)? {
+        ctx.db
+            .store_artifact_sizes(&payload.after, &sizes)
+            .await
+            .context("failed to store artifact sizes")?;
+    }
+
+    // Track and alert about significant regressions
+    ctx.enter_phase(JobPhase::Reporting).await;
+    if let Some((previous_commit_sha, previous_results)) = previous_run {
+        let mut regressions =
+            compare_main_runs(&previous_results, &icounts, &significance_thresholds)?;
+        regressions.retain(|diff| diff.is_significant() && diff.diff() > 0.0);
+        if !regressions.is_empty() {
+            report_regressions(
+                &ctx,
+                &branch,
+                previous_commit_sha,
+                &payload.after,
+                regressions,
+            )
+            .await?;
+        }
+    }
 
     if let Some(bencher_dev) = ctx.bencher_dev {
+        let bencher_branch = bencher_branch(ctx.config, &branch).unwrap_or(&branch);
         let result = bencher_dev
             .track_results(
-                MAIN_BRANCH,
+                bencher_branch,
                 &payload.after,
                 benchmark_run_start,
                 benchmark_run_end,
                 icounts,
+                aggregates,
                 walltimes,
             )
             .await
@@ -117,3 +302,101 @@ pub async fn bench_main(ctx: JobContext<'_>) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Tracks a regression detected on a tracked branch, comments on the PRs that introduced it and
+/// notifies the project
+async fn report_regressions(
+    ctx: &JobContext<'_>,
+    branch: &str,
+    previous_commit_sha: String,
+    commit_sha: &str,
+    regressions: Vec<ScenarioDiff>,
+) -> anyhow::Result<()> {
+    let scenarios: Vec<_> = regressions.iter().map(RegressedScenario::from).collect();
+    let regression_id = ctx
+        .db
+        .store_regression(branch, &previous_commit_sha, commit_sha, &scenarios)
+        .await
+        .context("failed to store regression")?;
+
+    let owners = scenario_owners(
+        &ctx.config.scenario_owners,
+        regressions.iter().map(|diff| diff.scenario_name.as_str()),
+    );
+
+    // Close the loop for contributors, on a best-effort basis
+    let octocrab = ctx.octocrab.cached();
+    let pr_numbers =
+        match merged_prs_between(&previous_commit_sha, commit_sha, ctx.config, &octocrab).await {
+            Ok(pr_numbers) => pr_numbers,
+            Err(e) => {
+                warn!(
+                    cause = format!("{e:#}"),
+                    "unable to find the PRs that introduced the regression"
+                );
+                Vec::new()
+            }
+        };
+
+    if !pr_numbers.is_empty() {
+        ctx.db
+            .link_regression_prs(regression_id, &pr_numbers)
+            .await
+            .context("failed to link regression to PRs")?;
+
+        let comment = PostMergeRegressionComment {
+            branch,
+            first_bad_commit: commit_sha,
+            last_good_commit: &previous_commit_sha,
+            scenarios: &scenarios,
+            pr_count: pr_numbers.len(),
+            regression_url: format!(
+                "{}/regressions/dashboard#{regression_id}",
+                ctx.config.app_base_url
+            ),
+            owners: &owners,
+        }
+        .render()
+        .expect("failed to render askama template");
+
+        for &pr_number in &pr_numbers {
+            let result = octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(pr_number, &comment)
+                .await;
+            if let Err(e) = result {
+                warn!(
+                    cause = e.to_string(),
+                    "unable to comment on PR #{pr_number} about a regression"
+                );
+            }
+        }
+    }
+
+    ctx.notifier
+        .notify(&Notification::Regression {
+            regression_id,
+            branch: branch.to_string(),
+            commit_sha: commit_sha.to_string(),
+            previous_commit_sha,
+            regressions,
+            owners,
+            pr_numbers,
+        })
+        .await;
+
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "post_merge_regression_comment.md")]
+struct PostMergeRegressionComment<'a> {
+    branch: &'a str,
+    first_bad_commit: &'a str,
+    last_good_commit: &'a str,
+    scenarios: &'a [RegressedScenario],
+    /// The number of merged PRs that introduced the regressed commit range
+    pr_count: usize,
+    regression_url: String,
+    owners: &'a [String],
+}
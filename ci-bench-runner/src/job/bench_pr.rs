@@ -1,7 +1,8 @@
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Write;
 use std::fs;
+use std::future::Future;
 use std::ops::Deref;
 use std::path::Path;
 use std::process::Command;
@@ -9,21 +10,56 @@ use std::process::Command;
 use anyhow::{anyhow, bail, Context};
 use askama::Template;
 use octocrab::models::pulls::PullRequest;
+use octocrab::models::reactions::ReactionContent;
 use octocrab::models::webhook_events::payload::PullRequestWebhookEventAction;
 use octocrab::models::webhook_events::{WebhookEvent, WebhookEventPayload};
-use octocrab::models::StatusState;
+use octocrab::models::CommentId;
 use octocrab::Octocrab;
+use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
 use time::{Duration, OffsetDateTime};
-use tracing::{error, trace};
+use tracing::{error, info, trace, warn};
+use uuid::Uuid;
 
-use super::{icounts_path, read_icount_results, read_walltime_results, walltimes_path};
-use crate::db::{BenchResult, ComparisonResult, ComparisonSubResult, ScenarioDiff, ScenarioKind};
-use crate::event_queue::JobContext;
-use crate::github::api::{CommentEvent, PullRequestReviewEvent};
-use crate::github::{self, update_commit_status};
-use crate::runner::{write_logs_for_run, BenchRunner, Log};
-use crate::CommitIdentifier;
+use super::backport::{backported_pr_number, compare_backport, BackportCheck, BackportStatus};
+use super::commands::{help_text, parse_comment, CommandHandler, ParsedComment};
+use super::dependency_bump::{bumped_crates, crate_costs, is_dependency_bump, DependencyUpgrade};
+use super::{
+    estimated_cycles, geometric_mean, is_tracked_branch, read_cache_misses, read_icount_results,
+    read_max_rss_results, read_walltime_results, CacheMisses, BUILD_TIME_SCENARIO, MAIN_BRANCH,
+};
+use crate::artifacts::{
+    store_comparison_result_with_overflow, write_job_logs, ArtifactStore, REPORT_FILE,
+};
+use crate::db::{
+    Annotation, ApiComparison, BenchResult, ComparisonResult, ComparisonSubResult, ScenarioDiff,
+    ScenarioKind, Verdict,
+};
+use crate::energy::read_energy_results;
+use crate::event_queue::{event_requester, EventQueue, JobContext, JobPhase, PoisonEvent};
+use crate::feature_flags::{FeatureRollout, ReportFeature, ReportFeatures};
+use crate::forge::{update_commit_status, CommitState, CommitStatus, StatusContext};
+use crate::github::api::{
+    CommentEvent, GitHubUser, OpenPullRequest, PullRequestFile, PullRequestReviewEvent,
+};
+use crate::github::{self, CommentSection, GitHubForge, ReportMetadata, REPORT_VERSION};
+use crate::perf_stat::{perf_stat_path, read_perf_stat_results, PerfCounters};
+use crate::runner::{
+    matches_glob, read_artifact_sizes, read_build_time, read_determinism_discrepancy,
+    read_reproduction, write_logs_for_run, BenchRunner, CancellationToken, DeterminismDiscrepancy,
+    FailureClass, Log, Reproduction,
+};
+use crate::units::{DisplayScale, ResultUnit};
+use crate::{
+    AppConfig, BenchSuiteConfig, CommitIdentifier, Db, PatchSeries, PinnedHarness,
+    PINNED_HARNESS_KEY_SEPARATOR,
+};
+
+/// The flag that makes a bench command benchmark the baseline with the candidate's bench harness
+static PIN_HARNESS_FLAG: &str = "--pin-harness";
+
+/// The flag that makes a benchmarking command jump ahead of other queued events
+static URGENT_FLAG: &str = "--urgent";
 
 static ALLOWED_AUTHOR_ASSOCIATIONS: &[&str] = &[
     // The owner of the repository
@@ -34,24 +70,38 @@ static ALLOWED_AUTHOR_ASSOCIATIONS: &[&str] = &[
     "COLLABORATOR",
 ];
 
+/// The author associations allowed to reprioritize the queue, which affects everyone's jobs
+static PRIORITIZE_AUTHOR_ASSOCIATIONS: &[&str] = &["OWNER", "MEMBER"];
+
 /// Handle an "issue comment"
 ///
 /// Runs the PR benchmarks if the comment:
 /// - Has just been created (edits are ignored);
 /// - Has been posted to a PR (not to an issue);
 /// - Has been posted by an authorized user; and
-/// - Addresses the bot with the right command (`@APP_NAME bench`).
+/// - Addresses the bot with the right command (`@bot_name bench`, `@bot_name bench main`,
+///   `@bot_name bench --quick`, `@bot_name bench-walltime` or
+///   `@bot_name bench-commits <first>..<last>`). Adding `--pin-harness` benchmarks the baseline
+///   with the candidate's bench harness, and adding a glob (e.g. `@bot_name bench handshake_*`)
+///   only benchmarks the matching scenarios. Adding `--urgent` handles the command before other
+///   queued events (see [`is_urgent_command`]). `@bot_name compare <base> <head>` compares two
+///   arbitrary commits of the repository instead of the PR's branches.
+///
+/// Discards the cached comparison of the PR's current commits and runs the benchmarks again if the
+/// comment is `@bot_name retry` (e.g. to regenerate a flaky result).
+///
+/// Refreshes the PR's report without running benchmarks if the comment is `@bot_name preview`.
+///
+/// Lists the available commands (see [`super::commands`]) if the comment is `@bot_name help` or
+/// an unknown command.
+///
+/// Authorized `@bot_name cancel` comments never reach the queue (see [`handle_cancel_command`]).
 pub async fn handle_issue_comment(ctx: JobContext<'_>) -> anyhow::Result<()> {
     // Ideally, we'd use WebhookEvent::try_from_header_and_body from `octocrab`, but it doesn't have
     // the `author_association` field on the comment, which we need.
-    let Ok(payload) = serde_json::from_slice::<CommentEvent>(ctx.event_payload) else {
-        error!(
-            event = ctx.event,
-            body = String::from_utf8_lossy(ctx.event_payload).to_string(),
-            "invalid JSON payload, ignoring event"
-        );
-        return Ok(());
-    };
+    let payload = serde_json::from_slice::<CommentEvent>(ctx.event_payload)
+        .context("invalid JSON payload")
+        .map_err(|e| e.context(PoisonEvent))?;
 
     if payload.issue.pull_request.is_none() {
         trace!("the comment was to a plain issue (not to a PR), ignoring event");
@@ -63,7 +113,7 @@ pub async fn handle_issue_comment(ctx: JobContext<'_>) -> anyhow::Result<()> {
         trace!("ignoring event for `{}` action", payload.action);
         return Ok(());
     }
-    if payload.comment.user.id == ctx.config.github_app_id {
+    if is_own_comment(ctx.config, &payload.comment.user) {
         trace!("ignoring comment from ourselves");
         return Ok(());
     }
@@ -72,364 +122,3112 @@ pub async fn handle_issue_comment(ctx: JobContext<'_>) -> anyhow::Result<()> {
             "ignoring comment from unauthorized user (author association = {})",
             payload.comment.author_association
         );
+
+        if body.contains(&format!("@{}", ctx.config.bot_name)) {
+            explain_skipped(&ctx, payload.issue.number, SkipReason::UnauthorizedUser).await?;
+        }
+
         return Ok(());
     }
 
     let octocrab = ctx.octocrab.cached();
-    if body.contains(&format!("@{APP_NAME} bench")) {
-        let pr = octocrab
-            .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
-            .get(payload.issue.number)
-            .await
-            .context("unable to get PR details")?;
+    let (name, handler, args) = match parse_comment(body, &ctx.config.bot_name) {
+        ParsedComment::NotAddressed => {
+            trace!("the comment was not addressed at the application");
+            return Ok(());
+        }
+        ParsedComment::Unknown => {
+            trace!("the comment was addressed at the application, but it is an unknown command!");
+            let comment = format!(
+                "Unrecognized command. Available commands are:\n{}",
+                help_text(&ctx.config.bot_name)
+            );
+            octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(payload.issue.number, comment)
+                .await?;
+            return Ok(());
+        }
+        ParsedComment::Command {
+            name,
+            handler,
+            args,
+        } => (name, handler, args),
+    };
 
-        let branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
-        bench_pr(ctx, pr.number, branches).await
-    } else if body.contains(&format!("@{APP_NAME}")) {
-        trace!("the comment was addressed at the application, but it is an unknown command!");
-        let comment = format!(
-            "Unrecognized command. Available commands are:\n\
-        * `@{APP_NAME} bench`: runs the instruction count benchmarks and reports the results"
-        );
-        octocrab
-            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
-            .create_comment(payload.issue.number, comment)
-            .await?;
-        Ok(())
-    } else {
-        trace!("the comment was not addressed at the application");
-        Ok(())
+    let config = ctx.config;
+    match handler {
+        CommandHandler::BenchPr | CommandHandler::RetryFailed => {
+            let Some(pr) = approved_pr(&ctx, &payload, &octocrab).await? else {
+                return Ok(());
+            };
+
+            let run = async {
+                match handler {
+                    CommandHandler::RetryFailed => {
+                        retry_failed_for_comment(ctx, pr, &octocrab).await
+                    }
+                    _ => {
+                        let command = BenchCommand::parse(name, args);
+                        bench_pr_for_comment(ctx, &payload, command, pr, &octocrab).await
+                    }
+                }
+            };
+            run_with_reactions(config, &payload, &octocrab, run).await
+        }
+        CommandHandler::ApproveFork => {
+            let run = approve_fork_for_comment(ctx, &payload, args, &octocrab);
+            run_with_reactions(config, &payload, &octocrab, run).await
+        }
+        CommandHandler::Compare => {
+            let run = compare_for_comment(ctx, &payload, args, &octocrab);
+            run_with_reactions(config, &payload, &octocrab, run).await
+        }
+        CommandHandler::Preview => preview_report(ctx, &payload, &octocrab).await,
+        CommandHandler::Cancel | CommandHandler::Prioritize => {
+            trace!(
+                "cancel and prioritize commands are handled as soon as they arrive, ignoring event"
+            );
+            Ok(())
+        }
+        CommandHandler::Help => {
+            let comment = format!(
+                "Available commands are:\n{}",
+                help_text(&ctx.config.bot_name)
+            );
+            octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(payload.issue.number, comment)
+                .await?;
+            Ok(())
+        }
     }
 }
 
-/// Handle a "PR review"
-///
-/// Runs the PR benchmarks if the review:
-/// - Is an approval; and
-/// - Has just been submitted by an authorized user.
-pub async fn handle_pr_review(ctx: JobContext<'_>) -> anyhow::Result<()> {
-    // Ideally, we'd use WebhookEvent::try_from_header_and_body from `octocrab`, but it doesn't have
-    // the `author_association` field on the review (which we need) and it requires the `head` field
-    // on the PR (which is not provided).
-    let Ok(payload) = serde_json::from_slice::<PullRequestReviewEvent>(ctx.event_payload) else {
-        error!(
-            event = ctx.event,
-            body = String::from_utf8_lossy(ctx.event_payload).to_string(),
-            "invalid JSON payload, ignoring event"
-        );
-        return Ok(());
+/// Runs a command that benchmarks something, reacting to the command's comment to signal its
+/// outcome (the command was already acknowledged when it was enqueued, see
+/// [`acknowledge_benchmarking_command`])
+async fn run_with_reactions(
+    config: &AppConfig,
+    payload: &CommentEvent,
+    octocrab: &Octocrab,
+    run: impl Future<Output = anyhow::Result<Verdict>>,
+) -> anyhow::Result<()> {
+    // GitHub doesn't offer a ❌ reaction, so failures are signaled with 😕 instead
+    let comment_id = payload.comment.id;
+    let result = run.await;
+    let reaction = match &result {
+        Ok(Verdict::Error) | Err(_) => ReactionContent::Confused,
+        Ok(_) => ReactionContent::Rocket,
     };
+    github::add_comment_reaction(comment_id, reaction, config, octocrab).await;
 
-    if payload.action != "submitted" {
-        trace!("ignoring pull request event with action {}", payload.action);
-        return Ok(());
-    }
+    result.map(|_| ())
+}
 
-    if !ALLOWED_AUTHOR_ASSOCIATIONS.contains(&payload.review.author_association.as_str()) {
-        trace!("ignoring review from untrusted author");
-        return Ok(());
-    }
+/// Returns the comment's PR, unless it comes from a fork and its head commit hasn't been approved
+/// by a maintainer yet (see [`approve_fork_for_comment`])
+///
+/// Without approval, a comment is posted asking a maintainer to review the PR's code and approve
+/// its head commit, since benchmarking means running the code on our machine
+async fn approved_pr(
+    ctx: &JobContext<'_>,
+    payload: &CommentEvent,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Option<PullRequest>> {
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(payload.issue.number)
+        .await
+        .context("unable to get PR details")?;
 
-    if payload.review.state != "approved" {
-        trace!("ignoring review with non-approved status");
-        return Ok(());
+    let branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
+    if !branches.is_from_fork()
+        || ctx
+            .db
+            .is_fork_commit_approved(pr.number, &branches.candidate.commit_sha)
+            .await?
+    {
+        return Ok(Some(pr));
     }
 
-    let pr = payload.pull_request;
-    let mut branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
-
-    // Ensure we bench the commit that was reviewed, and not something else
-    branches.candidate.commit_sha = payload.review.commit_id;
+    trace!("the PR comes from a fork and its head commit hasn't been approved");
+    let head_sha = &branches.candidate.commit_sha;
+    octocrab
+        .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .create_comment(
+            pr.number,
+            format!(
+                "This PR comes from a fork, so a maintainer must review its code before it runs \
+                on the benchmarking machine. After reviewing the PR up to commit `{head_sha}`, \
+                confirm by commenting `@{} approve {head_sha}`.",
+                ctx.config.bot_name
+            ),
+        )
+        .await?;
 
-    bench_pr(ctx, pr.number, branches).await
+    Ok(None)
 }
 
-/// Handle a "PR update"
+/// Approves running the code of a PR coming from a fork up to the provided commit, as requested by
+/// a `@bot_name approve <commit>` comment, and benchmarks it
 ///
-/// Runs the PR benchmarks if:
-/// - The PR originates from a trusted branch (i.e. branches from the repository, not from forks); and
-/// - The PR was just created (action is `opened`), or its branches were updated (action is `synchronize`).
-pub async fn handle_pr_update(ctx: JobContext<'_>) -> anyhow::Result<()> {
-    let Ok(event) = WebhookEvent::try_from_header_and_body(ctx.event, ctx.event_payload) else {
-        error!(
-            event = ctx.event,
-            body = String::from_utf8_lossy(ctx.event_payload).to_string(),
-            "invalid JSON payload, ignoring event"
-        );
-        return Ok(());
-    };
+/// The approval is pinned to the commit: it is only honored if the commit is still the PR's head,
+/// and new commits need a new approval. Code from forks runs in a stricter sandbox (see
+/// `BenchSuiteConfig::untrusted_command_prefix`).
+async fn approve_fork_for_comment(
+    ctx: JobContext<'_>,
+    payload: &CommentEvent,
+    args: &str,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Verdict> {
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(payload.issue.number)
+        .await
+        .context("unable to get PR details")?;
+    let branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
 
-    let WebhookEventPayload::PullRequest(payload) = event.specific else {
-        error!("invalid JSON payload, ignoring event");
-        return Ok(());
+    let approved_sha = args.split_whitespace().next().unwrap_or_default();
+    let head_sha = &branches.candidate.commit_sha;
+    let error = if !branches.is_from_fork() {
+        Some(format!(
+            "This PR doesn't come from a fork, so there is nothing to approve. Use `@{} bench` \
+            to run the benchmarks.",
+            ctx.config.bot_name
+        ))
+    } else if ctx.config.bench_suite.untrusted_command_prefix.is_empty() {
+        warn!("refusing to approve fork PR, because no `untrusted_command_prefix` is configured");
+        Some(
+            "Benchmarking code from forks is disabled, because no sandbox for untrusted code is \
+            configured (see `untrusted_command_prefix`)."
+                .to_string(),
+        )
+    } else if !is_commit_hash(approved_sha) {
+        Some(format!(
+            "Invalid `approve` command, expected `@{} approve <commit>` (where the commit is the \
+            hash of the PR's head commit)",
+            ctx.config.bot_name
+        ))
+    } else if !head_sha.starts_with(approved_sha) {
+        Some(format!(
+            "The PR's head commit is `{head_sha}`, not `{approved_sha}`. Please review the latest \
+            changes and approve `{head_sha}` instead."
+        ))
+    } else {
+        None
     };
-
-    let allowed_actions = [
-        PullRequestWebhookEventAction::Opened,
-        PullRequestWebhookEventAction::Synchronize,
-        PullRequestWebhookEventAction::Reopened,
-    ];
-    if !allowed_actions.contains(&payload.action) {
-        trace!(
-            "ignoring pull request event with action {:?}",
-            payload.action
-        );
-        return Ok(());
+    if let Some(error) = error {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(pr.number, error)
+            .await?;
+        return Ok(Verdict::Error);
     }
 
-    let branches =
-        pr_branches(&payload.pull_request).ok_or(anyhow!("unable to get PR branch details"))?;
-    if branches.baseline.clone_url != branches.candidate.clone_url {
-        trace!(
-            "ignoring pull request update for forked repo (base repo = {}, head repo = {})",
-            branches.baseline.clone_url,
-            branches.candidate.clone_url
-        );
-        return Ok(());
-    }
+    let approved_by = &payload.comment.user.login;
+    info!(
+        approved_by,
+        "approved commit {head_sha} of PR #{}", pr.number
+    );
+    ctx.db
+        .store_fork_approval(pr.number, head_sha, approved_by)
+        .await?;
 
-    bench_pr(ctx, payload.pull_request.number, branches).await
+    bench_pr(ctx, pr.number, branches, BenchMode::Full).await
 }
 
-pub async fn bench_pr(
-    ctx: JobContext<'_>,
-    pr_number: u64,
-    branches: PrBranches,
-) -> anyhow::Result<()> {
-    let job_url = format!("{}/jobs/{}", ctx.config.app_base_url, ctx.job_id);
-    let octocrab = ctx.octocrab.cached();
-    update_commit_status(
-        branches.candidate.commit_sha.clone(),
-        StatusState::Pending,
-        job_url.clone(),
-        ctx.config,
-        &octocrab,
+/// Returns the comment event if it is an authorized `@bot_name cancel` command on a PR
+pub fn parse_cancel_command(config: &AppConfig, event_payload: &[u8]) -> Option<CommentEvent> {
+    parse_pr_command(
+        config,
+        event_payload,
+        &[CommandHandler::Cancel],
+        ALLOWED_AUTHOR_ASSOCIATIONS,
     )
-    .await;
+}
 
-    let cached_result = ctx
-        .db
-        .comparison_result(
-            &branches.baseline.commit_sha,
-            &branches.candidate.commit_sha,
-        )
-        .await?;
-    let result = match cached_result {
-        Some(result) => Ok(result),
-        None => {
-            let mut logs = BenchPrLogs::default();
-            bench_pr_and_cache_results(&ctx, branches.clone(), &mut logs)
-                .await
-                .map_err(|error| BenchPrError { error, logs })
-        }
-    };
+/// Returns the comment event if it is a `@bot_name prioritize` command on a PR, posted by a
+/// repository owner or organization member
+pub fn parse_prioritize_command(config: &AppConfig, event_payload: &[u8]) -> Option<CommentEvent> {
+    parse_pr_command(
+        config,
+        event_payload,
+        &[CommandHandler::Prioritize],
+        PRIORITIZE_AUTHOR_ASSOCIATIONS,
+    )
+}
 
-    let cachegrind_diff_url = format!(
-        "{}/comparisons/{}:{}/cachegrind-diff",
-        ctx.config.app_base_url, branches.baseline.commit_sha, branches.candidate.commit_sha
-    );
-    let mut comment = markdown_comment(
-        &branches,
-        result,
-        &cachegrind_diff_url,
-        ctx.bencher_dev.map(|b| b.config.project_id.as_str()),
+/// Adds a 👀 reaction to the comment if it is an authorized command that benchmarks something on a
+/// PR, so users know it was accepted long before the results comment shows up
+///
+/// Meant to be called once the command's event is enqueued, since jobs report their outcome
+/// through reactions too (see [`run_with_reactions`])
+pub async fn acknowledge_benchmarking_command(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    event_payload: &[u8],
+) {
+    let payload = parse_pr_command(
+        config,
+        event_payload,
+        &[
+            CommandHandler::BenchPr,
+            CommandHandler::RetryFailed,
+            CommandHandler::ApproveFork,
+            CommandHandler::Compare,
+        ],
+        ALLOWED_AUTHOR_ASSOCIATIONS,
     );
-    github::maybe_truncate_comment(&mut comment);
+    if let Some(payload) = payload {
+        github::add_comment_reaction(payload.comment.id, ReactionContent::Eyes, config, octocrab)
+            .await;
+    }
+}
 
-    let update_result = try_update_comment(pr_number, &comment, &octocrab, &ctx).await;
-    if update_result.is_err() {
-        // Fall back to creating a comment if updating fails
-        let comment = octocrab
-            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
-            .create_comment(pr_number, comment)
-            .await?;
-        ctx.db
-            .store_result_comment_id(pr_number, comment.id)
-            .await?;
+/// Returns the comment event if it is a newly created PR comment, posted by a user with one of the
+/// author associations, that gives the application a command with one of the provided handlers
+fn parse_pr_command(
+    config: &AppConfig,
+    event_payload: &[u8],
+    handlers: &[CommandHandler],
+    author_associations: &[&str],
+) -> Option<CommentEvent> {
+    let payload = serde_json::from_slice::<CommentEvent>(event_payload).ok()?;
+    let is_command = payload.action == "created"
+        && payload.issue.pull_request.is_some()
+        && !is_own_comment(config, &payload.comment.user)
+        && author_associations.contains(&payload.comment.author_association.as_str())
+        && matches!(
+            parse_comment(&payload.comment.body, &config.bot_name),
+            ParsedComment::Command { handler, .. } if handlers.contains(&handler)
+        );
+
+    is_command.then_some(payload)
+}
+
+/// Returns true if the comment event is an authorized benchmarking command on a PR, flagged as
+/// urgent (e.g. `@bot_name bench --urgent`)
+pub fn is_urgent_command(config: &AppConfig, event_payload: &[u8]) -> bool {
+    let Ok(payload) = serde_json::from_slice::<CommentEvent>(event_payload) else {
+        return false;
+    };
+    let is_authorized_pr_comment = payload.action == "created"
+        && payload.issue.pull_request.is_some()
+        && !is_own_comment(config, &payload.comment.user)
+        && ALLOWED_AUTHOR_ASSOCIATIONS.contains(&payload.comment.author_association.as_str());
+    if !is_authorized_pr_comment {
+        return false;
     }
 
-    update_commit_status(
-        branches.candidate.commit_sha.clone(),
-        StatusState::Success,
-        job_url,
-        ctx.config,
-        &octocrab,
-    )
-    .await;
+    match parse_comment(&payload.comment.body, &config.bot_name) {
+        ParsedComment::Command {
+            handler: CommandHandler::BenchPr | CommandHandler::RetryFailed | CommandHandler::Compare,
+            args,
+            ..
+        } => args.split_whitespace().any(|arg| arg == URGENT_FLAG),
+        _ => false,
+    }
+}
 
-    Ok(())
+/// Returns true if the comment was posted by the application itself
+///
+/// Comments posted by a GitHub App are authored by its bot user, whose login is the app's slug
+/// followed by `[bot]`
+fn is_own_comment(config: &AppConfig, user: &GitHubUser) -> bool {
+    user.id == config.github_app_id || user.login == format!("{}[bot]", config.bot_name)
 }
 
-async fn try_update_comment(
-    pr_number: u64,
-    comment: &str,
+/// Cancels the PR's queued and running jobs, reporting the outcome on the PR
+///
+/// Unlike other commands, this is handled as soon as the comment arrives, since the queue handles
+/// events sequentially and the command would otherwise wait behind the jobs it should cancel.
+pub async fn handle_cancel_command(
+    event_queue: &EventQueue,
+    config: &AppConfig,
     octocrab: &Octocrab,
-    ctx: &JobContext<'_>,
+    payload: &CommentEvent,
 ) -> anyhow::Result<()> {
-    if let Some(comment_id) = ctx.db.result_comment_id(pr_number).await? {
-        octocrab
-            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
-            .update_comment(comment_id, comment)
-            .await?;
+    let pr_number = payload.issue.number;
+    let requested_by = &payload.comment.user.login;
+    let cancelled = event_queue.cancel_pr_jobs(pr_number).await?;
+    info!(pr_number, requested_by, "cancelled PR jobs: {cancelled:?}");
 
-        Ok(())
+    let comment = if cancelled.is_empty() {
+        "There are no queued or running benchmarks for this PR".to_string()
     } else {
-        bail!("no comment registered for PR")
-    }
-}
+        let mut comment = format!("Cancelled as requested by @{requested_by}:");
+        if let Some(job_id) = cancelled.running_job_id {
+            let job_url = format!("{}/jobs/{job_id}", config.app_base_url);
+            writeln!(comment, "\n* the [running benchmark job]({job_url})").ok();
 
-async fn bench_pr_and_cache_results(
-    ctx: &JobContext<'_>,
-    branches: PrBranches,
-    logs: &mut BenchPrLogs,
-) -> anyhow::Result<ComparisonResult> {
-    let cutoff_date = OffsetDateTime::now_utc() - Duration::days(30);
-    let historical_results = ctx
-        .db
-        .result_history(cutoff_date)
-        .await
-        .context("could not obtain result history")?;
+            // The job's pending status would otherwise stay around forever. It is set on the
+            // commit the job was benchmarking, which might no longer be the PR's head
+            if let Some(commit_sha) = &cancelled.pending_commit_sha {
+                let forge = GitHubForge::new(config, octocrab.clone());
+                for context in status_contexts(config, BenchMode::Full) {
+                    let status = CommitStatus {
+                        context,
+                        state: CommitState::Error,
+                        description: Some(format!("cancelled by @{requested_by}")),
+                        target_url: job_url.clone(),
+                    };
+                    update_commit_status(&forge, commit_sha, &status).await;
+                }
+            }
+        }
+        if cancelled.queued_events > 0 {
+            write!(
+                comment,
+                "\n* {} queued job(s) that had not started yet",
+                cancelled.queued_events
+            )
+            .ok();
+        }
+        comment
+    };
 
-    let icount_results = historical_results
-        .iter()
-        .filter(|r| r.scenario_kind == ScenarioKind::Icount)
-        .cloned();
-    let icount_significance_thresholds = calculate_significance_thresholds(icount_results);
+    octocrab
+        .issues(&config.github_repo_owner, &config.github_repo_name)
+        .create_comment(pr_number, comment)
+        .await?;
+    Ok(())
+}
 
-    let walltime_results = historical_results
-        .into_iter()
-        .filter(|r| r.scenario_kind == ScenarioKind::Walltime);
-    let walltime_significance_thresholds = calculate_significance_thresholds(walltime_results);
+/// Moves the PR's queued jobs to the front of the queue, recording the reprioritization in the
+/// audit log and reporting the outcome on the PR
+///
+/// Like cancel commands, this is handled as soon as the comment arrives, since it would otherwise
+/// wait behind the jobs it should overtake.
+pub async fn handle_prioritize_command(
+    event_queue: &EventQueue,
+    db: &Db,
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    payload: &CommentEvent,
+) -> anyhow::Result<()> {
+    let pr_number = payload.issue.number;
+    let requested_by = &payload.comment.user.login;
+    let prioritized = event_queue.prioritize_pr_events(pr_number).await?;
+    info!(
+        pr_number,
+        requested_by, "prioritized {prioritized} PR event(s)"
+    );
 
-    let significance_thresholds = SignificanceThresholds {
-        icount: icount_significance_thresholds,
-        walltime: walltime_significance_thresholds,
+    let comment = if prioritized == 0 {
+        "There are no queued benchmarks for this PR (running benchmarks can't be prioritized)"
+            .to_string()
+    } else {
+        db.record_audit_entry(
+            requested_by,
+            "prioritize",
+            &format!(
+                "moved {prioritized} queued event(s) of PR #{pr_number} to the front of the queue"
+            ),
+        )
+        .await?;
+        format!(
+            "Moved {prioritized} queued job(s) to the front of the queue, as requested by @{requested_by}"
+        )
     };
 
-    let job_output_dir = ctx.job_output_dir.clone();
-    let runner = ctx.bench_runner.clone();
-    let branches_cloned = branches.clone();
-    let (result, task_logs) = tokio::task::spawn_blocking(move || {
-        let mut logs = BenchPrLogs::default();
-
-        let result = compare_refs(
-            &branches_cloned,
-            &job_output_dir,
-            &mut logs,
-            runner.deref(),
-            &significance_thresholds,
-        );
+    octocrab
+        .issues(&config.github_repo_owner, &config.github_repo_name)
+        .create_comment(pr_number, comment)
+        .await?;
+    Ok(())
+}
 
-        if let Err(e) = &result {
-            error!(cause = e.to_string(), "unable to compare refs");
-        }
+/// A request for a full run of a PR's benchmarks, to follow up on a quick run (or to complete the
+/// pending backport check of a full run, see [`BackportOriginalRequest`])
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FullRunRequest {
+    pub pr_number: u64,
+    /// The baseline branch of the quick run
+    pub baseline_branch: String,
+    /// The baseline commit of the quick run (which might not be the PR's base)
+    pub baseline_commit: String,
+    /// The candidate commit of the quick run
+    pub candidate_commit: String,
+    /// Whether the quick run benchmarked the baseline with the candidate's bench harness
+    #[serde(default)]
+    pub pin_harness: bool,
+    /// The user who requested the quick run, to whom the full run's costs are attributed
+    #[serde(default)]
+    pub requester: Option<String>,
+}
 
-        (result, logs)
-    })
-    .await
-    .context("benchmarking task crashed")?;
+/// Handles a full run of a PR's benchmarks, enqueued to follow up on a quick run (cached results
+/// are reused, so following up on a full run only updates its report)
+///
+/// The full run is skipped if the PR has been updated since the quick run, because the report
+/// would no longer correspond to the PR's current commits
+pub async fn handle_full_run(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let request: FullRunRequest = serde_json::from_slice(ctx.event_payload)
+        .context("invalid full run request")
+        .map_err(|e| e.context(PoisonEvent))?;
 
-    *logs = task_logs;
+    let pr = ctx
+        .octocrab
+        .cached()
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(request.pr_number)
+        .await
+        .context("unable to get PR details")?;
 
-    // Write the task logs so they are available even if commenting to GitHub fails
-    let mut s = String::new();
-    writeln!(s, "### Candidate").ok();
-    write_logs_for_run(&mut s, &logs.candidate);
-    writeln!(s, "### Base").ok();
-    write_logs_for_run(&mut s, &logs.base);
-    fs::write(ctx.job_output_dir.join("logs.md"), s).context("unable to write job logs")?;
+    let mut branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
+    if branches.candidate.commit_sha != request.candidate_commit {
+        trace!("the PR was updated after the quick run, skipping full run");
+        return Ok(());
+    }
 
-    if let Ok(result) = &result {
-        ctx.db
-            .store_comparison_result(
-                branches.baseline.commit_sha,
-                branches.candidate.commit_sha,
-                result.clone(),
-            )
-            .await
-            .context("could not store comparison results")?;
+    branches.baseline.branch_name = request.baseline_branch;
+    branches.baseline.commit_sha = request.baseline_commit;
+    if request.pin_harness {
+        branches.pin_baseline_harness();
     }
+    bench_pr(ctx, request.pr_number, branches, BenchMode::Full).await?;
+    Ok(())
+}
 
-    result
+/// A comparison requested through the HTTP API, by an external CI system
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiCompareRequest {
+    /// The id of the stored comparison (see [`crate::db::ApiComparison`])
+    pub id: Uuid,
+    /// The API client that requested the comparison, to whom its costs are attributed
+    pub client: String,
 }
 
-fn pr_branches(pr: &PullRequest) -> Option<PrBranches> {
-    Some(PrBranches {
-        candidate: CommitIdentifier {
-            branch_name: pr.head.ref_field.clone(),
-            commit_sha: pr.head.sha.clone(),
-            clone_url: pr.head.repo.as_ref()?.clone_url.as_ref()?.to_string(),
-        },
-        baseline: CommitIdentifier {
-            branch_name: pr.base.ref_field.clone(),
-            commit_sha: pr.base.sha.clone(),
-            clone_url: pr.base.repo.as_ref()?.clone_url.as_ref()?.to_string(),
-        },
-    })
+/// Handles a comparison requested through the HTTP API, storing its outcome so the client can
+/// retrieve it
+///
+/// Nothing is reported to GitHub, since the comparison doesn't belong to a PR
+pub async fn handle_api_compare(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let request: ApiCompareRequest = serde_json::from_slice(ctx.event_payload)
+        .context("invalid compare request")
+        .map_err(|e| e.context(PoisonEvent))?;
+    let comparison = ctx
+        .db
+        .api_comparison(request.id)
+        .await?
+        .context("the requested comparison no longer exists")?;
+    ctx.db
+        .api_comparison_started(comparison.id, ctx.job_id)
+        .await?;
+
+    match api_compare(&ctx, &comparison).await {
+        Ok(result) => {
+            ctx.db
+                .api_comparison_finished(comparison.id, Ok(&result))
+                .await
+        }
+        Err(e) => {
+            ctx.db
+                .api_comparison_finished(comparison.id, Err(format!("{e:#}")))
+                .await?;
+            Err(e)
+        }
+    }
 }
 
-fn compare_refs(
-    pr_branches: &PrBranches,
-    job_output_path: &Path,
-    logs: &mut BenchPrLogs,
-    runner: &dyn BenchRunner,
-    significance_thresholds: &SignificanceThresholds,
+/// Compares the commits of a comparison requested through the HTTP API, reusing cached results if
+/// available
+async fn api_compare(
+    ctx: &JobContext<'_>,
+    comparison: &ApiComparison,
+) -> anyhow::Result<ComparisonResult> {
+    let octocrab = ctx.octocrab.cached();
+    let mut commits = Vec::new();
+    for git_ref in [&comparison.baseline_ref, &comparison.candidate_ref] {
+        let commit_sha = github::resolve_commit(git_ref, ctx.config, &octocrab)
+            .await
+            .with_context(|| format!("unable to find `{git_ref}` in the repository"))?;
+        commits.push(commit_sha);
+    }
+    let [baseline_commit, candidate_commit]: [String; 2] = commits
+        .try_into()
+        .map_err(|_| anyhow!("expected exactly two commits"))?;
+    ctx.db
+        .api_comparison_resolved(comparison.id, &baseline_commit, &candidate_commit)
+        .await?;
+
+    let clone_url = github::repo_clone_url(ctx.config, &octocrab).await?;
+    let branches = PrBranches {
+        baseline: CommitIdentifier {
+            clone_url: clone_url.clone(),
+            branch_name: comparison.baseline_ref.clone(),
+            commit_sha: baseline_commit,
+            patch_series: None,
+            pinned_harness: None,
+        },
+        candidate: CommitIdentifier {
+            clone_url,
+            branch_name: comparison.candidate_ref.clone(),
+            commit_sha: candidate_commit,
+            patch_series: None,
+            pinned_harness: None,
+        },
+        detached: true,
+    };
+
+    let cached_result = ctx
+        .db
+        .comparison_result(
+            &branches.baseline.comparison_key(),
+            &branches.candidate.comparison_key(),
+        )
+        .await?;
+    if let Some(result) = cached_result {
+        return Ok(result);
+    }
+
+    let mut logs = BenchPrLogs::default();
+    bench_pr_and_cache_results(
+        ctx,
+        branches,
+        BenchMode::Full,
+        &ctx.job_output_dir,
+        &mut logs,
+    )
+    .await
+}
+
+/// A benchmarking command (`bench`, `bench-walltime`, `bench-commits` or `retry`), with its
+/// arguments parsed
+#[derive(Debug, Default, PartialEq, Eq)]
+struct BenchCommand<'a> {
+    /// The `<first>..<last>` range of a `bench-commits` command, as written in the comment
+    commit_range: Option<&'a str>,
+    /// Whether to compare against the latest benchmarked commit of main (`main` argument)
+    against_main: bool,
+    /// Whether to only run the quick scenarios (`--quick` flag)
+    quick: bool,
+    /// Whether to only run the walltime benchmarks (`bench-walltime` command)
+    walltime: bool,
+    /// Whether to benchmark the baseline with the candidate's harness (`--pin-harness` flag)
+    pin_harness: bool,
+    /// Whether to discard the cached results first (`retry` command)
+    retry: bool,
+    /// The glob of the scenarios to run, if only some of them should run
+    scenario_glob: Option<&'a str>,
+}
+
+impl<'a> BenchCommand<'a> {
+    /// Parses the arguments of the command with the provided name, in any order
+    ///
+    /// The first argument that isn't a flag or the main branch is the commit range of
+    /// `bench-commits`, and the scenario glob of the other commands. Unknown flags (e.g.
+    /// `--urgent`, which only affects the queue) are ignored.
+    fn parse(name: &str, args: &'a str) -> Self {
+        let mut command = BenchCommand {
+            walltime: name == "bench-walltime",
+            retry: name == "retry",
+            ..BenchCommand::default()
+        };
+
+        let mut positional = None;
+        for arg in args.split_whitespace() {
+            match arg {
+                "--quick" => command.quick = true,
+                _ if arg == PIN_HARNESS_FLAG => command.pin_harness = true,
+                _ if arg.starts_with("--") => {}
+                _ if arg == MAIN_BRANCH => command.against_main = true,
+                _ => {
+                    positional.get_or_insert(arg);
+                }
+            }
+        }
+
+        if name == "bench-commits" {
+            command.commit_range = Some(positional.unwrap_or_default());
+        } else {
+            command.scenario_glob = positional;
+        }
+
+        command
+    }
+}
+
+/// Runs the PR benchmarks requested through a comment, returning the comparison's verdict
+async fn bench_pr_for_comment(
+    ctx: JobContext<'_>,
+    payload: &CommentEvent,
+    command: BenchCommand<'_>,
+    pr: PullRequest,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Verdict> {
+    let mut branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
+    if let Some(range) = command.commit_range {
+        let Some((first_commit, last_commit)) = parse_commit_range(range) else {
+            octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(
+                    payload.issue.number,
+                    format!(
+                        "Invalid commit range `{range}`, expected `<first>..<last>` (where both \
+                        ends are full commit hashes)"
+                    ),
+                )
+                .await?;
+            return Ok(Verdict::Error);
+        };
+
+        let Some(main_commit) = latest_main_commit_or_comment(&ctx, payload, octocrab).await?
+        else {
+            return Ok(Verdict::Error);
+        };
+
+        // Compare the patch series, applied on top of main, against main itself
+        branches.baseline.branch_name = MAIN_BRANCH.to_string();
+        branches.baseline.commit_sha = main_commit;
+        branches.candidate = CommitIdentifier {
+            patch_series: Some(PatchSeries {
+                clone_url: branches.candidate.clone_url,
+                first_commit: first_commit.to_string(),
+                last_commit: last_commit.to_string(),
+            }),
+            ..branches.baseline.clone()
+        };
+    } else if command.against_main {
+        // Compare against the latest benchmarked commit of main, instead of the PR's base
+        let Some(main_commit) = latest_main_commit_or_comment(&ctx, payload, octocrab).await?
+        else {
+            return Ok(Verdict::Error);
+        };
+
+        branches.baseline.branch_name = MAIN_BRANCH.to_string();
+        branches.baseline.commit_sha = main_commit;
+    }
+
+    if command.pin_harness {
+        branches.pin_baseline_harness();
+    }
+
+    if command.retry {
+        // The harness must be pinned beforehand, because it is part of the comparison's key
+        let changed_files = changed_files(&ctx, pr.number, octocrab).await;
+        pin_harness_if_modified(&ctx, pr.number, &mut branches, &changed_files);
+        let invalidated = ctx
+            .db
+            .invalidate_comparison_result(
+                &branches.baseline.comparison_key(),
+                &branches.candidate.comparison_key(),
+            )
+            .await?;
+        if invalidated {
+            info!("invalidated cached comparison for PR #{}", pr.number);
+        }
+    }
+
+    let mode = if command.quick {
+        if ctx.config.bench_suite.quick_suite().is_none() {
+            octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(
+                    payload.issue.number,
+                    "Quick mode is not available, because no quick scenarios have been configured",
+                )
+                .await?;
+            return Ok(Verdict::Error);
+        }
+
+        BenchMode::Quick
+    } else if command.walltime {
+        if ctx.config.bench_suite.walltime_suite().is_none() {
+            octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(
+                    payload.issue.number,
+                    "Walltime mode is not available, because no walltime benchmarks have been \
+                    configured",
+                )
+                .await?;
+            return Ok(Verdict::Error);
+        }
+
+        BenchMode::Walltime
+    } else if let Some(glob) = command.scenario_glob {
+        BenchMode::Filtered(glob)
+    } else {
+        BenchMode::Full
+    };
+
+    bench_pr(ctx, pr.number, branches, mode).await
+}
+
+/// Compares two arbitrary commits of the repository, as requested by a
+/// `@bot_name compare <base> <head>` comment, returning the comparison's verdict
+///
+/// Both sides are resolved to commit hashes through GitHub, so any commit hash, branch or tag
+/// of the repository can be used.
+async fn compare_for_comment(
+    ctx: JobContext<'_>,
+    payload: &CommentEvent,
+    args: &str,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Verdict> {
+    let Some((base, head)) = parse_compare_refs(args) else {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(
+                payload.issue.number,
+                format!(
+                    "Invalid `compare` command, expected `@{} compare <base> <head>` \
+                    (where both sides are commit hashes, branches or tags of the repository)",
+                    ctx.config.bot_name
+                ),
+            )
+            .await?;
+        return Ok(Verdict::Error);
+    };
+
+    let mut commits = Vec::new();
+    for git_ref in [base, head] {
+        match github::resolve_commit(git_ref, ctx.config, octocrab).await {
+            Ok(commit_sha) => commits.push(commit_sha),
+            Err(e) => {
+                warn!(git_ref, cause = e.to_string(), "unable to resolve ref");
+                octocrab
+                    .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                    .create_comment(
+                        payload.issue.number,
+                        format!("Unable to find `{git_ref}` in the repository"),
+                    )
+                    .await?;
+                return Ok(Verdict::Error);
+            }
+        }
+    }
+
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(payload.issue.number)
+        .await
+        .context("unable to get PR details")?;
+
+    // Both commits belong to the repository, which is the PR's base
+    let repo = pr_branches(&pr)
+        .ok_or(anyhow!("unable to get PR branch details"))?
+        .baseline;
+    let [base_commit, head_commit]: [String; 2] = commits
+        .try_into()
+        .map_err(|_| anyhow!("expected exactly two commits"))?;
+    let branches = PrBranches {
+        baseline: CommitIdentifier {
+            branch_name: base.to_string(),
+            commit_sha: base_commit,
+            ..repo.clone()
+        },
+        candidate: CommitIdentifier {
+            branch_name: head.to_string(),
+            commit_sha: head_commit,
+            ..repo
+        },
+        detached: true,
+    };
+
+    bench_pr(ctx, pr.number, branches, BenchMode::Full).await
+}
+
+/// Parses the `<base> <head>` arguments of a `@bot_name compare` command
+///
+/// Refs are restricted to the characters that can appear in commit hashes, branches and tags, since
+/// they end up in the URL of a GitHub API request
+fn parse_compare_refs(args: &str) -> Option<(&str, &str)> {
+    let mut refs = args.split_whitespace();
+    let (base, head) = (refs.next()?, refs.next()?);
+    (is_valid_ref(base) && is_valid_ref(head)).then_some((base, head))
+}
+
+/// Returns true if the string only contains characters that can appear in commit hashes, branches
+/// and tags (refs end up in the URL of a GitHub API request)
+pub fn is_valid_ref(s: &str) -> bool {
+    !s.is_empty()
+        && !s.starts_with(['-', '.', '/'])
+        && !s.contains("..")
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | '/'))
+}
+
+/// Re-runs the scenarios missing from the comparison last reported on the PR, as requested by a
+/// `@bot_name retry-failed` comment, returning the verdict of the merged comparison
+///
+/// Scenarios are considered missing if they failed in the baseline (see
+/// `scenarios_missing_in_baseline`) or if they are absent from both sides, while being present in
+/// the latest results of the branch the PR is judged by. The merged comparison replaces the
+/// previous one, and is reported in the same comment.
+async fn retry_failed_for_comment(
+    ctx: JobContext<'_>,
+    pr: PullRequest,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Verdict> {
+    let pr_number = pr.number;
+    let comparison_id = ctx.db.latest_pr_comparison(pr_number).await?;
+    let commits = match comparison_id {
+        Some(id) => ctx.db.comparison_commits(id).await?,
+        None => None,
+    };
+    let previous = match &commits {
+        Some((baseline_key, candidate_key)) => {
+            ctx.db
+                .comparison_result(baseline_key, candidate_key)
+                .await?
+        }
+        None => None,
+    };
+    let (Some((baseline_key, candidate_key)), Some(previous)) = (commits, previous) else {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(
+                pr_number,
+                format!(
+                    "There are no cached results to retry for this PR. Use `@{} bench` to run \
+                    the benchmarks.",
+                    ctx.config.bot_name
+                ),
+            )
+            .await?;
+        return Ok(Verdict::Error);
+    };
+
+    let branches = stored_comparison_branches(&pr, &baseline_key, &candidate_key)?;
+
+    let latest_results = ctx
+        .db
+        .latest_results(threshold_branch(ctx.config, &branches))
+        .await?
+        .map(|(_, results)| results)
+        .unwrap_or_default();
+    let failed = FailedScenarios::of(&previous, &latest_results, &ctx.config.bench_suite);
+    if failed.is_empty() {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(
+                pr_number,
+                "There are no failed or missing scenarios in the last report, so there is \
+                nothing to retry",
+            )
+            .await?;
+        return Ok(previous.verdict());
+    }
+
+    info!(
+        pr_number,
+        "retrying {} icount, {} walltime and {} memory usage scenarios",
+        failed.icount.len(),
+        failed.walltime.len(),
+        failed.max_rss.len()
+    );
+    let bench_suite =
+        ctx.config
+            .bench_suite
+            .scenarios_suite(&failed.icount, &failed.walltime, &failed.max_rss);
+    let mut logs = BenchPrLogs::default();
+    let output_dir = &ctx.job_output_dir;
+    let retried = run_comparison(&ctx, &branches, bench_suite, output_dir, &mut logs).await?;
+
+    // The merged comparison replaces the previous one, so it gets picked up when reporting
+    let merged = failed.merge(previous, retried);
+    ctx.db
+        .invalidate_comparison_result(&baseline_key, &candidate_key)
+        .await?;
+    cache_comparison_result(&ctx, baseline_key, candidate_key, &merged).await?;
+
+    bench_pr(ctx, pr_number, branches, BenchMode::Full).await
+}
+
+/// The scenarios of a comparison that failed or are missing, per kind of benchmarks
+#[derive(Debug, Default, PartialEq)]
+struct FailedScenarios {
+    icount: Vec<String>,
+    walltime: Vec<String>,
+    max_rss: Vec<String>,
+}
+
+impl FailedScenarios {
+    /// Returns the scenarios missing from the comparison, given the latest results of the branch
+    /// the comparison is judged by
+    ///
+    /// Kinds of benchmarks that aren't part of the bench suite are left out
+    fn of(
+        result: &ComparisonResult,
+        latest_results: &[BenchResult],
+        bench_suite: &BenchSuiteConfig,
+    ) -> Self {
+        let missing = |sub_result: &ComparisonSubResult, kind: ScenarioKind| {
+            let mut scenarios = sub_result.scenarios_missing_in_baseline.clone();
+            let expected = latest_results
+                .iter()
+                .filter(|r| r.scenario_kind == kind)
+                .map(|r| &r.scenario_name);
+            for scenario in expected {
+                let compared = sub_result
+                    .diffs
+                    .iter()
+                    .any(|d| &d.scenario_name == scenario);
+                if !compared && !scenarios.contains(scenario) {
+                    scenarios.push(scenario.clone());
+                }
+            }
+
+            scenarios.sort();
+            scenarios
+        };
+
+        let mut failed = Self::default();
+        if !bench_suite.icount_args.is_empty() {
+            failed.icount = missing(result.metric(ScenarioKind::Icount), ScenarioKind::Icount);
+        }
+        if !bench_suite.walltime_args.is_empty() {
+            failed.walltime = missing(
+                result.metric(ScenarioKind::Walltime),
+                ScenarioKind::Walltime,
+            );
+        }
+        if !bench_suite.max_rss_args.is_empty() {
+            failed.max_rss = missing(result.metric(ScenarioKind::MaxRss), ScenarioKind::MaxRss);
+        }
+        failed
+    }
+
+    fn is_empty(&self) -> bool {
+        self.icount.is_empty() && self.walltime.is_empty() && self.max_rss.is_empty()
+    }
+
+    /// Returns the retried scenarios that measure the metric, or `None` if the metric is not
+    /// measured again when retrying
+    fn retried(&self, kind: ScenarioKind) -> Option<&[String]> {
+        match kind {
+            // Cache misses (and the cycles estimated from them) are measured along with the icount
+            // scenarios
+            ScenarioKind::Icount
+            | ScenarioKind::D1Misses
+            | ScenarioKind::LlMisses
+            | ScenarioKind::EstimatedCycles => Some(&self.icount),
+            // Energy is measured along with the walltime scenarios
+            ScenarioKind::Walltime | ScenarioKind::Energy => Some(&self.walltime),
+            ScenarioKind::MaxRss => Some(&self.max_rss),
+            // The build time of a retry is not comparable to that of a full run, and the perf stat
+            // backend always measures all scenarios
+            ScenarioKind::BuildTime
+            | ScenarioKind::PerfCycles
+            | ScenarioKind::PerfInstructions
+            | ScenarioKind::PerfBranchMisses => None,
+            // The index is only tracked on main
+            ScenarioKind::IcountIndex => None,
+        }
+    }
+
+    /// Merges the results of retrying the scenarios into the previous comparison
+    ///
+    /// Results for scenarios that weren't retried are discarded, and retried scenarios that are
+    /// still missing in the baseline stay reported as such
+    fn merge(&self, previous: ComparisonResult, retried: ComparisonResult) -> ComparisonResult {
+        fn merge_sub_result(
+            scenarios: &[String],
+            mut previous: ComparisonSubResult,
+            retried: ComparisonSubResult,
+        ) -> ComparisonSubResult {
+            for diff in retried.diffs {
+                if scenarios.contains(&diff.scenario_name) {
+                    previous.diffs.retain(|d| {
+                        d.scenario_name != diff.scenario_name
+                            || d.scenario_kind != diff.scenario_kind
+                    });
+                    previous
+                        .scenarios_missing_in_baseline
+                        .retain(|s| s != &diff.scenario_name);
+                    previous.diffs.push(diff);
+                }
+            }
+
+            for scenario in retried.scenarios_missing_in_baseline {
+                if scenarios.contains(&scenario)
+                    && !previous.scenarios_missing_in_baseline.contains(&scenario)
+                {
+                    previous.scenarios_missing_in_baseline.push(scenario);
+                }
+            }
+
+            previous
+        }
+
+        let mut merged = previous;
+        for (kind, retried) in retried.metrics {
+            if let Some(scenarios) = self.retried(kind) {
+                let previous = merged.take_metric(kind);
+                merged
+                    .metrics
+                    .insert(kind, merge_sub_result(scenarios, previous, retried));
+            }
+        }
+        merged
+    }
+}
+
+/// Re-renders the PR's report from the cached comparison of its current commits, if available
+///
+/// Benchmarks are never run, which makes this useful to refresh old reports after changes to the
+/// report's formatting. For the same reason, the backport check is only included if the original
+/// change has already been benchmarked
+async fn preview_report(
+    ctx: JobContext<'_>,
+    payload: &CommentEvent,
+    octocrab: &Octocrab,
+) -> anyhow::Result<()> {
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(payload.issue.number)
+        .await
+        .context("unable to get PR details")?;
+    let branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
+
+    let cached_result = ctx
+        .db
+        .comparison_result(
+            &branches.baseline.commit_sha,
+            &branches.candidate.commit_sha,
+        )
+        .await?;
+    let Some(result) = cached_result else {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(
+                pr.number,
+                format!(
+                    "There are no cached results for the PR's current commits. Use \
+                    `@{} bench` to run the benchmarks.",
+                    ctx.config.bot_name
+                ),
+            )
+            .await?;
+        return Ok(());
+    };
+
+    let backport_check = if branches.baseline.branch_name != MAIN_BRANCH {
+        backport_check(&ctx, pr.number, &branches, &result, false, octocrab).await
+    } else {
+        None
+    };
+    let checks = PrChecks {
+        backport: backport_check.as_ref(),
+        ..PrChecks::default()
+    };
+    post_report(
+        &ctx,
+        pr.number,
+        &branches,
+        Ok(result),
+        checks,
+        BenchMode::Full,
+        false,
+    )
+    .await
+}
+
+/// Returns the latest benchmarked commit of main, or comments on the PR if there is none
+async fn latest_main_commit_or_comment(
+    ctx: &JobContext<'_>,
+    payload: &CommentEvent,
+    octocrab: &Octocrab,
+) -> anyhow::Result<Option<String>> {
+    let main_commit = ctx.db.latest_commit(MAIN_BRANCH).await?;
+    if main_commit.is_none() {
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(
+                payload.issue.number,
+                format!("There are no benchmarked commits of `{MAIN_BRANCH}` yet"),
+            )
+            .await?;
+    }
+
+    Ok(main_commit)
+}
+
+/// Parses a `<first>..<last>` commit range, as used by the `bench-commits` command
+///
+/// Only full commit hashes are accepted, since the range ends up as an argument to git (and
+/// `git fetch` can't resolve abbreviated hashes)
+fn parse_commit_range(range: &str) -> Option<(&str, &str)> {
+    let (first, last) = range.split_once("..")?;
+    (is_commit_hash(first) && is_commit_hash(last)).then_some((first, last))
+}
+
+/// Returns true if the string is a full (40 characters long) commit hash
+fn is_commit_hash(s: &str) -> bool {
+    s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Handle a "PR review"
+///
+/// Runs the PR benchmarks if the review:
+/// - Is an approval; and
+/// - Has just been submitted by an authorized user.
+pub async fn handle_pr_review(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    // Ideally, we'd use WebhookEvent::try_from_header_and_body from `octocrab`, but it doesn't have
+    // the `author_association` field on the review (which we need) and it requires the `head` field
+    // on the PR (which is not provided).
+    let payload = serde_json::from_slice::<PullRequestReviewEvent>(ctx.event_payload)
+        .context("invalid JSON payload")
+        .map_err(|e| e.context(PoisonEvent))?;
+
+    if payload.action != "submitted" {
+        trace!("ignoring pull request event with action {}", payload.action);
+        return Ok(());
+    }
+
+    if !ALLOWED_AUTHOR_ASSOCIATIONS.contains(&payload.review.author_association.as_str()) {
+        trace!("ignoring review from untrusted author");
+        return Ok(());
+    }
+
+    if payload.review.state != "approved" {
+        trace!("ignoring review with non-approved status");
+        return Ok(());
+    }
+
+    let pr = payload.pull_request;
+    let mut branches = pr_branches(&pr).ok_or(anyhow!("unable to get PR branch details"))?;
+
+    // Ensure we bench the commit that was reviewed, and not something else
+    branches.candidate.commit_sha = payload.review.commit_id;
+
+    if branches.is_from_fork() {
+        // The approval covers the reviewed commit, so later bench commands for it are honored too
+        ctx.db
+            .store_fork_approval(
+                pr.number,
+                &branches.candidate.commit_sha,
+                &payload.review.user.login,
+            )
+            .await?;
+    }
+
+    bench_pr(ctx, pr.number, branches, BenchMode::Full).await?;
+    Ok(())
+}
+
+/// Handle a "PR update"
+///
+/// Runs the PR benchmarks if:
+/// - The PR originates from a trusted branch (i.e. branches from the repository, not from forks);
+/// - The PR is not a draft, and targets a branch whose PRs are benchmarked automatically (see
+///   [`AppConfig::pr_base_branches`]); and
+/// - The PR was just created (action is `opened`), its branches were updated (action is
+///   `synchronize`) or it was marked as ready for review (action is `ready_for_review`).
+pub async fn handle_pr_update(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let event = WebhookEvent::try_from_header_and_body(ctx.event, ctx.event_payload)
+        .context("invalid JSON payload")
+        .map_err(|e| e.context(PoisonEvent))?;
+
+    let WebhookEventPayload::PullRequest(payload) = event.specific else {
+        return Err(anyhow!("not a pull request payload").context(PoisonEvent));
+    };
+
+    let allowed_actions = [
+        PullRequestWebhookEventAction::Opened,
+        PullRequestWebhookEventAction::Synchronize,
+        PullRequestWebhookEventAction::Reopened,
+        PullRequestWebhookEventAction::ReadyForReview,
+    ];
+    if !allowed_actions.contains(&payload.action) {
+        trace!(
+            "ignoring pull request event with action {:?}",
+            payload.action
+        );
+        return Ok(());
+    }
+
+    let branches =
+        pr_branches(&payload.pull_request).ok_or(anyhow!("unable to get PR branch details"))?;
+    let base_branch = &branches.baseline.branch_name;
+    let skip_reason = if branches.is_from_fork() {
+        trace!(
+            "ignoring pull request update for forked repo (base repo = {}, head repo = {})",
+            branches.baseline.clone_url,
+            branches.candidate.clone_url
+        );
+        Some(SkipReason::ForkedRepository)
+    } else if payload.pull_request.draft == Some(true) {
+        trace!("ignoring pull request update for draft PR");
+        Some(SkipReason::DraftPr)
+    } else if !ctx.config.pr_base_branches.is_empty()
+        && !ctx.config.pr_base_branches.contains(base_branch)
+    {
+        trace!("ignoring pull request update for PR against `{base_branch}`");
+        Some(SkipReason::OtherBaseBranch)
+    } else {
+        None
+    };
+
+    let pr_number = payload.pull_request.number;
+    if let Some(reason) = skip_reason {
+        // Explaining once, when the PR is opened, is enough
+        if payload.action == PullRequestWebhookEventAction::Opened {
+            explain_skipped(&ctx, pr_number, reason).await?;
+        }
+
+        return Ok(());
+    }
+
+    bench_pr(ctx, pr_number, branches, BenchMode::Full).await?;
+    Ok(())
+}
+
+/// How much of the bench suite to run when benchmarking a PR
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BenchMode<'a> {
+    /// Run the whole bench suite
+    Full,
+    /// Run only the curated subset of icount scenarios, for a fast (but partial) signal
+    Quick,
+    /// Run only the walltime benchmarks
+    Walltime,
+    /// Run only the scenarios matching a glob
+    Filtered(&'a str),
+}
+
+/// A reason for skipping benchmarks that the PR's author can do something about
+#[derive(Copy, Clone, Debug)]
+enum SkipReason {
+    /// The bench command was posted by a user without the necessary permissions
+    UnauthorizedUser,
+    /// The PR comes from a fork, so it isn't benchmarked automatically
+    ForkedRepository,
+    /// The PR is a draft, so it isn't benchmarked automatically until it is ready for review
+    DraftPr,
+    /// The PR targets a branch whose PRs aren't benchmarked automatically (see
+    /// [`AppConfig::pr_base_branches`])
+    OtherBaseBranch,
+}
+
+impl SkipReason {
+    /// The explanation posted on the PR
+    ///
+    /// It never mentions the app, since the app would otherwise be triggered by its own comment
+    fn explanation(&self, config: &AppConfig) -> String {
+        match self {
+            SkipReason::UnauthorizedUser => "Benchmarks were not run: only owners, members and \
+                collaborators of the repository can use benchmarking commands. Please ask a \
+                maintainer to trigger the benchmarks."
+                .to_string(),
+            SkipReason::ForkedRepository => "Benchmarks are not run automatically for PRs coming \
+                from forks. A maintainer can trigger them by approving the PR or through a `bench` \
+                command."
+                .to_string(),
+            SkipReason::DraftPr => "Benchmarks are not run automatically for draft PRs. They will \
+                run once the PR is marked as ready for review."
+                .to_string(),
+            SkipReason::OtherBaseBranch => {
+                let branches: Vec<_> = config
+                    .pr_base_branches
+                    .iter()
+                    .map(|branch| format!("`{branch}`"))
+                    .collect();
+                format!(
+                    "Benchmarks are only run automatically for PRs against {}. A maintainer can \
+                    trigger them through a `bench` command.",
+                    branches.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Posts a comment explaining why benchmarks were skipped, if enabled in the configuration
+async fn explain_skipped(
+    ctx: &JobContext<'_>,
+    pr_number: u64,
+    reason: SkipReason,
+) -> anyhow::Result<()> {
+    if !ctx.config.explain_skipped_benchmarks {
+        return Ok(());
+    }
+
+    trace!("explaining skipped benchmarks ({reason:?})");
+    ctx.octocrab
+        .cached()
+        .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .create_comment(pr_number, reason.explanation(ctx.config))
+        .await
+        .context("unable to post comment explaining skipped benchmarks")?;
+
+    Ok(())
+}
+
+/// Compares the PR's branches and reports the results, returning the comparison's verdict
+///
+/// Standalone comparisons (see [`PrBranches::is_standalone`]) are reported in a comment of their
+/// own, without commit statuses, since they don't correspond to a commit of the PR.
+///
+/// In quick mode, cached (full) results are used if available. Otherwise, only the quick subset of
+/// the bench suite is run, and its partial results are reported but not cached.
+pub async fn bench_pr(
+    ctx: JobContext<'_>,
+    pr_number: u64,
+    branches: PrBranches,
+    mode: BenchMode<'_>,
+) -> anyhow::Result<Verdict> {
+    let job_url = format!("{}/jobs/{}", ctx.config.app_base_url, ctx.job_id);
+    let octocrab = ctx.octocrab.cached();
+    let forge = GitHubForge::new(ctx.config, octocrab.clone());
+    let standalone = branches.is_standalone();
+    let mut branches = branches;
+
+    // The PR's changed files tell whether its harness must be pinned and whether it only bumps
+    // dependencies
+    let changed_files = if standalone {
+        Vec::new()
+    } else {
+        changed_files(&ctx, pr_number, &octocrab).await
+    };
+    pin_harness_if_modified(&ctx, pr_number, &mut branches, &changed_files);
+
+    if !standalone {
+        for context in status_contexts(ctx.config, mode) {
+            let status = pending_status(context, &job_url);
+            update_commit_status(&forge, &branches.candidate.commit_sha, &status).await;
+        }
+        ctx.event_queue
+            .set_pending_commit(ctx.job_id, &branches.candidate.commit_sha);
+    }
+
+    let baseline_key = branches.baseline.comparison_key();
+    let candidate_key = branches.candidate.comparison_key();
+    let cached_result = ctx
+        .db
+        .comparison_result(&baseline_key, &candidate_key)
+        .await?;
+    let (result, mode) = match cached_result {
+        Some(result) => (Ok(result), BenchMode::Full),
+        None => {
+            let mut logs = BenchPrLogs::default();
+            let output_dir = &ctx.job_output_dir;
+            let result =
+                bench_pr_and_cache_results(&ctx, branches.clone(), mode, output_dir, &mut logs)
+                    .await
+                    .map_err(|error| BenchPrError { error, logs });
+            (result, mode)
+        }
+    };
+
+    let (verdict, walltime_verdict) = match &result {
+        Ok(result) => (
+            result.verdict(),
+            result.metric(ScenarioKind::Walltime).verdict(),
+        ),
+        Err(error) => {
+            let class = FailureClass::of(&error.error);
+            ctx.db.record_job_failure(ctx.job_id, class).await?;
+            (Verdict::Error, Verdict::Error)
+        }
+    };
+
+    let backport_check = match &result {
+        Ok(result) if !standalone && branches.baseline.branch_name != MAIN_BRANCH => {
+            // Partial runs don't warrant benchmarking the original change
+            let benchmark_original = mode == BenchMode::Full;
+            backport_check(
+                &ctx,
+                pr_number,
+                &branches,
+                result,
+                benchmark_original,
+                &octocrab,
+            )
+            .await
+        }
+        _ => None,
+    };
+
+    // A quick run showing significant movement is followed up by a full run, which updates the
+    // same comment once it completes
+    let full_run_queued = mode == BenchMode::Quick
+        && !standalone
+        && matches!(verdict, Verdict::Improved | Verdict::Regressed)
+        && enqueue_full_run(&ctx, pr_number, &branches).await;
+
+    let dependency_upgrade = match &result {
+        Ok(result) if !standalone && mode != BenchMode::Walltime => {
+            dependency_upgrade(&changed_files, result)
+        }
+        _ => None,
+    };
+
+    let checks = PrChecks {
+        backport: backport_check.as_ref(),
+        dependency_upgrade: dependency_upgrade.as_ref(),
+    };
+    post_report(
+        &ctx,
+        pr_number,
+        &branches,
+        result,
+        checks,
+        mode,
+        full_run_queued,
+    )
+    .await?;
+
+    if !standalone {
+        for context in status_contexts(ctx.config, mode) {
+            let verdict = match context {
+                StatusContext::Icount => verdict,
+                StatusContext::Walltime => walltime_verdict,
+            };
+            let status = final_status(context, verdict, mode, &job_url);
+            update_commit_status(&forge, &branches.candidate.commit_sha, &status).await;
+        }
+    }
+
+    Ok(verdict)
+}
+
+/// Returns the status of a context while the job is benchmarking the commit
+pub(super) fn pending_status(context: StatusContext, job_url: &str) -> CommitStatus {
+    CommitStatus {
+        context,
+        state: CommitState::Pending,
+        description: None,
+        target_url: job_url.to_string(),
+    }
+}
+
+/// Returns the status of a context once the job has reached a verdict for the commit
+pub(super) fn final_status(
+    context: StatusContext,
+    verdict: Verdict,
+    mode: BenchMode<'_>,
+    job_url: &str,
+) -> CommitStatus {
+    // Failed runs link to their logs, which are more actionable than the job's status
+    let (state, target_url) = match verdict {
+        Verdict::Error => (CommitState::Error, format!("{job_url}/logs")),
+        _ => (CommitState::Success, job_url.to_string()),
+    };
+    let description = match mode {
+        BenchMode::Full | BenchMode::Walltime => format!("verdict: {}", verdict.as_str()),
+        BenchMode::Quick => format!("partial verdict (quick mode): {}", verdict.as_str()),
+        BenchMode::Filtered(glob) => format!("partial verdict ({glob}): {}", verdict.as_str()),
+    };
+
+    CommitStatus {
+        context,
+        state,
+        description: Some(description),
+        target_url,
+    }
+}
+
+/// Returns the commit status contexts reported on by a comparison in the given mode (i.e. one for
+/// each kind of benchmarks it runs)
+pub(super) fn status_contexts(config: &AppConfig, mode: BenchMode<'_>) -> Vec<StatusContext> {
+    let runs_walltime = config.bench_suite.walltime_suite().is_some();
+    match mode {
+        BenchMode::Quick => vec![StatusContext::Icount],
+        BenchMode::Walltime => vec![StatusContext::Walltime],
+        BenchMode::Full | BenchMode::Filtered(_) if runs_walltime => {
+            vec![StatusContext::Icount, StatusContext::Walltime]
+        }
+        BenchMode::Full | BenchMode::Filtered(_) => vec![StatusContext::Icount],
+    }
+}
+
+/// A comparison of a PR's branches, with its report rendered but not posted yet
+pub(super) struct RenderedComparison {
+    pub verdict: Verdict,
+    pub walltime_verdict: Verdict,
+    pub report: String,
+}
+
+/// Compares the PR's branches (using cached results if available) and renders the full report,
+/// for forges whose reports don't go through [`post_report`]
+pub(super) async fn compare_and_render(
+    ctx: &JobContext<'_>,
+    branches: &PrBranches,
+) -> anyhow::Result<RenderedComparison> {
+    let cached_result = ctx
+        .db
+        .comparison_result(
+            &branches.baseline.comparison_key(),
+            &branches.candidate.comparison_key(),
+        )
+        .await?;
+    let result = match cached_result {
+        Some(result) => Ok(result),
+        None => {
+            let mut logs = BenchPrLogs::default();
+            let output_dir = &ctx.job_output_dir;
+            bench_pr_and_cache_results(
+                ctx,
+                branches.clone(),
+                BenchMode::Full,
+                output_dir,
+                &mut logs,
+            )
+            .await
+            .map_err(|error| BenchPrError { error, logs })
+        }
+    };
+
+    let (verdict, walltime_verdict) = match &result {
+        Ok(result) => (
+            result.verdict(),
+            result.metric(ScenarioKind::Walltime).verdict(),
+        ),
+        Err(error) => {
+            let class = FailureClass::of(&error.error);
+            ctx.db.record_job_failure(ctx.job_id, class).await?;
+            (Verdict::Error, Verdict::Error)
+        }
+    };
+
+    let mut run_details = RunDetails::read(&ctx.job_output_dir);
+    run_details.artifact_sizes = artifact_size_diffs(&ctx.db, branches).await;
+    run_details.annotations = comparison_annotations(&ctx.db, branches).await;
+    run_details.job_url = Some(format!("{}/jobs/{}", ctx.config.app_base_url, ctx.job_id));
+    let report = render_report(
+        ctx.config,
+        branches,
+        result,
+        PrChecks::default(),
+        &run_details,
+        BenchMode::Full,
+        false,
+    );
+
+    if let Err(e) = fs::create_dir_all(&ctx.job_output_dir)
+        .and_then(|_| fs::write(ctx.job_output_dir.join(REPORT_FILE), &report))
+    {
+        warn!(cause = e.to_string(), "unable to write job report");
+    }
+
+    Ok(RenderedComparison {
+        verdict,
+        walltime_verdict,
+        report,
+    })
+}
+
+/// Renders the report of a comparison and posts it to the PR, linking it to the current job
+///
+/// The PR's results comment is updated if it exists, except for standalone comparisons, which are
+/// reported in a comment of their own
+async fn post_report(
+    ctx: &JobContext<'_>,
+    pr_number: u64,
+    branches: &PrBranches,
+    result: Result<ComparisonResult, BenchPrError>,
+    checks: PrChecks<'_>,
+    mode: BenchMode<'_>,
+    full_run_queued: bool,
+) -> anyhow::Result<()> {
+    ctx.enter_phase(JobPhase::Reporting).await;
+    let octocrab = ctx.octocrab.cached();
+    let standalone = branches.is_standalone();
+
+    // Only runs of the current job are described, so cached results come without details (which
+    // is fine, since unstable results are never cached)
+    let mut run_details = RunDetails::read(&ctx.job_output_dir);
+    run_details.artifact_sizes = artifact_size_diffs(&ctx.db, branches).await;
+    run_details.features = ReportFeatures::for_pr(ctx.config, &ctx.db, pr_number).await?;
+    run_details.annotations = comparison_annotations(&ctx.db, branches).await;
+    run_details.job_url = Some(format!("{}/jobs/{}", ctx.config.app_base_url, ctx.job_id));
+    let metadata = ReportMetadata {
+        report_version: REPORT_VERSION,
+        comparison_id: ctx
+            .db
+            .comparison_id(
+                &branches.baseline.comparison_key(),
+                &branches.candidate.comparison_key(),
+            )
+            .await?,
+        baseline_commit: branches.baseline.commit_sha.clone(),
+        candidate_commit: branches.candidate.commit_sha.clone(),
+        verdict: result.as_ref().map_or(Verdict::Error, |r| r.verdict()),
+    };
+    let report = render_report(
+        ctx.config,
+        branches,
+        result,
+        checks,
+        &run_details,
+        mode,
+        full_run_queued,
+    );
+
+    // Keep a copy of the full report next to the job's logs, for offline analysis and for readers
+    // of comments that had to be shrunk
+    let written = fs::create_dir_all(&ctx.job_output_dir)
+        .and_then(|_| fs::write(ctx.job_output_dir.join(REPORT_FILE), &report));
+    let full_report_url = match written {
+        Ok(()) => Some(format!(
+            "{}/jobs/{}/report.md",
+            ctx.config.app_base_url, ctx.job_id
+        )),
+        Err(e) => {
+            warn!(cause = e.to_string(), "unable to write job report");
+            None
+        }
+    };
+
+    let mut comment = report;
+    metadata.embed(&mut comment);
+    if !standalone {
+        github::mark_result_comment(&mut comment);
+    }
+    github::truncate_comment(
+        &mut comment,
+        &ctx.config.report.omit_order,
+        full_report_url.as_deref(),
+    );
+
+    let update_result = if standalone {
+        Err(anyhow!(
+            "standalone comparisons are reported in a new comment"
+        ))
+    } else {
+        try_update_comment(pr_number, &comment, &octocrab, ctx).await
+    };
+    let comment_id = match update_result {
+        Ok(comment_id) => comment_id,
+        Err(_) => {
+            // Fall back to creating a comment if updating fails
+            let comment = octocrab
+                .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+                .create_comment(pr_number, comment)
+                .await?;
+            if !standalone {
+                ctx.db
+                    .store_result_comment_id(pr_number, comment.id)
+                    .await?;
+            }
+
+            comment.id
+        }
+    };
+
+    ctx.db
+        .record_job_report(
+            ctx.job_id,
+            pr_number,
+            comment_id,
+            &branches.baseline.comparison_key(),
+            &branches.candidate.comparison_key(),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Enqueues a full run of the PR's benchmarks, returning whether it succeeded
+async fn enqueue_full_run(ctx: &JobContext<'_>, pr_number: u64, branches: &PrBranches) -> bool {
+    let request = FullRunRequest {
+        pr_number,
+        baseline_branch: branches.baseline.branch_name.clone(),
+        baseline_commit: branches.baseline.commit_sha.clone(),
+        candidate_commit: branches.candidate.commit_sha.clone(),
+        pin_harness: branches.baseline.pinned_harness.is_some(),
+        requester: event_requester(ctx.event, ctx.event_payload),
+    };
+
+    match ctx.event_queue.enqueue_full_run(&request).await {
+        Ok(event_id) => {
+            info!(
+                event_id = event_id.to_string(),
+                "enqueued full run for PR #{pr_number}"
+            );
+            true
+        }
+        Err(e) => {
+            error!(cause = e.to_string(), "unable to enqueue full run");
+            false
+        }
+    }
+}
+
+/// Renders the full markdown report of a comparison
+///
+/// The report might exceed GitHub's size limit, so it must go through
+/// [`github::truncate_comment`] before being posted
+fn render_report(
+    config: &AppConfig,
+    branches: &PrBranches,
+    result: Result<ComparisonResult, BenchPrError>,
+    checks: PrChecks<'_>,
+    run_details: &RunDetails,
+    mode: BenchMode<'_>,
+    full_run_queued: bool,
+) -> String {
+    markdown_comment(
+        config,
+        branches,
+        result,
+        checks,
+        run_details,
+        mode,
+        full_run_queued,
+    )
+}
+
+/// The outcome of re-rendering the report of a stored comparison
+#[derive(Debug)]
+pub enum RerenderOutcome {
+    /// The PR comment where the comparison was reported has been updated
+    Updated {
+        pr_number: u64,
+        comment_id: CommentId,
+    },
+    /// There is no comparison with the provided id
+    ComparisonNotFound,
+    /// The comparison was never reported in a PR comment
+    NoComment,
+    /// The comment now reports a different comparison, so it was left untouched
+    Superseded,
+}
+
+/// Re-renders the report of a stored comparison with the current templates, updating the PR
+/// comment where it was last reported
+///
+/// Checks tailored to the kind of PR (e.g. backport checks) are not included, since they aren't
+/// stored
+pub async fn rerender_report(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    db: &Db,
+    comparison_id: Uuid,
+) -> anyhow::Result<RerenderOutcome> {
+    let Some(comparison) = StoredComparison::load(db, comparison_id).await? else {
+        return Ok(RerenderOutcome::ComparisonNotFound);
+    };
+    let Some(report_comment) = db.report_comment(comparison_id).await? else {
+        return Ok(RerenderOutcome::NoComment);
+    };
+    if report_comment.superseded {
+        return Ok(RerenderOutcome::Superseded);
+    }
+
+    let is_result_comment =
+        db.result_comment_id(report_comment.pr_number).await? == Some(report_comment.comment_id);
+    let comment = render_stored_report(
+        config,
+        octocrab,
+        db,
+        report_comment.pr_number,
+        comparison,
+        is_result_comment,
+    )
+    .await?;
+    octocrab
+        .issues(&config.github_repo_owner, &config.github_repo_name)
+        .update_comment(report_comment.comment_id, comment)
+        .await
+        .context("unable to update PR comment")?;
+
+    Ok(RerenderOutcome::Updated {
+        pr_number: report_comment.pr_number,
+        comment_id: report_comment.comment_id,
+    })
+}
+
+/// A comparison retrieved from the database
+struct StoredComparison {
+    id: Uuid,
+    baseline_key: String,
+    candidate_key: String,
+    result: ComparisonResult,
+}
+
+impl StoredComparison {
+    async fn load(db: &Db, id: Uuid) -> anyhow::Result<Option<Self>> {
+        let Some((baseline_key, candidate_key)) = db.comparison_commits(id).await? else {
+            return Ok(None);
+        };
+        let Some(result) = db.comparison_result(&baseline_key, &candidate_key).await? else {
+            return Ok(None);
+        };
+
+        Ok(Some(Self {
+            id,
+            baseline_key,
+            candidate_key,
+            result,
+        }))
+    }
+}
+
+/// Renders the report of a stored comparison of the PR with the current templates
+async fn render_stored_report(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    db: &Db,
+    pr_number: u64,
+    comparison: StoredComparison,
+    is_result_comment: bool,
+) -> anyhow::Result<String> {
+    let pr = octocrab
+        .pulls(&config.github_repo_owner, &config.github_repo_name)
+        .get(pr_number)
+        .await
+        .context("unable to get PR details")?;
+    let branches =
+        stored_comparison_branches(&pr, &comparison.baseline_key, &comparison.candidate_key)?;
+
+    let run_details = RunDetails {
+        artifact_sizes: artifact_size_diffs(db, &branches).await,
+        features: ReportFeatures::for_pr(config, db, pr_number).await?,
+        annotations: comparison_annotations(db, &branches).await,
+        ..RunDetails::default()
+    };
+    let metadata = ReportMetadata {
+        report_version: REPORT_VERSION,
+        comparison_id: Some(comparison.id),
+        baseline_commit: branches.baseline.commit_sha.clone(),
+        candidate_commit: branches.candidate.commit_sha.clone(),
+        verdict: comparison.result.verdict(),
+    };
+    // The full report is only stored by the job that produced the comparison, so there is nothing
+    // to link to if the comment has to be shrunk
+    let mut comment = render_report(
+        config,
+        &branches,
+        Ok(comparison.result),
+        PrChecks::default(),
+        &run_details,
+        BenchMode::Full,
+        false,
+    );
+    metadata.embed(&mut comment);
+    if is_result_comment {
+        github::mark_result_comment(&mut comment);
+    }
+    github::truncate_comment(&mut comment, &config.report.omit_order, None);
+
+    Ok(comment)
+}
+
+/// The discrepancies between the database and GitHub found by [`reconcile_github_state`], and how
+/// they were repaired
+#[derive(Debug, Default, Serialize)]
+pub struct Reconciliation {
+    /// The open PRs with a result comment, which were all checked
+    pub checked_prs: Vec<u64>,
+    /// PRs whose result comment was deleted and has been replaced, either by another result
+    /// comment found in the PR or by a new one reporting the PR's latest comparison
+    pub replaced_comments: Vec<u64>,
+    /// PRs whose result comment was deleted without there being anything to report again, so the
+    /// comment was forgotten (the next report is posted in a new one)
+    pub forgotten_comments: Vec<u64>,
+    /// PRs whose head commit was missing the statuses of its stored comparison, which have been
+    /// posted again
+    pub restored_statuses: Vec<u64>,
+    /// PRs that could not be reconciled, along with the cause
+    pub failed: BTreeMap<u64, String>,
+}
+
+/// Cross-checks the stored result comments of open PRs, and the commit statuses of their latest
+/// comparisons, against GitHub, repairing whatever went missing (e.g. after an incident, or
+/// because someone deleted a comment)
+///
+/// The app doesn't report through check runs, so there are none to reconcile
+pub async fn reconcile_github_state(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    db: &Db,
+) -> anyhow::Result<Reconciliation> {
+    let result_comments: HashMap<_, _> = db.result_comments().await?.into_iter().collect();
+
+    let mut reconciliation = Reconciliation::default();
+    for pr in github::open_prs(config, octocrab).await? {
+        let Some(&comment_id) = result_comments.get(&pr.number) else {
+            continue;
+        };
+
+        reconciliation.checked_prs.push(pr.number);
+        if let Err(e) =
+            reconcile_pr(config, octocrab, db, &pr, comment_id, &mut reconciliation).await
+        {
+            warn!(
+                cause = format!("{e:#}"),
+                "unable to reconcile PR #{}", pr.number
+            );
+            reconciliation.failed.insert(pr.number, format!("{e:#}"));
+        }
+    }
+
+    Ok(reconciliation)
+}
+
+/// Reconciles the result comment and the commit statuses of a single PR
+async fn reconcile_pr(
+    config: &AppConfig,
+    octocrab: &Octocrab,
+    db: &Db,
+    pr: &OpenPullRequest,
+    comment_id: CommentId,
+    reconciliation: &mut Reconciliation,
+) -> anyhow::Result<()> {
+    let latest_comparison = db.latest_pr_comparison(pr.number).await?;
+
+    if !github::comment_exists(comment_id, config, octocrab).await? {
+        let found = github::find_result_comment(pr.number, config, octocrab, |user| {
+            is_own_comment(config, user)
+        })
+        .await?;
+        let new_comment_id = match (found, latest_comparison) {
+            (Some(found), _) => Some(found),
+            (None, Some(comparison_id)) => match StoredComparison::load(db, comparison_id).await? {
+                Some(comparison) => {
+                    let comment =
+                        render_stored_report(config, octocrab, db, pr.number, comparison, true)
+                            .await?;
+                    let posted = octocrab
+                        .issues(&config.github_repo_owner, &config.github_repo_name)
+                        .create_comment(pr.number, comment)
+                        .await
+                        .context("unable to post PR comment")?;
+                    Some(posted.id)
+                }
+                None => None,
+            },
+            (None, None) => None,
+        };
+
+        match new_comment_id {
+            Some(new_comment_id) => {
+                info!(
+                    "replacing deleted result comment {comment_id} of PR #{} with {new_comment_id}",
+                    pr.number
+                );
+                db.replace_result_comment(pr.number, comment_id, new_comment_id)
+                    .await?;
+                reconciliation.replaced_comments.push(pr.number);
+            }
+            None => {
+                info!(
+                    "forgetting deleted result comment {comment_id} of PR #{}",
+                    pr.number
+                );
+                db.forget_result_comment(pr.number).await?;
+                reconciliation.forgotten_comments.push(pr.number);
+            }
+        }
+    }
+
+    // Statuses are only restored if the latest comparison is of the PR's current head, otherwise
+    // the PR has been updated since and a new run will report them
+    let Some(comparison_id) = latest_comparison else {
+        return Ok(());
+    };
+    let Some(comparison) = StoredComparison::load(db, comparison_id).await? else {
+        return Ok(());
+    };
+    if comparison.candidate_key != pr.head.sha {
+        return Ok(());
+    }
+
+    let reported = github::commit_status_contexts(&pr.head.sha, config, octocrab).await?;
+    let missing: Vec<_> = status_contexts(config, BenchMode::Full)
+        .into_iter()
+        .filter(|context| !reported.iter().any(|r| r == context.as_str()))
+        .filter_map(|context| {
+            let kind = match context {
+                StatusContext::Icount => ScenarioKind::Icount,
+                StatusContext::Walltime => ScenarioKind::Walltime,
+            };
+            let metric = comparison.result.metrics.get(&kind)?;
+            Some((context, metric.verdict()))
+        })
+        .collect();
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let comparison_url = format!(
+        "{}/comparisons/{}:{}",
+        config.app_base_url, comparison.baseline_key, comparison.candidate_key
+    );
+    let forge = GitHubForge::new(config, octocrab.clone());
+    for (context, verdict) in missing {
+        let status = CommitStatus {
+            context,
+            state: CommitState::Success,
+            description: Some(format!("verdict: {}", verdict.as_str())),
+            target_url: comparison_url.clone(),
+        };
+        update_commit_status(&forge, &pr.head.sha, &status).await;
+    }
+    reconciliation.restored_statuses.push(pr.number);
+
+    Ok(())
+}
+
+/// Reconstructs the branches of a stored comparison, taking the repositories and branch names from
+/// the PR and the commits from the comparison itself (the PR might have been updated since)
+fn stored_comparison_branches(
+    pr: &PullRequest,
+    baseline_key: &str,
+    candidate_key: &str,
+) -> anyhow::Result<PrBranches> {
+    let mut branches = pr_branches(pr).ok_or(anyhow!("unable to get PR branch details"))?;
+    match baseline_key.split_once(PINNED_HARNESS_KEY_SEPARATOR) {
+        None => branches.baseline.commit_sha = baseline_key.to_string(),
+        Some((commit, harness_commit)) => {
+            branches.baseline.commit_sha = commit.to_string();
+            branches.baseline.pinned_harness = Some(PinnedHarness {
+                clone_url: branches.candidate.clone_url.clone(),
+                commit_sha: harness_commit.to_string(),
+            });
+        }
+    }
+
+    // See `CommitIdentifier::comparison_key` for the format of patch series keys
+    match candidate_key.split_once('+') {
+        None => branches.candidate.commit_sha = candidate_key.to_string(),
+        Some((commit, range)) => {
+            let (first_commit, last_commit) = parse_commit_range(range)
+                .ok_or(anyhow!("invalid patch series in stored comparison"))?;
+            branches.baseline.branch_name = MAIN_BRANCH.to_string();
+            branches.candidate = CommitIdentifier {
+                commit_sha: commit.to_string(),
+                patch_series: Some(PatchSeries {
+                    clone_url: branches.candidate.clone_url,
+                    first_commit: first_commit.to_string(),
+                    last_commit: last_commit.to_string(),
+                }),
+                pinned_harness: None,
+                ..branches.baseline.clone()
+            };
+        }
+    }
+
+    Ok(branches)
+}
+
+async fn try_update_comment(
+    pr_number: u64,
+    comment: &str,
+    octocrab: &Octocrab,
+    ctx: &JobContext<'_>,
+) -> anyhow::Result<CommentId> {
+    let comment_id = match ctx.db.result_comment_id(pr_number).await? {
+        Some(comment_id) => comment_id,
+        None => {
+            // A previous job might have posted the comment without getting to store its id (e.g.
+            // if the process crashed in between), so look for it before creating a duplicate
+            let found = github::find_result_comment(pr_number, ctx.config, octocrab, |user| {
+                is_own_comment(ctx.config, user)
+            })
+            .await;
+            let comment_id = match found {
+                Ok(Some(comment_id)) => comment_id,
+                Ok(None) => bail!("no comment registered for PR"),
+                Err(e) => {
+                    warn!(
+                        cause = format!("{e:#}"),
+                        "unable to look for an existing result comment"
+                    );
+                    bail!("no comment registered for PR")
+                }
+            };
+
+            info!("adopting existing result comment {comment_id} of PR #{pr_number}");
+            ctx.db
+                .store_result_comment_id(pr_number, comment_id)
+                .await?;
+            comment_id
+        }
+    };
+
+    octocrab
+        .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .update_comment(comment_id, comment)
+        .await?;
+
+    Ok(comment_id)
+}
+
+/// Checks whether a PR that backports a change from main performs like the original change
+///
+/// If the original change hasn't been benchmarked yet, it is benchmarked in a job of its own (see
+/// [`handle_backport_original`]) and the check is reported as pending, so the backport's report
+/// isn't delayed by a second comparison.
+///
+/// Returns `None` if the PR isn't marked as a backport (through a `backport of #N` marker in its
+/// description), or if the original change hasn't been benchmarked yet and `benchmark_original` is
+/// false (e.g. because the backport's run is only partial)
+async fn backport_check(
+    ctx: &JobContext<'_>,
+    pr_number: u64,
+    branches: &PrBranches,
+    backport: &ComparisonResult,
+    benchmark_original: bool,
+    octocrab: &Octocrab,
+) -> Option<BackportCheck> {
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(pr_number)
+        .await;
+    let pr = match pr {
+        Ok(pr) => pr,
+        Err(e) => {
+            error!(cause = e.to_string(), "unable to get PR details");
+            return None;
+        }
+    };
+
+    let original_pr = backported_pr_number(pr.body.as_deref()?)?;
+    trace!("PR #{pr_number} is a backport of #{original_pr}");
+
+    let original = match original_change_branches(ctx, original_pr, octocrab).await {
+        Ok(original) => original,
+        Err(e) => {
+            return Some(BackportCheck {
+                original_pr,
+                status: BackportStatus::Failed(format!("{e:#}")),
+            })
+        }
+    };
+
+    let status = match cached_comparison(ctx, &original).await {
+        Ok(Some(original)) => BackportStatus::Compared(compare_backport(&original, backport)),
+        Ok(None) if !benchmark_original => return None,
+        Ok(None) => {
+            let request = BackportOriginalRequest {
+                original_pr,
+                backport: FullRunRequest {
+                    pr_number,
+                    baseline_branch: branches.baseline.branch_name.clone(),
+                    baseline_commit: branches.baseline.commit_sha.clone(),
+                    candidate_commit: branches.candidate.commit_sha.clone(),
+                    pin_harness: branches.baseline.pinned_harness.is_some(),
+                    requester: event_requester(ctx.event, ctx.event_payload),
+                },
+            };
+            match ctx.event_queue.enqueue_backport_original(&request).await {
+                Ok(event_id) => {
+                    info!(
+                        event_id = event_id.to_string(),
+                        "enqueued benchmarks of #{original_pr}, backported by PR #{pr_number}"
+                    );
+                    BackportStatus::Pending
+                }
+                Err(e) => {
+                    error!(
+                        cause = e.to_string(),
+                        "unable to enqueue benchmarks of the original change"
+                    );
+                    BackportStatus::Failed("unable to benchmark the original change".to_string())
+                }
+            }
+        }
+        Err(e) => BackportStatus::Failed(format!("{e:#}")),
+    };
+
+    Some(BackportCheck {
+        original_pr,
+        status,
+    })
+}
+
+/// Returns the branches of the original change of a backport, which must target main
+async fn original_change_branches(
+    ctx: &JobContext<'_>,
+    original_pr: u64,
+    octocrab: &Octocrab,
+) -> anyhow::Result<PrBranches> {
+    let pr = octocrab
+        .pulls(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+        .get(original_pr)
+        .await
+        .context("unable to get details of the original PR")?;
+    let branches = pr_branches(&pr).context("unable to get branch details of the original PR")?;
+    if branches.baseline.branch_name != MAIN_BRANCH {
+        bail!("the original PR doesn't target `{MAIN_BRANCH}`");
+    }
+
+    Ok(branches)
+}
+
+/// Returns the cached comparison of the branches, if any
+async fn cached_comparison(
+    ctx: &JobContext<'_>,
+    branches: &PrBranches,
+) -> anyhow::Result<Option<ComparisonResult>> {
+    ctx.db
+        .comparison_result(
+            &branches.baseline.comparison_key(),
+            &branches.candidate.comparison_key(),
+        )
+        .await
+}
+
+/// A request to benchmark the change backported by a PR, enqueued when the PR's report is posted
+/// with a pending backport check
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackportOriginalRequest {
+    /// The PR that introduced the original change
+    pub original_pr: u64,
+    /// The run of the backport PR that reported the pending check, repeated once the original
+    /// change has been benchmarked (reusing its cached results) to complete the check
+    pub backport: FullRunRequest,
+}
+
+/// Handles the benchmarking of the change backported by a PR, with a low priority, and updates the
+/// backport PR's report once done
+///
+/// If the original change can't be benchmarked, the backport PR gets a comment saying so, instead
+/// of an updated report (which would enqueue the original change again)
+pub async fn handle_backport_original(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let request: BackportOriginalRequest = serde_json::from_slice(ctx.event_payload)
+        .context("invalid backport original request")
+        .map_err(|e| e.context(PoisonEvent))?;
+    let backport_pr = request.backport.pr_number;
+    let octocrab = ctx.octocrab.cached();
+
+    let result = benchmark_original_change(&ctx, request.original_pr, &octocrab).await;
+    if let Err(e) = result {
+        let comment = format!(
+            "Unable to benchmark the original change (#{}) of this backport, so its performance \
+            couldn't be checked: {e:#}",
+            request.original_pr
+        );
+        octocrab
+            .issues(&ctx.config.github_repo_owner, &ctx.config.github_repo_name)
+            .create_comment(backport_pr, comment)
+            .await
+            .context("unable to report the failed backport check")?;
+        return Err(e);
+    }
+
+    ctx.event_queue
+        .enqueue_full_run(&request.backport)
+        .await
+        .context("unable to enqueue the update of the backport's report")?;
+    info!("enqueued the update of the report of PR #{backport_pr}");
+    Ok(())
+}
+
+/// Benchmarks the original change of a backport and caches its results, unless they are already
+/// available (e.g. because another backport of the same change triggered them)
+async fn benchmark_original_change(
+    ctx: &JobContext<'_>,
+    original_pr: u64,
+    octocrab: &Octocrab,
+) -> anyhow::Result<()> {
+    let branches = original_change_branches(ctx, original_pr, octocrab).await?;
+    if cached_comparison(ctx, &branches).await?.is_some() {
+        return Ok(());
+    }
+
+    let mut logs = BenchPrLogs::default();
+    let output_dir = &ctx.job_output_dir;
+    bench_pr_and_cache_results(
+        ctx,
+        branches.clone(),
+        BenchMode::Full,
+        output_dir,
+        &mut logs,
+    )
+    .await
+    .context("unable to benchmark the original change")?;
+
+    // Otherwise, the backport's report would enqueue the original change again
+    if cached_comparison(ctx, &branches).await?.is_none() {
+        bail!("the original change was measured in an unstable environment");
+    }
+
+    Ok(())
+}
+
+pub(super) async fn bench_pr_and_cache_results(
+    ctx: &JobContext<'_>,
+    branches: PrBranches,
+    mode: BenchMode<'_>,
+    output_dir: &Path,
+    logs: &mut BenchPrLogs,
+) -> anyhow::Result<ComparisonResult> {
+    let bench_suite = match mode {
+        BenchMode::Full => ctx.config.bench_suite.clone(),
+        BenchMode::Quick => ctx
+            .config
+            .bench_suite
+            .quick_suite()
+            .context("quick mode is not configured")?,
+        BenchMode::Walltime => ctx
+            .config
+            .bench_suite
+            .walltime_suite()
+            .context("walltime mode is not configured")?,
+        BenchMode::Filtered(glob) => ctx.config.bench_suite.filtered_suite(glob)?,
+    };
+    let result = run_comparison(ctx, &branches, bench_suite, output_dir, logs).await;
+
+    if mode == BenchMode::Walltime {
+        // Partial results are not cached, so they never get in the way of a full run
+        return result;
+    }
+
+    if let BenchMode::Filtered(glob) = mode {
+        // Partial results are not cached, so they never get in the way of a full run
+        return result.map(|mut result| {
+            let matches = |scenario: &String| matches_glob(glob, scenario);
+            for (_, sub_result) in result
+                .metrics
+                .iter_mut()
+                .filter(|(&kind, _)| kind != ScenarioKind::BuildTime)
+            {
+                sub_result.diffs.retain(|d| matches(&d.scenario_name));
+                sub_result.scenarios_missing_in_baseline.retain(matches);
+            }
+            result
+        });
+    }
+
+    if mode == BenchMode::Quick {
+        // Partial results are not cached, so they never get in the way of a full run
+        return result.map(|mut result| {
+            let quick_scenarios = &ctx.config.bench_suite.quick_scenarios;
+            let is_quick = |scenario: &String| quick_scenarios.contains(scenario);
+            for kind in [
+                ScenarioKind::Icount,
+                ScenarioKind::D1Misses,
+                ScenarioKind::LlMisses,
+                ScenarioKind::EstimatedCycles,
+                ScenarioKind::PerfCycles,
+                ScenarioKind::PerfInstructions,
+                ScenarioKind::PerfBranchMisses,
+            ] {
+                if let Some(sub_result) = result.metrics.get_mut(&kind) {
+                    sub_result.diffs.retain(|d| is_quick(&d.scenario_name));
+                    sub_result.scenarios_missing_in_baseline.retain(is_quick);
+                }
+            }
+            result
+        });
+    }
+
+    // Results measured in an unstable environment are not cached, so they are measured again the
+    // next time they are needed
+    if let Ok(result) = &result {
+        if unstable_runs(output_dir).is_empty() {
+            let baseline_key = branches.baseline.comparison_key();
+            let candidate_key = branches.candidate.comparison_key();
+            cache_comparison_result(ctx, baseline_key, candidate_key, result).await?;
+        }
+    }
+
+    result
+}
+
+/// A run of a comparison that was flagged as environmentally unstable by the determinism check
+#[derive(Debug)]
+struct UnstableRun {
+    /// The side of the comparison the run belongs to (i.e. `baseline` or `candidate`)
+    side: &'static str,
+    discrepancy: DeterminismDiscrepancy,
+}
+
+/// The checks tailored to specific kinds of PRs, each reported in a section of its own
+#[derive(Debug, Default, Clone, Copy)]
+pub(super) struct PrChecks<'a> {
+    /// Whether the PR performs like the change it backports, for backport PRs
+    backport: Option<&'a BackportCheck>,
+    /// What the upgrade costs, for PRs that only bump dependencies
+    dependency_upgrade: Option<&'a DependencyUpgrade>,
+}
+
+/// Details about the runs of a comparison, which are only available to the job that measured it
+#[derive(Debug, Default)]
+pub(super) struct RunDetails {
+    /// The runs that were flagged as environmentally unstable by the determinism check
+    unstable_runs: Vec<UnstableRun>,
+    /// How to reproduce the candidate's measurements locally
+    reproduction: Option<Reproduction>,
+    /// The size of the artifacts built from both sides (loaded separately, since it is stored)
+    artifact_sizes: Vec<ArtifactSizeDiff>,
+    /// The instruction counts of the new scenarios, measured twice on the candidate
+    new_scenario_stability: Vec<NewScenarioStability>,
+    /// The report sections included for the PR (resolved separately, since rollouts are stored)
+    features: ReportFeatures,
+    /// The maintainers' notes on the compared results (loaded separately, since they are stored)
+    annotations: Vec<Annotation>,
+    /// The page of the job that ran the comparison, which tracks the job's status
+    job_url: Option<String>,
+}
+
+impl RunDetails {
+    /// Reads the details of the comparison whose output is stored in the provided dir
+    fn read(job_output_path: &Path) -> Self {
+        let reproduction =
+            read_reproduction(&job_output_path.join("candidate")).unwrap_or_else(|e| {
+                warn!(
+                    cause = format!("{e:#}"),
+                    "unable to read the reproduction steps of the candidate"
+                );
+                None
+            });
+
+        let new_scenario_stability =
+            read_new_scenario_stability(job_output_path).unwrap_or_else(|e| {
+                warn!(
+                    cause = format!("{e:#}"),
+                    "unable to read the stability of the new scenarios"
+                );
+                Vec::new()
+            });
+
+        Self {
+            unstable_runs: unstable_runs(job_output_path),
+            reproduction,
+            artifact_sizes: Vec::new(),
+            new_scenario_stability,
+            features: ReportFeatures::default(),
+            annotations: Vec::new(),
+            job_url: None,
+        }
+    }
+}
+
+/// Returns the runs of the comparison whose output is stored in the provided dir that were flagged
+/// as environmentally unstable
+fn unstable_runs(job_output_path: &Path) -> Vec<UnstableRun> {
+    [("baseline", "base"), ("candidate", "candidate")]
+        .into_iter()
+        .filter_map(
+            |(side, dir)| match read_determinism_discrepancy(&job_output_path.join(dir)) {
+                Ok(discrepancy) => discrepancy.map(|discrepancy| UnstableRun { side, discrepancy }),
+                Err(e) => {
+                    warn!(
+                        cause = format!("{e:#}"),
+                        "unable to read the determinism check results of the {side}"
+                    );
+                    None
+                }
+            },
+        )
+        .collect()
+}
+
+/// Benchmarks both branches with the provided bench suite and compares the results, writing the
+/// logs to the job's output directory
+async fn run_comparison(
+    ctx: &JobContext<'_>,
+    branches: &PrBranches,
+    bench_suite: BenchSuiteConfig,
+    output_dir: &Path,
+    logs: &mut BenchPrLogs,
+) -> anyhow::Result<ComparisonResult> {
+    // PRs against a tracked branch are judged by the branch's own history, and by main's otherwise
+    let threshold_branch = threshold_branch(ctx.config, branches);
+    let significance_thresholds = load_significance_thresholds(&ctx.db, threshold_branch).await?;
+
+    // Code from forks only runs after a maintainer approved it, and is sandboxed more strictly.
+    // Both sides use the same sandbox, so their results remain comparable.
+    let bench_suite = if branches.runs_fork_code() {
+        bench_suite.untrusted_suite()?
+    } else {
+        bench_suite
+    };
+
+    ctx.enter_phase(JobPhase::Benchmarking).await;
+    let job_output_dir = output_dir.to_owned();
+    let runner = ctx.bench_runner.clone();
+    let cancellation = ctx.cancellation.clone();
+    let branches_cloned = branches.clone();
+    let (result, task_logs) = tokio::task::spawn_blocking(move || {
+        let mut logs = BenchPrLogs::default();
+
+        let result = compare_refs(
+            &branches_cloned,
+            &job_output_dir,
+            &bench_suite,
+            &mut logs,
+            runner.deref(),
+            &significance_thresholds,
+            &cancellation,
+        );
+
+        if let Err(e) = &result {
+            error!(cause = e.to_string(), "unable to compare refs");
+        }
+
+        (result, logs)
+    })
+    .await
+    .context("benchmarking task crashed")?;
+
+    *logs = task_logs;
+
+    // Artifact sizes are stored per commit, so they are available to any comparison involving it
+    if result.is_ok() {
+        for (dir, commit) in [
+            ("base", &branches.baseline),
+            ("candidate", &branches.candidate),
+        ] {
+            if let Err(e) = store_artifact_sizes(ctx, &output_dir.join(dir), commit).await {
+                warn!(cause = format!("{e:#}"), "unable to store artifact sizes");
+            }
+        }
+    }
+
+    // Write the task logs so they are available even if commenting to GitHub fails
+    write_job_logs(
+        output_dir,
+        &[
+            ("Candidate", logs.candidate.as_slice()),
+            ("Base", logs.base.as_slice()),
+        ],
+    )?;
+
+    result
+}
+
+/// Stores the artifact sizes measured by the run whose output is stored in the provided dir, if
+/// any
+async fn store_artifact_sizes(
+    ctx: &JobContext<'_>,
+    run_output_dir: &Path,
+    commit: &CommitIdentifier,
+) -> anyhow::Result<()> {
+    if let Some(sizes) = read_artifact_sizes(run_output_dir)? {
+        ctx.db
+            .store_artifact_sizes(&commit.comparison_key(), &sizes)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// The size of an artifact built from both sides of a comparison
+#[derive(Debug)]
+struct ArtifactSizeDiff {
+    artifact: String,
+    baseline: f64,
+    candidate: f64,
+}
+
+impl ArtifactSizeDiff {
+    fn diff(&self) -> f64 {
+        self.candidate - self.baseline
+    }
+
+    fn diff_ratio(&self) -> f64 {
+        self.diff() / self.baseline
+    }
+
+    fn display_scale(&self) -> DisplayScale {
+        ResultUnit::Bytes.common_scale(self.baseline, self.candidate)
+    }
+}
+
+/// Returns the maintainers' notes that apply to the comparison (attached to it, or to a window of a
+/// tracked branch's history that it spans)
+async fn comparison_annotations(db: &Db, branches: &PrBranches) -> Vec<Annotation> {
+    db.comparison_annotations(
+        &branches.baseline.comparison_key(),
+        &branches.candidate.comparison_key(),
+    )
+    .await
+    .unwrap_or_else(|e| {
+        warn!(cause = format!("{e:#}"), "unable to load annotations");
+        Vec::new()
+    })
+}
+
+/// Returns the size diffs of the artifacts built from both sides of the comparison
+async fn artifact_size_diffs(db: &Db, branches: &PrBranches) -> Vec<ArtifactSizeDiff> {
+    let sizes = async {
+        let baseline = db
+            .artifact_sizes(&branches.baseline.comparison_key())
+            .await?;
+        let candidate = db
+            .artifact_sizes(&branches.candidate.comparison_key())
+            .await?;
+        anyhow::Ok((baseline, candidate))
+    };
+
+    let (baseline, candidate) = match sizes.await {
+        Ok(sizes) => sizes,
+        Err(e) => {
+            warn!(cause = format!("{e:#}"), "unable to load artifact sizes");
+            return Vec::new();
+        }
+    };
+
+    candidate
+        .into_iter()
+        .filter_map(|(artifact, candidate)| {
+            let baseline = *baseline.get(&artifact)?;
+            Some(ArtifactSizeDiff {
+                artifact,
+                baseline: baseline as f64,
+                candidate: candidate as f64,
+            })
+        })
+        .collect()
+}
+
+/// Returns the branch whose history determines the significance thresholds of a comparison
+fn threshold_branch<'a>(config: &AppConfig, branches: &'a PrBranches) -> &'a str {
+    if is_tracked_branch(config, &branches.baseline.branch_name) {
+        branches.baseline.branch_name.as_str()
+    } else {
+        MAIN_BRANCH
+    }
+}
+
+/// Stores the result of a comparison, so it can be reused and served through the API, and signs it
+/// if signing is enabled
+async fn cache_comparison_result(
+    ctx: &JobContext<'_>,
+    baseline_key: String,
+    candidate_key: String,
+    result: &ComparisonResult,
+) -> anyhow::Result<()> {
+    ctx.enter_phase(JobPhase::StoringResults).await;
+    let comparison_id = store_comparison_result_with_overflow(
+        &ctx.db,
+        &ArtifactStore::new(&ctx.config.job_output_dir),
+        ctx.config.cachegrind_diff_max_bytes,
+        baseline_key.clone(),
+        candidate_key.clone(),
+        result.clone(),
+    )
+    .await
+    .context("could not store comparison results")?;
+
+    if let Some(signer) = ctx.signer {
+        let signed = signer.sign_comparison(comparison_id, &baseline_key, &candidate_key, result);
+        ctx.db
+            .store_comparison_signature(comparison_id, &signed)
+            .await
+            .context("could not store comparison signature")?;
+    }
+
+    Ok(())
+}
+
+/// Calculates the significance thresholds of each scenario, based on the last 30 days of results of
+/// the tracked branch
+pub(super) async fn load_significance_thresholds(
+    db: &Db,
+    branch: &str,
+) -> anyhow::Result<SignificanceThresholds> {
+    let cutoff_date = OffsetDateTime::now_utc() - Duration::days(30);
+    let historical_results = db
+        .result_history(branch, cutoff_date)
+        .await
+        .context("could not obtain result history")?;
+
+    // Each metric has its own thresholds, derived from its own history
+    let mut thresholds = HashMap::new();
+    for kind in ScenarioKind::ALL {
+        let results = historical_results
+            .iter()
+            .filter(|r| r.scenario_kind == kind)
+            .cloned();
+        thresholds.insert(kind, calculate_significance_thresholds(results));
+    }
+
+    Ok(SignificanceThresholds(thresholds))
+}
+
+fn pr_branches(pr: &PullRequest) -> Option<PrBranches> {
+    Some(PrBranches {
+        candidate: CommitIdentifier {
+            branch_name: pr.head.ref_field.clone(),
+            commit_sha: pr.head.sha.clone(),
+            clone_url: pr.head.repo.as_ref()?.clone_url.as_ref()?.to_string(),
+            patch_series: None,
+            pinned_harness: None,
+        },
+        baseline: CommitIdentifier {
+            branch_name: pr.base.ref_field.clone(),
+            commit_sha: pr.base.sha.clone(),
+            clone_url: pr.base.repo.as_ref()?.clone_url.as_ref()?.to_string(),
+            patch_series: None,
+            pinned_harness: None,
+        },
+        detached: false,
+    })
+}
+
+/// Selects a single metric out of the results of a scenario
+type MetricFn<T> = fn(&T) -> f64;
+
+pub(super) fn compare_refs(
+    pr_branches: &PrBranches,
+    job_output_path: &Path,
+    bench_suite: &BenchSuiteConfig,
+    logs: &mut BenchPrLogs,
+    runner: &dyn BenchRunner,
+    significance_thresholds: &SignificanceThresholds,
+    cancellation: &CancellationToken,
 ) -> anyhow::Result<ComparisonResult> {
     let candidate_repo = TempDir::new().context("Unable to create temp dir")?;
     let candidate_repo_path = candidate_repo.path().to_owned();
 
-    let base_repo = TempDir::new().context("Unable to create temp dir")?;
-    let base_repo_path = base_repo.path().to_owned();
+    let base_repo = TempDir::new().context("Unable to create temp dir")?;
+    let base_repo_path = base_repo.path().to_owned();
+
+    runner.checkout_and_run_benchmarks(
+        &pr_branches.candidate,
+        &candidate_repo_path,
+        &job_output_path.join("candidate"),
+        bench_suite,
+        &mut logs.candidate,
+        cancellation,
+    )?;
+
+    runner.checkout_and_run_benchmarks(
+        &pr_branches.baseline,
+        &base_repo_path,
+        &job_output_path.join("base"),
+        bench_suite,
+        &mut logs.base,
+        cancellation,
+    )?;
+
+    let base_output_path = job_output_path.join("base");
+    let candidate_output_path = job_output_path.join("candidate");
+
+    // The icount benchmarks are skipped when there are no arguments for them (e.g. in walltime mode),
+    // and replaced by hardware counters when running under `perf stat`
+    let icounts_measured = !bench_suite.icount_args.is_empty()
+        && bench_suite.icounts_path(&candidate_output_path).is_file();
+    let (icount_baseline, icount_candidate) = if !icounts_measured {
+        (HashMap::new(), HashMap::new())
+    } else {
+        (
+            read_icount_results(&bench_suite.icounts_path(&base_output_path))?,
+            read_icount_results(&bench_suite.icounts_path(&candidate_output_path))?,
+        )
+    };
+    let mut result = ComparisonResult::default();
+    let mut compare = |kind: ScenarioKind,
+                       cachegrind_diff: Option<&CachegrindDiffFn<'_>>,
+                       baseline: &HashMap<String, f64>,
+                       candidate: &HashMap<String, f64>|
+     -> anyhow::Result<()> {
+        // Metrics without candidate results weren't measured
+        if candidate.is_empty() {
+            return Ok(());
+        }
+
+        let (default_noise_threshold, minimum_noise_threshold) = noise_thresholds(kind);
+        let (diffs, scenarios_missing_in_baseline) = compare_results(
+            cachegrind_diff,
+            baseline,
+            candidate,
+            significance_thresholds.of(kind),
+            kind,
+            default_noise_threshold,
+            minimum_noise_threshold,
+        )?;
+        result.metrics.insert(
+            kind,
+            ComparisonSubResult {
+                diffs,
+                scenarios_missing_in_baseline,
+            },
+        );
+        Ok(())
+    };
+
+    let cachegrind_diff = |scenario: &str| callgrind_diff(job_output_path, bench_suite, scenario);
+    compare(
+        ScenarioKind::Icount,
+        Some(&cachegrind_diff),
+        &icount_baseline,
+        &icount_candidate,
+    )?;
+
+    // Cache misses are only available if the icount benchmarks run with cache simulation
+    let cache_misses_baseline =
+        read_cache_misses(bench_suite, &base_output_path, icount_baseline.keys())?;
+    let cache_misses_candidate =
+        read_cache_misses(bench_suite, &candidate_output_path, icount_candidate.keys())?;
+    let cache_miss_metrics: [(ScenarioKind, MetricFn<CacheMisses>); 2] = [
+        (ScenarioKind::D1Misses, |misses| misses.d1),
+        (ScenarioKind::LlMisses, |misses| misses.ll),
+    ];
+    for (kind, metric) in cache_miss_metrics {
+        let select = |results: &HashMap<String, CacheMisses>| -> HashMap<String, f64> {
+            results
+                .iter()
+                .map(|(scenario, misses)| (scenario.clone(), metric(misses)))
+                .collect()
+        };
+        compare(
+            kind,
+            None,
+            &select(&cache_misses_baseline),
+            &select(&cache_misses_candidate),
+        )?;
+    }
+    compare(
+        ScenarioKind::EstimatedCycles,
+        None,
+        &estimated_cycles(&icount_baseline, &cache_misses_baseline),
+        &estimated_cycles(&icount_candidate, &cache_misses_candidate),
+    )?;
+
+    // Hardware counters are only available if the benchmarks run under `perf stat`
+    let perf_baseline = read_perf_stat_results(&perf_stat_path(bench_suite, &base_output_path))?;
+    let perf_candidate =
+        read_perf_stat_results(&perf_stat_path(bench_suite, &candidate_output_path))?;
+    let perf_metrics: [(ScenarioKind, MetricFn<PerfCounters>); 3] = [
+        (ScenarioKind::PerfCycles, |counters| counters.cycles),
+        (ScenarioKind::PerfInstructions, |counters| {
+            counters.instructions
+        }),
+        (ScenarioKind::PerfBranchMisses, |counters| {
+            counters.branch_misses
+        }),
+    ];
+    for (kind, metric) in perf_metrics {
+        let select = |results: &HashMap<String, PerfCounters>| -> HashMap<String, f64> {
+            results
+                .iter()
+                .map(|(scenario, counters)| (scenario.clone(), metric(counters)))
+                .collect()
+        };
+        compare(
+            kind,
+            None,
+            &select(&perf_baseline),
+            &select(&perf_candidate),
+        )?;
+    }
+
+    // The walltime benchmarks are skipped when there are no arguments for them (e.g. in quick mode)
+    if !bench_suite.walltime_args.is_empty() {
+        compare(
+            ScenarioKind::Walltime,
+            None,
+            &read_walltime_results(&bench_suite.walltimes_path(&base_output_path))?,
+            &read_walltime_results(&bench_suite.walltimes_path(&candidate_output_path))?,
+        )?;
+    }
+
+    // Energy is only available if the walltime benchmarks run with a RAPL zone configured
+    compare(
+        ScenarioKind::Energy,
+        None,
+        &read_energy_results(&bench_suite.energy_path(&base_output_path))?,
+        &read_energy_results(&bench_suite.energy_path(&candidate_output_path))?,
+    )?;
+
+    // The memory usage benchmarks are skipped when there are no arguments for them
+    if !bench_suite.max_rss_args.is_empty() {
+        compare(
+            ScenarioKind::MaxRss,
+            None,
+            &read_max_rss_results(&bench_suite.max_rss_path(&base_output_path))?,
+            &read_max_rss_results(&bench_suite.max_rss_path(&candidate_output_path))?,
+        )?;
+    }
+
+    // The runner times the build of each side, which is compared as a single scenario
+    let read_build_times = |output_path: &Path| -> anyhow::Result<HashMap<String, f64>> {
+        Ok(read_build_time(output_path)?
+            .map(|build_time| (BUILD_TIME_SCENARIO.to_string(), build_time))
+            .into_iter()
+            .collect())
+    };
+    compare(
+        ScenarioKind::BuildTime,
+        None,
+        &read_build_times(&base_output_path)?,
+        &read_build_times(&candidate_output_path)?,
+    )?;
+
+    // Scenarios missing in the baseline are already reported as part of the icount results
+    for kind in [
+        ScenarioKind::D1Misses,
+        ScenarioKind::LlMisses,
+        ScenarioKind::EstimatedCycles,
+    ] {
+        if let Some(sub_result) = result.metrics.get_mut(&kind) {
+            sub_result.scenarios_missing_in_baseline.clear();
+        }
+    }
+
+    // New icount scenarios have no history to judge them by, so they are benchmarked again on the
+    // candidate to report how stable they are
+    let new_scenarios = &result
+        .metric(ScenarioKind::Icount)
+        .scenarios_missing_in_baseline;
+    if !new_scenarios.is_empty() {
+        let rerun_repo = TempDir::new().context("Unable to create temp dir")?;
+        let rerun_output_path = job_output_path.join(NEW_SCENARIOS_RERUN_DIR);
+        let rerun_suite = bench_suite.scenarios_suite(new_scenarios, &[], &[]);
+        let rerun = runner
+            .checkout_and_run_benchmarks(
+                &pr_branches.candidate,
+                rerun_repo.path(),
+                &rerun_output_path,
+                &rerun_suite,
+                &mut logs.candidate,
+                cancellation,
+            )
+            .and_then(|_| read_icount_results(&rerun_suite.icounts_path(&rerun_output_path)));
+
+        match rerun {
+            Ok(rerun) => {
+                let stability: Vec<_> = new_scenarios
+                    .iter()
+                    .filter_map(|scenario| {
+                        Some(NewScenarioStability {
+                            scenario: scenario.clone(),
+                            first_icount: *icount_candidate.get(scenario)?,
+                            second_icount: *rerun.get(scenario)?,
+                        })
+                    })
+                    .collect();
+                write_new_scenario_stability(job_output_path, &stability)?;
+            }
+            Err(e) if cancellation.is_cancelled() => return Err(e),
+            // The comparison itself succeeded, so it is reported without stability information
+            Err(e) => warn!(
+                cause = format!("{e:#}"),
+                "unable to benchmark the new scenarios again"
+            ),
+        }
+    }
+
+    Ok(result)
+}
+
+/// The instruction counts of a scenario missing in the baseline, measured twice on the candidate
+#[derive(Debug, Clone, PartialEq)]
+struct NewScenarioStability {
+    scenario: String,
+    first_icount: f64,
+    second_icount: f64,
+}
+
+impl NewScenarioStability {
+    fn diff_ratio(&self) -> f64 {
+        (self.second_icount - self.first_icount) / self.first_icount
+    }
+}
+
+/// Stores the stability of the new scenarios in the job's output dir, as CSV
+fn write_new_scenario_stability(
+    job_output_path: &Path,
+    stability: &[NewScenarioStability],
+) -> anyhow::Result<()> {
+    let csv: String = stability
+        .iter()
+        .map(|s| format!("{},{},{}\n", s.scenario, s.first_icount, s.second_icount))
+        .collect();
+
+    fs::write(job_output_path.join(NEW_SCENARIO_STABILITY_FILE), csv)
+        .context("failed to write new scenario stability to disk")
+}
+
+/// Reads the stability of the new scenarios from the job's output dir, if they were benchmarked
+/// twice
+fn read_new_scenario_stability(
+    job_output_path: &Path,
+) -> anyhow::Result<Vec<NewScenarioStability>> {
+    let path = job_output_path.join(NEW_SCENARIO_STABILITY_FILE);
+    if !path.is_file() {
+        return Ok(Vec::new());
+    }
+
+    let csv = fs::read_to_string(&path).context("unable to read new scenario stability")?;
+    let mut stability = Vec::new();
+    for line in csv.lines().filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<_> = line.split(',').collect();
+        let &[scenario, first_icount, second_icount] = fields.as_slice() else {
+            bail!("invalid new scenario stability row: {line}");
+        };
+
+        let parse = |value: &str| -> anyhow::Result<f64> {
+            value
+                .parse()
+                .with_context(|| format!("invalid new scenario stability row: {line}"))
+        };
+        stability.push(NewScenarioStability {
+            scenario: scenario.to_string(),
+            first_icount: parse(first_icount)?,
+            second_icount: parse(second_icount)?,
+        });
+    }
 
-    runner.checkout_and_run_benchmarks(
-        &pr_branches.candidate,
-        &candidate_repo_path,
-        &job_output_path.join("candidate"),
-        &mut logs.candidate,
-    )?;
+    Ok(stability)
+}
 
-    runner.checkout_and_run_benchmarks(
-        &pr_branches.baseline,
-        &base_repo_path,
-        &job_output_path.join("base"),
-        &mut logs.base,
-    )?;
+/// Returns the default and minimum noise thresholds of the metric, which apply when its history
+/// doesn't provide a (higher) significance threshold for a scenario
+fn noise_thresholds(kind: ScenarioKind) -> (f64, f64) {
+    match kind {
+        // Estimated cycles and the index are as deterministic as the instruction counts they are
+        // derived from
+        ScenarioKind::Icount | ScenarioKind::EstimatedCycles | ScenarioKind::IcountIndex => (
+            DEFAULT_ICOUNT_NOISE_THRESHOLD,
+            MINIMUM_ICOUNT_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::Walltime => (
+            DEFAULT_WALLTIME_NOISE_THRESHOLD,
+            MINIMUM_WALLTIME_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::MaxRss => (
+            DEFAULT_MAX_RSS_NOISE_THRESHOLD,
+            MINIMUM_MAX_RSS_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::D1Misses | ScenarioKind::LlMisses => (
+            DEFAULT_CACHE_MISS_NOISE_THRESHOLD,
+            MINIMUM_CACHE_MISS_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::BuildTime => (
+            DEFAULT_BUILD_TIME_NOISE_THRESHOLD,
+            MINIMUM_BUILD_TIME_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::PerfCycles
+        | ScenarioKind::PerfInstructions
+        | ScenarioKind::PerfBranchMisses => (
+            DEFAULT_PERF_STAT_NOISE_THRESHOLD,
+            MINIMUM_PERF_STAT_NOISE_THRESHOLD,
+        ),
+        ScenarioKind::Energy => (
+            DEFAULT_ENERGY_NOISE_THRESHOLD,
+            MINIMUM_ENERGY_NOISE_THRESHOLD,
+        ),
+    }
+}
 
-    let icount_baseline = read_icount_results(&icounts_path(&job_output_path.join("base")))?;
-    let icount_candidate = read_icount_results(&icounts_path(&job_output_path.join("candidate")))?;
-    let (icount_diffs, icount_missing) = compare_results(
-        job_output_path,
-        &icount_baseline,
-        &icount_candidate,
-        &significance_thresholds.icount,
+/// Compares the icount results of a bench run of main to those of the previous run, returning the
+/// diffs of the scenarios present in both
+///
+/// Walltime results are left out, because they are too noisy to alert about (like in PR verdicts)
+pub(super) fn compare_main_runs(
+    previous: &[BenchResult],
+    icounts: &HashMap<String, f64>,
+    significance_thresholds: &SignificanceThresholds,
+) -> anyhow::Result<Vec<ScenarioDiff>> {
+    let previous = previous
+        .iter()
+        .filter(|r| r.scenario_kind == ScenarioKind::Icount)
+        .map(|r| (r.scenario_name.clone(), r.result))
+        .collect();
+
+    let (diffs, _) = compare_results(
+        None,
+        &previous,
+        icounts,
+        significance_thresholds.of(ScenarioKind::Icount),
         ScenarioKind::Icount,
         DEFAULT_ICOUNT_NOISE_THRESHOLD,
         MINIMUM_ICOUNT_NOISE_THRESHOLD,
     )?;
 
-    let walltime_baseline = read_walltime_results(&walltimes_path(&job_output_path.join("base")))?;
-    let walltime_candidate =
-        read_walltime_results(&walltimes_path(&job_output_path.join("candidate")))?;
-    let (walltime_diffs, walltime_missing) = compare_results(
-        job_output_path,
-        &walltime_baseline,
-        &walltime_candidate,
-        &significance_thresholds.walltime,
-        ScenarioKind::Walltime,
-        DEFAULT_WALLTIME_NOISE_THRESHOLD,
-        MINIMUM_WALLTIME_NOISE_THRESHOLD,
-    )?;
-
-    Ok(ComparisonResult {
-        icount: ComparisonSubResult {
-            diffs: icount_diffs,
-            scenarios_missing_in_baseline: icount_missing,
-        },
-        walltime: ComparisonSubResult {
-            diffs: walltime_diffs,
-            scenarios_missing_in_baseline: walltime_missing,
-        },
-    })
+    Ok(diffs)
 }
 
 /// Returns the calculated significance threshold for each scenario
@@ -447,85 +3245,301 @@ pub fn calculate_significance_thresholds(
             .push(result.result);
     }
 
-    let mut significance_thresholds = HashMap::with_capacity(results_by_name.len());
-    for (name, results) in results_by_name {
-        // Ensure we have at least 10 results available
-        if results.len() < 10 {
-            continue;
-        }
-
-        // A bench result is significant if the change percentage exceeds a threshold derived
-        // from historic change percentages. We use inter-quartile range fencing by a factor of 3.0,
-        // similar to the Rust compiler's benchmarks.
-        // (see https://github.com/rust-lang/rustc-perf/blob/4f313add609f43e928e98132358e8426ed3969ae/site/src/comparison.rs#L1219)
-        let mut historic_changes = results
-            .windows(2)
-            .map(|window| (window[0] - window[1]).abs() / window[0])
-            .collect::<Vec<_>>();
-        historic_changes.sort_unstable_by(|x, y| x.partial_cmp(y).unwrap_or(Ordering::Equal));
+    results_by_name
+        .into_iter()
+        .filter_map(|(name, results)| Some((name, significance_threshold(&results)?)))
+        .collect()
+}
 
-        let q1 = historic_changes[historic_changes.len() / 4];
-        let q3 = historic_changes[(historic_changes.len() * 3) / 4];
-        let iqr = q3 - q1;
-        let iqr_multiplier = 3.0;
-        let significance_threshold = q3 + iqr * iqr_multiplier;
-        significance_thresholds.insert(name, significance_threshold);
+/// Returns the significance threshold derived from a scenario's historical results (oldest first),
+/// or `None` if there are less than 10 results
+fn significance_threshold(results: &[f64]) -> Option<f64> {
+    if results.len() < 10 {
+        return None;
     }
 
-    significance_thresholds
+    // A bench result is significant if the change percentage exceeds a threshold derived
+    // from historic change percentages. We use inter-quartile range fencing by a factor of 3.0,
+    // similar to the Rust compiler's benchmarks.
+    // (see https://github.com/rust-lang/rustc-perf/blob/4f313add609f43e928e98132358e8426ed3969ae/site/src/comparison.rs#L1219)
+    let mut historic_changes = results
+        .windows(2)
+        .map(|window| (window[0] - window[1]).abs() / window[0])
+        .collect::<Vec<_>>();
+    historic_changes.sort_unstable_by(|x, y| x.partial_cmp(y).unwrap_or(Ordering::Equal));
+
+    let q1 = historic_changes[historic_changes.len() / 4];
+    let q3 = historic_changes[(historic_changes.len() * 3) / 4];
+    let iqr = q3 - q1;
+    let iqr_multiplier = 3.0;
+    Some(q3 + iqr * iqr_multiplier)
+}
+
+/// Returns the indices of the results (oldest first) that changed significantly compared to the
+/// previous one, i.e. the change points of the series
+///
+/// The significance threshold is derived from the series itself, and clamped like in comparisons
+pub fn change_points(kind: ScenarioKind, results: &[f64]) -> Vec<usize> {
+    let (default_noise_threshold, minimum_noise_threshold) = noise_thresholds(kind);
+    let threshold = significance_threshold(results)
+        .unwrap_or(default_noise_threshold)
+        .max(minimum_noise_threshold);
+
+    results
+        .windows(2)
+        .enumerate()
+        .filter(|(_, window)| (window[1] - window[0]).abs() / window[0] >= threshold)
+        .map(|(i, _)| i + 1)
+        .collect()
 }
 
-struct SignificanceThresholds {
-    icount: HashMap<String, f64>,
-    walltime: HashMap<String, f64>,
+/// Significance thresholds per metric and scenario
+pub(super) struct SignificanceThresholds(HashMap<ScenarioKind, HashMap<String, f64>>);
+
+impl SignificanceThresholds {
+    /// Returns the significance threshold of each scenario measured through the metric
+    fn of(&self, kind: ScenarioKind) -> &HashMap<String, f64> {
+        &self.0[&kind]
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct PrBranches {
     pub baseline: CommitIdentifier,
     pub candidate: CommitIdentifier,
+    /// Whether the branches were picked explicitly, regardless of the PR's commits (e.g. by a
+    /// `compare` command)
+    pub detached: bool,
+}
+
+impl PrBranches {
+    /// Returns true if the comparison doesn't correspond to a commit of the PR (i.e. patch series
+    /// and detached comparisons)
+    pub fn is_standalone(&self) -> bool {
+        self.detached || self.candidate.patch_series.is_some()
+    }
+
+    /// Returns true if the PR's head lives in a fork, rather than in the repository itself
+    pub fn is_from_fork(&self) -> bool {
+        self.candidate.clone_url != self.baseline.clone_url
+    }
+
+    /// Returns true if benchmarking the branches runs code from outside the repository, as the
+    /// candidate, as a patch series or as the baseline's pinned harness
+    pub fn runs_fork_code(&self) -> bool {
+        let repo_url = &self.baseline.clone_url;
+        let urls = [
+            Some(&self.candidate.clone_url),
+            self.candidate.patch_series.as_ref().map(|s| &s.clone_url),
+            self.baseline.pinned_harness.as_ref().map(|h| &h.clone_url),
+        ];
+        urls.into_iter().flatten().any(|url| url != repo_url)
+    }
+
+    /// Makes the baseline use the candidate's bench harness, so both sides run the same scenarios
+    ///
+    /// Patch series are left alone, since their harness isn't part of a single commit
+    fn pin_baseline_harness(&mut self) {
+        if self.candidate.patch_series.is_some() {
+            return;
+        }
+
+        self.baseline.pinned_harness = Some(PinnedHarness {
+            clone_url: self.candidate.clone_url.clone(),
+            commit_sha: self.candidate.commit_sha.clone(),
+        });
+    }
+}
+
+/// Pins the baseline's bench harness if the PR modifies it and the configuration asks for it
+///
+/// Standalone comparisons and comparisons with an already pinned harness are left alone
+fn pin_harness_if_modified(
+    ctx: &JobContext<'_>,
+    pr_number: u64,
+    branches: &mut PrBranches,
+    changed_files: &[PullRequestFile],
+) {
+    let modifies_bench_harness = changed_files
+        .iter()
+        .any(|file| Path::new(&file.filename).starts_with(&ctx.config.bench_suite.bench_dir));
+    if ctx.config.pin_harness_when_changed
+        && branches.baseline.pinned_harness.is_none()
+        && !branches.is_standalone()
+        && modifies_bench_harness
+    {
+        info!("PR #{pr_number} modifies the bench harness, pinning the baseline's harness");
+        branches.pin_baseline_harness();
+    }
+}
+
+/// Returns the files changed by the PR
+///
+/// Errors are logged and treated as if no files were changed, since the files are only used for
+/// optional refinements (pinning the harness, reporting the cost of dependency upgrades)
+async fn changed_files(
+    ctx: &JobContext<'_>,
+    pr_number: u64,
+    octocrab: &Octocrab,
+) -> Vec<PullRequestFile> {
+    match github::pr_changed_files(pr_number, ctx.config, octocrab).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!(
+                cause = format!("{e:#}"),
+                "unable to list the PR's changed files"
+            );
+            Vec::new()
+        }
+    }
+}
+
+/// Attributes the PR's instruction count changes to dependency crates, if the PR only bumps
+/// dependencies
+fn dependency_upgrade(
+    changed_files: &[PullRequestFile],
+    result: &ComparisonResult,
+) -> Option<DependencyUpgrade> {
+    if !is_dependency_bump(changed_files.iter().map(|file| file.filename.as_str())) {
+        return None;
+    }
+
+    let bumped_crates: Vec<_> = changed_files
+        .iter()
+        .filter(|file| file.filename.ends_with("Cargo.lock"))
+        .filter_map(|file| file.patch.as_deref())
+        .flat_map(bumped_crates)
+        .collect();
+    let crate_costs = crate_costs(&result.metric(ScenarioKind::Icount).diffs, &bumped_crates);
+    Some(DependencyUpgrade {
+        bumped_crates,
+        crate_costs,
+    })
 }
 
 #[derive(Debug)]
-struct BenchPrError {
+pub(super) struct BenchPrError {
     error: anyhow::Error,
     logs: BenchPrLogs,
 }
 
 #[derive(Debug, Default)]
-struct BenchPrLogs {
+pub(super) struct BenchPrLogs {
     base: Vec<Log>,
     candidate: Vec<Log>,
 }
 
 /// Creates a markdown version of the results for posting to GitHub as a comment
-fn markdown_comment(
+pub(super) fn markdown_comment(
+    config: &AppConfig,
     branches: &PrBranches,
     result: Result<ComparisonResult, BenchPrError>,
-    diff_url: &str,
-    bencher_project_id: Option<&str>,
+    checks: PrChecks<'_>,
+    run_details: &RunDetails,
+    mode: BenchMode<'_>,
+    full_run_queued: bool,
 ) -> String {
+    let bench_suite = &config.bench_suite;
+    let cachegrind_diff_url = format!(
+        "{}/comparisons/{}:{}/cachegrind-diff",
+        config.app_base_url,
+        branches.baseline.comparison_key(),
+        branches.candidate.comparison_key()
+    );
     match result {
-        Ok(bench_results) => ComparisonSuccessComment {
-            cachegrind_diff_url: diff_url,
-            icount: Diffs::from_sub_result(bench_results.icount),
-            walltime: Diffs::from_sub_result(bench_results.walltime),
-            branches,
-            bencher_project_id,
-            common_time_unit: |x, y| common_time_unit(*x, *y),
-        }
-        .render()
-        .expect("failed to render askama template"),
+        Ok(mut bench_results) => {
+            // Metrics with a dedicated section are taken out, the rest are reported generically
+            let icount = bench_results.take_metric(ScenarioKind::Icount);
+            let walltime = bench_results.take_metric(ScenarioKind::Walltime);
+            let max_rss = bench_results.take_metric(ScenarioKind::MaxRss);
+            let mut cache_misses = bench_results.take_metric(ScenarioKind::D1Misses);
+            cache_misses
+                .diffs
+                .extend(bench_results.take_metric(ScenarioKind::LlMisses).diffs);
+            let build_time = bench_results.take_metric(ScenarioKind::BuildTime);
+            let estimated_cycles = bench_results
+                .take_metric(ScenarioKind::EstimatedCycles)
+                .diffs
+                .into_iter()
+                .map(|diff| (diff.scenario_name.clone(), diff))
+                .collect();
+            let other_metrics = bench_results
+                .metrics
+                .into_iter()
+                .map(|(kind, sub_result)| (kind, Diffs::from_sub_result(sub_result)))
+                .filter(|(_, diffs)| !diffs.is_empty())
+                .collect();
+
+            // Sections that are being rolled out are left empty for PRs that don't get them yet
+            let features = &run_details.features;
+            let included = |feature| features.includes(feature);
+
+            ComparisonSuccessComment {
+                cachegrind_diff_url: &cachegrind_diff_url,
+                report: &config.report,
+                quick_scenario_count: match mode {
+                    BenchMode::Full | BenchMode::Walltime | BenchMode::Filtered(_) => None,
+                    BenchMode::Quick => Some(bench_suite.quick_scenarios.len()),
+                },
+                scenario_filter: match mode {
+                    BenchMode::Filtered(glob) => Some(glob),
+                    _ => None,
+                },
+                walltime_only: mode == BenchMode::Walltime,
+                full_run_queued,
+                app_name: &config.bot_name,
+                diverging_pairs: if included(ReportFeature::DivergingPairs) {
+                    diverging_scenario_pairs(&icount.diffs, bench_suite)
+                } else {
+                    Vec::new()
+                },
+                top_movers: if included(ReportFeature::TopMovers) {
+                    top_movers(&icount.diffs)
+                } else {
+                    Vec::new()
+                },
+                icount_index_change: icount_index_change(&icount.diffs)
+                    .filter(|_| included(ReportFeature::IcountIndex)),
+                icount: Diffs::from_sub_result(icount),
+                walltime: Diffs::from_sub_result(walltime),
+                max_rss: Diffs::from_sub_result(max_rss),
+                cache_misses: Diffs::from_sub_result(cache_misses),
+                build_time: build_time.diffs.into_iter().next(),
+                estimated_cycles,
+                other_metrics,
+                branches,
+                bencher_project_id: config.bencher.as_ref().map(|b| b.project_id.as_str()),
+                backport_check: checks.backport,
+                dependency_upgrade: checks.dependency_upgrade,
+                unstable_runs: &run_details.unstable_runs,
+                reproduction: run_details.reproduction.as_ref(),
+                artifact_sizes: if included(ReportFeature::ArtifactSizes) {
+                    &run_details.artifact_sizes
+                } else {
+                    &[]
+                },
+                new_scenario_stability: if included(ReportFeature::NewScenarioStability) {
+                    &run_details.new_scenario_stability
+                } else {
+                    &[]
+                },
+                annotations: &run_details.annotations,
+            }
+            .render()
+            .expect("failed to render askama template")
+        }
         Err(error) => {
             let mut baseline_logs = String::new();
             write_logs_for_run(&mut baseline_logs, &error.logs.base);
             let mut candidate_logs = String::new();
             write_logs_for_run(&mut candidate_logs, &error.logs.candidate);
             ComparisonErrorComment {
+                class: FailureClass::of(&error.error),
                 error: format!("{:?}", error.error),
                 baseline_logs,
                 candidate_logs,
                 branches,
+                job_url: run_details.job_url.as_deref(),
             }
             .render()
             .expect("failed to render askama template")
@@ -533,10 +3547,15 @@ fn markdown_comment(
     }
 }
 
+/// Returns the cachegrind diff of the provided scenario
+type CachegrindDiffFn<'a> = dyn Fn(&str) -> anyhow::Result<String> + 'a;
+
 /// Returns an internal representation of the comparison between the baseline and the candidate
 /// measurements
+///
+/// Cachegrind diffs are only available for icount results, and only if `cachegrind_diff` is provided
 fn compare_results(
-    job_output_path: &Path,
+    cachegrind_diff: Option<&CachegrindDiffFn<'_>>,
     baseline: &HashMap<String, f64>,
     candidate: &HashMap<String, f64>,
     significance_thresholds: &HashMap<String, f64>,
@@ -552,10 +3571,11 @@ fn compare_results(
             continue;
         };
 
-        let cachegrind_diff = if scenario_kind == ScenarioKind::Icount {
-            Some(callgrind_diff(job_output_path, scenario)?)
-        } else {
-            None
+        let cachegrind_diff = match cachegrind_diff {
+            Some(cachegrind_diff) if scenario_kind == ScenarioKind::Icount => {
+                Some(cachegrind_diff(scenario)?)
+            }
+            _ => None,
         };
 
         diffs.push(ScenarioDiff {
@@ -568,6 +3588,7 @@ fn compare_results(
                 .cloned()
                 .unwrap_or(default_noise_threshold)
                 .max(minimum_noise_threshold),
+            result_unit: scenario_kind.unit(),
             cachegrind_diff,
         });
     }
@@ -589,10 +3610,10 @@ fn split_on_threshold(diffs: Vec<ScenarioDiff>) -> (Vec<ScenarioDiff>, Vec<Scena
     let mut negligible = Vec::new();
 
     for diff in diffs {
-        if diff.diff_ratio().abs() < diff.significance_threshold {
-            negligible.push(diff);
-        } else {
+        if diff.is_significant() {
             significant.push(diff);
+        } else {
+            negligible.push(diff);
         }
     }
 
@@ -602,27 +3623,103 @@ fn split_on_threshold(diffs: Vec<ScenarioDiff>) -> (Vec<ScenarioDiff>, Vec<Scena
     (significant, negligible)
 }
 
+/// Returns the relative change of the geometric mean across all scenarios present in both the
+/// baseline and the candidate
+fn icount_index_change(diffs: &[ScenarioDiff]) -> Option<f64> {
+    let ratios = diffs
+        .iter()
+        .map(|diff| diff.candidate_result / diff.baseline_result);
+    geometric_mean(ratios).map(|ratio| ratio - 1.0)
+}
+
+/// Returns the scenarios with the largest absolute changes, regardless of their significance
+///
+/// Large scenarios can drift by many instructions while staying below their significance
+/// threshold, which would otherwise go unnoticed
+fn top_movers(diffs: &[ScenarioDiff]) -> Vec<ScenarioDiff> {
+    let mut movers: Vec<_> = diffs.iter().filter(|d| d.diff() != 0.0).cloned().collect();
+    movers.sort_by(|d1, d2| {
+        f64::partial_cmp(&d2.diff().abs(), &d1.diff().abs()).unwrap_or(Ordering::Equal)
+    });
+    movers.truncate(TOP_MOVERS_COUNT);
+    movers
+}
+
+/// Returns the client/server scenario pairs whose results moved in opposite directions, where at
+/// least one of the two moved significantly
+///
+/// Both sides of a pair are expected to move together, so a divergence often points to a problem
+/// in the benchmark harness rather than to a real performance change
+fn diverging_scenario_pairs(
+    diffs: &[ScenarioDiff],
+    bench_suite: &BenchSuiteConfig,
+) -> Vec<ScenarioPair> {
+    let by_name: HashMap<_, _> = diffs
+        .iter()
+        .map(|diff| (diff.scenario_name.as_str(), diff))
+        .collect();
+
+    let mut pairs = Vec::new();
+    for client in diffs {
+        let Some(scenario) = client
+            .scenario_name
+            .strip_suffix(&bench_suite.client_scenario_suffix)
+        else {
+            continue;
+        };
+
+        let server_name = format!("{scenario}{}", bench_suite.server_scenario_suffix);
+        let Some(&server) = by_name.get(server_name.as_str()) else {
+            continue;
+        };
+
+        let opposite_directions = client.diff() * server.diff() < 0.0;
+        if opposite_directions && (client.is_significant() || server.is_significant()) {
+            pairs.push(ScenarioPair {
+                scenario: scenario.to_string(),
+                client: client.clone(),
+                server: server.clone(),
+            });
+        }
+    }
+
+    pairs.sort_by(|p1, p2| p1.scenario.cmp(&p2.scenario));
+    pairs
+}
+
 /// Returns the detailed instruction diff between the baseline and the candidate
-pub fn callgrind_diff(job_output_path: &Path, scenario: &str) -> anyhow::Result<String> {
+pub fn callgrind_diff(
+    job_output_path: &Path,
+    bench_suite: &BenchSuiteConfig,
+    scenario: &str,
+) -> anyhow::Result<String> {
+    callgrind_diff_between(
+        &job_output_path.join("base"),
+        &job_output_path.join("candidate"),
+        bench_suite,
+        scenario,
+    )
+}
+
+/// Returns the detailed instruction diff between the outputs of two bench runs (e.g. the baseline
+/// and the candidate of a comparison, or two runs of main)
+pub fn callgrind_diff_between(
+    base_output_path: &Path,
+    candidate_output_path: &Path,
+    bench_suite: &BenchSuiteConfig,
+    scenario: &str,
+) -> anyhow::Result<String> {
     // callgrind_annotate formats the callgrind output file, suitable for comparison with
     // callgrind_differ
     let callgrind_annotate_base = Command::new("callgrind_annotate")
-        .arg(
-            job_output_path
-                .join("base/results/callgrind")
-                .join(scenario),
-        )
+        .arg(bench_suite.callgrind_path(base_output_path, scenario))
         // do not annotate source, to keep output compact
         .arg("--auto=no")
         .output()
         .context("error waiting for callgrind_annotate to finish")?;
 
     let callgrind_annotate_candidate = Command::new("callgrind_annotate")
-        .arg(
-            job_output_path
-                .join("candidate/results/callgrind")
-                .join(scenario),
-        )
+        .arg(bench_suite.callgrind_path(candidate_output_path, scenario))
         // do not annotate source, to keep output compact
         .arg("--auto=no")
         .output()
@@ -662,14 +3759,55 @@ pub struct ComparisonSuccessComment<'a> {
     icount: Diffs,
     /// Diffs for the walltime benchmarks
     walltime: Diffs,
+    /// Diffs for the memory usage benchmarks
+    max_rss: Diffs,
+    /// Diffs for the cache miss counts of the icount scenarios
+    cache_misses: Diffs,
+    /// Diff of the clean release build's wall time, if measured on both sides
+    build_time: Option<ScenarioDiff>,
+    /// Diffs for the estimated cycles of the icount scenarios, shown next to their instruction
+    /// counts, per scenario
+    estimated_cycles: HashMap<String, ScenarioDiff>,
+    /// Diffs for the metrics without a dedicated section in the report, per metric
+    other_metrics: Vec<(ScenarioKind, Diffs)>,
+    /// The relative change of the geometric mean of all icount scenarios, if available
+    icount_index_change: Option<f64>,
+    /// The icount scenarios with the largest absolute changes
+    top_movers: Vec<ScenarioDiff>,
+    /// Client/server icount scenario pairs whose results diverged
+    diverging_pairs: Vec<ScenarioPair>,
     /// The base url to obtain cachegrind diffs
     cachegrind_diff_url: &'a str,
+    /// How feedback should be presented
+    report: &'a ReportConfig,
+    /// The number of scenarios that were benchmarked, if the results are partial (quick mode)
+    quick_scenario_count: Option<usize>,
+    /// Whether a full run has been queued to follow up on the quick run
+    full_run_queued: bool,
+    /// Whether only the walltime benchmarks were run (walltime mode)
+    walltime_only: bool,
+    /// The glob matching the benchmarked scenarios, if only some of them were benchmarked
+    scenario_filter: Option<&'a str>,
+    /// The name under which the application can be addressed in comments
+    app_name: &'a str,
     /// Information about the branches that were compared
     branches: &'a PrBranches,
     /// Bencher's project id, if available
     bencher_project_id: Option<&'a str>,
-    /// A function to obtain the time unit used to report the walltimes
-    common_time_unit: fn(&f64, &f64) -> TimeUnit,
+    /// Whether the PR performs like the change it backports, for backport PRs
+    backport_check: Option<&'a BackportCheck>,
+    /// What the upgrade costs, for PRs that only bump dependencies
+    dependency_upgrade: Option<&'a DependencyUpgrade>,
+    /// The runs that were flagged as environmentally unstable by the determinism check
+    unstable_runs: &'a [UnstableRun],
+    /// How to reproduce the candidate's measurements locally, if known
+    reproduction: Option<&'a Reproduction>,
+    /// The size of the artifacts built from both sides, if tracked
+    artifact_sizes: &'a [ArtifactSizeDiff],
+    /// The instruction counts of the new scenarios, measured twice on the candidate (if available)
+    new_scenario_stability: &'a [NewScenarioStability],
+    /// The maintainers' notes on the compared results (e.g. known upstream causes of changes)
+    annotations: &'a [Annotation],
 }
 
 pub struct Diffs {
@@ -690,11 +3828,141 @@ impl Diffs {
             scenarios_missing_in_baseline: sub_result.scenarios_missing_in_baseline,
         }
     }
+
+    /// Returns true if there are no results at all (e.g. because the benchmarks were skipped)
+    fn is_empty(&self) -> bool {
+        self.significant_diffs.is_empty()
+            && self.negligible_diffs.is_empty()
+            && self.scenarios_missing_in_baseline.is_empty()
+    }
+}
+
+/// Configures how comparison feedback is presented in PR comments
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ReportConfig {
+    /// Use plain-text markers instead of emoji (friendlier to screen readers)
+    pub plain_text: bool,
+    /// Custom marker for regressions (overrides the default)
+    pub regression_marker: Option<String>,
+    /// Custom marker for improvements (overrides the default)
+    pub improvement_marker: Option<String>,
+    /// Custom marker for warnings (overrides the default)
+    pub warning_marker: Option<String>,
+    /// Significant diffs exceeding their threshold at least this many times are `moderate`
+    pub moderate_severity_multiple: u32,
+    /// Significant diffs exceeding their threshold at least this many times are `major`
+    pub major_severity_multiple: u32,
+    /// The sections omitted, in order, from comments exceeding GitHub's size limit (defaults to
+    /// the negligible diffs, then the logs)
+    pub omit_order: Vec<CommentSection>,
+    /// Lists of scenarios missing in the baseline longer than this are collapsed behind their
+    /// count (defaults to 10)
+    pub missing_scenarios_collapse_threshold: usize,
+    /// The PRs whose reports include each of the sections being rolled out (sections without a
+    /// rollout are included in every report). Can be overridden at runtime through
+    /// `/admin/report-features`
+    pub feature_rollouts: BTreeMap<ReportFeature, FeatureRollout>,
+}
+
+impl Default for ReportConfig {
+    fn default() -> Self {
+        Self {
+            plain_text: false,
+            regression_marker: None,
+            improvement_marker: None,
+            warning_marker: None,
+            moderate_severity_multiple: 2,
+            major_severity_multiple: 5,
+            omit_order: vec![CommentSection::NegligibleDiffs, CommentSection::Logs],
+            missing_scenarios_collapse_threshold: 10,
+            feature_rollouts: BTreeMap::new(),
+        }
+    }
+}
+
+impl ReportConfig {
+    fn regression_marker(&self) -> &str {
+        match &self.regression_marker {
+            Some(marker) => marker,
+            None if self.plain_text => "Regression",
+            None => "⚠️",
+        }
+    }
+
+    fn improvement_marker(&self) -> &str {
+        match &self.improvement_marker {
+            Some(marker) => marker,
+            None if self.plain_text => "Improvement",
+            None => "✅",
+        }
+    }
+
+    fn warning_marker(&self) -> &str {
+        match &self.warning_marker {
+            Some(marker) => marker,
+            None if self.plain_text => "Warning:",
+            None => "⚠️",
+        }
+    }
+
+    /// Returns the severity of a significant diff, based on how many times it exceeds its threshold
+    fn severity(&self, diff: &ScenarioDiff) -> Severity {
+        let multiple = diff.diff_ratio().abs() / diff.significance_threshold;
+        if multiple >= self.major_severity_multiple as f64 {
+            Severity::Major
+        } else if multiple >= self.moderate_severity_multiple as f64 {
+            Severity::Moderate
+        } else {
+            Severity::Minor
+        }
+    }
+
+    /// Returns the marker (including severity) that precedes a significant diff
+    fn marker(&self, diff: &ScenarioDiff) -> String {
+        let marker = if diff.diff() > 0.0 {
+            self.regression_marker()
+        } else if diff.diff() < 0.0 {
+            self.improvement_marker()
+        } else {
+            return String::new();
+        };
+
+        format!("{marker} ({}) ", self.severity(diff).as_str())
+    }
+}
+
+/// How far a significant diff exceeds its significance threshold
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Severity {
+    Minor,
+    Moderate,
+    Major,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Minor => "minor",
+            Severity::Moderate => "moderate",
+            Severity::Major => "major",
+        }
+    }
+}
+
+/// The client and server sides of a scenario
+pub struct ScenarioPair {
+    /// The scenario's name, without the client/server suffix
+    scenario: String,
+    client: ScenarioDiff,
+    server: ScenarioDiff,
 }
 
 #[derive(Template)]
 #[template(path = "comparison_error_comment.md")]
 pub struct ComparisonErrorComment<'a> {
+    /// The class of the error, to explain it in human-readable terms
+    class: FailureClass,
     /// The error that caused the comparison to fail
     error: String,
     /// Information about the branches that were compared
@@ -703,78 +3971,84 @@ pub struct ComparisonErrorComment<'a> {
     candidate_logs: String,
     /// Logs from trying to benchmark the baseline branch
     baseline_logs: String,
-}
-
-/// Returns a time unit that has enough resolution to represent both values
-fn common_time_unit(x: f64, y: f64) -> TimeUnit {
-    let max = x.max(y);
-    if max < 1_000.0 {
-        TimeUnit::Nanoseconds
-    } else if max < 1_000_000.0 {
-        TimeUnit::Microseconds
-    } else if max < 1_000_000_000.0 {
-        TimeUnit::Milliseconds
-    } else {
-        TimeUnit::Seconds
-    }
-}
-
-#[derive(Copy, Clone, Debug, PartialEq)]
-enum TimeUnit {
-    Nanoseconds,
-    Microseconds,
-    Milliseconds,
-    Seconds,
+    /// The page of the failed job, if known
+    job_url: Option<&'a str>,
 }
 
 static DEFAULT_ICOUNT_NOISE_THRESHOLD: f64 = 0.002; // 0.2%
 static MINIMUM_ICOUNT_NOISE_THRESHOLD: f64 = 0.002; // 0.2%
 static DEFAULT_WALLTIME_NOISE_THRESHOLD: f64 = 0.05; // 5%
 static MINIMUM_WALLTIME_NOISE_THRESHOLD: f64 = 0.01; // 1%
-static APP_NAME: &str = "rustls-benchmarking";
+static DEFAULT_MAX_RSS_NOISE_THRESHOLD: f64 = 0.02; // 2%
+static MINIMUM_MAX_RSS_NOISE_THRESHOLD: f64 = 0.005; // 0.5%
+static DEFAULT_CACHE_MISS_NOISE_THRESHOLD: f64 = 0.01; // 1%
+static MINIMUM_CACHE_MISS_NOISE_THRESHOLD: f64 = 0.005; // 0.5%
+static DEFAULT_BUILD_TIME_NOISE_THRESHOLD: f64 = 0.05; // 5%
+static MINIMUM_BUILD_TIME_NOISE_THRESHOLD: f64 = 0.02; // 2%
+static DEFAULT_PERF_STAT_NOISE_THRESHOLD: f64 = 0.03; // 3%
+static MINIMUM_PERF_STAT_NOISE_THRESHOLD: f64 = 0.01; // 1%
+static DEFAULT_ENERGY_NOISE_THRESHOLD: f64 = 0.05; // 5%
+static MINIMUM_ENERGY_NOISE_THRESHOLD: f64 = 0.02; // 2%
+static TOP_MOVERS_COUNT: usize = 5;
 
-/// Functions inside this module will be available as askama filters
-mod filters {
-    use std::borrow::Borrow;
+/// The directory where the new scenarios are benchmarked again, in the job's output dir
+static NEW_SCENARIOS_RERUN_DIR: &str = "candidate-rerun";
 
+/// The name of the CSV file where the stability of the new scenarios is stored, in the job's output
+/// dir
+static NEW_SCENARIO_STABILITY_FILE: &str = "new_scenario_stability.csv";
+
+#[cfg(test)]
+mod test {
     use super::*;
 
-    pub fn format_timing(
-        timing_ns: impl Borrow<f64>,
-        unit: impl Borrow<TimeUnit>,
-    ) -> askama::Result<String> {
-        // Note: we need to use `Borrow` to sidestep askama limitations (plain types result in
-        // compile errors)
-        let timing_ns = *timing_ns.borrow();
-        let unit = *unit.borrow();
-
-        let (number, unit, precision) = match unit {
-            TimeUnit::Nanoseconds => (timing_ns, "ns", 0),
-            TimeUnit::Microseconds => (timing_ns / 1_000.0, "µs", 2),
-            TimeUnit::Milliseconds => (timing_ns / 1_000_000.0, "ms", 2),
-            TimeUnit::Seconds => (timing_ns / 1_000_000_000.0, "s", 2),
+    #[test]
+    fn failed_scenarios_merge_replaces_retried_scenarios() {
+        let diff = |scenario, candidate| ScenarioDiff::icount(scenario, 1.0, candidate, 0.1);
+        let result =
+            |missing: &[&str], diffs| ComparisonResult::from_diffs(diffs, missing, Vec::new());
+
+        let failed = FailedScenarios {
+            icount: vec!["a".to_string(), "b".to_string()],
+            walltime: Vec::new(),
+            max_rss: Vec::new(),
         };
+        let previous = result(&["a", "b"], vec![diff("c", 1.0)]);
+        // The retried run may include other scenarios, which are ignored
+        let retried = result(&["b"], vec![diff("a", 2.0), diff("c", 3.0)]);
 
-        Ok(format!("{number:.0$} {unit}", precision))
+        let merged = failed.merge(previous, retried);
+        assert_eq!(
+            merged
+                .metric(ScenarioKind::Icount)
+                .scenarios_missing_in_baseline,
+            ["b"]
+        );
+        assert_eq!(merged.metric(ScenarioKind::Icount).diffs.len(), 2);
+        assert_eq!(
+            merged.metric(ScenarioKind::Icount).diffs[0].scenario_name,
+            "c"
+        );
+        assert_eq!(
+            merged.metric(ScenarioKind::Icount).diffs[0].candidate_result,
+            1.0
+        );
+        assert_eq!(
+            merged.metric(ScenarioKind::Icount).diffs[1].scenario_name,
+            "a"
+        );
+        assert_eq!(
+            merged.metric(ScenarioKind::Icount).diffs[1].candidate_result,
+            2.0
+        );
     }
-}
-
-#[cfg(test)]
-mod test {
-    use super::*;
 
     #[test]
     fn split_on_threshold_sorts_using_absolute_value() {
-        fn diff(scenario: &str, baseline: f64, candidate: f64) -> ScenarioDiff {
-            ScenarioDiff {
-                scenario_name: scenario.to_string(),
-                scenario_kind: ScenarioKind::Icount,
-                baseline_result: baseline,
-                candidate_result: candidate,
-                significance_threshold: f64::MAX, // Everything is negligible
-                cachegrind_diff: Some(String::new()),
-            }
-        }
+        // Everything is negligible
+        let diff = |scenario, baseline, candidate| {
+            ScenarioDiff::icount(scenario, baseline, candidate, f64::MAX)
+        };
 
         let diffs = vec![
             diff("x", 1.2, 1.0),
@@ -790,38 +4064,172 @@ mod test {
     }
 
     #[test]
-    fn test_common_time_unit() {
-        assert_eq!(common_time_unit(500.0, 999.0), TimeUnit::Nanoseconds);
-        assert_eq!(common_time_unit(500.0, 1_999.0), TimeUnit::Microseconds);
-        assert_eq!(common_time_unit(1_000.0, 1_999.0), TimeUnit::Microseconds);
+    fn bench_command_combines_flags_in_any_order() {
+        assert_eq!(BenchCommand::parse("bench", ""), BenchCommand::default());
+        assert_eq!(
+            BenchCommand::parse("bench", "main --quick"),
+            BenchCommand {
+                against_main: true,
+                quick: true,
+                ..BenchCommand::default()
+            }
+        );
+        assert_eq!(
+            BenchCommand::parse("bench", "--pin-harness --quick"),
+            BenchCommand::parse("bench", "--quick --pin-harness"),
+        );
+        assert_eq!(
+            BenchCommand::parse("bench", "--urgent handshake_* main --pin-harness"),
+            BenchCommand {
+                against_main: true,
+                pin_harness: true,
+                scenario_glob: Some("handshake_*"),
+                ..BenchCommand::default()
+            }
+        );
+        assert_eq!(
+            BenchCommand::parse("retry", "--quick"),
+            BenchCommand {
+                quick: true,
+                retry: true,
+                ..BenchCommand::default()
+            }
+        );
+        assert_eq!(
+            BenchCommand::parse("bench-walltime", "--pin-harness"),
+            BenchCommand {
+                walltime: true,
+                pin_harness: true,
+                ..BenchCommand::default()
+            }
+        );
         assert_eq!(
-            common_time_unit(1_000_000.0, 1_999.0),
-            TimeUnit::Milliseconds
+            BenchCommand::parse("bench-commits", "--pin-harness abc1234..def5678"),
+            BenchCommand {
+                commit_range: Some("abc1234..def5678"),
+                pin_harness: true,
+                ..BenchCommand::default()
+            }
         );
         assert_eq!(
-            common_time_unit(1_000_000_000.0, 1_999.0),
-            TimeUnit::Seconds
+            BenchCommand::parse("bench-commits", "").commit_range,
+            Some("")
         );
     }
 
     #[test]
-    fn format_timing() {
-        assert_eq!(
-            filters::format_timing(100.0, TimeUnit::Nanoseconds).unwrap(),
-            "100 ns"
-        );
+    fn parse_commit_range_only_accepts_hashes() {
+        let first = "1234567890abcdef1234567890abcdef12345678";
+        let last = "89abcdef89abcdef89abcdef89abcdef89abcdef";
         assert_eq!(
-            filters::format_timing(1_500.0, TimeUnit::Microseconds).unwrap(),
-            "1.50 µs"
+            parse_commit_range(&format!("{first}..{last}")),
+            Some((first, last))
         );
+        assert_eq!(parse_commit_range(first), None);
+        // Abbreviated hashes can't be fetched
+        assert_eq!(parse_commit_range(&format!("1234567..{last}")), None);
+        assert_eq!(parse_commit_range(&format!("main..{last}")), None);
         assert_eq!(
-            filters::format_timing(1_250_000.0, TimeUnit::Milliseconds).unwrap(),
-            "1.25 ms"
+            parse_commit_range(&format!("--upload-pack=x..{last}")),
+            None
         );
+    }
+
+    #[test]
+    fn parse_compare_refs_validates_refs() {
         assert_eq!(
-            filters::format_timing(1_420_000_000.0, TimeUnit::Seconds).unwrap(),
-            "1.42 s"
+            parse_compare_refs("v0.21.0 release/0.22"),
+            Some(("v0.21.0", "release/0.22"))
         );
+        assert_eq!(parse_compare_refs("main"), None);
+        assert_eq!(parse_compare_refs("--upload-pack=x main"), None);
+        assert_eq!(parse_compare_refs("../pulls main"), None);
+        assert_eq!(parse_compare_refs("main?x=1 main"), None);
+    }
+
+    #[test]
+    fn report_markers_include_severity() {
+        let diff = |baseline, candidate| ScenarioDiff::icount("foo", baseline, candidate, 0.01);
+
+        let report = ReportConfig::default();
+        assert_eq!(report.marker(&diff(100.0, 101.5)), "⚠️ (minor) ");
+        assert_eq!(report.marker(&diff(100.0, 97.0)), "✅ (moderate) ");
+        assert_eq!(report.marker(&diff(100.0, 110.0)), "⚠️ (major) ");
+        assert_eq!(report.marker(&diff(100.0, 100.0)), "");
+
+        let report = ReportConfig {
+            plain_text: true,
+            improvement_marker: Some("[faster]".to_string()),
+            ..ReportConfig::default()
+        };
+        assert_eq!(report.marker(&diff(100.0, 101.5)), "Regression (minor) ");
+        assert_eq!(report.marker(&diff(100.0, 90.0)), "[faster] (major) ");
+        assert_eq!(report.warning_marker(), "Warning:");
+    }
+
+    #[test]
+    fn icount_index_change_uses_geometric_mean() {
+        let diff = |baseline, candidate| ScenarioDiff::icount("foo", baseline, candidate, 0.01);
+
+        assert_eq!(icount_index_change(&[]), None);
+
+        // A 2x slowdown and a 2x speedup cancel each other out
+        let change = icount_index_change(&[diff(100.0, 200.0), diff(1000.0, 500.0)]).unwrap();
+        assert!(change.abs() < 1e-9);
+
+        let change = icount_index_change(&[diff(100.0, 110.0), diff(1000.0, 1100.0)]).unwrap();
+        assert!((change - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn top_movers_sorts_by_absolute_diff() {
+        // Everything is negligible
+        let diff = |scenario, baseline, candidate| {
+            ScenarioDiff::icount(scenario, baseline, candidate, f64::MAX)
+        };
+
+        let diffs = vec![
+            diff("small", 100.0, 110.0),
+            diff("huge", 1_000_000.0, 999_000.0),
+            diff("unchanged", 100.0, 100.0),
+            diff("a", 100.0, 101.0),
+            diff("b", 100.0, 102.0),
+            diff("c", 100.0, 97.0),
+            diff("d", 100.0, 104.0),
+        ];
+
+        let movers: Vec<_> = top_movers(&diffs)
+            .into_iter()
+            .map(|d| d.scenario_name)
+            .collect();
+        assert_eq!(movers, vec!["huge", "small", "d", "c", "b"]);
+    }
+
+    #[test]
+    fn diverging_scenario_pairs_requires_opposite_significant_moves() {
+        let diff = |scenario, baseline, candidate| {
+            ScenarioDiff::icount(scenario, baseline, candidate, 0.01)
+        };
+
+        let diffs = vec![
+            // Diverging: the client improved significantly, but the server regressed
+            diff("diverging_client", 100.0, 90.0),
+            diff("diverging_server", 100.0, 100.5),
+            // Moving together
+            diff("together_client", 100.0, 90.0),
+            diff("together_server", 100.0, 95.0),
+            // Opposite directions, but within the noise
+            diff("noise_client", 100.0, 99.5),
+            diff("noise_server", 100.0, 100.5),
+            // No counterpart
+            diff("lonely_client", 100.0, 90.0),
+        ];
+
+        let pairs = diverging_scenario_pairs(&diffs, &BenchSuiteConfig::default());
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].scenario, "diverging");
+        assert_eq!(pairs[0].client.scenario_name, "diverging_client");
+        assert_eq!(pairs[0].server.scenario_name, "diverging_server");
     }
 
     #[test]
@@ -840,6 +4248,7 @@ mod test {
             scenario_name: "foo".to_string(),
             scenario_kind: ScenarioKind::Icount,
             result,
+            result_unit: ResultUnit::Instructions,
         });
         let thresholds = calculate_significance_thresholds(bench_results);
 
@@ -847,6 +4256,36 @@ mod test {
         assert_eq!((thresholds["foo"] * 100.0).round(), 9.0);
     }
 
+    #[test]
+    fn new_scenario_stability_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert!(read_new_scenario_stability(dir.path()).unwrap().is_empty());
+
+        let stability = vec![NewScenarioStability {
+            scenario: "new_scenario".to_string(),
+            first_icount: 1000.0,
+            second_icount: 1001.0,
+        }];
+        write_new_scenario_stability(dir.path(), &stability).unwrap();
+        let read = read_new_scenario_stability(dir.path()).unwrap();
+        assert_eq!(read, stability);
+        assert_eq!(read[0].diff_ratio(), 0.001);
+    }
+
+    #[test]
+    fn change_points_of_series() {
+        let mut results = vec![100.0, 100.1, 99.9, 100.0, 100.2, 100.1, 99.9, 100.0, 100.1];
+        results.extend([110.0, 110.1, 109.9, 110.0]);
+        assert_eq!(change_points(ScenarioKind::Icount, &results), [9]);
+
+        // Short series fall back to the default threshold
+        assert_eq!(
+            change_points(ScenarioKind::Icount, &[100.0, 100.001, 120.0]),
+            [2]
+        );
+        assert!(change_points(ScenarioKind::Icount, &[100.0]).is_empty());
+    }
+
     #[test]
     fn compare_results_with_different_thresholds() {
         let baseline = HashMap::from([
@@ -861,7 +4300,7 @@ mod test {
         ]);
         let thresholds = HashMap::from([("foo".to_string(), 0.005), ("baz".to_string(), 0.02)]);
         let (diffs, missing) = compare_results(
-            Path::new("dummy"),
+            None,
             &baseline,
             &candidate,
             &thresholds,
@@ -0,0 +1,290 @@
+use std::fmt::Write;
+
+/// A command that can be given to the application through a PR comment
+#[derive(Debug)]
+pub struct Command {
+    /// The word following the `@bot_name` mention in the comment (see `AppConfig::bot_name`)
+    pub name: &'static str,
+    /// The arguments of this form of the command, as shown in the help text
+    pub args: &'static str,
+    pub description: &'static str,
+    pub handler: CommandHandler,
+}
+
+/// How a command is handled
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CommandHandler {
+    /// Benchmarks the PR's branches, or variations of them (see [`super::bench_pr`])
+    BenchPr,
+    /// Re-runs the scenarios missing from the PR's last comparison, merging them into it
+    RetryFailed,
+    /// Approves running the code of a PR coming from a fork, and benchmarks it
+    ApproveFork,
+    /// Benchmarks two arbitrary commits of the repository
+    Compare,
+    /// Re-renders the PR's report from cached results
+    Preview,
+    /// Cancels the PR's jobs, as soon as the command arrives (bypassing the queue)
+    Cancel,
+    /// Moves the PR's queued jobs to the front of the queue, as soon as the command arrives
+    /// (bypassing the queue)
+    Prioritize,
+    /// Replies with the available commands
+    Help,
+}
+
+/// The available commands, in the order they are listed in the help text
+///
+/// A command can appear multiple times, to document each of its forms
+pub static COMMANDS: &[Command] = &[
+    Command {
+        name: "bench",
+        args: "",
+        description: "runs the instruction count benchmarks and reports the results",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench",
+        args: "main",
+        description: "like `bench`, but compares against the latest benchmarked commit of `main` \
+            instead of the PR's base",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench",
+        args: "--quick",
+        description: "like `bench`, but only runs a representative subset of the scenarios, for \
+            a fast (partial) signal",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench",
+        args: "<glob>",
+        description: "like `bench`, but only runs the scenarios matching the glob (e.g. \
+            `handshake_tls13*`)",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench",
+        args: "--pin-harness",
+        description: "like `bench` (and combinable with its other forms), but benchmarks the \
+            baseline with the candidate's bench harness, so both sides run the same scenarios",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench",
+        args: "--urgent",
+        description: "like `bench` (and combinable with its other forms, as well as with \
+            `bench-walltime`, `bench-commits`, `retry`, `retry-failed` and `compare`), but handled \
+            before any other queued benchmarks",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench-walltime",
+        args: "",
+        description: "like `bench`, but only runs the wall-time benchmarks",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "bench-commits",
+        args: "<first>..<last>",
+        description: "cherry-picks the PR's commits from `<first>` to `<last>` (both included) \
+            on top of the latest benchmarked commit of `main`, and compares the result against \
+            that commit",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "retry",
+        args: "",
+        description: "like `bench`, but discards the cached results for the PR's current commits \
+            (if any) and runs the benchmarks again",
+        handler: CommandHandler::BenchPr,
+    },
+    Command {
+        name: "retry-failed",
+        args: "",
+        description: "re-runs only the scenarios missing from the PR's last report (e.g. because \
+            they failed), and merges their results into it",
+        handler: CommandHandler::RetryFailed,
+    },
+    Command {
+        name: "approve",
+        args: "<commit>",
+        description:
+            "for PRs coming from forks, confirms that the PR's code up to `<commit>` (its \
+            head) was reviewed and can run on the benchmarking machine, and benchmarks it",
+        handler: CommandHandler::ApproveFork,
+    },
+    Command {
+        name: "compare",
+        args: "<base> <head>",
+        description: "compares two arbitrary commits, branches or tags of the repository, and \
+            reports the results in a new comment",
+        handler: CommandHandler::Compare,
+    },
+    Command {
+        name: "preview",
+        args: "",
+        description: "re-renders the report from the cached results for the PR's current \
+            commits, without running the benchmarks",
+        handler: CommandHandler::Preview,
+    },
+    Command {
+        name: "cancel",
+        args: "",
+        description: "cancels the PR's queued and running benchmarks",
+        handler: CommandHandler::Cancel,
+    },
+    Command {
+        name: "prioritize",
+        args: "",
+        description: "moves the PR's queued benchmarks to the front of the queue (only available \
+            to repository owners and organization members)",
+        handler: CommandHandler::Prioritize,
+    },
+    Command {
+        name: "help",
+        args: "",
+        description: "lists the available commands",
+        handler: CommandHandler::Help,
+    },
+];
+
+/// The outcome of looking for a command in a comment
+#[derive(Debug, PartialEq, Eq)]
+pub enum ParsedComment<'a> {
+    /// The comment doesn't address the application
+    NotAddressed,
+    /// The comment addresses the application with an unknown command
+    Unknown,
+    /// The comment addresses the application with a known command, followed by its arguments
+    Command {
+        /// The name of the command (commands sharing a handler are told apart by it)
+        name: &'static str,
+        handler: CommandHandler,
+        args: &'a str,
+    },
+}
+
+/// Finds the command the comment addresses to the application, which is the word right after the
+/// first `@bot_name` mention (trailing punctuation is ignored)
+pub fn parse_comment<'a>(body: &'a str, bot_name: &str) -> ParsedComment<'a> {
+    let mention = format!("@{bot_name}");
+    let Some((_, rest)) = body.split_once(&mention) else {
+        return ParsedComment::NotAddressed;
+    };
+
+    // Don't mistake a mention of another app for ours (e.g. `@rustls-benchmarking-test`)
+    if rest.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_') {
+        return ParsedComment::NotAddressed;
+    }
+
+    let rest = rest.trim_start();
+    let word_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+    let (word, args) = rest.split_at(word_end);
+    let name = word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '-');
+    match COMMANDS.iter().find(|command| command.name == name) {
+        Some(command) => ParsedComment::Command {
+            name: command.name,
+            handler: command.handler,
+            args: args.lines().next().unwrap_or_default().trim(),
+        },
+        None => ParsedComment::Unknown,
+    }
+}
+
+/// Renders the list of available commands, as markdown
+pub fn help_text(bot_name: &str) -> String {
+    let mut help = String::new();
+    for command in COMMANDS {
+        let usage = if command.args.is_empty() {
+            format!("@{bot_name} {}", command.name)
+        } else {
+            format!("@{bot_name} {} {}", command.name, command.args)
+        };
+        writeln!(help, "* `{usage}`: {}", command.description).unwrap();
+    }
+
+    help
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const BOT_NAME: &str = "rustls-benchmarking";
+
+    #[test]
+    fn parse_comment_finds_command() {
+        assert_eq!(
+            parse_comment(
+                "Let's see\n@rustls-benchmarking bench-commits abc..def\nThanks!",
+                BOT_NAME
+            ),
+            ParsedComment::Command {
+                name: "bench-commits",
+                handler: CommandHandler::BenchPr,
+                args: "abc..def",
+            }
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking compare v0.21.0 main", BOT_NAME),
+            ParsedComment::Command {
+                name: "compare",
+                handler: CommandHandler::Compare,
+                args: "v0.21.0 main",
+            }
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking cancel.", BOT_NAME),
+            ParsedComment::Command {
+                name: "cancel",
+                handler: CommandHandler::Cancel,
+                args: "",
+            }
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking prioritize", BOT_NAME),
+            ParsedComment::Command {
+                name: "prioritize",
+                handler: CommandHandler::Prioritize,
+                args: "",
+            }
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking please", BOT_NAME),
+            ParsedComment::Unknown
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking", BOT_NAME),
+            ParsedComment::Unknown
+        );
+        assert_eq!(
+            parse_comment("@rustls-benchmarking-test bench", BOT_NAME),
+            ParsedComment::NotAddressed
+        );
+        assert_eq!(parse_comment("LGTM", BOT_NAME), ParsedComment::NotAddressed);
+        assert_eq!(
+            parse_comment(
+                "@rustls-benchmarking-staging bench",
+                "rustls-benchmarking-staging"
+            ),
+            ParsedComment::Command {
+                name: "bench",
+                handler: CommandHandler::BenchPr,
+                args: "",
+            }
+        );
+    }
+
+    #[test]
+    fn help_text_lists_all_commands() {
+        let help = help_text(BOT_NAME);
+        assert_eq!(help.lines().count(), COMMANDS.len());
+        assert!(help.contains(
+            "* `@rustls-benchmarking bench-commits <first>..<last>`: cherry-picks the PR's commits"
+        ));
+        assert!(help.contains("* `@rustls-benchmarking help`: lists the available commands\n"));
+        assert!(help_text("staging-bot").contains("* `@staging-bot help`"));
+    }
+}
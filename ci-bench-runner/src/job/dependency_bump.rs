@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use crate::cachegrind::function_diffs;
+use crate::db::ScenarioDiff;
+
+/// The number of crates listed in the cost of an upgrade (the ones whose instruction counts moved
+/// the most)
+const UPGRADE_COST_CRATE_COUNT: usize = 10;
+
+/// The cost of a PR that only bumps dependencies, attributed to the crates whose code changed
+#[derive(Debug, PartialEq)]
+pub struct DependencyUpgrade {
+    /// The crates whose locked version changed
+    pub bumped_crates: Vec<BumpedCrate>,
+    /// The crates whose instruction counts moved the most, across all icount scenarios
+    pub crate_costs: Vec<CrateCost>,
+}
+
+/// A crate whose locked version changed
+#[derive(Debug, Clone, PartialEq)]
+pub struct BumpedCrate {
+    pub name: String,
+    /// The version before the PR (`None` if the crate was added)
+    pub from: Option<String>,
+    /// The version after the PR (`None` if the crate was removed)
+    pub to: Option<String>,
+}
+
+impl BumpedCrate {
+    /// Describes the version change, e.g. `0.17.7 ➡️ 0.17.8`
+    pub fn change(&self) -> String {
+        match (&self.from, &self.to) {
+            (Some(from), Some(to)) => format!("{from} ➡️ {to}"),
+            (None, Some(to)) => format!("added ({to})"),
+            (Some(from), None) => format!("removed ({from})"),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+/// The instructions executed by a crate's functions, summed across all icount scenarios
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrateCost {
+    /// The crate's name, as it appears in symbols (i.e. with underscores instead of dashes)
+    pub name: String,
+    pub baseline: u64,
+    pub candidate: u64,
+    /// Whether the crate is one of the bumped dependencies
+    pub bumped: bool,
+}
+
+impl CrateCost {
+    pub fn diff(&self) -> i64 {
+        self.candidate as i64 - self.baseline as i64
+    }
+}
+
+/// Returns whether the changed files are only dependency manifests and lockfiles (e.g. the
+/// changes of a Dependabot PR)
+pub fn is_dependency_bump<'a>(changed_files: impl IntoIterator<Item = &'a str>) -> bool {
+    let mut changed_files = changed_files.into_iter().peekable();
+    changed_files.peek().is_some()
+        && changed_files.all(|file| {
+            let file_name = Path::new(file).file_name().and_then(|name| name.to_str());
+            matches!(file_name, Some("Cargo.lock" | "Cargo.toml"))
+        })
+}
+
+/// Returns the crates whose version changed, according to the patch of a `Cargo.lock` file
+///
+/// Each `version` line belongs to the closest `name` line above it, which the diff's context
+/// always includes, since `version` follows `name` in every package entry
+pub fn bumped_crates(lockfile_patch: &str) -> Vec<BumpedCrate> {
+    let mut crates: BTreeMap<&str, BumpedCrate> = BTreeMap::new();
+    let mut name = None;
+    for line in lockfile_patch.lines() {
+        let Some(marker) = line.chars().next() else {
+            continue;
+        };
+        let content = &line[marker.len_utf8()..];
+        if let Some(value) = quoted_value(content, "name") {
+            name = Some(value);
+            continue;
+        }
+
+        let (Some(version), Some(name)) = (quoted_value(content, "version"), name) else {
+            continue;
+        };
+        let bumped = crates.entry(name).or_insert_with(|| BumpedCrate {
+            name: name.to_string(),
+            from: None,
+            to: None,
+        });
+        match marker {
+            '-' => bumped.from = Some(version.to_string()),
+            '+' => bumped.to = Some(version.to_string()),
+            _ => {}
+        }
+    }
+
+    crates
+        .into_values()
+        .filter(|bumped| bumped.from != bumped.to)
+        .collect()
+}
+
+/// Returns the value of a `key = "value"` line
+fn quoted_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line
+        .trim()
+        .strip_prefix(key)?
+        .trim_start()
+        .strip_prefix('=')?;
+    value.trim().strip_prefix('"')?.strip_suffix('"')
+}
+
+/// Attributes the instruction count changes of the icount scenarios to the crates whose functions
+/// executed them, according to the scenarios' cachegrind diffs
+///
+/// Returns the crates whose instruction counts moved the most, biggest change first. Functions
+/// that can't be attributed to a crate (e.g. from libc) are left out.
+pub fn crate_costs(diffs: &[ScenarioDiff], bumped_crates: &[BumpedCrate]) -> Vec<CrateCost> {
+    let mut costs: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    let functions: Vec<_> = diffs
+        .iter()
+        .filter_map(|diff| diff.cachegrind_diff.as_deref())
+        .flat_map(function_diffs)
+        .collect();
+    for function in &functions {
+        if let Some(name) = crate_of_function(&function.function) {
+            let (baseline, candidate) = costs.entry(name).or_default();
+            *baseline += function.baseline;
+            *candidate += function.candidate;
+        }
+    }
+
+    let mut costs: Vec<_> = costs
+        .into_iter()
+        .map(|(name, (baseline, candidate))| CrateCost {
+            name: name.to_string(),
+            baseline,
+            candidate,
+            bumped: bumped_crates
+                .iter()
+                .any(|bumped| bumped.name.replace('-', "_") == name),
+        })
+        .filter(|cost| cost.diff() != 0)
+        .collect();
+    costs.sort_by_key(|cost| std::cmp::Reverse(cost.diff().abs()));
+    costs.truncate(UPGRADE_COST_CRATE_COUNT);
+    costs
+}
+
+/// Returns the crate a function belongs to, given its name as listed by `callgrind_annotate`
+/// (e.g. `???:ring::aead::seal` or `<ring::aead::Key as core::fmt::Debug>::fmt`, which both belong
+/// to `ring`)
+fn crate_of_function(function: &str) -> Option<&str> {
+    // Functions are prefixed by their source file, separated by a single colon
+    let bytes = function.as_bytes();
+    let separator = (0..bytes.len()).find(|&i| {
+        bytes[i] == b':' && bytes.get(i + 1) != Some(&b':') && (i == 0 || bytes[i - 1] != b':')
+    });
+    let path = match separator {
+        Some(i) => &function[i + 1..],
+        None => function,
+    };
+
+    // Trait implementations are attributed to the crate of the implementing type
+    let path = path.trim_start_matches(['<', '&', '*']);
+    let path = path.strip_prefix("mut ").unwrap_or(path);
+    let path = path.strip_prefix("dyn ").unwrap_or(path);
+
+    let (name, _) = path.split_once("::")?;
+    let is_identifier =
+        !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_identifier.then_some(name)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::db::ScenarioKind;
+    use crate::units::ResultUnit;
+
+    #[test]
+    fn dependency_bumps_only_touch_manifests() {
+        assert!(is_dependency_bump(["Cargo.lock"]));
+        assert!(is_dependency_bump(["Cargo.lock", "rustls/Cargo.toml"]));
+        assert!(!is_dependency_bump(["Cargo.lock", "rustls/src/lib.rs"]));
+        assert!(!is_dependency_bump(Vec::<&str>::new()));
+    }
+
+    #[test]
+    fn bumped_crates_from_lockfile_patch() {
+        let patch = r#"@@ -1200,9 +1200,9 @@ dependencies = [
+
+ [[package]]
+ name = "ring"
+-version = "0.17.7"
++version = "0.17.8"
+ source = "registry+https://github.com/rust-lang/crates.io-index"
+-checksum = "688c63d65483050968b2a8937f7995f443e27041a0f7700aa59b0822aedebb74"
++checksum = "c17fa4cb658e3583423e915b9f3acc01cceaee1860e33d59ebae66adc3a2dc0d"
+@@ -1300,3 +1300,8 @@
++
++[[package]]
++name = "spin"
++version = "0.9.8"
++source = "registry+https://github.com/rust-lang/crates.io-index"
+"#;
+
+        assert_eq!(
+            bumped_crates(patch),
+            [
+                BumpedCrate {
+                    name: "ring".to_string(),
+                    from: Some("0.17.7".to_string()),
+                    to: Some("0.17.8".to_string()),
+                },
+                BumpedCrate {
+                    name: "spin".to_string(),
+                    from: None,
+                    to: Some("0.9.8".to_string()),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn functions_are_attributed_to_crates() {
+        assert_eq!(crate_of_function("???:ring::aead::seal"), Some("ring"));
+        assert_eq!(
+            crate_of_function("/rustc/src/ptr/mod.rs:core::ptr::drop_in_place<u8>"),
+            Some("core")
+        );
+        assert_eq!(
+            crate_of_function("???:<aws_lc_rs::aead::Key as core::fmt::Debug>::fmt"),
+            Some("aws_lc_rs")
+        );
+        assert_eq!(crate_of_function("???:memcpy"), None);
+    }
+
+    #[test]
+    fn crate_costs_across_scenarios() {
+        let cachegrind_diff = |ring_baseline, ring_candidate| {
+            format!(
+                "\
+Base output:
+--------------------------------------------------------------------------------
+Ir                  file:function
+--------------------------------------------------------------------------------
+{ring_baseline} (50.00%)  ???:ring::aead::seal [/bench]
+1,000 (50.00%)  ???:rustls::conn::process [/bench]
+
+=====
+
+Candidate output:
+--------------------------------------------------------------------------------
+Ir                  file:function
+--------------------------------------------------------------------------------
+{ring_candidate} (50.00%)  ???:ring::aead::seal [/bench]
+1,000 (50.00%)  ???:rustls::conn::process [/bench]
+"
+            )
+        };
+        let diff = |scenario: &str, cachegrind_diff| ScenarioDiff {
+            scenario_name: scenario.to_string(),
+            scenario_kind: ScenarioKind::Icount,
+            baseline_result: 2_000.0,
+            candidate_result: 2_100.0,
+            significance_threshold: 0.01,
+            result_unit: ResultUnit::Instructions,
+            cachegrind_diff: Some(cachegrind_diff),
+        };
+        let diffs = [
+            diff("handshake", cachegrind_diff("1,000", "1,100")),
+            diff("transfer", cachegrind_diff("2,000", "2,050")),
+        ];
+        let bumped = [BumpedCrate {
+            name: "ring".to_string(),
+            from: Some("0.17.7".to_string()),
+            to: Some("0.17.8".to_string()),
+        }];
+
+        // Crates without changes are left out
+        assert_eq!(
+            crate_costs(&diffs, &bumped),
+            [CrateCost {
+                name: "ring".to_string(),
+                baseline: 3_000,
+                candidate: 3_150,
+                bumped: true,
+            }]
+        );
+    }
+}
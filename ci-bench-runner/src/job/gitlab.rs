@@ -0,0 +1,163 @@
+use anyhow::Context;
+use tracing::trace;
+
+use super::bench_pr::{
+    compare_and_render, final_status, pending_status, status_contexts, BenchMode, PrBranches,
+};
+use super::commands::{parse_comment, CommandHandler, ParsedComment};
+use crate::event_queue::{JobContext, JobPhase, PoisonEvent};
+use crate::forge::{update_commit_status, Forge, StatusContext};
+use crate::gitlab::api::{MergeRequestAttributes, MergeRequestEvent, NoteEvent};
+use crate::gitlab::{GitLabForge, COMMAND_ACCESS_LEVEL};
+use crate::CommitIdentifier;
+
+/// Handles a GitLab merge request event
+///
+/// Runs the benchmarks when the merge request is opened or reopened, and when new commits are
+/// pushed to it (other updates, like title changes, are ignored). Merge requests coming from forks
+/// are only benchmarked on request, through `@bot_name bench`.
+pub async fn handle_gitlab_merge_request(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let Some(gitlab) = &ctx.config.gitlab else {
+        trace!("ignoring merge request event, since GitLab is no longer configured");
+        return Ok(());
+    };
+    let event: MergeRequestEvent = serde_json::from_slice(ctx.event_payload)
+        .context("invalid merge request event")
+        .map_err(|e| e.context(PoisonEvent))?;
+
+    let merge_request = &event.object_attributes;
+    let has_new_commits = match merge_request.action.as_deref() {
+        Some("open" | "reopen") => true,
+        Some("update") => merge_request.oldrev.is_some(),
+        _ => false,
+    };
+    if !has_new_commits {
+        trace!("ignoring merge request event without new commits");
+        return Ok(());
+    }
+    if merge_request.source != merge_request.target {
+        trace!("ignoring merge request event from a forked project");
+        return Ok(());
+    }
+
+    let forge = GitLabForge::new(gitlab, &event.project.path_with_namespace);
+    bench_merge_request(&ctx, &forge, merge_request).await
+}
+
+/// Handles a GitLab note event
+///
+/// Runs the benchmarks if the note has been posted to a merge request by a project member with at
+/// least Developer access, and addresses the bot with `@bot_name bench`. The other commands are
+/// only available on GitHub.
+pub async fn handle_gitlab_note(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let Some(gitlab) = &ctx.config.gitlab else {
+        trace!("ignoring note event, since GitLab is no longer configured");
+        return Ok(());
+    };
+    let event: NoteEvent = serde_json::from_slice(ctx.event_payload)
+        .context("invalid note event")
+        .map_err(|e| e.context(PoisonEvent))?;
+
+    let Some(merge_request) = &event.merge_request else {
+        trace!("ignoring note that was not posted to a merge request");
+        return Ok(());
+    };
+
+    let forge = GitLabForge::new(gitlab, &event.project.path_with_namespace);
+    let args = match parse_comment(&event.object_attributes.note, &ctx.config.bot_name) {
+        ParsedComment::NotAddressed => {
+            trace!("ignoring note that doesn't address the bot");
+            return Ok(());
+        }
+        ParsedComment::Command {
+            name: "bench",
+            handler: CommandHandler::BenchPr,
+            args,
+        } => args,
+        ParsedComment::Command { .. } | ParsedComment::Unknown => {
+            let reply = format!(
+                "Only `@{} bench` is supported on GitLab merge requests",
+                ctx.config.bot_name
+            );
+            forge.post_comment(merge_request.iid, &reply).await?;
+            return Ok(());
+        }
+    };
+    if !args.is_empty() {
+        let reply = format!(
+            "`@{} bench` doesn't take arguments on GitLab merge requests",
+            ctx.config.bot_name
+        );
+        forge.post_comment(merge_request.iid, &reply).await?;
+        return Ok(());
+    }
+
+    let access_level = forge.access_level(event.user.id).await?;
+    if access_level.is_none_or(|level| level < COMMAND_ACCESS_LEVEL) {
+        trace!(
+            "ignoring command from @{}, who lacks Developer access",
+            event.user.username
+        );
+        return Ok(());
+    }
+
+    bench_merge_request(&ctx, &forge, merge_request).await
+}
+
+/// Compares the merge request's head against its merge base and reports the results through the
+/// forge
+///
+/// Every run posts a new note, since GitLab's notes are not tracked like GitHub's result comments
+async fn bench_merge_request(
+    ctx: &JobContext<'_>,
+    forge: &GitLabForge<'_>,
+    merge_request: &MergeRequestAttributes,
+) -> anyhow::Result<()> {
+    // The webhook payload doesn't tell the merge base, so it is fetched along with the head (which
+    // might also have moved since the event was sent)
+    let current = forge.merge_request(merge_request.iid).await?;
+    let diff_refs = current
+        .diff_refs
+        .context("GitLab has not computed the merge request's diff yet")?;
+    let branches = PrBranches {
+        baseline: CommitIdentifier {
+            clone_url: merge_request.target.git_http_url.clone(),
+            branch_name: merge_request.target_branch.clone(),
+            commit_sha: diff_refs.base_sha,
+            patch_series: None,
+            pinned_harness: None,
+        },
+        candidate: CommitIdentifier {
+            clone_url: merge_request.source.git_http_url.clone(),
+            branch_name: merge_request.source_branch.clone(),
+            commit_sha: diff_refs.head_sha,
+            patch_series: None,
+            pinned_harness: None,
+        },
+        detached: false,
+    };
+
+    let job_url = format!("{}/jobs/{}", ctx.config.app_base_url, ctx.job_id);
+    let contexts = status_contexts(ctx.config, BenchMode::Full);
+    for &context in &contexts {
+        let status = pending_status(context, &job_url);
+        update_commit_status(forge, &branches.candidate.commit_sha, &status).await;
+    }
+
+    let comparison = compare_and_render(ctx, &branches).await?;
+    ctx.enter_phase(JobPhase::Reporting).await;
+    forge
+        .post_comment(merge_request.iid, &comparison.report)
+        .await?;
+
+    for context in contexts {
+        let verdict = match context {
+            StatusContext::Icount => comparison.verdict,
+            StatusContext::Walltime => comparison.walltime_verdict,
+        };
+        let status = final_status(context, verdict, BenchMode::Full, &job_url);
+        update_commit_status(forge, &branches.candidate.commit_sha, &status).await;
+    }
+
+    Ok(())
+}
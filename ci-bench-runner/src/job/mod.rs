@@ -1,23 +1,140 @@
 use std::collections::HashMap;
+use std::fs;
 use std::fs::File;
 use std::io::{BufRead, BufReader};
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::{anyhow, bail, Context};
 use tracing::trace;
 
-pub use bench_main::bench_main;
-pub use bench_pr::{handle_issue_comment, handle_pr_review, handle_pr_update};
+use crate::runner::BenchSuiteConfig;
 
+pub use bench_main::{
+    bench_main, bencher_branch, is_tracked_branch, TrackedBranchConfig, MAIN_BRANCH,
+};
+pub use bench_pr::{
+    acknowledge_benchmarking_command, callgrind_diff_between, change_points, handle_api_compare,
+    handle_backport_original, handle_cancel_command, handle_full_run, handle_issue_comment,
+    handle_pr_review, handle_pr_update, handle_prioritize_command, is_urgent_command, is_valid_ref,
+    parse_cancel_command, parse_prioritize_command, reconcile_github_state, rerender_report,
+    ApiCompareRequest, BackportOriginalRequest, FullRunRequest, Reconciliation, ReportConfig,
+    RerenderOutcome,
+};
+pub use gitlab::{handle_gitlab_merge_request, handle_gitlab_note};
+pub use scheduled::handle_scheduled_job;
+pub use self_test::{handle_self_test, SelfTestRequest, SelfTestTimings};
+
+mod backport;
 mod bench_main;
 mod bench_pr;
+mod commands;
+mod dependency_bump;
+mod gitlab;
+mod scheduled;
+mod self_test;
+
+/// Name of the synthetic scenario tracking the geometric mean of all icount scenarios
+pub static GEOMETRIC_MEAN_SCENARIO: &str = "geometric_mean";
+
+/// The name of the single scenario of the `BuildTime` kind
+pub static BUILD_TIME_SCENARIO: &str = "release_build";
+
+/// Returns the geometric mean of the values, or `None` if there are no values
+fn geometric_mean(values: impl Iterator<Item = f64>) -> Option<f64> {
+    let (count, log_sum) = values.fold((0, 0.0), |(count, sum), value| {
+        (count + 1, sum + value.ln())
+    });
+    if count == 0 {
+        return None;
+    }
+
+    Some((log_sum / count as f64).exp())
+}
+
+/// A TLS protocol version, whose icount scenarios are tracked as an aggregate series of their own
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ProtocolVersion {
+    Tls12,
+    Tls13,
+}
+
+impl ProtocolVersion {
+    pub const ALL: [ProtocolVersion; 2] = [ProtocolVersion::Tls12, ProtocolVersion::Tls13];
+
+    /// Returns the protocol version exercised by the scenario, if its name mentions one (e.g.
+    /// `handshake_no_resume_ring_1.3_rsa_aes_server`)
+    pub fn of_scenario(scenario_name: &str) -> Option<Self> {
+        scenario_name.split('_').find_map(|part| match part {
+            "1.2" | "tls12" => Some(ProtocolVersion::Tls12),
+            "1.3" | "tls13" => Some(ProtocolVersion::Tls13),
+            _ => None,
+        })
+    }
+
+    /// Name of the synthetic scenario tracking the geometric mean of the icount scenarios of this
+    /// protocol version
+    pub fn geometric_mean_scenario(self) -> &'static str {
+        match self {
+            ProtocolVersion::Tls12 => "geometric_mean_tls12",
+            ProtocolVersion::Tls13 => "geometric_mean_tls13",
+        }
+    }
+}
+
+/// Returns the synthetic scenarios derived from the icount results of a run: the geometric mean
+/// of all scenarios, and the geometric mean of the scenarios of each protocol version
+///
+/// Versions without scenarios are left out. The results are stored as
+/// [`ScenarioKind::IcountIndex`](crate::db::ScenarioKind::IcountIndex), so they are neither
+/// compared nor alerted about like real scenarios
+fn aggregate_icounts(icounts: &HashMap<String, f64>) -> Vec<(String, f64)> {
+    let mut aggregates = Vec::new();
+    if let Some(index) = geometric_mean(icounts.values().copied()) {
+        aggregates.push((GEOMETRIC_MEAN_SCENARIO.to_string(), index));
+    }
+
+    for version in ProtocolVersion::ALL {
+        let values = icounts
+            .iter()
+            .filter(|(scenario, _)| ProtocolVersion::of_scenario(scenario) == Some(version))
+            .map(|(_, &icount)| icount);
+        if let Some(index) = geometric_mean(values) {
+            aggregates.push((version.geometric_mean_scenario().to_string(), index));
+        }
+    }
+
+    aggregates
+}
+
+/// Returns whether the scenario is one of the synthetic scenarios aggregating the others (see
+/// [`aggregate_icounts`])
+pub fn is_aggregate_scenario(scenario_name: &str) -> bool {
+    scenario_name == GEOMETRIC_MEAN_SCENARIO
+        || ProtocolVersion::ALL
+            .into_iter()
+            .any(|version| version.geometric_mean_scenario() == scenario_name)
+}
 
 /// Reads the (benchmark, result) pairs from previous CSV output
-fn read_icount_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
+pub fn read_icount_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
     trace!(
         path = path.display().to_string(),
         "reading icount results from CSV file"
     );
+    read_single_value_results(path)
+}
+
+/// Reads the (benchmark, peak memory usage) pairs from previous CSV output
+pub fn read_max_rss_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
+    trace!(
+        path = path.display().to_string(),
+        "reading memory usage results from CSV file"
+    );
+    read_single_value_results(path)
+}
+
+/// Reads (benchmark, result) pairs from a CSV file with a single value per row
+fn read_single_value_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
     let file = File::open(path).context(format!(
         "CSV file for comparison not found: {}",
         path.display()
@@ -37,7 +154,7 @@ fn read_icount_results(path: &Path) -> anyhow::Result<HashMap<String, f64>> {
                 .next()
                 .ok_or(anyhow!("CSV is wrongly formatted"))?
                 .parse()
-                .context("failed to parse instruction count")?,
+                .context("failed to parse result")?,
         );
 
         if parts.next().is_some() {
@@ -80,10 +197,213 @@ pub fn read_walltime_results(path: &Path) -> anyhow::Result<HashMap<String, f64>
     Ok(results)
 }
 
-fn icounts_path(base: &Path) -> PathBuf {
-    base.join("results/icounts.csv")
+/// The cache miss counts of a scenario, as simulated by cachegrind
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheMisses {
+    /// Misses in the first-level instruction cache
+    pub i1: f64,
+    /// Misses in the first-level data cache (reads and writes)
+    pub d1: f64,
+    /// Misses in the last-level cache (instruction fetches, reads and writes)
+    pub ll: f64,
+}
+
+impl CacheMisses {
+    /// Estimates the CPU cycles spent executing the instructions, using cachegrind's standard cost
+    /// model (a first-level miss costs 10 cycles, and a last-level miss 100 cycles)
+    ///
+    /// The estimate correlates better with wall time than the instruction count, while being just
+    /// as deterministic
+    pub fn estimated_cycles(&self, instructions: f64) -> f64 {
+        instructions + 10.0 * (self.i1 + self.d1) + 100.0 * self.ll
+    }
 }
 
-pub fn walltimes_path(base: &Path) -> PathBuf {
-    base.join("results/walltimes.csv")
+/// Returns the estimated cycles of the scenarios for which both the instruction count and the
+/// cache misses are known
+pub fn estimated_cycles(
+    icounts: &HashMap<String, f64>,
+    cache_misses: &HashMap<String, CacheMisses>,
+) -> HashMap<String, f64> {
+    cache_misses
+        .iter()
+        .filter_map(|(scenario, misses)| {
+            let instructions = icounts.get(scenario)?;
+            Some((scenario.clone(), misses.estimated_cycles(*instructions)))
+        })
+        .collect()
+}
+
+/// Parses the cache miss counts from the contents of a callgrind output file
+///
+/// Returns `None` if the file lacks them, which happens when the benchmarks run without cache
+/// simulation (i.e. without `--cache-sim=yes`)
+pub fn parse_cache_misses(callgrind_output: &str) -> anyhow::Result<Option<CacheMisses>> {
+    let mut events = None;
+    let mut totals = None;
+    for line in callgrind_output.lines() {
+        if let Some(names) = line.strip_prefix("events:") {
+            events = Some(names.split_whitespace().collect::<Vec<_>>());
+        } else if let Some(values) = line.strip_prefix("summary:") {
+            totals = Some(values);
+        } else if let Some(values) = line.strip_prefix("totals:") {
+            totals = totals.or(Some(values));
+        }
+    }
+
+    let (Some(events), Some(totals)) = (events, totals) else {
+        return Ok(None);
+    };
+
+    let totals: Vec<f64> = totals
+        .split_whitespace()
+        .map(|value| value.parse())
+        .collect::<Result<_, _>>()
+        .context("invalid totals in callgrind output")?;
+    let event = |name: &str| -> Option<f64> {
+        let index = events.iter().position(|&event| event == name)?;
+        // Trailing events with zero counts may be omitted
+        Some(totals.get(index).copied().unwrap_or(0.0))
+    };
+
+    let misses = || -> Option<CacheMisses> {
+        Some(CacheMisses {
+            i1: event("I1mr")?,
+            d1: event("D1mr")? + event("D1mw")?,
+            ll: event("ILmr")? + event("DLmr")? + event("DLmw")?,
+        })
+    };
+
+    Ok(misses())
+}
+
+/// Reads the cache miss counts of the scenarios from their callgrind output files
+///
+/// Scenarios without a callgrind output file or without cache simulation are skipped
+pub fn read_cache_misses<'a>(
+    bench_suite: &BenchSuiteConfig,
+    job_output_dir: &Path,
+    scenarios: impl Iterator<Item = &'a String>,
+) -> anyhow::Result<HashMap<String, CacheMisses>> {
+    let mut results = HashMap::new();
+    for scenario in scenarios {
+        let path = bench_suite.callgrind_path(job_output_dir, scenario);
+        if !path.is_file() {
+            continue;
+        }
+
+        let output = fs::read_to_string(&path)
+            .with_context(|| format!("unable to read callgrind output at {}", path.display()))?;
+        let misses = parse_cache_misses(&output)
+            .with_context(|| format!("invalid callgrind output at {}", path.display()))?;
+        if let Some(misses) = misses {
+            results.insert(scenario.clone(), misses);
+        }
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn aggregate_icounts_per_protocol_version() {
+        let icounts = HashMap::from([
+            (
+                "handshake_no_resume_ring_1.2_rsa_aes_server".to_string(),
+                100.0,
+            ),
+            (
+                "handshake_no_resume_ring_1.3_rsa_aes_server".to_string(),
+                200.0,
+            ),
+            (
+                "transfer_no_resume_ring_1.3_rsa_aes_client".to_string(),
+                800.0,
+            ),
+            ("fake_bench".to_string(), 1000.0),
+        ]);
+
+        let mut aggregates = aggregate_icounts(&icounts);
+        aggregates.sort_by(|a, b| a.0.cmp(&b.0));
+        let rounded: Vec<_> = aggregates
+            .iter()
+            .map(|(scenario, index)| (scenario.as_str(), index.round()))
+            .collect();
+        assert_eq!(
+            rounded,
+            [
+                ("geometric_mean", 356.0),
+                ("geometric_mean_tls12", 100.0),
+                ("geometric_mean_tls13", 400.0),
+            ]
+        );
+        assert!(aggregates
+            .iter()
+            .all(|(scenario, _)| is_aggregate_scenario(scenario)));
+        assert!(!is_aggregate_scenario("fake_bench"));
+
+        // Versions without scenarios are left out
+        let icounts = HashMap::from([("fake_bench".to_string(), 1000.0)]);
+        assert_eq!(aggregate_icounts(&icounts).len(), 1);
+    }
+
+    #[test]
+    fn parse_cache_misses_from_callgrind_output() {
+        let output = "\
+version: 1
+creator: callgrind-3.21.0
+events: Ir Dr Dw I1mr D1mr D1mw ILmr DLmr DLmw
+fn=(1) main
+0 100 40 20 3 5 2 1 4
+summary: 100 40 20 3 5 2 1 4
+totals: 100 40 20 3 5 2 1 4 1
+";
+        let misses = parse_cache_misses(output).unwrap().unwrap();
+        assert_eq!(
+            misses,
+            CacheMisses {
+                i1: 3.0,
+                d1: 7.0,
+                ll: 5.0
+            }
+        );
+
+        // Trailing zeroes may be omitted
+        let output = "events: Ir I1mr D1mr D1mw ILmr DLmr DLmw\nsummary: 100 0 5 2 1\n";
+        let misses = parse_cache_misses(output).unwrap().unwrap();
+        assert_eq!(
+            misses,
+            CacheMisses {
+                i1: 0.0,
+                d1: 7.0,
+                ll: 1.0
+            }
+        );
+
+        // Without cache simulation, only instructions are counted
+        let output = "events: Ir\nsummary: 100\n";
+        assert_eq!(parse_cache_misses(output).unwrap(), None);
+
+        let output = "events: Ir I1mr D1mr D1mw ILmr DLmr DLmw\nsummary: 100 x\n";
+        assert!(parse_cache_misses(output).is_err());
+    }
+
+    #[test]
+    fn estimated_cycles_weights_cache_misses() {
+        let misses = CacheMisses {
+            i1: 3.0,
+            d1: 7.0,
+            ll: 5.0,
+        };
+        assert_eq!(misses.estimated_cycles(1000.0), 1000.0 + 100.0 + 500.0);
+
+        // Scenarios without cache misses are left out
+        let icounts = HashMap::from([("a".to_string(), 1000.0), ("b".to_string(), 2000.0)]);
+        let cache_misses = HashMap::from([("a".to_string(), misses)]);
+        let cycles = estimated_cycles(&icounts, &cache_misses);
+        assert_eq!(cycles, HashMap::from([("a".to_string(), 1600.0)]));
+    }
 }
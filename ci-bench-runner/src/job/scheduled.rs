@@ -0,0 +1,120 @@
+use anyhow::Context;
+use time::OffsetDateTime;
+use tracing::{info, warn};
+
+use super::bench_main::bench_push;
+use super::bench_pr::{bench_pr_and_cache_results, BenchMode, BenchPrLogs, PrBranches};
+use super::MAIN_BRANCH;
+use crate::artifacts::prune_job_output_dirs;
+use crate::event_queue::{JobContext, PoisonEvent};
+use crate::github;
+use crate::github::api::{PushEvent, Repo};
+use crate::scheduler::{ScheduledJobRequest, ScheduledTask};
+use crate::CommitIdentifier;
+
+/// How long dropped webhooks and finished self-tests are kept around before being pruned
+const PRUNE_RETENTION: time::Duration = time::Duration::days(90);
+
+/// Handles a job whose schedule is due (see [`crate::scheduler`])
+pub async fn handle_scheduled_job(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let request: ScheduledJobRequest = serde_json::from_slice(ctx.event_payload)
+        .context("invalid scheduled job")
+        .map_err(|e| e.context(PoisonEvent))?;
+    info!("running scheduled job `{}`", request.name);
+
+    match request.task {
+        ScheduledTask::BenchMain => {
+            let head = main_head(&ctx).await?;
+            let payload = PushEvent {
+                git_ref: format!("refs/heads/{MAIN_BRANCH}"),
+                repository: Repo {
+                    clone_url: head.clone_url,
+                },
+                after: head.commit_sha,
+                deleted: false,
+            };
+            bench_push(ctx, payload).await
+        }
+        ScheduledTask::NoiseCalibration => calibrate_noise(&ctx).await,
+        ScheduledTask::PruneDb => {
+            let deleted = ctx
+                .db
+                .prune(OffsetDateTime::now_utc() - PRUNE_RETENTION)
+                .await?;
+            info!("pruned {deleted} rows from the database");
+
+            if let Some(days) = ctx.config.job_output_retention_days {
+                let before = OffsetDateTime::now_utc() - time::Duration::days(days.into());
+                let pruned = prune_job_output_dirs(&ctx.config.job_output_dir, before)?;
+                info!("pruned the output of {pruned} jobs older than {days} days");
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Returns the current head of main, as reported by GitHub
+async fn main_head(ctx: &JobContext<'_>) -> anyhow::Result<CommitIdentifier> {
+    let octocrab = ctx.octocrab.cached();
+    let commit_sha = github::resolve_commit(MAIN_BRANCH, ctx.config, &octocrab)
+        .await
+        .context("unable to find the head of main")?;
+    let clone_url = github::repo_clone_url(ctx.config, &octocrab).await?;
+
+    Ok(CommitIdentifier {
+        clone_url,
+        branch_name: MAIN_BRANCH.to_string(),
+        commit_sha,
+        patch_series: None,
+        pinned_harness: None,
+    })
+}
+
+/// Compares the head of main against itself, so any significant difference is noise
+///
+/// The comparison is stored like any other (replacing a previous calibration of the same commit),
+/// so it can be inspected through `/comparisons/<commit>:<commit>`
+async fn calibrate_noise(ctx: &JobContext<'_>) -> anyhow::Result<()> {
+    let head = main_head(ctx).await?;
+    ctx.db
+        .invalidate_comparison_result(&head.commit_sha, &head.commit_sha)
+        .await?;
+
+    let branches = PrBranches {
+        baseline: head.clone(),
+        candidate: head,
+        detached: true,
+    };
+    let commit_sha = branches.candidate.commit_sha.clone();
+    let mut logs = BenchPrLogs::default();
+    let result = bench_pr_and_cache_results(
+        ctx,
+        branches,
+        BenchMode::Full,
+        &ctx.job_output_dir,
+        &mut logs,
+    )
+    .await?;
+
+    let noisy: Vec<_> = result
+        .metrics
+        .iter()
+        .flat_map(|(kind, sub_result)| {
+            sub_result
+                .diffs
+                .iter()
+                .filter(|diff| diff.is_significant())
+                .map(move |diff| format!("{} ({})", diff.scenario_name, kind.as_str()))
+        })
+        .collect();
+    if noisy.is_empty() {
+        info!("noise calibration of {commit_sha} found no significant differences");
+    } else {
+        warn!(
+            "noise calibration of {commit_sha} found significant differences in: {}",
+            noisy.join(", ")
+        );
+    }
+
+    Ok(())
+}
@@ -0,0 +1,201 @@
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use uuid::Uuid;
+
+use super::bench_pr::{
+    compare_refs, load_significance_thresholds, markdown_comment, BenchMode, BenchPrLogs,
+    PrBranches, PrChecks, RunDetails,
+};
+use super::MAIN_BRANCH;
+use crate::db::ScenarioKind;
+use crate::event_queue::{JobContext, PoisonEvent};
+use crate::runner::{BenchRunner, CancellationToken, Log};
+use crate::{BenchSuiteConfig, CommitIdentifier};
+
+/// The built-in scenarios measured by a self-test, with their baseline and candidate instruction
+/// counts (the second one regresses, so the report has something to show)
+static FIXTURE_SCENARIOS: &[(&str, u64, u64)] = &[
+    ("self_test_handshake", 1_000_000, 1_000_000),
+    ("self_test_transfer", 1_000_000, 1_100_000),
+];
+
+/// The commit "benchmarked" as the baseline of a self-test
+static FIXTURE_BASELINE: &str = "self-test-baseline";
+
+/// The commit "benchmarked" as the candidate of a self-test
+static FIXTURE_CANDIDATE: &str = "self-test-candidate";
+
+/// A self-test requested through the admin API
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SelfTestRequest {
+    /// The id of the self-test, under which its outcome is stored
+    pub id: Uuid,
+}
+
+/// How long each stage of a self-test took
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SelfTestTimings {
+    /// Time spent in the queue, from the request to the start of the job
+    pub queued_seconds: f64,
+    /// Time spent writing the fixture results of the built-in scenarios and comparing them
+    pub compare_seconds: f64,
+    /// Time spent rendering the report
+    pub render_seconds: f64,
+}
+
+/// Handles a self-test, which compares fixture results of built-in scenarios and renders their
+/// report (no code is checked out or benchmarked, and GitHub is never contacted)
+///
+/// Meant as a smoke check of the app after deploying, since it exercises the queue, the comparison
+/// logic and the report templates. The fixture results are written instead of measured, so the host
+/// only gets to run `callgrind_annotate` (for the cachegrind diffs), never the benchmarks
+pub async fn handle_self_test(ctx: JobContext<'_>) -> anyhow::Result<()> {
+    let request: SelfTestRequest = serde_json::from_slice(ctx.event_payload)
+        .context("invalid self-test request")
+        .map_err(|e| e.context(PoisonEvent))?;
+    let self_test = ctx
+        .db
+        .self_test(request.id)
+        .await?
+        .context("the requested self-test no longer exists")?;
+    ctx.db.self_test_started(self_test.id, ctx.job_id).await?;
+
+    match self_test_stages(&ctx, self_test.created_utc).await {
+        Ok(timings) => ctx.db.self_test_finished(self_test.id, Ok(&timings)).await,
+        Err(e) => {
+            ctx.db
+                .self_test_finished(self_test.id, Err(format!("{e:#}")))
+                .await?;
+            Err(e)
+        }
+    }
+}
+
+/// Runs the stages of a self-test, returning how long each of them took
+async fn self_test_stages(
+    ctx: &JobContext<'_>,
+    requested_utc: OffsetDateTime,
+) -> anyhow::Result<SelfTestTimings> {
+    let queued_seconds = (OffsetDateTime::now_utc() - requested_utc).as_seconds_f64();
+
+    let fixture_commit = |commit_sha: &str| CommitIdentifier {
+        clone_url: format!(
+            "https://github.com/{}/{}.git",
+            ctx.config.github_repo_owner, ctx.config.github_repo_name
+        ),
+        branch_name: MAIN_BRANCH.to_string(),
+        commit_sha: commit_sha.to_string(),
+        patch_series: None,
+        pinned_harness: None,
+    };
+    let branches = PrBranches {
+        baseline: fixture_commit(FIXTURE_BASELINE),
+        candidate: fixture_commit(FIXTURE_CANDIDATE),
+        detached: true,
+    };
+
+    // Only the icount results are faked, so the other benchmarks are skipped
+    let bench_suite = BenchSuiteConfig {
+        icount_args: vec!["{results_dir}".to_string()],
+        walltime_args: Vec::new(),
+        max_rss_args: Vec::new(),
+        ..ctx.config.bench_suite.clone()
+    };
+
+    let start = Instant::now();
+    let significance_thresholds = load_significance_thresholds(&ctx.db, MAIN_BRANCH).await?;
+    let job_output_dir = ctx.job_output_dir.clone();
+    let cancellation = ctx.cancellation.clone();
+    let task_branches = branches.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        compare_refs(
+            &task_branches,
+            &job_output_dir,
+            &bench_suite,
+            &mut BenchPrLogs::default(),
+            &FixtureBenchRunner,
+            &significance_thresholds,
+            &cancellation,
+        )
+    })
+    .await
+    .context("self-test task crashed")??;
+
+    let icount_diffs = result.metric(ScenarioKind::Icount).diffs.len();
+    if icount_diffs != FIXTURE_SCENARIOS.len() {
+        bail!(
+            "expected {} icount scenarios to be compared, found {icount_diffs}",
+            FIXTURE_SCENARIOS.len()
+        );
+    }
+    let compare_seconds = start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    let report = markdown_comment(
+        ctx.config,
+        &branches,
+        Ok(result),
+        PrChecks::default(),
+        &RunDetails::default(),
+        BenchMode::Full,
+        false,
+    );
+    for (scenario, _, _) in FIXTURE_SCENARIOS {
+        if !report.contains(scenario) {
+            bail!("the rendered report doesn't mention `{scenario}`");
+        }
+    }
+    let render_seconds = start.elapsed().as_secs_f64();
+
+    Ok(SelfTestTimings {
+        queued_seconds,
+        compare_seconds,
+        render_seconds,
+    })
+}
+
+/// A bench runner that writes the fixture results of the built-in scenarios, instead of checking out
+/// and benchmarking code
+struct FixtureBenchRunner;
+
+impl BenchRunner for FixtureBenchRunner {
+    fn checkout_and_run_benchmarks(
+        &self,
+        commit: &CommitIdentifier,
+        _: &Path,
+        job_output_dir: &Path,
+        bench_suite: &BenchSuiteConfig,
+        _: &mut Vec<Log>,
+        _: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let is_candidate = commit.commit_sha == FIXTURE_CANDIDATE;
+        let callgrind_dir = bench_suite
+            .results_path(job_output_dir)
+            .join(&bench_suite.callgrind_dir);
+        fs::create_dir_all(callgrind_dir).context("Unable to create dir for results")?;
+
+        let mut icounts = String::new();
+        for &(scenario, baseline, candidate) in FIXTURE_SCENARIOS {
+            let icount = if is_candidate { candidate } else { baseline };
+            writeln!(icounts, "{scenario},{icount}").expect("unreachable code");
+
+            // A minimal callgrind output, so the cachegrind diff can be generated
+            fs::write(
+                bench_suite.callgrind_path(job_output_dir, scenario),
+                format!(
+                    "events: Ir\nfl=self_test.rs\nfn=self_test::{scenario}\n1 {icount}\nsummary: {icount}\n"
+                ),
+            )
+            .context("failed to write fixture callgrind output")?;
+        }
+
+        fs::write(bench_suite.icounts_path(job_output_dir), icounts)
+            .context("failed to write fixture icount results")
+    }
+}
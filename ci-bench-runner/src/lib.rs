@@ -1,48 +1,130 @@
 #[cfg(test)]
 mod test;
 
+mod artifacts;
 mod bencher_dev;
+mod bootstrap;
+mod cachegrind;
+mod correlations;
+mod costs;
 mod db;
+mod energy;
 mod event_queue;
+mod export;
+mod feature_flags;
+mod forge;
 mod github;
+mod gitlab;
+mod import;
 mod job;
+mod logging;
+mod metrics;
+mod notifications;
+mod perf_stat;
+mod redelivery;
+mod regressions;
 mod runner;
+mod scheduler;
+mod scrub;
+mod signing;
+mod trends;
+mod units;
 
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::future::Future;
 use std::net::SocketAddr;
 use std::ops::DerefMut;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::Context;
-use axum::body::Bytes;
-use axum::extract::{Path, State};
-use axum::http::{HeaderMap, StatusCode};
-use axum::response::{IntoResponse, Response};
-use axum::routing::{get, post};
+use axum::body::{Body, Bytes, Full};
+use axum::extract::{Path, Query, State};
+use axum::http::header::{
+    ACCEPT, AUTHORIZATION, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_TYPE,
+};
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::{Html, IntoResponse, Response};
+use axum::routing::{delete, get, post, put};
 use axum::{Json, Router};
 use bencher_client::json::Jwt;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use sqlx::migrate::Migrator;
 use sqlx::SqliteConnection;
+use time::format_description::well_known::Rfc3339;
+use time::OffsetDateTime;
+use tokio::signal::unix::SignalKind;
 use tokio::sync::Mutex;
 use tower_http::trace::TraceLayer;
-use tracing::{error, info, trace};
+use tracing::{debug, error, info, trace, warn};
 use uuid::Uuid;
 
+use crate::artifacts::{
+    full_cachegrind_diff, job_logs_tarball, render_job_logs, ArtifactStore, REPORT_FILE,
+};
+pub use crate::bootstrap::bootstrap;
+use crate::correlations::{correlated_groups, CorrelatedGroup, CORRELATION_WINDOW};
+use crate::costs::COSTS_WINDOW;
 pub use crate::db::Db;
-use crate::event_queue::EventQueue;
+use crate::db::{
+    Annotation, AnnotationTarget, AuditLogEntry, ComparisonResult, CostGrouping, DroppedWebhook,
+    JobCostSummary, JobTrace, QueuePause, Regression, RegressionState, ScenarioKind,
+    ScenarioRunResult, ScenarioSetChange, Verdict,
+};
+use crate::event_queue::{
+    AllowedEvent, EnqueuedWebhook, EventQueue, HandoffMode, JobStatus, JobsPage, ReplayOutcome,
+};
+pub use crate::event_queue::{NightlyWindowConfig, RetryPolicyConfig};
+pub use crate::export::export;
+use crate::feature_flags::{effective_rollouts, EffectiveRollout};
+pub use crate::feature_flags::{FeatureRollout, ReportFeature};
 use crate::github::verify_webhook_signature;
 pub use crate::github::CachedOctocrab;
-use crate::runner::BenchRunner;
-pub use crate::runner::LocalBenchRunner;
+pub use crate::gitlab::GitLabConfig;
+use crate::gitlab::{queued_event, webhook_project};
+pub use crate::import::import_bencher;
+pub use crate::job::ReportConfig;
+pub use crate::job::TrackedBranchConfig;
+use crate::job::{
+    acknowledge_benchmarking_command, callgrind_diff_between, change_points, handle_cancel_command,
+    handle_prioritize_command, is_aggregate_scenario, is_tracked_branch, is_valid_ref,
+    parse_cancel_command, parse_prioritize_command, reconcile_github_state, rerender_report,
+    ApiCompareRequest, Reconciliation, RerenderOutcome, SelfTestRequest, SelfTestTimings,
+    MAIN_BRANCH,
+};
+pub use crate::logging::{init_logging, LogFormat, LoggingConfig};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::notifications::{job_success_rate, Notifier, SCENARIO_CHANGES_WINDOW};
+pub use crate::notifications::{
+    JobSuccessSloConfig, NotificationsConfig, ScenarioOwnersConfig, ZulipConfig,
+};
+pub use crate::perf_stat::{PerfStatBenchRunner, PerfStatConfig};
+use crate::redelivery::spawn_webhook_redelivery;
+pub use crate::redelivery::WebhookRedeliveryConfig;
+use crate::regressions::render_dashboard;
+pub use crate::runner::{
+    BenchRunner, BenchSuiteConfig, DeterminismCheckConfig, GitSourceProvider, HostIdleConfig,
+    LocalBenchRunner, SourceMirrorConfig, SourceProvider,
+};
+use crate::scheduler::spawn_scheduled_jobs;
+pub use crate::scheduler::{ScheduledJobConfig, ScheduledTask};
+use crate::scrub::Scrubber;
+use crate::signing::{SignedSummary, Signer, SIGNING_ALGORITHM};
+use crate::trends::SCENARIO_HISTORY_WINDOW;
 
 /// The application's state, accessible when handling requests
 struct AppState {
     config: Arc<AppConfig>,
     event_queue: EventQueue,
     db: Db,
+    octocrab: CachedOctocrab,
+    signer: Option<Arc<Signer>>,
+    scrubber: Scrubber,
+    metrics: Arc<Metrics>,
 }
 
 /// The application's configuration
@@ -54,6 +136,10 @@ pub struct AppConfig {
     pub app_base_url: String,
     /// Local directory where job output will be stored
     pub job_output_dir: PathBuf,
+    /// How many days the output of a job (its logs, results and report) is kept around, before
+    /// being deleted by the `prune_db` scheduled task. Unset means forever
+    #[serde(default)]
+    pub job_output_retention_days: Option<u32>,
     /// Path to the SQLite database used for persistence
     pub path_to_db: String,
     /// Secret used by GitHub to sign webhook payloads
@@ -68,10 +154,130 @@ pub struct AppConfig {
     pub github_repo_name: String,
     /// Sentry DSN
     pub sentry_dsn: String,
+    /// How traces are logged (defaults to text, with the default levels)
+    #[serde(default)]
+    pub logging: LoggingConfig,
     /// Port where the application should listen (defaults to 0 if unset)
     pub port: Option<u16>,
     /// Optional configuration to publish benchmark results to bencher.dev
     pub bencher: Option<BencherConfig>,
+    /// How to build and run the repository's benchmark suite (defaults to rustls' `ci-bench`)
+    #[serde(default)]
+    pub bench_suite: BenchSuiteConfig,
+    /// How feedback is presented in PR comments (defaults to emoji markers)
+    #[serde(default)]
+    pub report: ReportConfig,
+    /// The maximum size of a cachegrind diff stored in the database, in bytes (defaults to 1 MiB).
+    /// The end of larger diffs is kept in the artifact store, under the job output dir
+    #[serde(default = "default_cachegrind_diff_max_bytes")]
+    pub cachegrind_diff_max_bytes: usize,
+    /// Whether to comment on PRs when benchmarks are skipped for a reason the author can fix
+    #[serde(default)]
+    pub explain_skipped_benchmarks: bool,
+    /// The base branches whose PRs are benchmarked automatically when opened or updated (defaults
+    /// to all). PRs against other branches can still be benchmarked through bench commands
+    #[serde(default)]
+    pub pr_base_branches: Vec<String>,
+    /// Whether to log webhook events of unexpected types at warn level (instead of debug), to
+    /// notice GitHub App permission misconfigurations quickly
+    #[serde(default)]
+    pub warn_on_unexpected_events: bool,
+    /// Repositories (as `owner/name`) whose webhook events are processed besides the configured
+    /// one, for deployments serving several repositories. Events from other repositories (e.g.
+    /// because the GitHub App was installed elsewhere by accident) are dropped
+    #[serde(default)]
+    pub allowed_repositories: Vec<String>,
+    /// Optional recovery of webhook deliveries that failed (e.g. while the application was being
+    /// deployed)
+    pub webhook_redelivery: Option<WebhookRedeliveryConfig>,
+    /// Optional daily window reserved for benchmarking `main`, which is benchmarked as the window
+    /// starts
+    pub nightly_window: Option<NightlyWindowConfig>,
+    /// Optional configuration to measure hardware counters with `perf stat` instead of running the
+    /// icount benchmarks under valgrind (for hosts where valgrind is too slow)
+    pub perf_stat: Option<PerfStatConfig>,
+    /// Mirrors from which commits are fetched instead of the repositories they belong to (e.g. for
+    /// air-gapped deployments)
+    #[serde(default)]
+    pub source_mirrors: Vec<SourceMirrorConfig>,
+    /// Optional conditions the host must meet before measurements start
+    pub host_idle_check: Option<HostIdleConfig>,
+    /// Optional check that the environment is stable (i.e. instruction counts are reproducible)
+    /// before measurements start
+    pub determinism_check: Option<DeterminismCheckConfig>,
+    /// Bearer token required to use the `/admin` endpoints (they are disabled if unset)
+    pub admin_token: Option<String>,
+    /// External CI systems allowed to request comparisons through `/api/compare` (the API is
+    /// disabled if empty)
+    #[serde(default)]
+    pub api_clients: Vec<ApiClientConfig>,
+    /// How long a queued event waits before its priority is boosted by one level (repeatedly), so
+    /// low-priority work like full runs eventually runs on busy days (defaults to 120, 0 disables
+    /// aging)
+    #[serde(default = "default_queue_aging_minutes")]
+    pub queue_aging_minutes: u32,
+    /// How many queued events are handled at the same time (defaults to 1, i.e. one after the
+    /// other). Events of the same PR, or pushes to the same branch, are never handled at the same
+    /// time. Note that the CPU time of jobs can't be told apart when they run at the same time, so
+    /// it is only tracked with a single worker
+    #[serde(default = "default_queue_workers")]
+    pub queue_workers: usize,
+    /// The runner resource class of each kind of event (e.g. `push = "bench-host"`). Events of the
+    /// same class are never handled at the same time, so classes can be used to keep jobs that
+    /// benchmark on the same host from disturbing each other's measurements
+    #[serde(default)]
+    pub queue_resource_classes: BTreeMap<String, String>,
+    /// How many times failed jobs are retried, per failure class
+    #[serde(default)]
+    pub retry_policy: RetryPolicyConfig,
+    /// How long a job may run before its benchmark processes (and their children) are killed, in
+    /// minutes. The job then fails as timed out, reporting the failure on the PR. Unset means no
+    /// limit
+    #[serde(default)]
+    pub job_timeout_minutes: Option<u64>,
+    /// Internal jobs enqueued on a cron schedule (e.g. a nightly run of main's head)
+    #[serde(default)]
+    pub scheduled_jobs: Vec<ScheduledJobConfig>,
+    /// Hex-encoded 32-byte Ed25519 seed, used to sign comparison summaries and exports (signing is
+    /// disabled if unset)
+    pub signing_key: Option<String>,
+    /// Where to post main branch regression alerts and weekly digests
+    #[serde(default)]
+    pub notifications: NotificationsConfig,
+    /// Who to mention when a category of scenarios regresses on main
+    #[serde(default)]
+    pub scenario_owners: Vec<ScenarioOwnersConfig>,
+    /// Long-lived branches that are benchmarked on push, besides main (which is always tracked)
+    #[serde(default)]
+    pub tracked_branches: Vec<TrackedBranchConfig>,
+    /// Whether to benchmark the baseline of PRs that modify the bench harness with the candidate's
+    /// harness, so both sides run the same scenarios (it can also be requested per comparison, with
+    /// `--pin-harness`)
+    #[serde(default)]
+    pub pin_harness_when_changed: bool,
+    /// The name under which the application is addressed in PR comments (i.e. `@bot_name bench`),
+    /// which should match the GitHub App's slug (defaults to `rustls-benchmarking`)
+    #[serde(default = "default_bot_name")]
+    pub bot_name: String,
+    /// Optional configuration to benchmark merge requests of projects hosted on GitLab, besides
+    /// the PRs of the GitHub repository
+    pub gitlab: Option<GitLabConfig>,
+}
+
+fn default_queue_aging_minutes() -> u32 {
+    120
+}
+
+fn default_queue_workers() -> usize {
+    1
+}
+
+fn default_cachegrind_diff_max_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_bot_name() -> String {
+    "rustls-benchmarking".to_string()
 }
 
 /// Bencher.dev's configuration
@@ -85,7 +291,19 @@ pub struct BencherConfig {
     pub testbed_id: String,
 }
 
+/// An external CI system allowed to request comparisons through the HTTP API
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ApiClientConfig {
+    /// The client's name, to which the costs of its comparisons are attributed
+    pub name: String,
+    /// Bearer token the client authenticates with
+    pub token: String,
+}
+
 /// Creates a new instance of the HTTP server and returns the address at which it is listening
+///
+/// The server stops on SIGTERM or Ctrl+C, interrupting the running jobs so their events are handled
+/// again after restarting (see [`EventQueue::shutdown`])
 pub async fn server(
     config: Arc<AppConfig>,
     bench_runner: Arc<dyn BenchRunner>,
@@ -104,43 +322,222 @@ pub async fn server(
     // Set up dependencies
     let octocrab = CachedOctocrab::new(&config).await?;
     let db = Db::with_connection(sqlite);
-    let event_queue = EventQueue::new(config.clone(), db.clone(), bench_runner, octocrab)?;
+    let signer = Signer::from_config(&config)
+        .context("invalid signing key")?
+        .map(Arc::new);
+    let notifier = Notifier::new(config.clone());
+    notifier.spawn_weekly_digest(db.clone());
+    notifier.spawn_job_success_monitor(db.clone());
+    if let Some(redelivery) = &config.webhook_redelivery {
+        spawn_webhook_redelivery(redelivery.clone(), octocrab.clone());
+    }
+    let metrics = Arc::new(Metrics::default());
+    let event_queue = EventQueue::new(
+        config.clone(),
+        db.clone(),
+        bench_runner,
+        octocrab.clone(),
+        signer.clone(),
+        notifier,
+        metrics.clone(),
+    )?;
+    let mut scheduled_jobs = config.scheduled_jobs.clone();
+    scheduled_jobs.extend(
+        config
+            .nightly_window
+            .as_ref()
+            .map(NightlyWindowConfig::bench_main_job),
+    );
+    spawn_scheduled_jobs(&scheduled_jobs, event_queue.clone())?;
 
     // Create the application's state, accessible when handling requests
     let state = Arc::new(AppState {
+        scrubber: Scrubber::new(&config),
+        metrics,
         config,
         event_queue,
         db,
+        octocrab,
+        signer,
     });
 
     // Set up the axum application
+    let event_queue = state.event_queue.clone();
     let app = Router::new()
         .route("/webhooks/github", post(handle_github_webhook))
+        .route("/webhooks/gitlab", post(handle_gitlab_webhook))
         .route("/info", get(get_server_info))
+        .route("/metrics", get(get_metrics))
+        .route("/jobs", get(get_jobs))
         .route("/jobs/:id", get(get_job_view))
+        .route("/jobs/:id/logs", get(get_job_logs_page))
+        .route("/jobs/:id/logs.tar.gz", get(get_job_logs))
+        .route("/jobs/:id/report.md", get(get_job_report))
+        .route("/trace/:id", get(get_trace))
+        .route("/signing-key", get(get_signing_key))
+        .route("/api/compare", post(request_api_comparison))
+        .route("/api/compare/:id", get(get_api_comparison))
+        .route("/api/annotations", get(get_annotations))
+        .route("/comparisons/:commits", get(get_comparison))
         .route(
             "/comparisons/:commits/cachegrind-diff/:scenario",
             get(get_cachegrind_diff),
         )
+        .route("/regressions", get(get_regressions))
+        .route("/scenarios/changes", get(get_scenario_changes))
+        .route("/scenarios/correlations", get(get_scenario_correlations))
+        .route("/scenarios/:name/compare", get(get_scenario_time_travel))
+        .route("/api/scenarios/:name/history", get(get_scenario_history))
+        .route("/dashboard", get(get_trends_dashboard))
+        .route("/regressions/dashboard", get(get_regressions_dashboard))
+        .route("/costs", get(get_costs))
+        .route("/costs/dashboard", get(get_costs_dashboard))
+        .route("/admin/comparisons/:id/rerender", post(rerender_comparison))
+        .route("/admin/regressions/:id/state", post(set_regression_state))
+        .route("/admin/annotations", post(create_annotation))
+        .route("/admin/annotations/:id", delete(delete_annotation))
+        .route("/admin/events/:id/replay", post(replay_event))
+        .route("/admin/dead-events", get(get_dead_events))
+        .route("/admin/dead-events/:id/replay", post(replay_dead_event))
+        .route("/admin/reconcile", post(reconcile))
+        .route("/admin/self-tests", post(request_self_test))
+        .route("/admin/self-tests/:id", get(get_self_test))
+        .route("/admin/report-features", get(get_report_features))
+        .route("/admin/report-features/:feature", put(set_report_feature))
+        .route("/admin/dropped-webhooks", get(get_dropped_webhooks))
+        .route("/admin/audit-log", get(get_audit_log))
+        .route("/admin/queue", get(get_queue_state))
+        .route("/admin/queue/pause", post(pause_queue))
+        .route("/admin/queue/resume", post(resume_queue))
+        .route("/admin/handoff", post(hand_off_queue))
+        .route("/admin/log-levels", get(get_log_levels))
+        .route("/admin/log-levels", put(set_log_levels))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            scrub_public_response,
+        ))
         .with_state(state)
         .layer(TraceLayer::new_for_http());
 
     let addr = SocketAddr::from(([127, 0, 0, 1], port));
     let server = axum::Server::bind(&addr).serve(app.into_make_service());
     let addr = server.local_addr();
+    let server = server.with_graceful_shutdown(shutdown_signal(event_queue));
 
     info!("listening on port {}", addr.port());
     Ok((server, addr))
 }
 
+/// Completes once the application has been asked to stop and the running jobs have been
+/// interrupted
+async fn shutdown_signal(event_queue: EventQueue) {
+    let terminate = async {
+        match tokio::signal::unix::signal(SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(e) => {
+                error!(cause = e.to_string(), "unable to listen for SIGTERM");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    tokio::select! {
+        _ = terminate => info!("received SIGTERM"),
+        Ok(()) = tokio::signal::ctrl_c() => info!("received Ctrl+C"),
+    }
+
+    event_queue.shutdown(SHUTDOWN_TIMEOUT).await;
+}
+
+/// Removes sensitive data (tokens, comment ids, webhook payloads, internal paths) from responses
+///
+/// Requests authorized with the admin token get the unscrubbed response
+async fn scrub_public_response(
+    State(state): State<Arc<AppState>>,
+    request: Request<Body>,
+    next: Next<Body>,
+) -> Response {
+    let is_admin = authorize_admin(&state.config, request.headers()).is_ok();
+    let response = next.run(request).await;
+    if is_admin {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let Ok(body) = hyper::body::to_bytes(body).await else {
+        return (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response();
+    };
+
+    let content_type = parts
+        .headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    let body = if content_type.starts_with("application/json") {
+        let Ok(mut value) = serde_json::from_slice(&body) else {
+            return (StatusCode::INTERNAL_SERVER_ERROR, "internal server error").into_response();
+        };
+        state.scrubber.scrub_json(&mut value);
+        Bytes::from(serde_json::to_vec(&value).expect("unreachable code"))
+    } else if content_type.starts_with("text/") {
+        let text = String::from_utf8_lossy(&body);
+        Bytes::from(state.scrubber.scrub_text(&text))
+    } else {
+        body
+    };
+
+    // The length might have changed, so let hyper recompute it
+    parts.headers.remove(CONTENT_LENGTH);
+    Response::from_parts(parts, axum::body::boxed(Full::from(body)))
+}
+
 /// Returns git commit information about the binary that is currently deployed
-async fn get_server_info(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
-    Json(json!({
+async fn get_server_info(
+    State(state): State<Arc<AppState>>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    // The success rate of the recent jobs, with its daily trend, if an SLO is configured
+    let job_success = match &state.config.notifications.job_success_slo {
+        Some(slo) => Some(
+            job_success_rate(&state.db, slo, OffsetDateTime::now_utc())
+                .await
+                .map_err(|_| "internal server error")?,
+        ),
+        None => None,
+    };
+    let active_job_ids = state.event_queue.active_job_ids();
+
+    Ok(Json(json!({
         "git_commit_sha": env!("GIT_HEAD_SHA").to_string(),
         "git_commit_message": env!("GIT_HEAD_COMMIT_MESSAGE").to_string(),
-        "active_job_id": state.event_queue.active_job_id(),
+        "active_job_id": active_job_ids.first(),
+        "active_job_ids": active_job_ids,
         "event_processing_enabled": state.event_queue.event_processing_enabled(),
-    }))
+        "queue_owner": state.event_queue.owns_queue(),
+        "job_success": job_success,
+    })))
+}
+
+/// Returns the application's metrics (e.g. the webhook events that were dropped, by type)
+async fn get_metrics(State(state): State<Arc<AppState>>) -> Json<MetricsSnapshot> {
+    Json(state.metrics.snapshot())
+}
+
+/// Returns a page of jobs, newest first, optionally filtered by status
+async fn get_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<JobsQuery>,
+) -> axum::response::Result<Json<JobsPage>> {
+    let page = query.page.max(1);
+    let per_page = query.per_page.clamp(1, MAX_JOBS_PER_PAGE);
+    let jobs = state
+        .event_queue
+        .job_views(query.status, page, per_page)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(jobs))
 }
 
 /// Returns information about the job
@@ -162,101 +559,1996 @@ async fn get_job_view(
     Ok(response)
 }
 
-/// Returns the cachegrind diff between the specified commits, for the provided scenario
-async fn get_cachegrind_diff(
+/// Returns an HTML page with the output of each command run by the job
+async fn get_job_logs_page(
     State(state): State<Arc<AppState>>,
-    Path((compared_commits, scenario_name)): Path<(String, String)>,
-) -> axum::response::Result<String> {
-    // Extract commit hashes from URL
-    let mut commit_parts = compared_commits.split(':');
-    let (baseline_commit, candidate_commit) = match (
-        commit_parts.next(),
-        commit_parts.next(),
-        commit_parts.next(),
-    ) {
-        (Some(baseline), Some(candidate), None) => (baseline, candidate),
-        _ => Err((StatusCode::BAD_REQUEST, "malformed URL"))?,
+    Path(id): Path<Uuid>,
+) -> axum::response::Result<Html<String>> {
+    state
+        .db
+        .maybe_job(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let job_output_dir = state.config.job_output_dir.join(id.to_string());
+    let page = render_job_logs(id, &job_output_dir)
+        .map_err(|e| {
+            error!(cause = format!("{e:#}"), "unable to render job logs");
+            "internal server error"
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "the job's logs are no longer available",
+        ))?;
+
+    Ok(Html(page))
+}
+
+/// Returns a gzipped tarball with the job's logs, results and report
+///
+/// The artifacts are scrubbed like other public responses, unless the request is authorized with
+/// the admin token
+async fn get_job_logs(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    state
+        .db
+        .maybe_job(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let is_admin = authorize_admin(&state.config, &headers).is_ok();
+    let scrubber = (!is_admin).then_some(&state.scrubber);
+    let job_output_dir = state.config.job_output_dir.join(id.to_string());
+    let tarball = job_logs_tarball(&job_output_dir, &state.config.bench_suite, scrubber)
+        .map_err(|e| {
+            error!(cause = format!("{e:#}"), "unable to package job logs");
+            "internal server error"
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "the job's artifacts are no longer available",
+        ))?;
+
+    Ok((
+        [
+            (CONTENT_TYPE, "application/gzip".to_string()),
+            (
+                CONTENT_DISPOSITION,
+                format!("attachment; filename=\"job-{id}-logs.tar.gz\""),
+            ),
+        ],
+        tarball,
+    )
+        .into_response())
+}
+
+/// Returns the full markdown report of a job, which might have been shrunk when posted to GitHub
+async fn get_job_report(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> axum::response::Result<Response> {
+    state
+        .db
+        .maybe_job(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let report_path = state
+        .config
+        .job_output_dir
+        .join(id.to_string())
+        .join(REPORT_FILE);
+    let report = std::fs::read_to_string(report_path).map_err(|_| {
+        (
+            StatusCode::NOT_FOUND,
+            "the job's report is no longer available",
+        )
+    })?;
+
+    Ok((
+        [(CONTENT_TYPE, "text/markdown; charset=utf-8".to_string())],
+        report,
+    )
+        .into_response())
+}
+
+/// Returns the provenance of a job's results, from the triggering event to the GitHub comment
+///
+/// The id can be that of a job, an event, a bench run or a comparison
+async fn get_trace(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> axum::response::Result<Json<TraceView>> {
+    let trace = state
+        .db
+        .trace(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let config = &state.config;
+    let comparison_url = trace.comparison.as_ref().map(|c| {
+        format!(
+            "{}/comparisons/{}:{}",
+            config.app_base_url, c.baseline_commit, c.candidate_commit
+        )
+    });
+    let comment_url = trace.pr_number.zip(trace.comment_id).map(|(pr, comment)| {
+        format!(
+            "https://github.com/{}/{}/pull/{pr}#issuecomment-{comment}",
+            config.github_repo_owner, config.github_repo_name
+        )
+    });
+
+    let job_output_dir = config.job_output_dir.join(trace.job.id.to_string());
+    let mut artifacts = Vec::new();
+    list_files(&job_output_dir, &job_output_dir, &mut artifacts);
+    artifacts.sort();
+
+    Ok(Json(TraceView {
+        job_url: format!("{}/jobs/{}", config.app_base_url, trace.job.id),
+        comparison_url,
+        comment_url,
+        artifacts,
+        trace,
+    }))
+}
+
+/// Collects the paths of the files under `dir`, relative to `root`
+///
+/// Missing or unreadable directories are skipped, since artifacts are a best-effort addition to
+/// the trace (e.g. they are gone if the job output dir was cleaned up)
+fn list_files(root: &std::path::Path, dir: &std::path::Path, files: &mut Vec<String>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
     };
 
-    Ok(state
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            list_files(root, &path, files);
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.display().to_string());
+        }
+    }
+}
+
+/// Returns the comparison between the specified commits
+///
+/// A `.csv` or `.json` suffix (e.g. `/comparisons/<baseline>:<candidate>.csv`) exports the
+/// comparison's scenarios as a flat table instead, for post-processing in spreadsheets or scripts
+async fn get_comparison(
+    State(state): State<Arc<AppState>>,
+    Path(compared_commits): Path<String>,
+) -> axum::response::Result<Response> {
+    let (compared_commits, export_format) =
+        if let Some(commits) = compared_commits.strip_suffix(".csv") {
+            (commits, Some(ExportFormat::Csv))
+        } else if let Some(commits) = compared_commits.strip_suffix(".json") {
+            (commits, Some(ExportFormat::Json))
+        } else {
+            (compared_commits.as_str(), None)
+        };
+
+    let (baseline_commit, candidate_commit) =
+        resolve_compared_commits(&state.config, &state.db, compared_commits).await?;
+
+    let result = state
         .db
-        .cachegrind_diff(baseline_commit, candidate_commit, &scenario_name)
+        .comparison_result(&baseline_commit, &candidate_commit)
         .await
         .map_err(|_| "internal server error")?
         .ok_or((
             StatusCode::NOT_FOUND,
-            "comparison not found for the provided commit hashes and scenario",
-        ))?)
+            "comparison not found for the provided commit hashes",
+        ))?;
+
+    if let Some(format) = export_format {
+        let rows = comparison_export_rows(&result);
+        return Ok(match format {
+            ExportFormat::Csv => (
+                [(CONTENT_TYPE, "text/csv; charset=utf-8")],
+                comparison_export_csv(&rows),
+            )
+                .into_response(),
+            ExportFormat::Json => Json(rows).into_response(),
+        });
+    }
+
+    let signature = state
+        .db
+        .comparison_signature(&baseline_commit, &candidate_commit)
+        .await
+        .map_err(|_| "internal server error")?;
+    let annotations = state
+        .db
+        .comparison_annotations(&baseline_commit, &candidate_commit)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(ComparisonView {
+        baseline_commit,
+        candidate_commit,
+        verdict: result.verdict(),
+        result,
+        signature,
+        annotations,
+    })
+    .into_response())
 }
 
-/// Handles an incoming GitHub webhook
-async fn handle_github_webhook(
+/// Flattens the scenarios of a comparison into rows, for exports
+fn comparison_export_rows(result: &ComparisonResult) -> Vec<ComparisonExportRow> {
+    result
+        .metrics
+        .iter()
+        .flat_map(|(kind, metric)| {
+            metric.diffs.iter().map(|diff| ComparisonExportRow {
+                scenario: diff.scenario_name.clone(),
+                kind: kind.as_str(),
+                baseline: diff.baseline_result,
+                candidate: diff.candidate_result,
+                diff: diff.diff(),
+                diff_ratio: diff.diff_ratio(),
+                threshold: diff.significance_threshold,
+            })
+        })
+        .collect()
+}
+
+/// Renders the rows of a comparison export as CSV, with a header
+fn comparison_export_csv(rows: &[ComparisonExportRow]) -> String {
+    let mut csv = String::from("scenario,kind,baseline,candidate,diff,diff_ratio,threshold\n");
+    for row in rows {
+        // Scenario names are quoted, in case they contain commas or quotes
+        writeln!(
+            csv,
+            "\"{}\",{},{},{},{},{},{}",
+            row.scenario.replace('"', "\"\""),
+            row.kind,
+            row.baseline,
+            row.candidate,
+            row.diff,
+            row.diff_ratio,
+            row.threshold
+        )
+        .expect("unreachable code");
+    }
+
+    csv
+}
+
+/// Requests a comparison between two refs of the repository on behalf of an external CI system,
+/// returning the id under which its outcome can be polled
+async fn request_api_comparison(
     State(state): State<Arc<AppState>>,
     headers: HeaderMap,
-    body: Bytes,
-) -> StatusCode {
-    trace!("incoming webhook");
+    Json(request): Json<ApiCompareBody>,
+) -> axum::response::Result<Response> {
+    let client = authorize_api_client(&state.config, &headers)?;
+    if !is_valid_ref(&request.baseline) || !is_valid_ref(&request.candidate) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "refs must be commit hashes, branches or tags of the repository",
+        )
+            .into());
+    }
 
-    let Some(signature) = headers.get(WEBHOOK_SIGNATURE_HEADER) else {
-        trace!("{WEBHOOK_SIGNATURE_HEADER} header is missing, ignoring event");
-        return StatusCode::BAD_REQUEST;
-    };
+    let id = state
+        .db
+        .store_api_comparison(&client.name, &request.baseline, &request.candidate)
+        .await
+        .map_err(|_| "internal server error")?;
+    state
+        .event_queue
+        .enqueue_api_compare(&ApiCompareRequest {
+            id,
+            client: client.name.clone(),
+        })
+        .await
+        .map_err(|_| "internal server error")?;
 
-    let Ok(signature) = signature.to_str() else {
-        trace!("{WEBHOOK_SIGNATURE_HEADER} has an invalid header value, ignoring event");
-        return StatusCode::BAD_REQUEST;
+    info!(
+        client = client.name,
+        comparison_id = id.to_string(),
+        "comparison requested through the API"
+    );
+    let status_url = format!("{}/api/compare/{id}", state.config.app_base_url);
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "id": id, "status_url": status_url })),
+    )
+        .into_response())
+}
+
+/// Returns the status of a comparison requested through the API, along with its results once it
+/// has completed
+///
+/// Clients can only see the comparisons they requested
+async fn get_api_comparison(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<ApiComparisonView>> {
+    let client = authorize_api_client(&state.config, &headers)?;
+    let comparison = state
+        .db
+        .api_comparison(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .filter(|comparison| comparison.client == client.name)
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let status = match (&comparison.finished_utc, &comparison.error) {
+        (None, _) => ApiComparisonStatus::Pending,
+        (Some(_), None) => ApiComparisonStatus::Completed,
+        (Some(_), Some(_)) => ApiComparisonStatus::Failed,
     };
+    let result = comparison
+        .result
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|_| "internal server error")?;
 
-    if !verify_webhook_signature(&body, signature, &state.config.webhook_secret) {
-        trace!("{WEBHOOK_SIGNATURE_HEADER} invalid signature, ignoring event");
-        return StatusCode::BAD_REQUEST;
+    Ok(Json(ApiComparisonView {
+        id: comparison.id,
+        status,
+        baseline_ref: comparison.baseline_ref,
+        candidate_ref: comparison.candidate_ref,
+        baseline_commit: comparison.baseline_commit,
+        candidate_commit: comparison.candidate_commit,
+        job_url: comparison
+            .job_id
+            .map(|job_id| format!("{}/jobs/{job_id}", state.config.app_base_url)),
+        verdict: comparison.verdict,
+        result,
+        error: comparison.error,
+    }))
+}
+
+/// Returns the public key against which signed benchmark data can be verified
+async fn get_signing_key(
+    State(state): State<Arc<AppState>>,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    let signer = state
+        .signer
+        .as_ref()
+        .ok_or((StatusCode::NOT_FOUND, "signing is not enabled"))?;
+
+    Ok(Json(json!({
+        "algorithm": SIGNING_ALGORITHM,
+        "public_key": signer.public_key_hex(),
+    })))
+}
+
+/// Returns the cachegrind diff between the specified commits, for the provided scenario
+///
+/// Browsers (i.e. requests accepting HTML) get a page with the diff split by function, other
+/// clients get the plain-text diff
+async fn get_cachegrind_diff(
+    State(state): State<Arc<AppState>>,
+    Path((compared_commits, scenario_name)): Path<(String, String)>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    let (baseline_commit, candidate_commit) =
+        resolve_compared_commits(&state.config, &state.db, &compared_commits).await?;
+
+    let store = ArtifactStore::new(&state.config.job_output_dir);
+    let diff = full_cachegrind_diff(
+        &state.db,
+        &store,
+        &baseline_commit,
+        &candidate_commit,
+        &scenario_name,
+    )
+    .await
+    .map_err(|_| "internal server error")?
+    .ok_or((
+        StatusCode::NOT_FOUND,
+        "comparison not found for the provided commit hashes and scenario",
+    ))?;
+
+    let accepts_html = headers
+        .get(ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if !accepts_html {
+        return Ok(diff.into_response());
     }
 
-    let Some(event) = headers.get(WEBHOOK_EVENT_HEADER) else {
-        trace!("{WEBHOOK_EVENT_HEADER} header is missing, ignoring event");
-        return StatusCode::BAD_REQUEST;
-    };
+    let result = state
+        .db
+        .comparison_result(&baseline_commit, &candidate_commit)
+        .await
+        .map_err(|_| "internal server error")?;
+    let scenario_diff = result.as_ref().and_then(|result| {
+        result
+            .metric(ScenarioKind::Icount)
+            .diffs
+            .iter()
+            .find(|diff| diff.scenario_name == scenario_name)
+    });
 
-    let Ok(event) = event.to_str() else {
-        trace!("{WEBHOOK_EVENT_HEADER} has an invalid header value, ignoring event");
-        return StatusCode::BAD_REQUEST;
-    };
+    Ok(Html(cachegrind::render_diff_page(
+        &state.config,
+        &compared_commits,
+        &scenario_name,
+        scenario_diff,
+        &diff,
+    ))
+    .into_response())
+}
 
-    // Events are enqueued and processed sequentially in the background
-    match state.event_queue.enqueue(event, body).await {
-        Ok(Some(event_id)) => {
-            trace!("enqueued webhook event `{event}` with id `{event_id}`");
-            StatusCode::OK
-        }
-        Ok(None) => {
-            error!("unsupported webhook event: {event}");
-            StatusCode::BAD_REQUEST
-        }
-        Err(e) => {
+/// Re-renders the report of a stored comparison with the current templates and updates the PR
+/// comment where it was reported
+async fn rerender_comparison(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<serde_json::Value>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let octocrab = state.octocrab.cached();
+    let outcome = rerender_report(&state.config, &octocrab, &state.db, id)
+        .await
+        .map_err(|e| {
             error!(
-                cause = e.to_string(),
-                "unable to enqueue webhook event: {event}"
+                cause = format!("{e:#}"),
+                "unable to re-render comparison {id}"
             );
-            StatusCode::INTERNAL_SERVER_ERROR
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+        })?;
+
+    match outcome {
+        RerenderOutcome::Updated {
+            pr_number,
+            comment_id,
+        } => Ok(Json(json!({
+            "pr_number": pr_number,
+            "comment_url": format!(
+                "https://github.com/{}/{}/pull/{pr_number}#issuecomment-{comment_id}",
+                state.config.github_repo_owner, state.config.github_repo_name
+            ),
+        }))),
+        RerenderOutcome::ComparisonNotFound => {
+            Err((StatusCode::NOT_FOUND, "comparison not found").into())
         }
+        RerenderOutcome::NoComment => Err((
+            StatusCode::NOT_FOUND,
+            "the comparison was never reported in a PR comment",
+        )
+            .into()),
+        RerenderOutcome::Superseded => Err((
+            StatusCode::CONFLICT,
+            "the PR comment now reports a different comparison",
+        )
+            .into()),
     }
 }
 
-/// The HTTP header containing the SHA256 signature of the GitHub webhook payload
-pub static WEBHOOK_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+/// Enqueues a copy of a handled event (e.g. one whose job failed for transient reasons), to handle
+/// it again
+async fn replay_event(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    authorize_admin(&state.config, &headers)?;
 
-/// The HTTP header containing the name of the event that triggered the GitHub webhook
-pub static WEBHOOK_EVENT_HEADER: &str = "X-GitHub-Event";
+    let outcome = state.event_queue.replay_event(id).await.map_err(|e| {
+        error!(cause = format!("{e:#}"), "unable to replay event {id}");
+        (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+    })?;
 
-/// Identifies a specific commit in a repository
-#[derive(Debug, Clone, Eq, PartialEq)]
-pub struct CommitIdentifier {
-    /// The URL at which the repository can be cloned
-    pub clone_url: String,
-    /// The branch we are interested in (for reporting purposes)
-    pub branch_name: String,
-    /// The specific commit we are interested in
-    pub commit_sha: String,
+    match outcome {
+        ReplayOutcome::Enqueued(event_id) => {
+            Ok((StatusCode::ACCEPTED, Json(json!({ "event_id": event_id }))).into_response())
+        }
+        ReplayOutcome::StillQueued => Err((
+            StatusCode::CONFLICT,
+            "the event is still queued, so it will be handled anyway",
+        )
+            .into()),
+        ReplayOutcome::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            "event not found (handled events are only kept for a limited time)",
+        )
+            .into()),
+    }
+}
+
+/// Returns the events that were taken out of the queue because they couldn't be handled, most
+/// recent first
+async fn get_dead_events(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<Vec<DeadEventView>>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let dead_events = state
+        .db
+        .dead_events()
+        .await
+        .map_err(|_| "internal server error")?;
+
+    let views = dead_events
+        .into_iter()
+        .map(|dead_event| DeadEventView {
+            id: dead_event.id,
+            event: dead_event.event,
+            created_utc: dead_event.created_utc,
+            died_utc: dead_event.died_utc,
+            job_url: dead_event
+                .job_id
+                .map(|job_id| format!("{}/jobs/{job_id}", state.config.app_base_url)),
+            error: dead_event.error,
+            // Invalid payloads are a common reason for events to end up here, so they are returned
+            // as text if they aren't valid JSON
+            payload: serde_json::from_slice(&dead_event.payload).unwrap_or_else(|_| {
+                serde_json::Value::String(String::from_utf8_lossy(&dead_event.payload).to_string())
+            }),
+        })
+        .collect();
+
+    Ok(Json(views))
+}
+
+/// Moves a dead event back to the queue (e.g. after deploying a fix for the bug that made its job
+/// crash)
+async fn replay_dead_event(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    authorize_admin(&state.config, &headers)?;
+
+    let event_id = state
+        .event_queue
+        .replay_dead_event(id)
+        .await
+        .map_err(|e| {
+            error!(cause = format!("{e:#}"), "unable to replay dead event {id}");
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+        })?
+        .ok_or((StatusCode::NOT_FOUND, "dead event not found"))?;
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "event_id": event_id }))).into_response())
+}
+
+/// Cross-checks the stored result comments and commit statuses against GitHub, repairing any
+/// discrepancies (meant to be run after incidents)
+async fn reconcile(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<Reconciliation>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let octocrab = state.octocrab.cached();
+    let reconciliation = reconcile_github_state(&state.config, &octocrab, &state.db)
+        .await
+        .map_err(|e| {
+            error!(cause = format!("{e:#}"), "unable to reconcile with GitHub");
+            (StatusCode::INTERNAL_SERVER_ERROR, "internal server error")
+        })?;
+
+    Ok(Json(reconciliation))
+}
+
+/// Enqueues a self-test, which compares and reports the fixture results of built-in scenarios (meant
+/// as a smoke check of the app after deploying), returning the id under which its outcome can be
+/// polled
+async fn request_self_test(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Response> {
+    authorize_admin(&state.config, &headers)?;
+
+    let id = state
+        .db
+        .store_self_test()
+        .await
+        .map_err(|_| "internal server error")?;
+    state
+        .event_queue
+        .enqueue_self_test(&SelfTestRequest { id })
+        .await
+        .map_err(|_| "internal server error")?;
+
+    info!(self_test_id = id.to_string(), "self-test requested");
+    let status_url = format!("{}/admin/self-tests/{id}", state.config.app_base_url);
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(json!({ "id": id, "status_url": status_url })),
+    )
+        .into_response())
+}
+
+/// Returns the status of a self-test, along with the timing of its stages once it has completed
+async fn get_self_test(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<SelfTestView>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let self_test = state
+        .db
+        .self_test(id)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "not found"))?;
+
+    let status = match (&self_test.finished_utc, &self_test.error) {
+        (None, _) => ApiComparisonStatus::Pending,
+        (Some(_), None) => ApiComparisonStatus::Completed,
+        (Some(_), Some(_)) => ApiComparisonStatus::Failed,
+    };
+    let timings = self_test
+        .timings
+        .as_deref()
+        .map(serde_json::from_str)
+        .transpose()
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(SelfTestView {
+        id: self_test.id,
+        status,
+        job_url: self_test
+            .job_id
+            .map(|job_id| format!("{}/jobs/{job_id}", state.config.app_base_url)),
+        timings,
+        error: self_test.error,
+    }))
+}
+
+/// Returns the regressions detected on the tracked branches, most recent first
+async fn get_regressions(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RegressionsQuery>,
+) -> axum::response::Result<Json<Vec<Regression>>> {
+    let regressions = state
+        .db
+        .regressions(query.state, query.branch.as_deref())
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(regressions))
+}
+
+/// Returns the scenarios added to or removed from a tracked branch (main by default) in the past
+/// month, oldest first
+async fn get_scenario_changes(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ScenarioChangesQuery>,
+) -> axum::response::Result<Json<Vec<ScenarioSetChange>>> {
+    let branch = query.branch.as_deref().unwrap_or(MAIN_BRANCH);
+    if !is_tracked_branch(&state.config, branch) {
+        return Err((StatusCode::NOT_FOUND, "the branch is not tracked").into());
+    }
+
+    let since = OffsetDateTime::now_utc() - SCENARIO_CHANGES_WINDOW;
+    let changes = state
+        .db
+        .scenario_set_changes(branch, since)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(changes))
+}
+
+/// Returns the groups of scenarios whose results moved together in the runs of a tracked branch
+/// (main by default) over the past three months, biggest group first
+async fn get_scenario_correlations(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<ScenarioCorrelationsQuery>,
+) -> axum::response::Result<Json<Vec<CorrelatedGroup>>> {
+    let branch = query.branch.as_deref().unwrap_or(MAIN_BRANCH);
+    if !is_tracked_branch(&state.config, branch) {
+        return Err((StatusCode::NOT_FOUND, "the branch is not tracked").into());
+    }
+
+    let since = OffsetDateTime::now_utc() - CORRELATION_WINDOW;
+    let scenario_kind = query.kind.unwrap_or(ScenarioKind::Icount);
+    let runs = state
+        .db
+        .results_by_run(branch, scenario_kind, since)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(correlated_groups(&runs)))
+}
+
+/// Returns how a scenario changed between the runs of a tracked branch (main by default) closest to
+/// two dates, including the cachegrind diff between them
+///
+/// The cachegrind diff is taken from a stored comparison between both commits if there is one, and
+/// computed from the callgrind outputs of the runs otherwise (it is missing if they are gone)
+async fn get_scenario_time_travel(
+    State(state): State<Arc<AppState>>,
+    Path(scenario_name): Path<String>,
+    Query(query): Query<ScenarioTimeTravelQuery>,
+) -> axum::response::Result<Json<ScenarioTimeTravelView>> {
+    let branch = query.branch.as_deref().unwrap_or(MAIN_BRANCH);
+    if !is_tracked_branch(&state.config, branch) {
+        return Err((StatusCode::NOT_FOUND, "the branch is not tracked").into());
+    }
+
+    // Instruction counts are compared, since the cachegrind diff explains their changes
+    let from_date = parse_date(&query.from)?;
+    let to_date = parse_date(&query.to)?;
+    let from = state
+        .db
+        .closest_scenario_result(branch, &scenario_name, ScenarioKind::Icount, from_date)
+        .await
+        .map_err(|_| "internal server error")?;
+    let to = state
+        .db
+        .closest_scenario_result(branch, &scenario_name, ScenarioKind::Icount, to_date)
+        .await
+        .map_err(|_| "internal server error")?;
+    let (Some(from), Some(to)) = (from, to) else {
+        return Err((StatusCode::NOT_FOUND, "no results found for the scenario").into());
+    };
+
+    let store = ArtifactStore::new(&state.config.job_output_dir);
+    let stored_diff = full_cachegrind_diff(
+        &state.db,
+        &store,
+        &from.commit_sha,
+        &to.commit_sha,
+        &scenario_name,
+    )
+    .await
+    .map_err(|_| "internal server error")?;
+    let cachegrind_diff = match (stored_diff, from.job_id, to.job_id) {
+        (Some(diff), _, _) => Some(diff),
+        (None, Some(from_job_id), Some(to_job_id)) => {
+            let config = state.config.clone();
+            let scenario = scenario_name.clone();
+            tokio::task::spawn_blocking(move || {
+                let bench_suite = &config.bench_suite;
+                let from_path = config.job_output_dir.join(from_job_id.to_string());
+                let to_path = config.job_output_dir.join(to_job_id.to_string());
+                let outputs_available = bench_suite.callgrind_path(&from_path, &scenario).exists()
+                    && bench_suite.callgrind_path(&to_path, &scenario).exists();
+                if !outputs_available {
+                    return Ok(None);
+                }
+
+                callgrind_diff_between(&from_path, &to_path, bench_suite, &scenario).map(Some)
+            })
+            .await
+            .map_err(|_| "internal server error")?
+            .map_err(|e| {
+                error!(
+                    cause = format!("{e:#}"),
+                    "unable to compute cachegrind diff for {scenario_name}"
+                );
+                "internal server error"
+            })?
+        }
+        _ => None,
+    };
+
+    Ok(Json(ScenarioTimeTravelView {
+        diff: to.result - from.result,
+        diff_ratio: (to.result - from.result) / from.result,
+        scenario_name,
+        from,
+        to,
+        cachegrind_diff,
+    }))
+}
+
+/// Returns the time series of a scenario's results in the runs of a tracked branch (main by
+/// default), along with its change points, for consumption by external tooling and dashboards
+async fn get_scenario_history(
+    State(state): State<Arc<AppState>>,
+    Path(scenario_name): Path<String>,
+    Query(query): Query<ScenarioHistoryQuery>,
+) -> axum::response::Result<Json<ScenarioHistoryView>> {
+    let branch = query.branch.unwrap_or_else(|| MAIN_BRANCH.to_string());
+    if !is_tracked_branch(&state.config, &branch) {
+        return Err((StatusCode::NOT_FOUND, "the branch is not tracked").into());
+    }
+
+    let since = match &query.since {
+        Some(since) => parse_date(since)?,
+        None => OffsetDateTime::now_utc() - SCENARIO_HISTORY_WINDOW,
+    };
+    let scenario_kind = query
+        .kind
+        .unwrap_or(if is_aggregate_scenario(&scenario_name) {
+            ScenarioKind::IcountIndex
+        } else {
+            ScenarioKind::Icount
+        });
+    let results = state
+        .db
+        .scenario_history(&branch, &scenario_name, scenario_kind, since)
+        .await
+        .map_err(|_| "internal server error")?;
+    let values: Vec<_> = results.iter().map(|r| r.result).collect();
+    let annotations = state
+        .db
+        .branch_annotations(&branch, since)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(ScenarioHistoryView {
+        scenario_name,
+        scenario_kind,
+        branch,
+        since,
+        change_points: change_points(scenario_kind, &values),
+        results,
+        annotations,
+    }))
+}
+
+/// Parses an RFC 3339 date-time (e.g. `2023-10-11T12:00:00Z`), or a plain date (e.g.
+/// `2023-10-11`, meaning midnight UTC)
+fn parse_date(date: &str) -> Result<OffsetDateTime, (StatusCode, &'static str)> {
+    let date_time = if date.len() == "YYYY-MM-DD".len() {
+        format!("{date}T00:00:00Z")
+    } else {
+        date.to_string()
+    };
+
+    OffsetDateTime::parse(&date_time, &Rfc3339).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid date, expected YYYY-MM-DD or an RFC 3339 date-time",
+        )
+    })
+}
+
+/// Returns an HTML page plotting the icount results of main over time, for the scenarios of its
+/// latest run
+async fn get_trends_dashboard(
+    State(state): State<Arc<AppState>>,
+) -> axum::response::Result<Html<String>> {
+    let latest = state
+        .db
+        .latest_results(MAIN_BRANCH)
+        .await
+        .map_err(|_| "internal server error")?;
+    let mut scenarios: Vec<_> = latest
+        .map(|(_, results)| results)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|r| {
+            matches!(
+                r.scenario_kind,
+                ScenarioKind::Icount | ScenarioKind::IcountIndex
+            )
+        })
+        .map(|r| r.scenario_name)
+        .collect();
+
+    // The aggregate series give an overview, so they come first
+    scenarios.sort_by_key(|scenario| (!is_aggregate_scenario(scenario), scenario.clone()));
+
+    Ok(Html(trends::render_dashboard(&state.config, &scenarios)))
+}
+
+/// Returns an HTML page listing the regressions detected on the tracked branches
+async fn get_regressions_dashboard(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<RegressionsQuery>,
+) -> axum::response::Result<Html<String>> {
+    let regressions = state
+        .db
+        .regressions(query.state, query.branch.as_deref())
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Html(render_dashboard(
+        &state.config,
+        query.branch.as_deref(),
+        &regressions,
+    )))
+}
+
+/// Returns the resources consumed by the jobs of the past month, grouped by requester (default),
+/// PR or event kind
+async fn get_costs(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CostsQuery>,
+) -> axum::response::Result<Json<Vec<JobCostSummary>>> {
+    let since = OffsetDateTime::now_utc() - COSTS_WINDOW;
+    let costs = state
+        .db
+        .job_costs(query.group_by, since)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(costs))
+}
+
+/// Returns an HTML page showing the resources consumed by the jobs of the past month
+async fn get_costs_dashboard(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<CostsQuery>,
+) -> axum::response::Result<Html<String>> {
+    let since = OffsetDateTime::now_utc() - COSTS_WINDOW;
+    let costs = state
+        .db
+        .job_costs(query.group_by, since)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Html(costs::render_dashboard(query.group_by, &costs)))
+}
+
+/// Moves a regression to a new triage state
+async fn set_regression_state(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+    Json(request): Json<RegressionStateRequest>,
+) -> axum::response::Result<Json<Regression>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let regression = state
+        .db
+        .set_regression_state(id, request.state)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((StatusCode::NOT_FOUND, "regression not found"))?;
+
+    Ok(Json(regression))
+}
+
+/// Returns the maintainers' notes on comparisons and on windows of the tracked branches' history,
+/// most recent first
+async fn get_annotations(
+    State(state): State<Arc<AppState>>,
+) -> axum::response::Result<Json<Vec<Annotation>>> {
+    let annotations = state
+        .db
+        .annotations()
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(annotations))
+}
+
+/// Attaches a note to a comparison or to a window of a tracked branch's history (e.g. to explain
+/// changes caused by a dependency upgrade), which then shows up in charts, digests and reports
+async fn create_annotation(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<AnnotationRequest>,
+) -> axum::response::Result<Response> {
+    authorize_admin(&state.config, &headers)?;
+
+    let AnnotationRequest {
+        note,
+        comparison_id,
+        branch,
+        from_commit,
+        to_commit,
+    } = request;
+    let target = match (comparison_id, from_commit) {
+        (Some(id), None) if to_commit.is_none() => AnnotationTarget::Comparison(id),
+        (None, Some(from_commit)) => {
+            let branch = branch.unwrap_or_else(|| MAIN_BRANCH.to_string());
+            if !is_tracked_branch(&state.config, &branch) {
+                return Err((StatusCode::BAD_REQUEST, "the branch is not tracked").into());
+            }
+
+            AnnotationTarget::Window {
+                branch,
+                // A single commit by default
+                to_commit: to_commit.unwrap_or_else(|| from_commit.clone()),
+                from_commit,
+            }
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "expected either a comparison id or the commits of a window",
+            )
+                .into())
+        }
+    };
+
+    let annotation = state
+        .db
+        .store_annotation(&note, &target)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "comparison not found, or commits not benchmarked on the branch",
+        ))?;
+
+    info!(
+        annotation_id = annotation.id.to_string(),
+        "annotation created"
+    );
+    Ok((StatusCode::CREATED, Json(annotation)).into_response())
+}
+
+/// Deletes an annotation
+async fn delete_annotation(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+    headers: HeaderMap,
+) -> axum::response::Result<StatusCode> {
+    authorize_admin(&state.config, &headers)?;
+
+    let deleted = state
+        .db
+        .delete_annotation(id)
+        .await
+        .map_err(|_| "internal server error")?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "annotation not found").into());
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Returns the most recent webhook events that were dropped because they came from a repository we
+/// don't serve
+async fn get_dropped_webhooks(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<Vec<DroppedWebhook>>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let dropped = state
+        .db
+        .dropped_webhooks(100)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(dropped))
+}
+
+/// Returns the most recent entries of the audit log, which records maintainers' actions that
+/// affect other people's jobs (e.g. reprioritizing the queue)
+async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<Vec<AuditLogEntry>>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let entries = state
+        .db
+        .audit_log(100)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(entries))
+}
+
+/// The state of the event queue, as reported by the admin endpoints
+#[derive(Serialize)]
+struct QueueState {
+    /// Whether jobs are being started (false while paused through the API or the `pause` file)
+    event_processing_enabled: bool,
+    /// The pause requested through the API, if any
+    pause: Option<QueuePause>,
+    queued_events: i64,
+}
+
+impl QueueState {
+    async fn load(state: &AppState) -> anyhow::Result<Self> {
+        Ok(Self {
+            event_processing_enabled: state.event_queue.event_processing_enabled(),
+            pause: state.db.queue_pause().await?,
+            queued_events: state.db.queued_event_count().await?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct PauseQueueRequest {
+    reason: Option<String>,
+}
+
+/// Returns whether the event queue is paused
+async fn get_queue_state(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<QueueState>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let queue_state = QueueState::load(&state)
+        .await
+        .map_err(|_| "internal server error")?;
+    Ok(Json(queue_state))
+}
+
+/// Stops starting jobs (e.g. during host maintenance), while webhooks are still accepted and
+/// queued. The pause survives restarts, until the queue is resumed
+async fn pause_queue(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Option<Json<PauseQueueRequest>>,
+) -> axum::response::Result<Json<QueueState>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let reason = request.and_then(|Json(request)| request.reason);
+    state
+        .event_queue
+        .pause(reason.as_deref())
+        .await
+        .map_err(|_| "internal server error")?;
+
+    let queue_state = QueueState::load(&state)
+        .await
+        .map_err(|_| "internal server error")?;
+    Ok(Json(queue_state))
+}
+
+/// Resumes starting jobs after a pause, handling the events queued in the meantime in the usual
+/// order
+async fn resume_queue(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<QueueState>> {
+    authorize_admin(&state.config, &headers)?;
+
+    state
+        .event_queue
+        .resume()
+        .await
+        .map_err(|_| "internal server error")?;
+
+    let queue_state = QueueState::load(&state)
+        .await
+        .map_err(|_| "internal server error")?;
+    Ok(Json(queue_state))
+}
+
+#[derive(Deserialize)]
+struct HandoffRequest {
+    #[serde(default)]
+    mode: HandoffMode,
+}
+
+/// Hands the event queue off to a new instance of the application, during a blue/green deploy
+/// (see [`EventQueue::hand_off`])
+///
+/// Responds right away, since running jobs might take a while to finish. The handoff is complete
+/// once the new instance reports itself as the `queue_owner` in `/info`
+async fn hand_off_queue(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    request: Option<Json<HandoffRequest>>,
+) -> axum::response::Result<StatusCode> {
+    authorize_admin(&state.config, &headers)?;
+
+    let mode = request
+        .map(|Json(request)| request.mode)
+        .unwrap_or_default();
+    let event_queue = state.event_queue.clone();
+    tokio::spawn(async move {
+        if let Err(e) = event_queue.hand_off(mode, SHUTDOWN_TIMEOUT).await {
+            error!(
+                cause = format!("{e:#}"),
+                "unable to hand off the event queue"
+            );
+        }
+    });
+
+    Ok(StatusCode::ACCEPTED)
+}
+
+/// Returns the per-module log level overrides currently in effect
+async fn get_log_levels(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<LogLevels>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let level_overrides = logging::level_overrides().ok_or((
+        StatusCode::NOT_FOUND,
+        "log levels cannot be changed at runtime",
+    ))?;
+
+    Ok(Json(LogLevels { level_overrides }))
+}
+
+/// Replaces the per-module log level overrides, without restarting the application
+async fn set_log_levels(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<LogLevels>,
+) -> axum::response::Result<Json<LogLevels>> {
+    authorize_admin(&state.config, &headers)?;
+
+    if logging::level_overrides().is_none() {
+        return Err((
+            StatusCode::NOT_FOUND,
+            "log levels cannot be changed at runtime",
+        )
+            .into());
+    }
+
+    if let Err(e) = logging::set_level_overrides(&request.level_overrides) {
+        return Err((StatusCode::BAD_REQUEST, format!("{e:#}")).into());
+    }
+
+    info!(
+        level_overrides = %request.level_overrides,
+        "log level overrides changed"
+    );
+    Ok(Json(request))
+}
+
+/// Returns the rollout in effect for each report feature
+async fn get_report_features(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> axum::response::Result<Json<BTreeMap<ReportFeature, EffectiveRollout>>> {
+    authorize_admin(&state.config, &headers)?;
+
+    let rollouts = effective_rollouts(&state.config, &state.db)
+        .await
+        .map_err(|_| "internal server error")?;
+
+    Ok(Json(rollouts))
+}
+
+#[derive(Deserialize)]
+struct ReportFeatureRequest {
+    /// The new rollout, or `None` to go back to the configured one
+    rollout: Option<FeatureRollout>,
+}
+
+/// Overrides the rollout of a report feature, without redeploying (reports that were already
+/// posted are only affected once they are re-rendered)
+async fn set_report_feature(
+    State(state): State<Arc<AppState>>,
+    Path(feature): Path<ReportFeature>,
+    headers: HeaderMap,
+    Json(request): Json<ReportFeatureRequest>,
+) -> axum::response::Result<Json<BTreeMap<ReportFeature, EffectiveRollout>>> {
+    authorize_admin(&state.config, &headers)?;
+
+    if request.rollout.as_ref().is_some_and(|r| r.percent > 100) {
+        return Err((StatusCode::BAD_REQUEST, "percent must be between 0 and 100").into());
+    }
+
+    state
+        .db
+        .set_report_feature_override(feature, request.rollout.as_ref())
+        .await
+        .map_err(|_| "internal server error")?;
+    info!(?feature, rollout = ?request.rollout, "report feature rollout changed");
+
+    let rollouts = effective_rollouts(&state.config, &state.db)
+        .await
+        .map_err(|_| "internal server error")?;
+    Ok(Json(rollouts))
+}
+
+/// Ensures the request carries the configured admin token (admin endpoints are disabled if no
+/// token has been configured)
+fn authorize_admin(
+    config: &AppConfig,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    let Some(admin_token) = &config.admin_token else {
+        return Err((StatusCode::NOT_FOUND, "admin endpoints are disabled"));
+    };
+
+    if bearer_token(headers).is_some_and(|provided| tokens_match(provided, admin_token)) {
+        Ok(())
+    } else {
+        Err((StatusCode::UNAUTHORIZED, "invalid admin token"))
+    }
+}
+
+/// Ensures the request carries the token of one of the configured API clients, returning the
+/// client (the comparison API is disabled if no clients have been configured)
+fn authorize_api_client<'a>(
+    config: &'a AppConfig,
+    headers: &HeaderMap,
+) -> Result<&'a ApiClientConfig, (StatusCode, &'static str)> {
+    if config.api_clients.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "the comparison API is disabled"));
+    }
+
+    bearer_token(headers)
+        .and_then(|provided| {
+            config
+                .api_clients
+                .iter()
+                .find(|client| tokens_match(provided, &client.token))
+        })
+        .ok_or((StatusCode::UNAUTHORIZED, "invalid API token"))
+}
+
+/// Returns the token of the request's `Authorization: Bearer <token>` header, if any
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Compares the tokens in constant time, to avoid leaking the expected token through timing
+/// differences
+fn tokens_match(provided: &str, expected: &str) -> bool {
+    provided.len() == expected.len()
+        && provided
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |acc, (x, y)| acc | (x ^ y))
+            == 0
+}
+
+/// Extracts the baseline and candidate commit hashes from a `<baseline>:<candidate>` URL segment
+///
+/// Either commit can be given as the name of a tracked branch (e.g. `main`), which is resolved to
+/// the latest benchmarked commit of the branch
+async fn resolve_compared_commits(
+    config: &AppConfig,
+    db: &Db,
+    compared_commits: &str,
+) -> axum::response::Result<(String, String)> {
+    let mut commit_parts = compared_commits.split(':');
+    let (baseline_commit, candidate_commit) = match (
+        commit_parts.next(),
+        commit_parts.next(),
+        commit_parts.next(),
+    ) {
+        (Some(baseline), Some(candidate), None) => (baseline, candidate),
+        _ => Err((StatusCode::BAD_REQUEST, "malformed URL"))?,
+    };
+
+    Ok((
+        resolve_commit(config, db, baseline_commit).await?,
+        resolve_commit(config, db, candidate_commit).await?,
+    ))
+}
+
+/// Resolves the name of a tracked branch (e.g. `main`) to its latest benchmarked commit
+async fn resolve_commit(
+    config: &AppConfig,
+    db: &Db,
+    commit: &str,
+) -> axum::response::Result<String> {
+    if !is_tracked_branch(config, commit) {
+        return Ok(commit.to_string());
+    }
+
+    Ok(db
+        .latest_commit(commit)
+        .await
+        .map_err(|_| "internal server error")?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            "no benchmarked commit found for the branch",
+        ))?)
+}
+
+/// Handles an incoming GitHub webhook
+async fn handle_github_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    trace!("incoming webhook");
+
+    let Some(signature) = headers.get(WEBHOOK_SIGNATURE_HEADER) else {
+        trace!("{WEBHOOK_SIGNATURE_HEADER} header is missing, ignoring event");
+        return StatusCode::BAD_REQUEST;
+    };
+
+    let Ok(signature) = signature.to_str() else {
+        trace!("{WEBHOOK_SIGNATURE_HEADER} has an invalid header value, ignoring event");
+        return StatusCode::BAD_REQUEST;
+    };
+
+    if !verify_webhook_signature(&body, signature, &state.config.webhook_secret) {
+        trace!("{WEBHOOK_SIGNATURE_HEADER} invalid signature, ignoring event");
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let Some(event) = headers.get(WEBHOOK_EVENT_HEADER) else {
+        trace!("{WEBHOOK_EVENT_HEADER} header is missing, ignoring event");
+        return StatusCode::BAD_REQUEST;
+    };
+
+    let Ok(event) = event.to_str() else {
+        trace!("{WEBHOOK_EVENT_HEADER} has an invalid header value, ignoring event");
+        return StatusCode::BAD_REQUEST;
+    };
+
+    // Only the event types we handle are accepted. Anything else hints at the GitHub App being
+    // subscribed to more events than needed (or, if expected events never show up, fewer)
+    if AllowedEvent::from_event_string(event).is_none() {
+        state.metrics.record_dropped_webhook_event(event);
+        if state.config.warn_on_unexpected_events {
+            warn!("dropped webhook event of unexpected type: {event}");
+        } else {
+            debug!("dropped webhook event of unexpected type: {event}");
+        }
+
+        return StatusCode::BAD_REQUEST;
+    }
+
+    // Events from other repositories would otherwise be processed with this repository's config
+    let repository = webhook_repository(&body);
+    if !repository
+        .as_deref()
+        .is_some_and(|repository| is_allowed_repository(&state.config, repository))
+    {
+        let repository = repository.as_deref();
+        warn!(
+            "dropped webhook event `{event}` from repository {}",
+            repository.unwrap_or("<unknown>")
+        );
+        state
+            .metrics
+            .record_foreign_webhook_event(repository.unwrap_or("unknown"));
+        if let Err(e) = state.db.record_dropped_webhook(event, repository).await {
+            error!(cause = e.to_string(), "unable to record dropped webhook");
+        }
+
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let delivery = headers
+        .get(WEBHOOK_DELIVERY_HEADER)
+        .and_then(|delivery| delivery.to_str().ok());
+
+    // Cancel commands skip the queue, or they would only run after the jobs they should cancel
+    if event == "issue_comment" {
+        if let Some(payload) = parse_cancel_command(&state.config, &body) {
+            if let Some(status) = skip_redelivered_command(&state, event, delivery).await {
+                return status;
+            }
+
+            let state = state.clone();
+            tokio::spawn(async move {
+                let octocrab = state.octocrab.cached();
+                let result =
+                    handle_cancel_command(&state.event_queue, &state.config, &octocrab, &payload)
+                        .await;
+                if let Err(e) = result {
+                    error!(cause = format!("{e:#}"), "unable to handle cancel command");
+                }
+            });
+
+            return StatusCode::OK;
+        }
+
+        // Same for prioritize commands, which would otherwise wait behind the jobs they should
+        // overtake
+        if let Some(payload) = parse_prioritize_command(&state.config, &body) {
+            if let Some(status) = skip_redelivered_command(&state, event, delivery).await {
+                return status;
+            }
+
+            let state = state.clone();
+            tokio::spawn(async move {
+                let octocrab = state.octocrab.cached();
+                let result = handle_prioritize_command(
+                    &state.event_queue,
+                    &state.db,
+                    &state.config,
+                    &octocrab,
+                    &payload,
+                )
+                .await;
+                if let Err(e) = result {
+                    error!(
+                        cause = format!("{e:#}"),
+                        "unable to handle prioritize command"
+                    );
+                }
+            });
+
+            return StatusCode::OK;
+        }
+    }
+
+    // Events are enqueued and processed sequentially in the background
+    match state
+        .event_queue
+        .enqueue(&state.config, event, delivery, body.clone())
+        .await
+    {
+        Ok(EnqueuedWebhook::Enqueued(event_id)) => {
+            trace!("enqueued webhook event `{event}` with id `{event_id}`");
+            if event == "issue_comment" {
+                tokio::spawn(async move {
+                    let octocrab = state.octocrab.cached();
+                    acknowledge_benchmarking_command(&state.config, &octocrab, &body).await;
+                });
+            }
+            StatusCode::OK
+        }
+        Ok(EnqueuedWebhook::Duplicate(event_id)) => {
+            // Responding with success, so GitHub stops redelivering it
+            info!(
+                "skipped redelivery of webhook event `{event}`, already enqueued with id `{event_id}`"
+            );
+            StatusCode::OK
+        }
+        Ok(EnqueuedWebhook::Unsupported) => {
+            error!("unsupported webhook event: {event}");
+            StatusCode::BAD_REQUEST
+        }
+        Err(e) => {
+            error!(
+                cause = e.to_string(),
+                "unable to enqueue webhook event: {event}"
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Records the delivery of a command handled outside the queue, returning the status to respond
+/// with if it must be skipped (because it is a redelivery or its delivery can't be recorded)
+async fn skip_redelivered_command(
+    state: &AppState,
+    event: &str,
+    delivery: Option<&str>,
+) -> Option<StatusCode> {
+    let delivery = delivery?;
+    match state.db.record_webhook_delivery(delivery).await {
+        Ok(true) => None,
+        Ok(false) => {
+            // Responding with success, so GitHub stops redelivering it
+            info!("skipped redelivery of webhook event `{event}` with delivery `{delivery}`");
+            Some(StatusCode::OK)
+        }
+        Err(e) => {
+            error!(
+                cause = e.to_string(),
+                "unable to record delivery of webhook event: {event}"
+            );
+            Some(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+async fn handle_gitlab_webhook(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    trace!("incoming GitLab webhook");
+
+    let Some(gitlab) = &state.config.gitlab else {
+        return StatusCode::NOT_FOUND;
+    };
+
+    let token = headers
+        .get(gitlab::WEBHOOK_TOKEN_HEADER)
+        .and_then(|token| token.to_str().ok());
+    if !token.is_some_and(|token| tokens_match(token, &gitlab.webhook_secret)) {
+        trace!(
+            "{} is missing or invalid, ignoring event",
+            gitlab::WEBHOOK_TOKEN_HEADER
+        );
+        return StatusCode::BAD_REQUEST;
+    }
+
+    let Some(event) = headers
+        .get(gitlab::WEBHOOK_EVENT_HEADER)
+        .and_then(|event| event.to_str().ok())
+    else {
+        trace!(
+            "{} is missing or invalid, ignoring event",
+            gitlab::WEBHOOK_EVENT_HEADER
+        );
+        return StatusCode::BAD_REQUEST;
+    };
+
+    let Some(queued_event) = queued_event(event) else {
+        state.metrics.record_dropped_webhook_event(event);
+        debug!("dropped GitLab webhook event of unexpected type: {event}");
+        return StatusCode::BAD_REQUEST;
+    };
+
+    // Events from other projects would otherwise be processed with this configuration
+    let project = webhook_project(&body);
+    if !project
+        .as_ref()
+        .is_some_and(|project| gitlab.projects.contains(project))
+    {
+        let project = project.as_deref();
+        warn!(
+            "dropped GitLab webhook event `{event}` from project {}",
+            project.unwrap_or("<unknown>")
+        );
+        state
+            .metrics
+            .record_foreign_webhook_event(project.unwrap_or("unknown"));
+        if let Err(e) = state.db.record_dropped_webhook(queued_event, project).await {
+            error!(cause = e.to_string(), "unable to record dropped webhook");
+        }
+
+        return StatusCode::BAD_REQUEST;
+    }
+
+    match state.event_queue.enqueue_gitlab(queued_event, body).await {
+        Ok(event_id) => {
+            trace!("enqueued GitLab webhook event `{event}` with id `{event_id}`");
+            StatusCode::OK
+        }
+        Err(e) => {
+            error!(
+                cause = e.to_string(),
+                "unable to enqueue GitLab webhook event: {event}"
+            );
+            StatusCode::INTERNAL_SERVER_ERROR
+        }
+    }
+}
+
+/// Returns the full name (i.e. `owner/name`) of the repository a webhook payload comes from, if
+/// available
+fn webhook_repository(body: &[u8]) -> Option<String> {
+    #[derive(Deserialize)]
+    struct Payload {
+        repository: Repository,
+    }
+
+    #[derive(Deserialize)]
+    struct Repository {
+        full_name: String,
+    }
+
+    let payload: Payload = serde_json::from_slice(body).ok()?;
+    Some(payload.repository.full_name)
+}
+
+/// Returns whether webhook events of the repository (as `owner/name`) should be processed
+fn is_allowed_repository(config: &AppConfig, repository: &str) -> bool {
+    // GitHub treats owner and repository names case-insensitively
+    let configured = format!("{}/{}", config.github_repo_owner, config.github_repo_name);
+    repository.eq_ignore_ascii_case(&configured)
+        || config
+            .allowed_repositories
+            .iter()
+            .any(|allowed| repository.eq_ignore_ascii_case(allowed))
+}
+
+/// The HTTP header containing the SHA256 signature of the GitHub webhook payload
+pub static WEBHOOK_SIGNATURE_HEADER: &str = "X-Hub-Signature-256";
+
+/// The HTTP header containing the name of the event that triggered the GitHub webhook
+pub static WEBHOOK_EVENT_HEADER: &str = "X-GitHub-Event";
+
+/// The HTTP header containing the GUID of a GitHub webhook delivery, which is shared by its
+/// redeliveries
+pub static WEBHOOK_DELIVERY_HEADER: &str = "X-GitHub-Delivery";
+
+/// The maximum amount of jobs returned per page by the `/jobs` endpoint
+const MAX_JOBS_PER_PAGE: usize = 100;
+
+/// How long to wait for the running jobs to record their interruption when shutting down
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Identifies a specific commit in a repository
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CommitIdentifier {
+    /// The URL at which the repository can be cloned
+    pub clone_url: String,
+    /// The branch we are interested in (for reporting purposes)
+    pub branch_name: String,
+    /// The specific commit we are interested in
+    pub commit_sha: String,
+    /// A series of commits to cherry-pick on top of `commit_sha` before benchmarking, if any
+    pub patch_series: Option<PatchSeries>,
+    /// Another commit whose bench harness replaces the checked out one before benchmarking, if any
+    pub pinned_harness: Option<PinnedHarness>,
+}
+
+impl CommitIdentifier {
+    /// Returns the key under which comparisons involving this commit are stored
+    ///
+    /// Patch series are benchmarked on top of a commit, and pinned harnesses replace the commit's
+    /// own, so both need to be distinguished from the commit itself
+    pub fn comparison_key(&self) -> String {
+        let mut key = match &self.patch_series {
+            None => self.commit_sha.clone(),
+            Some(series) => format!(
+                "{}+{}..{}",
+                self.commit_sha, series.first_commit, series.last_commit
+            ),
+        };
+        if let Some(harness) = &self.pinned_harness {
+            key = format!("{key}{PINNED_HARNESS_KEY_SEPARATOR}{}", harness.commit_sha);
+        }
+
+        key
+    }
+}
+
+/// Separates a commit from its pinned harness in comparison keys
+pub static PINNED_HARNESS_KEY_SEPARATOR: &str = "+harness-";
+
+/// The commit from which the bench harness (i.e. the bench suite's `bench_dir`) is taken, instead
+/// of the benchmarked commit
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PinnedHarness {
+    /// The URL at which the repository containing the commit can be cloned
+    pub clone_url: String,
+    pub commit_sha: String,
+}
+
+/// A series of consecutive commits (both ends included), to be applied on top of another commit
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PatchSeries {
+    /// The URL at which the repository containing the series can be cloned
+    pub clone_url: String,
+    /// The first commit of the series
+    pub first_commit: String,
+    /// The last commit of the series
+    pub last_commit: String,
+}
+
+/// A comparison between two commits, as returned by the HTTP API
+#[derive(Debug, Serialize)]
+struct ComparisonView {
+    /// The baseline commit (resolved, if it was requested through the `main` shorthand)
+    baseline_commit: String,
+    /// The candidate commit (resolved, if it was requested through the `main` shorthand)
+    candidate_commit: String,
+    /// The comparison's overall verdict
+    verdict: Verdict,
+    #[serde(flatten)]
+    result: ComparisonResult,
+    /// A signed summary of the comparison, if it was stored while signing was enabled
+    signature: Option<SignedSummary>,
+    /// The maintainers' notes on the compared results
+    annotations: Vec<Annotation>,
+}
+
+/// The formats in which a comparison can be exported
+#[derive(Debug, Clone, Copy)]
+enum ExportFormat {
+    Csv,
+    Json,
+}
+
+/// A scenario of an exported comparison
+#[derive(Debug, Serialize)]
+struct ComparisonExportRow {
+    scenario: String,
+    /// The scenario's kind (e.g. `icount`)
+    kind: &'static str,
+    baseline: f64,
+    candidate: f64,
+    /// The difference between the candidate and the baseline
+    diff: f64,
+    /// The difference, relative to the baseline
+    diff_ratio: f64,
+    /// The significance threshold of the scenario, relative to the baseline
+    threshold: f64,
+}
+
+/// The body of a request for a comparison through the API
+#[derive(Debug, Deserialize)]
+struct ApiCompareBody {
+    /// A commit hash, branch or tag of the repository
+    baseline: String,
+    /// A commit hash, branch or tag of the repository
+    candidate: String,
+}
+
+/// The status of a comparison requested through the API (or of a self-test)
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum ApiComparisonStatus {
+    /// The comparison is queued or running (failed comparisons might go back to pending when they
+    /// are retried)
+    Pending,
+    Completed,
+    Failed,
+}
+
+/// A comparison requested through the API, as returned by the HTTP API
+#[derive(Debug, Serialize)]
+struct ApiComparisonView {
+    id: Uuid,
+    status: ApiComparisonStatus,
+    baseline_ref: String,
+    candidate_ref: String,
+    /// The commit the baseline ref resolved to, once known
+    baseline_commit: Option<String>,
+    /// The commit the candidate ref resolved to, once known
+    candidate_commit: Option<String>,
+    /// The job handling the comparison, once it has started
+    job_url: Option<String>,
+    /// The comparison's verdict, once completed
+    verdict: Option<String>,
+    /// The comparison's results, grouped by metric, once completed
+    result: Option<serde_json::Value>,
+    /// The reason the comparison failed, if it did
+    error: Option<String>,
+}
+
+/// An event that was taken out of the queue, as returned by the admin API
+#[derive(Debug, Serialize)]
+struct DeadEventView {
+    id: Uuid,
+    event: String,
+    #[serde(with = "time::serde::rfc3339")]
+    created_utc: OffsetDateTime,
+    /// The moment at which the event was taken out of the queue
+    #[serde(with = "time::serde::rfc3339")]
+    died_utc: OffsetDateTime,
+    /// The job that was handling the event, if any
+    job_url: Option<String>,
+    /// Why the event was taken out of the queue
+    error: String,
+    payload: serde_json::Value,
+}
+
+/// A self-test, as returned by the admin API
+#[derive(Debug, Serialize)]
+struct SelfTestView {
+    id: Uuid,
+    status: ApiComparisonStatus,
+    /// The job handling the self-test, once it has started
+    job_url: Option<String>,
+    /// How long each stage took, once completed
+    timings: Option<SelfTestTimings>,
+    /// The reason the self-test failed, if it did
+    error: Option<String>,
+}
+
+/// The provenance of a job's results, as returned by the HTTP API
+#[derive(Debug, Serialize)]
+struct TraceView {
+    #[serde(flatten)]
+    trace: JobTrace,
+    /// Where to find the job's status
+    job_url: String,
+    /// Where to find the reported comparison, if any
+    comparison_url: Option<String>,
+    /// The GitHub comment where the results were reported, if any
+    comment_url: Option<String>,
+    /// Files in the job's output directory, relative to it
+    artifacts: Vec<String>,
+}
+
+/// Query parameters to filter regressions
+#[derive(Debug, Deserialize)]
+struct RegressionsQuery {
+    /// Only return regressions in this triage state
+    state: Option<RegressionState>,
+    /// Only return regressions detected on this tracked branch
+    branch: Option<String>,
+}
+
+/// Query parameters to select the branch whose scenario changes are returned
+#[derive(Debug, Deserialize)]
+struct ScenarioChangesQuery {
+    branch: Option<String>,
+}
+
+/// Query parameters to select the results whose scenarios are correlated
+#[derive(Debug, Deserialize)]
+struct ScenarioCorrelationsQuery {
+    branch: Option<String>,
+    /// The metric whose results are correlated (defaults to icount)
+    kind: Option<ScenarioKind>,
+}
+
+/// A scenario's change between two runs of a tracked branch, as returned by the HTTP API
+#[derive(Debug, Serialize)]
+struct ScenarioTimeTravelView {
+    scenario_name: String,
+    /// The run closest to the `from` date
+    from: ScenarioRunResult,
+    /// The run closest to the `to` date
+    to: ScenarioRunResult,
+    /// The measured difference between the `to` and `from` results
+    diff: f64,
+    /// The ratio of change respective to the `from` result
+    diff_ratio: f64,
+    /// Function-level cachegrind diff between both runs, for icount scenarios (if available)
+    cachegrind_diff: Option<String>,
+}
+
+/// The time series of a scenario's results, as returned by the HTTP API
+#[derive(Debug, Serialize)]
+struct ScenarioHistoryView {
+    scenario_name: String,
+    scenario_kind: ScenarioKind,
+    branch: String,
+    #[serde(with = "time::serde::rfc3339")]
+    since: OffsetDateTime,
+    /// The scenario's result in each run since the `since` date, oldest first
+    results: Vec<ScenarioRunResult>,
+    /// The indices of the results that changed significantly compared to the previous one
+    change_points: Vec<usize>,
+    /// The maintainers' notes on windows of the branch's history overlapping the results
+    annotations: Vec<Annotation>,
+}
+
+/// Query parameters to select the runs whose results make up a scenario's history
+#[derive(Debug, Deserialize)]
+struct ScenarioHistoryQuery {
+    /// Only return results of runs after this date (defaults to [`SCENARIO_HISTORY_WINDOW`] ago)
+    since: Option<String>,
+    branch: Option<String>,
+    /// The metric whose results are returned (defaults to icount, or to the icount index for the
+    /// aggregate scenarios)
+    kind: Option<ScenarioKind>,
+}
+
+/// Query parameters to select the dates between which a scenario is compared
+#[derive(Debug, Deserialize)]
+struct ScenarioTimeTravelQuery {
+    from: String,
+    to: String,
+    branch: Option<String>,
+}
+
+/// Query parameters to select a page of jobs
+#[derive(Debug, Deserialize)]
+struct JobsQuery {
+    /// Only return jobs with this status
+    status: Option<JobStatus>,
+    /// The page to return, starting at 1
+    #[serde(default = "default_jobs_page")]
+    page: usize,
+    /// How many jobs to return per page (at most [`MAX_JOBS_PER_PAGE`])
+    #[serde(default = "default_jobs_per_page")]
+    per_page: usize,
+}
+
+fn default_jobs_page() -> usize {
+    1
+}
+
+fn default_jobs_per_page() -> usize {
+    50
+}
+
+/// Query parameters to select how job costs are aggregated
+#[derive(Debug, Deserialize)]
+struct CostsQuery {
+    #[serde(default)]
+    group_by: CostGrouping,
+}
+
+/// The body of a request to change a regression's triage state
+#[derive(Debug, Deserialize)]
+struct RegressionStateRequest {
+    state: RegressionState,
+}
+
+/// The body of a request to annotate a comparison (through its id) or a window of a tracked
+/// branch's history (through the commits of its first and last runs)
+#[derive(Debug, Deserialize)]
+struct AnnotationRequest {
+    note: String,
+    comparison_id: Option<Uuid>,
+    /// The branch whose history is annotated (defaults to main)
+    branch: Option<String>,
+    from_commit: Option<String>,
+    /// The last commit of the window (defaults to `from_commit`)
+    to_commit: Option<String>,
+}
+
+/// The body of requests and responses of the `/admin/log-levels` endpoint
+#[derive(Debug, Serialize, Deserialize)]
+struct LogLevels {
+    level_overrides: String,
 }
 
 /// Migrator for our SQLite database
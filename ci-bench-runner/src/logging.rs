@@ -0,0 +1,150 @@
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Context;
+use serde::Deserialize;
+use tracing::Level;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{reload, Registry};
+
+/// The filter of the global subscriber, which can be changed at runtime (only available after
+/// [`init_logging`] has been called)
+static RELOADABLE_FILTER: OnceLock<ReloadableFilter> = OnceLock::new();
+
+struct ReloadableFilter {
+    handle: reload::Handle<Targets, Registry>,
+    /// The overrides the current filter was created from
+    level_overrides: Mutex<String>,
+}
+
+/// How traces are logged to stdout
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+pub struct LoggingConfig {
+    /// The format of the logs (defaults to human-readable text)
+    #[serde(default)]
+    pub format: LogFormat,
+    /// Per-module level overrides, using the same syntax as `RUST_LOG` (e.g.
+    /// `sqlx=warn,octocrab=info`). Can be changed at runtime through `/admin/log-levels`
+    #[serde(default)]
+    pub level_overrides: String,
+}
+
+/// The format of the logs
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, for consumption by log aggregators
+    Json,
+}
+
+/// Initializes tracing, dumping traces to stdout as well as to Sentry
+pub fn init_logging(config: &LoggingConfig) -> anyhow::Result<()> {
+    let filter = filter(&config.level_overrides).context("invalid log level overrides")?;
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let json = config.format == LogFormat::Json;
+    tracing_subscriber::registry()
+        .with(filter)
+        .with((!json).then(tracing_subscriber::fmt::layer))
+        .with(json.then(|| tracing_subscriber::fmt::layer().json()))
+        .with(sentry_tracing::layer())
+        .try_init()
+        .context("unable to initialize tracing")?;
+
+    RELOADABLE_FILTER
+        .set(ReloadableFilter {
+            handle,
+            level_overrides: Mutex::new(config.level_overrides.clone()),
+        })
+        .map_err(|_| anyhow::anyhow!("logging was already initialized"))?;
+
+    Ok(())
+}
+
+/// Returns the level overrides currently in effect, or `None` if logging was not initialized
+pub fn level_overrides() -> Option<String> {
+    let reloadable = RELOADABLE_FILTER.get()?;
+    let level_overrides = reloadable.level_overrides.lock().unwrap().clone();
+    Some(level_overrides)
+}
+
+/// Replaces the level overrides currently in effect
+pub fn set_level_overrides(level_overrides: &str) -> anyhow::Result<()> {
+    let reloadable = RELOADABLE_FILTER
+        .get()
+        .context("logging was not initialized")?;
+
+    let filter = filter(level_overrides)?;
+    reloadable
+        .handle
+        .reload(filter)
+        .context("unable to reload log filter")?;
+    *reloadable.level_overrides.lock().unwrap() = level_overrides.to_string();
+
+    Ok(())
+}
+
+/// Returns the default filter, with the provided overrides applied on top
+fn filter(level_overrides: &str) -> anyhow::Result<Targets> {
+    let mut filter = Targets::default()
+        .with_target("ci_bench_runner", Level::TRACE)
+        .with_target("sqlx", Level::DEBUG)
+        .with_target("octocrab", Level::DEBUG)
+        .with_default(Level::INFO);
+
+    if level_overrides.trim().is_empty() {
+        return Ok(filter);
+    }
+
+    let overrides: Targets = level_overrides
+        .parse()
+        .with_context(|| format!("invalid level overrides `{level_overrides}`"))?;
+    if let Some(default_level) = overrides.default_level() {
+        filter = filter.with_default(default_level);
+    }
+
+    Ok(filter.with_targets(overrides))
+}
+
+#[cfg(test)]
+mod test {
+    use tracing::level_filters::LevelFilter;
+
+    use super::*;
+
+    #[test]
+    fn filter_without_overrides() {
+        let filter = filter("  ").unwrap();
+        assert!(filter.would_enable("ci_bench_runner::job", &Level::TRACE));
+        assert!(filter.would_enable("sqlx::query", &Level::DEBUG));
+        assert!(!filter.would_enable("hyper", &Level::DEBUG));
+    }
+
+    #[test]
+    fn filter_with_overrides() {
+        let filter = filter("sqlx=warn,octocrab=info,hyper=trace").unwrap();
+        assert!(!filter.would_enable("sqlx::query", &Level::INFO));
+        assert!(filter.would_enable("sqlx::query", &Level::WARN));
+        assert!(!filter.would_enable("octocrab", &Level::DEBUG));
+        assert!(filter.would_enable("hyper", &Level::TRACE));
+
+        // Targets that were not overridden keep their default level
+        assert!(filter.would_enable("ci_bench_runner::job", &Level::TRACE));
+    }
+
+    #[test]
+    fn filter_with_default_override() {
+        let filter = filter("warn,ci_bench_runner=debug").unwrap();
+        assert_eq!(filter.default_level(), Some(LevelFilter::WARN));
+        assert!(!filter.would_enable("hyper", &Level::INFO));
+        assert!(!filter.would_enable("ci_bench_runner", &Level::TRACE));
+        assert!(filter.would_enable("ci_bench_runner", &Level::DEBUG));
+    }
+
+    #[test]
+    fn filter_with_invalid_overrides() {
+        assert!(filter("sqlx=loud").is_err());
+    }
+}
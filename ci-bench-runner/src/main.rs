@@ -1,16 +1,21 @@
+use std::path::PathBuf;
 use std::str::FromStr;
 use std::sync::Arc;
 use std::{env, fs};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 use sentry::types::Dsn;
 use sqlx::{Connection, SqliteConnection};
+use tokio::runtime::Runtime;
 use tokio::sync::Mutex;
-use tracing::Level;
-use tracing_subscriber::filter::Targets;
-use tracing_subscriber::prelude::*;
 
-use ci_bench_runner::{server, AppConfig, LocalBenchRunner};
+use ci_bench_runner::{
+    bootstrap, export, import_bencher, init_logging, server, AppConfig, BenchRunner,
+    GitSourceProvider, LocalBenchRunner, PerfStatBenchRunner,
+};
+
+/// The subcommands supported by the binary (running without a subcommand is equivalent to `serve`)
+static SUBCOMMANDS: &[&str] = &["serve", "bootstrap", "export <path>", "import-bencher"];
 
 fn main() -> anyhow::Result<()> {
     // Load the application's configuration
@@ -20,6 +25,27 @@ fn main() -> anyhow::Result<()> {
         .with_context(|| format!("unable to parse config (CWD = {})", cwd()))?;
     let config = Arc::new(config);
 
+    let subcommand = env::args().nth(1);
+    match subcommand.as_deref() {
+        None | Some("serve") => serve(config),
+        Some("bootstrap") => runtime()?.block_on(bootstrap(&config)),
+        Some("export") => {
+            let export_path = env::args()
+                .nth(2)
+                .map(PathBuf::from)
+                .context("missing path for the exported database")?;
+            runtime()?.block_on(export(&config, &export_path))
+        }
+        Some("import-bencher") => runtime()?.block_on(import_bencher(&config)),
+        Some(subcommand) => bail!(
+            "unknown subcommand `{subcommand}` (available subcommands: {})",
+            SUBCOMMANDS.join(", ")
+        ),
+    }
+}
+
+/// Runs the HTTP server until it crashes
+fn serve(config: Arc<AppConfig>) -> anyhow::Result<()> {
     // Initialize Sentry. Their [docs](https://docs.sentry.io/platforms/rust/) say we should run
     // this before the async runtime is initialized
     let _guard = sentry::init(sentry::ClientOptions {
@@ -28,29 +54,29 @@ fn main() -> anyhow::Result<()> {
         ..sentry::ClientOptions::default()
     });
 
-    // Initialize tracing, dumping traces to stdout as well as to Sentry
-    tracing_subscriber::registry()
-        .with(tracing_subscriber::fmt::layer().with_filter(filter()))
-        .with(sentry_tracing::layer().with_filter(filter()))
-        .init();
+    init_logging(&config.logging)?;
 
-    // We use a single-threaded runtime, since we expect a low volume of requests and we want the
-    // application to run with as low overhead as possible
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()?;
-
-    rt.block_on(async {
+    runtime()?.block_on(async {
         let sqlite = SqliteConnection::connect(&format!("sqlite:{}", config.path_to_db)).await?;
 
         // Initialize the server
-        let (server, _) = server(
-            config,
-            Arc::new(LocalBenchRunner),
-            Arc::new(Mutex::new(sqlite)),
-        )
-        .await
-        .context("unable to initialize server")?;
+        let source = Arc::new(GitSourceProvider::new(config.source_mirrors.clone()));
+        let bench_runner: Arc<dyn BenchRunner> = match &config.perf_stat {
+            Some(perf_stat) => Arc::new(PerfStatBenchRunner::new(
+                perf_stat.clone(),
+                source,
+                config.host_idle_check.clone(),
+                config.determinism_check.clone(),
+            )),
+            None => Arc::new(LocalBenchRunner::new(
+                source,
+                config.host_idle_check.clone(),
+                config.determinism_check.clone(),
+            )),
+        };
+        let (server, _) = server(config, bench_runner, Arc::new(Mutex::new(sqlite)))
+            .await
+            .context("unable to initialize server")?;
 
         // Listen
         server.await.context("server crashed")?;
@@ -59,12 +85,14 @@ fn main() -> anyhow::Result<()> {
     })
 }
 
-fn filter() -> Targets {
-    Targets::default()
-        .with_target("ci_bench_runner", Level::TRACE)
-        .with_target("sqlx", Level::DEBUG)
-        .with_target("octocrab", Level::DEBUG)
-        .with_default(Level::INFO)
+/// Creates the async runtime.
+///
+/// We use a single-threaded runtime, since we expect a low volume of requests and we want the
+/// application to run with as low overhead as possible
+fn runtime() -> anyhow::Result<Runtime> {
+    Ok(tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?)
 }
 
 fn cwd() -> String {
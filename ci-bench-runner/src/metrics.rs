@@ -0,0 +1,75 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use serde::Serialize;
+
+/// Counters about the application's operation, reset on restart
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Webhook events dropped because their type is not handled, by type
+    dropped_webhook_events: Mutex<BTreeMap<String, u64>>,
+    /// Webhook events dropped because they came from a repository we don't serve, by repository
+    foreign_webhook_events: Mutex<BTreeMap<String, u64>>,
+    /// The longest time an event waited in the queue before being handled, in seconds, by event
+    /// kind
+    max_queue_wait_seconds: Mutex<BTreeMap<String, u64>>,
+    /// Events that were handled thanks to the priority boost they got by waiting, by event kind
+    aged_events_handled: Mutex<BTreeMap<String, u64>>,
+}
+
+impl Metrics {
+    /// Counts a webhook event that was dropped because its type is not handled
+    pub fn record_dropped_webhook_event(&self, event: &str) {
+        *self
+            .dropped_webhook_events
+            .lock()
+            .unwrap()
+            .entry(event.to_string())
+            .or_default() += 1;
+    }
+
+    /// Counts a webhook event that was dropped because it came from a repository we don't serve
+    pub fn record_foreign_webhook_event(&self, repository: &str) {
+        *self
+            .foreign_webhook_events
+            .lock()
+            .unwrap()
+            .entry(repository.to_string())
+            .or_default() += 1;
+    }
+
+    /// Records how long an event waited in the queue, and whether its priority was boosted
+    pub fn record_queue_wait(&self, event: &str, wait_seconds: u64, aged: bool) {
+        let mut max_wait = self.max_queue_wait_seconds.lock().unwrap();
+        let max_wait = max_wait.entry(event.to_string()).or_default();
+        *max_wait = (*max_wait).max(wait_seconds);
+
+        if aged {
+            *self
+                .aged_events_handled
+                .lock()
+                .unwrap()
+                .entry(event.to_string())
+                .or_default() += 1;
+        }
+    }
+
+    /// Returns the current value of the counters
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            dropped_webhook_events: self.dropped_webhook_events.lock().unwrap().clone(),
+            foreign_webhook_events: self.foreign_webhook_events.lock().unwrap().clone(),
+            max_queue_wait_seconds: self.max_queue_wait_seconds.lock().unwrap().clone(),
+            aged_events_handled: self.aged_events_handled.lock().unwrap().clone(),
+        }
+    }
+}
+
+/// The value of the [`Metrics`] at a given moment
+#[derive(Debug, Serialize)]
+pub struct MetricsSnapshot {
+    pub dropped_webhook_events: BTreeMap<String, u64>,
+    pub foreign_webhook_events: BTreeMap<String, u64>,
+    pub max_queue_wait_seconds: BTreeMap<String, u64>,
+    pub aged_events_handled: BTreeMap<String, u64>,
+}
@@ -0,0 +1,624 @@
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, Time, Weekday};
+use tracing::{error, info, trace};
+use uuid::Uuid;
+
+use crate::correlations::{correlated_groups, CorrelatedGroup, CORRELATION_WINDOW};
+use crate::db::{Annotation, DailyJobOutcomes, Db, ScenarioDiff, ScenarioKind, ScenarioSetChange};
+use crate::job::{GEOMETRIC_MEAN_SCENARIO, MAIN_BRANCH};
+use crate::AppConfig;
+
+/// The day of the week on which the weekly digest is sent
+const DIGEST_WEEKDAY: Weekday = Weekday::Monday;
+
+/// The hour (in UTC) at which the weekly digest is sent
+const DIGEST_HOUR_UTC: u8 = 9;
+
+/// How far back to look for scenarios that were added to or removed from a tracked branch
+pub const SCENARIO_CHANGES_WINDOW: time::Duration = time::Duration::days(30);
+
+/// How often the job success rate is checked against its SLO
+const JOB_SUCCESS_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Where notifications are sent (no notifications are sent if no sinks are configured)
+#[derive(Debug, Clone, Default, Eq, PartialEq, Deserialize)]
+pub struct NotificationsConfig {
+    /// Posts notifications to a Zulip stream
+    pub zulip: Option<ZulipConfig>,
+    /// Raises an alert when too many jobs fail (not tracked if missing)
+    pub job_success_slo: Option<JobSuccessSloConfig>,
+}
+
+/// The objective for the share of benchmark jobs that succeed
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct JobSuccessSloConfig {
+    /// The minimum percentage of finished jobs that must succeed (e.g. 90)
+    pub min_success_percent: u32,
+    /// The number of days over which the success rate is computed (defaults to 7)
+    #[serde(default = "default_slo_window_days")]
+    pub window_days: u32,
+}
+
+fn default_slo_window_days() -> u32 {
+    7
+}
+
+/// Zulip's configuration
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ZulipConfig {
+    /// Base URL of the Zulip server (e.g. `https://rustls.zulipchat.com`)
+    pub site: String,
+    /// Email address of the bot that posts the messages
+    pub bot_email: String,
+    /// API key of the bot that posts the messages
+    pub api_key: String,
+    /// Stream to which messages are posted
+    pub stream: String,
+    /// Topic for regression alerts about main and the other tracked branches
+    pub regressions_topic: String,
+    /// Topic for weekly digests
+    pub digest_topic: String,
+    /// Topic for alerts about the health of the infrastructure (defaults to the regressions topic)
+    #[serde(default)]
+    pub infra_topic: Option<String>,
+}
+
+/// The owners of a category of scenarios, who get mentioned when the category regresses on main
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ScenarioOwnersConfig {
+    /// The prefix shared by the names of the category's scenarios (e.g. `handshake_`)
+    pub scenario_prefix: String,
+    /// GitHub usernames or teams (e.g. `octocat` or `rustls/maintainers`)
+    pub owners: Vec<String>,
+}
+
+/// Returns the owners of the provided scenarios, sorted and without duplicates
+pub fn scenario_owners<'a>(
+    config: &[ScenarioOwnersConfig],
+    scenarios: impl Iterator<Item = &'a str>,
+) -> Vec<String> {
+    let mut owners = Vec::new();
+    for scenario in scenarios {
+        for category in config {
+            if scenario.starts_with(&category.scenario_prefix) {
+                owners.extend(category.owners.iter().cloned());
+            }
+        }
+    }
+
+    owners.sort();
+    owners.dedup();
+    owners
+}
+
+/// Something worth telling the project about
+#[derive(Debug)]
+pub enum Notification {
+    /// A push to a tracked branch made some scenarios significantly slower than the previous run of
+    /// the branch
+    Regression {
+        /// The id under which the regression is tracked
+        regression_id: Uuid,
+        /// The tracked branch (main, or e.g. a release branch)
+        branch: String,
+        commit_sha: String,
+        previous_commit_sha: String,
+        regressions: Vec<ScenarioDiff>,
+        /// The owners of the regressed scenarios
+        owners: Vec<String>,
+        /// The merged PRs that introduced the regression, if known
+        pr_numbers: Vec<u64>,
+    },
+    /// A summary of the past week's benchmarking activity
+    WeeklyDigest(WeeklyDigest),
+    /// The share of jobs that succeeded dropped below its SLO
+    JobSuccessSloBreach(JobSuccessRate),
+}
+
+impl Notification {
+    /// Renders the notification as markdown, which is understood by all sinks
+    fn to_markdown(&self, config: &AppConfig) -> String {
+        let commit_link = |sha: &str| {
+            format!(
+                "[`{}`](https://github.com/{}/{}/commit/{sha})",
+                &sha[..sha.len().min(7)],
+                config.github_repo_owner,
+                config.github_repo_name
+            )
+        };
+
+        let mut s = String::new();
+        match self {
+            Notification::Regression {
+                regression_id,
+                branch,
+                commit_sha,
+                previous_commit_sha,
+                regressions,
+                owners,
+                pr_numbers,
+            } => {
+                writeln!(
+                    s,
+                    ":warning: {} regressed {} scenario(s) on `{branch}` compared to {}",
+                    commit_link(commit_sha),
+                    regressions.len(),
+                    commit_link(previous_commit_sha)
+                )
+                .ok();
+                writeln!(s).ok();
+                writeln!(
+                    s,
+                    "| Scenario | Kind | Previous | Current | Change | Threshold |"
+                )
+                .ok();
+                writeln!(s, "| --- | --- | ---: | ---: | ---: | ---: |").ok();
+                for diff in regressions {
+                    writeln!(
+                        s,
+                        "| {} | {} | {:.0} | {:.0} | {:+.2}% | {:.2}% |",
+                        diff.scenario_name,
+                        diff.scenario_kind.as_str(),
+                        diff.baseline_result,
+                        diff.candidate_result,
+                        diff.diff_ratio() * 100.0,
+                        diff.significance_threshold * 100.0
+                    )
+                    .ok();
+                }
+                if !pr_numbers.is_empty() {
+                    let links = pr_numbers
+                        .iter()
+                        .map(|pr| {
+                            format!(
+                                "[#{pr}](https://github.com/{}/{}/pull/{pr})",
+                                config.github_repo_owner, config.github_repo_name
+                            )
+                        })
+                        .collect::<Vec<_>>();
+                    writeln!(s).ok();
+                    writeln!(s, "Introduced by {}", links.join(", ")).ok();
+                }
+                writeln!(s).ok();
+                writeln!(
+                    s,
+                    "Triage it at {}/regressions/dashboard#{regression_id}",
+                    config.app_base_url
+                )
+                .ok();
+                if !owners.is_empty() {
+                    let mentions = owners
+                        .iter()
+                        .map(|owner| format!("@{owner}"))
+                        .collect::<Vec<_>>();
+                    writeln!(s).ok();
+                    writeln!(s, "cc {}", mentions.join(" ")).ok();
+                }
+            }
+            Notification::WeeklyDigest(digest) => {
+                writeln!(s, "**Benchmarking digest for the past week**").ok();
+                writeln!(s).ok();
+                writeln!(s, "- Runs of `main`: {}", digest.main_runs).ok();
+                if let Some(change) = digest.index_change() {
+                    writeln!(
+                        s,
+                        "- Instruction count index change: {:+.2}%",
+                        change * 100.0
+                    )
+                    .ok();
+                }
+                let comparisons = digest
+                    .comparisons
+                    .iter()
+                    .map(|(verdict, count)| format!("{count} {verdict}"))
+                    .collect::<Vec<_>>();
+                if comparisons.is_empty() {
+                    writeln!(s, "- PR comparisons: none").ok();
+                } else {
+                    writeln!(s, "- PR comparisons: {}", comparisons.join(", ")).ok();
+                }
+                writeln!(s, "- Failed jobs: {}", digest.failed_jobs).ok();
+                let changes_header = format!(
+                    "- Scenarios added or removed on `{MAIN_BRANCH}` in the past {} days",
+                    SCENARIO_CHANGES_WINDOW.whole_days()
+                );
+                if digest.scenario_changes.is_empty() {
+                    writeln!(s, "{changes_header}: none").ok();
+                } else {
+                    writeln!(s, "{changes_header}:").ok();
+                    for change in &digest.scenario_changes {
+                        writeln!(
+                            s,
+                            "  - {} `{}` ({}) in {}",
+                            if change.added { "added" } else { "removed" },
+                            change.scenario_name,
+                            change.scenario_kind.as_str(),
+                            commit_link(&change.commit_sha)
+                        )
+                        .ok();
+                    }
+                }
+                if !digest.correlated_groups.is_empty() {
+                    writeln!(
+                        s,
+                        "- Scenarios moving together on `{MAIN_BRANCH}` in the past {} days (shared \
+                        noise sources or redundant scenarios?):",
+                        CORRELATION_WINDOW.whole_days()
+                    )
+                    .ok();
+                    for group in &digest.correlated_groups {
+                        let scenarios = group
+                            .scenarios
+                            .iter()
+                            .map(|scenario| format!("`{scenario}`"))
+                            .collect::<Vec<_>>();
+                        writeln!(
+                            s,
+                            "  - {} (correlation of at least {:.2})",
+                            scenarios.join(", "),
+                            group.min_correlation
+                        )
+                        .ok();
+                    }
+                }
+                for annotation in &digest.annotations {
+                    let Some(window) = &annotation.window else {
+                        continue;
+                    };
+                    let commits = if window.from_commit == window.to_commit {
+                        commit_link(&window.from_commit)
+                    } else {
+                        format!(
+                            "{}..{}",
+                            commit_link(&window.from_commit),
+                            commit_link(&window.to_commit)
+                        )
+                    };
+                    writeln!(s, "- Note on {commits}: {}", annotation.note).ok();
+                }
+            }
+            Notification::JobSuccessSloBreach(rate) => {
+                writeln!(
+                    s,
+                    ":rotating_light: Only {:.1}% of the benchmark jobs of the past {} days succeeded, \
+                    below the SLO of {}% ({} of {} jobs failed)",
+                    rate.success_percent.unwrap_or_default(),
+                    rate.window_days,
+                    rate.target_percent,
+                    rate.failed,
+                    rate.succeeded + rate.failed
+                )
+                .ok();
+                writeln!(s).ok();
+                writeln!(
+                    s,
+                    "The failed jobs are listed at {}/jobs?status=Failure",
+                    config.app_base_url
+                )
+                .ok();
+            }
+        }
+
+        s
+    }
+}
+
+/// The share of jobs that succeeded over a rolling window, compared to its SLO
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct JobSuccessRate {
+    /// The minimum percentage of jobs that must succeed
+    pub target_percent: u32,
+    pub window_days: u32,
+    /// The percentage of jobs that succeeded (missing if no jobs finished in the window)
+    pub success_percent: Option<f64>,
+    pub succeeded: i64,
+    pub failed: i64,
+    /// The outcomes of each day of the window, oldest first, to show the trend
+    pub daily: Vec<DailyJobOutcomes>,
+}
+
+impl JobSuccessRate {
+    fn new(config: &JobSuccessSloConfig, daily: Vec<DailyJobOutcomes>) -> Self {
+        let succeeded = daily.iter().map(|d| d.succeeded).sum();
+        let failed = daily.iter().map(|d| d.failed).sum();
+        let total = succeeded + failed;
+        Self {
+            target_percent: config.min_success_percent,
+            window_days: config.window_days,
+            success_percent: (total > 0).then(|| succeeded as f64 / total as f64 * 100.0),
+            succeeded,
+            failed,
+            daily,
+        }
+    }
+
+    /// Returns true if fewer jobs succeeded than required by the SLO
+    pub fn is_breached(&self) -> bool {
+        self.success_percent
+            .is_some_and(|percent| percent < self.target_percent as f64)
+    }
+}
+
+/// Computes the job success rate over the SLO's window before `now`
+pub async fn job_success_rate(
+    db: &Db,
+    config: &JobSuccessSloConfig,
+    now: OffsetDateTime,
+) -> anyhow::Result<JobSuccessRate> {
+    let since = now - time::Duration::days(config.window_days.into());
+    let daily = db.job_outcomes_by_day(since).await?;
+    Ok(JobSuccessRate::new(config, daily))
+}
+
+/// A summary of the benchmarking activity since a point in time
+#[derive(Debug)]
+pub struct WeeklyDigest {
+    /// How many times main was benchmarked
+    pub main_runs: i64,
+    /// The instruction count index of the first and last runs of main, if any
+    pub index_range: Option<(f64, f64)>,
+    /// The number of PR comparisons, by verdict
+    pub comparisons: Vec<(String, i64)>,
+    /// How many jobs failed
+    pub failed_jobs: i64,
+    /// The scenarios added to or removed from main in the past month, to notice accidental
+    /// deletions that silently shrink coverage
+    pub scenario_changes: Vec<ScenarioSetChange>,
+    /// The annotations of windows of main's history overlapping the past week, explaining changes
+    /// with a known upstream cause
+    pub annotations: Vec<Annotation>,
+    /// The groups of scenarios whose instruction counts moved together on main in the past three
+    /// months, to guide the curation of the bench suite
+    pub correlated_groups: Vec<CorrelatedGroup>,
+}
+
+impl WeeklyDigest {
+    /// Returns the ratio of change of the instruction count index, if it was tracked
+    fn index_change(&self) -> Option<f64> {
+        let (first, last) = self.index_range?;
+        Some((last - first) / first)
+    }
+}
+
+/// Sends notifications to the configured sinks
+#[derive(Clone)]
+pub struct Notifier {
+    config: Arc<AppConfig>,
+    client: reqwest::Client,
+}
+
+impl Notifier {
+    pub fn new(config: Arc<AppConfig>) -> Self {
+        Self {
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends the notification to all sinks
+    ///
+    /// Notifications are best-effort, so failures are logged instead of returned
+    pub async fn notify(&self, notification: &Notification) {
+        let Some(zulip) = &self.config.notifications.zulip else {
+            trace!("no notification sinks configured, skipping notification");
+            return;
+        };
+
+        if let Err(e) = self.post_to_zulip(zulip, notification).await {
+            error!(
+                cause = format!("{e:#}"),
+                "unable to post notification to Zulip"
+            );
+        }
+    }
+
+    async fn post_to_zulip(
+        &self,
+        zulip: &ZulipConfig,
+        notification: &Notification,
+    ) -> anyhow::Result<()> {
+        let topic = match notification {
+            Notification::Regression { .. } => &zulip.regressions_topic,
+            Notification::WeeklyDigest(_) => &zulip.digest_topic,
+            Notification::JobSuccessSloBreach(_) => zulip
+                .infra_topic
+                .as_ref()
+                .unwrap_or(&zulip.regressions_topic),
+        };
+        let content = notification.to_markdown(&self.config);
+
+        self.client
+            .post(format!(
+                "{}/api/v1/messages",
+                zulip.site.trim_end_matches('/')
+            ))
+            .basic_auth(&zulip.bot_email, Some(&zulip.api_key))
+            .form(&[
+                ("type", "stream"),
+                ("to", zulip.stream.as_str()),
+                ("topic", topic.as_str()),
+                ("content", content.as_str()),
+            ])
+            .send()
+            .await
+            .context("failed to send request")?
+            .error_for_status()
+            .context("the Zulip API returned an error")?;
+
+        Ok(())
+    }
+
+    /// Spawns a background task that sends a digest of the past week's activity once a week
+    pub fn spawn_weekly_digest(&self, db: Db) {
+        if self.config.notifications.zulip.is_none() {
+            return;
+        }
+
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = OffsetDateTime::now_utc();
+                let next_digest = next_digest_at(now);
+                info!("next weekly digest scheduled for {next_digest}");
+                let wait = Duration::try_from(next_digest - now).unwrap_or_default();
+                tokio::time::sleep(wait).await;
+
+                match weekly_digest(&db, next_digest).await {
+                    Ok(digest) => notifier.notify(&Notification::WeeklyDigest(digest)).await,
+                    Err(e) => error!(cause = format!("{e:#}"), "unable to build weekly digest"),
+                }
+            }
+        });
+    }
+
+    /// Spawns a background task that periodically checks the job success rate against its SLO,
+    /// raising an alert when it is first breached
+    pub fn spawn_job_success_monitor(&self, db: Db) {
+        let Some(slo) = self.config.notifications.job_success_slo.clone() else {
+            return;
+        };
+        if self.config.notifications.zulip.is_none() {
+            return;
+        }
+
+        let notifier = self.clone();
+        tokio::spawn(async move {
+            // Alerts are only raised once per breach, instead of on every check
+            let mut breached = false;
+            let mut interval = tokio::time::interval(JOB_SUCCESS_CHECK_INTERVAL);
+            loop {
+                interval.tick().await;
+
+                let rate = match job_success_rate(&db, &slo, OffsetDateTime::now_utc()).await {
+                    Ok(rate) => rate,
+                    Err(e) => {
+                        error!(
+                            cause = format!("{e:#}"),
+                            "unable to compute job success rate"
+                        );
+                        continue;
+                    }
+                };
+
+                if rate.is_breached() && !breached {
+                    notifier
+                        .notify(&Notification::JobSuccessSloBreach(rate))
+                        .await;
+                    breached = true;
+                } else if !rate.is_breached() && breached {
+                    info!("job success rate is back within its SLO");
+                    breached = false;
+                }
+            }
+        });
+    }
+}
+
+/// Builds the digest of the week before `now`
+async fn weekly_digest(db: &Db, now: OffsetDateTime) -> anyhow::Result<WeeklyDigest> {
+    let since = now - time::Duration::weeks(1);
+    let mut digest = db
+        .digest(since, MAIN_BRANCH, GEOMETRIC_MEAN_SCENARIO)
+        .await?;
+    digest.scenario_changes = db
+        .scenario_set_changes(MAIN_BRANCH, now - SCENARIO_CHANGES_WINDOW)
+        .await?;
+    digest.annotations = db.branch_annotations(MAIN_BRANCH, since).await?;
+    let runs = db
+        .results_by_run(MAIN_BRANCH, ScenarioKind::Icount, now - CORRELATION_WINDOW)
+        .await?;
+    digest.correlated_groups = correlated_groups(&runs);
+
+    Ok(digest)
+}
+
+/// Returns the next time at which the weekly digest should be sent
+fn next_digest_at(now: OffsetDateTime) -> OffsetDateTime {
+    let digest_time = Time::from_hms(DIGEST_HOUR_UTC, 0, 0).expect("unreachable code");
+    let mut next = now.replace_time(digest_time);
+    while next.weekday() != DIGEST_WEEKDAY || next <= now {
+        next += time::Duration::days(1);
+    }
+
+    next
+}
+
+#[cfg(test)]
+mod test {
+    use time::{Date, Month};
+
+    use super::*;
+
+    fn datetime(day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        Date::from_calendar_date(2023, Month::November, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    #[test]
+    fn scenario_owners_are_matched_by_prefix() {
+        let config = vec![
+            ScenarioOwnersConfig {
+                scenario_prefix: "handshake_".to_string(),
+                owners: vec!["rustls/handshake".to_string(), "alice".to_string()],
+            },
+            ScenarioOwnersConfig {
+                scenario_prefix: "handshake_resume".to_string(),
+                owners: vec!["alice".to_string(), "bob".to_string()],
+            },
+        ];
+
+        let owners = scenario_owners(&config, ["handshake_resume_tls13"].into_iter());
+        assert_eq!(owners, vec!["alice", "bob", "rustls/handshake"]);
+        let owners = scenario_owners(&config, ["transfer_no_resume"].into_iter());
+        assert!(owners.is_empty());
+    }
+
+    #[test]
+    fn job_success_rate_is_computed_over_all_days() {
+        let config = JobSuccessSloConfig {
+            min_success_percent: 90,
+            window_days: 7,
+        };
+        let day = |day: &str, succeeded, failed| DailyJobOutcomes {
+            day: day.to_string(),
+            succeeded,
+            failed,
+        };
+
+        let rate = JobSuccessRate::new(&config, Vec::new());
+        assert_eq!(rate.success_percent, None);
+        assert!(!rate.is_breached());
+
+        let rate = JobSuccessRate::new(
+            &config,
+            vec![day("2023-11-14", 10, 0), day("2023-11-15", 8, 2)],
+        );
+        assert_eq!(rate.success_percent, Some(90.0));
+        assert!(!rate.is_breached());
+
+        let rate = JobSuccessRate::new(
+            &config,
+            vec![day("2023-11-14", 10, 0), day("2023-11-15", 7, 3)],
+        );
+        assert_eq!(rate.success_percent, Some(85.0));
+        assert!(rate.is_breached());
+    }
+
+    #[test]
+    fn next_digest_is_next_monday_morning() {
+        // A Wednesday
+        assert_eq!(next_digest_at(datetime(15, 12, 0)), datetime(20, 9, 0));
+
+        // A Monday, before and after the digest was sent
+        assert_eq!(next_digest_at(datetime(20, 8, 59)), datetime(20, 9, 0));
+        assert_eq!(next_digest_at(datetime(20, 9, 0)), datetime(27, 9, 0));
+    }
+}
@@ -0,0 +1,462 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use tracing::trace;
+
+use crate::runner::{
+    build_artifacts, build_benchmarks, checkout, run_command, run_max_rss_benchmarks,
+    run_walltime_benchmarks, write_reproduction, BenchRunner, BenchSuiteConfig, CancellationToken,
+    DeterminismCheckConfig, FailureClass, HostIdleConfig, Log, SourceProvider,
+};
+use crate::CommitIdentifier;
+
+/// The hardware events counted for each scenario
+static PERF_EVENTS: &str = "cycles,instructions,branch-misses";
+
+/// The name of the CSV file where the counters of all scenarios are stored, in the results dir
+static PERF_STAT_FILE: &str = "perf_stat.csv";
+
+/// The directory where the raw `perf stat` output of each scenario is stored, in the results dir
+static PERF_STAT_DIR: &str = "perf_stat";
+
+/// How to run the benchmarks under `perf stat`, for hosts where valgrind is too slow
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct PerfStatConfig {
+    /// Arguments passed to the benchmark executable to list the scenarios, which are expected to
+    /// be printed to stdout (one per line)
+    pub list_scenarios_args: Vec<String>,
+    /// Arguments passed to the benchmark executable to run a single scenario. The `{scenario}`
+    /// placeholder is replaced by the scenario's name
+    pub scenario_args: Vec<String>,
+    /// How many times each scenario is run, its counters being averaged (defaults to 5)
+    #[serde(default = "default_repetitions")]
+    pub repetitions: u32,
+}
+
+fn default_repetitions() -> u32 {
+    5
+}
+
+/// The hardware counters of a scenario, averaged over its repetitions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PerfCounters {
+    pub cycles: f64,
+    pub instructions: f64,
+    pub branch_misses: f64,
+}
+
+/// A bench runner that measures hardware counters with `perf stat`, instead of running the icount
+/// benchmarks under valgrind (the rest of the bench suite runs as usual)
+///
+/// Hardware counters are much cheaper to obtain than simulated ones, but they are also noisier
+#[derive(Debug)]
+pub struct PerfStatBenchRunner {
+    config: PerfStatConfig,
+    /// Where the commits to benchmark are fetched from
+    source: Arc<dyn SourceProvider>,
+    /// When set, measurements are delayed until the host is idle
+    idle_check: Option<HostIdleConfig>,
+    /// When set, the stability of the environment is verified before measuring
+    determinism_check: Option<DeterminismCheckConfig>,
+}
+
+impl PerfStatBenchRunner {
+    pub fn new(
+        config: PerfStatConfig,
+        source: Arc<dyn SourceProvider>,
+        idle_check: Option<HostIdleConfig>,
+        determinism_check: Option<DeterminismCheckConfig>,
+    ) -> Self {
+        Self {
+            config,
+            source,
+            idle_check,
+            determinism_check,
+        }
+    }
+
+    /// Waits until the host is idle, as defined in the configuration (if any)
+    fn wait_for_idle_host(&self, cancellation: &CancellationToken) {
+        if let Some(config) = &self.idle_check {
+            config.wait_for_idle_host(cancellation);
+        }
+    }
+
+    /// Runs the determinism check (if configured), counting the instructions of its scenario with
+    /// `perf stat`
+    fn check_determinism(
+        &self,
+        bench_suite: &BenchSuiteConfig,
+        bench_exe_path: &Path,
+        bench_path: &Path,
+        job_output_dir: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let Some(config) = &self.determinism_check else {
+            return Ok(());
+        };
+
+        config.run(job_output_dir, |run_dir| {
+            let counters = self.count_scenario(
+                bench_suite,
+                bench_exe_path,
+                bench_path,
+                &config.scenario,
+                &run_dir.join(&config.scenario),
+                command_logs,
+                cancellation,
+            )?;
+            Ok(Some(counters.instructions))
+        })
+    }
+
+    /// Runs the benchmark executable to obtain the names of its scenarios
+    fn list_scenarios(
+        &self,
+        bench_suite: &BenchSuiteConfig,
+        bench_exe_path: &Path,
+        bench_path: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<Vec<String>> {
+        let mut command = bench_suite.command(bench_exe_path);
+        command
+            .args(&self.config.list_scenarios_args)
+            .current_dir(bench_path);
+
+        run_command(
+            command,
+            command_logs,
+            FailureClass::HarnessCrash,
+            cancellation,
+        )?;
+
+        let stdout = String::from_utf8_lossy(&command_logs.last().unwrap().stdout);
+        Ok(stdout
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Runs the scenario under `perf stat`, storing its raw output at the provided path, and
+    /// returns its counters
+    #[allow(clippy::too_many_arguments)]
+    fn count_scenario(
+        &self,
+        bench_suite: &BenchSuiteConfig,
+        bench_exe_path: &Path,
+        bench_path: &Path,
+        scenario: &str,
+        raw_output_path: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<PerfCounters> {
+        let mut command = bench_suite.command("perf");
+        command
+            .args(["stat", "-x", ",", "-e", PERF_EVENTS, "-r"])
+            .arg(self.config.repetitions.to_string())
+            .arg("-o")
+            .arg(raw_output_path)
+            .arg("--")
+            .arg(bench_exe_path)
+            .args(
+                self.config
+                    .scenario_args
+                    .iter()
+                    .map(|arg| arg.replace("{scenario}", scenario)),
+            )
+            .current_dir(bench_path);
+
+        run_command(
+            command,
+            command_logs,
+            FailureClass::HarnessCrash,
+            cancellation,
+        )?;
+
+        let raw_output =
+            fs::read_to_string(raw_output_path).context("unable to read perf stat output")?;
+        parse_perf_stat_output(&raw_output)
+            .with_context(|| format!("invalid perf stat output for `{scenario}`"))
+            .map_err(|e| e.context(FailureClass::HarnessCrash))
+    }
+}
+
+impl BenchRunner for PerfStatBenchRunner {
+    fn checkout_and_run_benchmarks(
+        &self,
+        commit: &CommitIdentifier,
+        checkout_target_dir: &Path,
+        job_output_dir: &Path,
+        bench_suite: &BenchSuiteConfig,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        // The commands of this run, from which the reproduction steps are derived
+        let first_log = command_logs.len();
+
+        checkout(
+            commit,
+            checkout_target_dir,
+            bench_suite,
+            self.source.as_ref(),
+            command_logs,
+            cancellation,
+        )?;
+        build_benchmarks(
+            checkout_target_dir,
+            job_output_dir,
+            bench_suite,
+            command_logs,
+            cancellation,
+        )?;
+
+        let bench_path = checkout_target_dir.join(&bench_suite.bench_dir);
+        let bench_exe_path = checkout_target_dir.join(&bench_suite.bench_executable);
+
+        // The counters take the place of the icount benchmarks, so they are skipped when those
+        // would be (e.g. in walltime mode)
+        if bench_suite.icount_args.is_empty() {
+            trace!("skipping perf stat benchmarks");
+        } else {
+            self.wait_for_idle_host(cancellation);
+            self.check_determinism(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            trace!("running perf stat benchmarks");
+            let start = Instant::now();
+
+            let scenarios = self.list_scenarios(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                command_logs,
+                cancellation,
+            )?;
+            if scenarios.is_empty() {
+                return Err(anyhow!("the benchmark executable listed no scenarios")
+                    .context(FailureClass::HarnessCrash));
+            }
+
+            let raw_output_dir = bench_suite.results_path(job_output_dir).join(PERF_STAT_DIR);
+            fs::create_dir_all(&raw_output_dir).context("Unable to create dir for results")?;
+
+            let mut csv = String::new();
+            for scenario in scenarios {
+                let counters = self.count_scenario(
+                    bench_suite,
+                    &bench_exe_path,
+                    &bench_path,
+                    &scenario,
+                    &raw_output_dir.join(&scenario),
+                    command_logs,
+                    cancellation,
+                )?;
+                writeln!(
+                    csv,
+                    "{scenario},{},{},{}",
+                    counters.cycles, counters.instructions, counters.branch_misses
+                )
+                .expect("unreachable code");
+            }
+
+            fs::write(perf_stat_path(bench_suite, job_output_dir), csv)
+                .context("failed to write perf stat results to disk")?;
+
+            trace!(
+                "perf stat benchmarks run in {:.2} s",
+                (Instant::now() - start).as_secs_f64()
+            );
+        }
+
+        // The other benchmarks don't need valgrind, so they run as usual
+        if !bench_suite.max_rss_args.is_empty() {
+            trace!("running memory usage benchmarks");
+            run_max_rss_benchmarks(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+        }
+        if !bench_suite.walltime_args.is_empty() {
+            self.wait_for_idle_host(cancellation);
+            trace!("running walltime benchmarks");
+            run_walltime_benchmarks(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+        }
+        build_artifacts(
+            checkout_target_dir,
+            job_output_dir,
+            bench_suite,
+            command_logs,
+            cancellation,
+        )?;
+
+        write_reproduction(
+            &command_logs[first_log..],
+            checkout_target_dir,
+            job_output_dir,
+            &bench_path,
+        )
+    }
+}
+
+/// Returns the path to the hardware counters of a job's output
+pub fn perf_stat_path(bench_suite: &BenchSuiteConfig, job_output_dir: &Path) -> PathBuf {
+    bench_suite
+        .results_path(job_output_dir)
+        .join(PERF_STAT_FILE)
+}
+
+/// Parses the counters from the CSV output of `perf stat -x ,`
+fn parse_perf_stat_output(output: &str) -> anyhow::Result<PerfCounters> {
+    let mut counters = HashMap::new();
+    for line in output.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Fields: value, unit, event (possibly with modifiers, e.g. `cycles:u`), and then some
+        // statistics we don't need
+        let fields: Vec<_> = line.split(',').collect();
+        let (Some(value), Some(event)) = (fields.first(), fields.get(2)) else {
+            bail!("unexpected line: {line}");
+        };
+        let event = event.split(':').next().unwrap_or_default();
+        if value.starts_with('<') {
+            bail!("`{event}` could not be measured ({value})");
+        }
+
+        let value: f64 = value
+            .parse()
+            .with_context(|| format!("invalid value for `{event}`: {value}"))?;
+        counters.insert(event.to_string(), value);
+    }
+
+    let counter = |event: &str| {
+        counters
+            .get(event)
+            .copied()
+            .with_context(|| format!("missing `{event}` counter"))
+    };
+    Ok(PerfCounters {
+        cycles: counter("cycles")?,
+        instructions: counter("instructions")?,
+        branch_misses: counter("branch-misses")?,
+    })
+}
+
+/// Reads the hardware counters of the scenarios, stored by [`PerfStatBenchRunner`]
+///
+/// Returns an empty map if the benchmarks didn't run under `perf stat`
+pub fn read_perf_stat_results(path: &Path) -> anyhow::Result<HashMap<String, PerfCounters>> {
+    if !path.is_file() {
+        return Ok(HashMap::new());
+    }
+
+    let csv = fs::read_to_string(path)
+        .with_context(|| format!("unable to read perf stat results at {}", path.display()))?;
+    let mut results = HashMap::new();
+    for line in csv.lines().filter(|line| !line.trim().is_empty()) {
+        let fields: Vec<_> = line.trim().split(',').collect();
+        let &[scenario, cycles, instructions, branch_misses] = fields.as_slice() else {
+            bail!("invalid perf stat results row: {line}");
+        };
+
+        let parse = |value: &str| -> anyhow::Result<f64> {
+            value
+                .parse()
+                .with_context(|| format!("invalid perf stat results row: {line}"))
+        };
+        results.insert(
+            scenario.to_string(),
+            PerfCounters {
+                cycles: parse(cycles)?,
+                instructions: parse(instructions)?,
+                branch_misses: parse(branch_misses)?,
+            },
+        );
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse_perf_stat_output_with_repetitions() {
+        let output = "\
+# started on Mon Jan  1 00:00:00 2024
+
+1200.50,,cycles:u,0.52%,1000000,100.00,,
+1000,,instructions:u,0.01%,1000000,100.00,0.83,insn per cycle
+12.25,,branch-misses:u,3.10%,1000000,100.00,,
+";
+        let counters = parse_perf_stat_output(output).unwrap();
+        assert_eq!(
+            counters,
+            PerfCounters {
+                cycles: 1200.5,
+                instructions: 1000.0,
+                branch_misses: 12.25,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_perf_stat_output_without_counters() {
+        let output = "<not supported>,,cycles,0,100.00,,\n1000,,instructions,0,100.00,,\n";
+        assert!(parse_perf_stat_output(output).is_err());
+
+        let output = "1000,,instructions,0,100.00,,\n";
+        assert!(parse_perf_stat_output(output).is_err());
+    }
+
+    #[test]
+    fn perf_stat_results_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(PERF_STAT_FILE);
+        assert!(read_perf_stat_results(&path).unwrap().is_empty());
+
+        fs::write(&path, "handshake,1200.5,1000,12\n").unwrap();
+        let results = read_perf_stat_results(&path).unwrap();
+        assert_eq!(
+            results["handshake"],
+            PerfCounters {
+                cycles: 1200.5,
+                instructions: 1000.0,
+                branch_misses: 12.0,
+            }
+        );
+
+        fs::write(&path, "handshake,1200.5\n").unwrap();
+        assert!(read_perf_stat_results(&path).is_err());
+    }
+}
@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::Context;
+use octocrab::Octocrab;
+use serde::Deserialize;
+use time::OffsetDateTime;
+use tracing::{error, info, warn};
+
+use crate::github::CachedOctocrab;
+
+/// How many deliveries are inspected, which covers any reasonable deployment downtime
+const DELIVERIES_PER_CHECK: usize = 100;
+
+/// How many times a failed delivery is redelivered before giving up on it
+const MAX_REDELIVERY_ATTEMPTS: usize = 3;
+
+/// How long to wait after startup before the first check, so the webhook endpoint is listening
+/// when GitHub redelivers the events
+const STARTUP_DELAY: Duration = Duration::from_secs(5);
+
+/// Recovery of webhook deliveries that failed (e.g. because the application was being deployed)
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct WebhookRedeliveryConfig {
+    /// How far back to look for failed deliveries
+    pub lookback_hours: u32,
+    /// How often to look for failed deliveries, besides on startup (only on startup if unset)
+    pub interval_minutes: Option<u32>,
+}
+
+/// A delivery of a webhook event to our webhook endpoint, as reported by GitHub
+#[derive(Debug, Clone, Deserialize)]
+struct WebhookDelivery {
+    id: u64,
+    /// Identifies the event, which is shared by all its deliveries
+    guid: String,
+    #[serde(with = "time::serde::rfc3339")]
+    delivered_at: OffsetDateTime,
+    /// Whether the delivery was requested through the API (i.e. it isn't the original one)
+    redelivery: bool,
+    /// The HTTP status code our endpoint responded with (0 if it couldn't be reached)
+    status_code: u16,
+}
+
+impl WebhookDelivery {
+    fn succeeded(&self) -> bool {
+        (200..300).contains(&self.status_code)
+    }
+}
+
+/// Spawns a background task that asks GitHub to redeliver the webhook events that failed to reach
+/// us, on startup and then regularly (if configured)
+pub fn spawn_webhook_redelivery(config: WebhookRedeliveryConfig, octocrab: CachedOctocrab) {
+    tokio::spawn(async move {
+        tokio::time::sleep(STARTUP_DELAY).await;
+        loop {
+            let since =
+                OffsetDateTime::now_utc() - time::Duration::hours(config.lookback_hours.into());
+            if let Err(e) = redeliver_failed_webhooks(&octocrab.app(), since).await {
+                error!(
+                    cause = format!("{e:#}"),
+                    "unable to redeliver failed webhooks"
+                );
+            }
+
+            let Some(interval_minutes) = config.interval_minutes else {
+                break;
+            };
+            tokio::time::sleep(Duration::from_secs(u64::from(interval_minutes) * 60)).await;
+        }
+    });
+}
+
+/// Asks GitHub to redeliver the events whose deliveries since the provided moment failed
+async fn redeliver_failed_webhooks(
+    app_client: &Octocrab,
+    since: OffsetDateTime,
+) -> anyhow::Result<()> {
+    let deliveries: Vec<WebhookDelivery> = app_client
+        .get(
+            format!("/app/hook/deliveries?per_page={DELIVERIES_PER_CHECK}"),
+            None::<&()>,
+        )
+        .await
+        .context("failed to list webhook deliveries")?;
+
+    let to_redeliver = deliveries_to_redeliver(&deliveries, since);
+    if !to_redeliver.is_empty() {
+        info!("requesting redelivery of {} webhooks", to_redeliver.len());
+    }
+
+    for delivery_id in to_redeliver {
+        let result: Result<serde_json::Value, _> = app_client
+            .post(
+                format!("/app/hook/deliveries/{delivery_id}/attempts"),
+                None::<&()>,
+            )
+            .await;
+        if let Err(e) = result {
+            warn!(
+                cause = e.to_string(),
+                delivery_id, "unable to request webhook redelivery"
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Returns the ids of the deliveries to retry: one per event that was delivered since the
+/// provided moment, never reached us successfully and hasn't been redelivered too often yet
+fn deliveries_to_redeliver(deliveries: &[WebhookDelivery], since: OffsetDateTime) -> Vec<u64> {
+    let mut deliveries_by_event: HashMap<&str, Vec<&WebhookDelivery>> = HashMap::new();
+    for delivery in deliveries {
+        deliveries_by_event
+            .entry(&delivery.guid)
+            .or_default()
+            .push(delivery);
+    }
+
+    let mut to_redeliver: Vec<_> = deliveries_by_event
+        .into_values()
+        .filter(|deliveries| {
+            let recent = deliveries.iter().any(|d| d.delivered_at >= since);
+            let succeeded = deliveries.iter().any(|d| d.succeeded());
+            let redeliveries = deliveries.iter().filter(|d| d.redelivery).count();
+            recent && !succeeded && redeliveries < MAX_REDELIVERY_ATTEMPTS
+        })
+        .filter_map(|deliveries| deliveries.iter().map(|d| d.id).max())
+        .collect();
+
+    to_redeliver.sort_unstable();
+    to_redeliver
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn delivery(
+        id: u64,
+        guid: &str,
+        minutes_ago: i64,
+        redelivery: bool,
+        status_code: u16,
+    ) -> WebhookDelivery {
+        WebhookDelivery {
+            id,
+            guid: guid.to_string(),
+            delivered_at: OffsetDateTime::now_utc() - time::Duration::minutes(minutes_ago),
+            redelivery,
+            status_code,
+        }
+    }
+
+    #[test]
+    fn only_failed_recent_events_are_redelivered() {
+        let since = OffsetDateTime::now_utc() - time::Duration::hours(1);
+        let deliveries = [
+            // Delivered successfully
+            delivery(1, "ok", 10, false, 200),
+            // Failed while we were down
+            delivery(2, "down", 10, false, 502),
+            // Failed, but too long ago
+            delivery(3, "old", 120, false, 0),
+            // Failed, but a redelivery succeeded
+            delivery(4, "recovered", 20, false, 502),
+            delivery(5, "recovered", 15, true, 200),
+            // Failed, and so did the redelivery, which is retried
+            delivery(6, "flaky", 20, false, 502),
+            delivery(7, "flaky", 15, true, 502),
+        ];
+
+        assert_eq!(deliveries_to_redeliver(&deliveries, since), [2, 7]);
+    }
+
+    #[test]
+    fn events_are_not_redelivered_forever() {
+        let since = OffsetDateTime::now_utc() - time::Duration::hours(1);
+        let mut deliveries = vec![delivery(1, "broken", 30, false, 500)];
+        for id in 2..=MAX_REDELIVERY_ATTEMPTS as u64 + 1 {
+            deliveries.push(delivery(id, "broken", 20, true, 500));
+        }
+
+        assert!(deliveries_to_redeliver(&deliveries, since).is_empty());
+    }
+}
@@ -0,0 +1,59 @@
+use askama::Template;
+
+use crate::db::{Regression, RegressionState};
+use crate::job::MAIN_BRANCH;
+use crate::AppConfig;
+
+/// A simple HTML page listing the regressions detected on the tracked branches, along with their
+/// triage state
+#[derive(Template)]
+#[template(path = "regressions_dashboard.html")]
+struct RegressionsDashboard<'a> {
+    regressions: &'a [Regression],
+    /// The states the regressions can be filtered by
+    states: [RegressionState; 4],
+    /// The tracked branches the regressions can be filtered by
+    branches: Vec<&'a str>,
+    /// The branch the regressions are filtered by, if any
+    selected_branch: Option<&'a str>,
+    repo_owner: &'a str,
+    repo_name: &'a str,
+}
+
+/// Renders the regressions dashboard
+pub fn render_dashboard(
+    config: &AppConfig,
+    selected_branch: Option<&str>,
+    regressions: &[Regression],
+) -> String {
+    let mut branches = vec![MAIN_BRANCH];
+    for tracked in &config.tracked_branches {
+        if !branches.contains(&tracked.name.as_str()) {
+            branches.push(&tracked.name);
+        }
+    }
+
+    RegressionsDashboard {
+        regressions,
+        branches,
+        selected_branch,
+        states: [
+            RegressionState::New,
+            RegressionState::Triaged,
+            RegressionState::Accepted,
+            RegressionState::Fixed,
+        ],
+        repo_owner: &config.github_repo_owner,
+        repo_name: &config.github_repo_name,
+    }
+    .render()
+    .expect("failed to render askama template")
+}
+
+/// Functions inside this module will be available as askama filters
+mod filters {
+    /// Shortens a commit hash, like GitHub does
+    pub fn short_sha(sha: &str) -> askama::Result<String> {
+        Ok(sha.chars().take(7).collect())
+    }
+}
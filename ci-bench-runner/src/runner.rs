@@ -1,29 +1,646 @@
-use std::fmt::Write;
-use std::fs;
-use std::path::Path;
-use std::process::Command;
-use std::time::Instant;
+use std::collections::{BTreeMap, HashSet};
+use std::ffi::OsStr;
+use std::fmt::{Debug, Display, Formatter, Write};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, fs};
 
-use anyhow::{bail, Context};
-use tracing::trace;
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use tracing::{info, trace, warn};
 
-use crate::job::walltimes_path;
+use crate::energy::run_energy_benchmarks;
+use crate::job::read_icount_results;
 use crate::CommitIdentifier;
 
 pub trait BenchRunner: Send + Sync {
-    /// Checks out the specified commit and runs the benchmarks
+    /// Checks out the specified commit and runs the benchmarks, as described by the bench suite
+    ///
+    /// Once the job is cancelled through `cancellation`, running commands are killed and no further
+    /// commands are started
     fn checkout_and_run_benchmarks(
         &self,
         commit: &CommitIdentifier,
         checkout_target_dir: &Path,
         job_output_dir: &Path,
+        bench_suite: &BenchSuiteConfig,
         command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
     ) -> anyhow::Result<()>;
 }
 
+/// Cancels a job's benchmarks from another thread, killing the commands they are running
+#[derive(Clone, Debug, Default)]
+pub struct CancellationToken(Arc<CancellationState>);
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    cancelled: AtomicBool,
+    /// Whether the job was cancelled because it exceeded its time limit
+    timed_out: AtomicBool,
+    /// The process groups of the running commands (each command runs in a group of its own, so
+    /// its children are killed along with it)
+    process_groups: Mutex<HashSet<u32>>,
+}
+
+impl CancellationToken {
+    /// Kills the running commands and prevents new ones from starting
+    pub fn cancel(&self) {
+        let process_groups = self.0.process_groups.lock().unwrap();
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        for &process_group in process_groups.iter() {
+            let result = Command::new("kill")
+                .args(["-KILL", "--", &format!("-{process_group}")])
+                .output();
+            if let Err(e) = result {
+                warn!(
+                    cause = e.to_string(),
+                    "unable to kill process group {process_group}"
+                );
+            }
+        }
+    }
+
+    /// Returns whether the job has been cancelled
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Cancels the job because it exceeded its time limit, so its failures are classified as
+    /// timeouts
+    pub fn time_out(&self) {
+        self.0.timed_out.store(true, Ordering::SeqCst);
+        self.cancel();
+    }
+
+    /// Returns whether the job has been cancelled because it exceeded its time limit
+    pub fn is_timed_out(&self) -> bool {
+        self.0.timed_out.load(Ordering::SeqCst)
+    }
+}
+
+/// Describes how to build and run a repository's benchmark suite, and where to find its results
+///
+/// Missing fields fall back to the layout of rustls' `ci-bench` crate
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct BenchSuiteConfig {
+    /// Directory from which the benchmarks are built and run, relative to the repository's root
+    pub bench_dir: PathBuf,
+    /// Command used to build the benchmarks (the first element is the program)
+    pub build_command: Vec<String>,
+    /// Path to the benchmark executable, relative to the repository's root
+    pub bench_executable: PathBuf,
+    /// Arguments passed to the benchmark executable to run the icount benchmarks. The
+    /// `{results_dir}` placeholder is replaced by the absolute path of the results directory.
+    /// Leave empty to skip the icount benchmarks
+    pub icount_args: Vec<String>,
+    /// Arguments passed to the benchmark executable to run the walltime benchmarks, which are
+    /// expected to print their results to stdout. Leave empty to skip the walltime benchmarks
+    pub walltime_args: Vec<String>,
+    /// Arguments passed to the benchmark executable to measure the peak memory usage of each
+    /// scenario. The `{results_dir}` placeholder is replaced by the absolute path of the results
+    /// directory. Leave empty to skip the memory usage benchmarks
+    pub max_rss_args: Vec<String>,
+    /// Powercap zone (e.g. `/sys/class/powercap/intel-rapl:0`) whose package energy counter is
+    /// read while running each walltime scenario on its own, which requires `filter_walltime_args`.
+    /// Leave unset to skip the energy benchmarks
+    pub rapl_zone: Option<PathBuf>,
+    /// Command that builds the artifacts whose size is tracked (the first element is the program),
+    /// run from the repository's root. Leave empty to skip tracking artifact sizes
+    pub artifact_build_command: Vec<String>,
+    /// Paths of the artifacts whose size is tracked (e.g. `target/release/librustls.rlib`),
+    /// relative to the repository's root. Artifacts missing after the build are skipped
+    pub artifacts: Vec<PathBuf>,
+    /// Directory where results are stored, relative to the job's output directory
+    pub results_dir: PathBuf,
+    /// CSV file containing the icount results, relative to the results directory
+    pub icounts_file: PathBuf,
+    /// CSV file containing the walltime results, relative to the results directory
+    pub walltimes_file: PathBuf,
+    /// CSV file containing the peak memory usage results (in bytes), relative to the results
+    /// directory
+    pub max_rss_file: PathBuf,
+    /// CSV file containing the energy results (in joules), relative to the results directory
+    pub energy_file: PathBuf,
+    /// Directory containing one callgrind output file per scenario, relative to the results
+    /// directory
+    pub callgrind_dir: PathBuf,
+    /// Suffix identifying the client side of a client/server scenario pair
+    pub client_scenario_suffix: String,
+    /// Suffix identifying the server side of a client/server scenario pair
+    pub server_scenario_suffix: String,
+    /// Curated subset of icount scenarios benchmarked in quick mode (quick mode is unavailable if
+    /// empty)
+    pub quick_scenarios: Vec<String>,
+    /// Extra arguments passed to the benchmark executable to run only a list of icount scenarios
+    /// (i.e. the quick scenarios in quick mode, or the failed scenarios when retrying them). The
+    /// `{scenarios}` placeholder is replaced by the comma-separated list of scenarios. Results for
+    /// other scenarios are discarded anyway, so this can be left empty if the benchmark executable
+    /// can't filter scenarios
+    pub quick_icount_args: Vec<String>,
+    /// Extra arguments passed to the benchmark executable to run only the icount scenarios matching
+    /// a glob (e.g. `handshake_tls13*`), which replaces the `{filter}` placeholder. Results for
+    /// other scenarios are discarded anyway, but filtered runs are refused if neither this nor
+    /// `filter_walltime_args` is set, since they would take as long as a full run
+    pub filter_icount_args: Vec<String>,
+    /// Like `filter_icount_args`, but for the walltime benchmarks
+    pub filter_walltime_args: Vec<String>,
+    /// Program and arguments prepended to the commands that build and run the benchmarks (e.g. to
+    /// run them under `systemd-run` with resource limits). Leave empty to run them directly
+    pub command_prefix: Vec<String>,
+    /// Like `command_prefix`, but used instead of it when benchmarking code from forks, which should
+    /// be sandboxed more strictly (e.g. without network access and with a read-only view of the
+    /// host). Code from forks is never benchmarked if this is left empty
+    pub untrusted_command_prefix: Vec<String>,
+}
+
+impl BenchSuiteConfig {
+    /// Returns the path to the results directory of a job's output
+    pub fn results_path(&self, job_output_dir: &Path) -> PathBuf {
+        job_output_dir.join(&self.results_dir)
+    }
+
+    /// Returns the path to the icount results of a job's output
+    pub fn icounts_path(&self, job_output_dir: &Path) -> PathBuf {
+        self.results_path(job_output_dir).join(&self.icounts_file)
+    }
+
+    /// Returns the path to the walltime results of a job's output
+    pub fn walltimes_path(&self, job_output_dir: &Path) -> PathBuf {
+        self.results_path(job_output_dir).join(&self.walltimes_file)
+    }
+
+    /// Returns the path to the peak memory usage results of a job's output
+    pub fn max_rss_path(&self, job_output_dir: &Path) -> PathBuf {
+        self.results_path(job_output_dir).join(&self.max_rss_file)
+    }
+
+    /// Returns the path to the energy results of a job's output
+    pub fn energy_path(&self, job_output_dir: &Path) -> PathBuf {
+        self.results_path(job_output_dir).join(&self.energy_file)
+    }
+
+    /// Returns the bench suite used in quick mode, which only runs the icount benchmarks (and
+    /// filters them, if supported), or `None` if quick mode hasn't been configured
+    pub fn quick_suite(&self) -> Option<BenchSuiteConfig> {
+        if self.quick_scenarios.is_empty() {
+            return None;
+        }
+
+        let scenarios = self.quick_scenarios.join(",");
+        let mut suite = self.clone();
+        suite.icount_args.extend(
+            self.quick_icount_args
+                .iter()
+                .map(|arg| arg.replace("{scenarios}", &scenarios)),
+        );
+        suite.walltime_args.clear();
+        suite.max_rss_args.clear();
+        suite.artifact_build_command.clear();
+        Some(suite)
+    }
+
+    /// Returns the bench suite that only runs the provided scenarios, as far as the benchmark
+    /// executable supports filtering them (see `quick_icount_args`)
+    ///
+    /// Each kind of benchmarks is skipped entirely if none of its scenarios are provided
+    pub fn scenarios_suite(
+        &self,
+        icount_scenarios: &[String],
+        walltime_scenarios: &[String],
+        max_rss_scenarios: &[String],
+    ) -> BenchSuiteConfig {
+        let mut suite = self.clone();
+        if icount_scenarios.is_empty() {
+            suite.icount_args.clear();
+        } else if !suite.icount_args.is_empty() {
+            let scenarios = icount_scenarios.join(",");
+            suite.icount_args.extend(
+                self.quick_icount_args
+                    .iter()
+                    .map(|arg| arg.replace("{scenarios}", &scenarios)),
+            );
+        }
+        if walltime_scenarios.is_empty() {
+            suite.walltime_args.clear();
+        }
+        if max_rss_scenarios.is_empty() {
+            suite.max_rss_args.clear();
+        }
+        suite.artifact_build_command.clear();
+        suite
+    }
+
+    /// Returns the bench suite used in walltime mode, which only runs the walltime benchmarks, or
+    /// `None` if there are no walltime benchmarks
+    pub fn walltime_suite(&self) -> Option<BenchSuiteConfig> {
+        if self.walltime_args.is_empty() {
+            return None;
+        }
+
+        let mut suite = self.clone();
+        suite.icount_args.clear();
+        suite.max_rss_args.clear();
+        suite.artifact_build_command.clear();
+        Some(suite)
+    }
+
+    /// Returns the bench suite that only runs the scenarios matching the glob, as far as the
+    /// benchmark executable supports filtering them (see `filter_icount_args`)
+    ///
+    /// Fails if no filter arguments are configured, because the filtered run would silently
+    /// benchmark every scenario
+    pub fn filtered_suite(&self, glob: &str) -> anyhow::Result<BenchSuiteConfig> {
+        if self.filter_icount_args.is_empty() && self.filter_walltime_args.is_empty() {
+            bail!(
+                "refusing to benchmark only the scenarios matching `{glob}`, because neither \
+                `filter_icount_args` nor `filter_walltime_args` are configured to filter them"
+            );
+        }
+
+        let mut suite = self.clone();
+        suite.artifact_build_command.clear();
+        // The energy benchmarks filter the walltime scenarios themselves
+        suite.rapl_zone = None;
+        if !suite.icount_args.is_empty() {
+            suite.icount_args.extend(
+                self.filter_icount_args
+                    .iter()
+                    .map(|arg| arg.replace("{filter}", glob)),
+            );
+        }
+        if !suite.walltime_args.is_empty() {
+            suite.walltime_args.extend(
+                self.filter_walltime_args
+                    .iter()
+                    .map(|arg| arg.replace("{filter}", glob)),
+            );
+        }
+        Ok(suite)
+    }
+
+    /// Returns the bench suite used to benchmark code from forks, which is run with the stricter
+    /// `untrusted_command_prefix`
+    ///
+    /// Fails if no `untrusted_command_prefix` is configured, because falling back to
+    /// `command_prefix` would run code from forks with the same privileges as trusted code
+    pub fn untrusted_suite(&self) -> anyhow::Result<BenchSuiteConfig> {
+        if self.untrusted_command_prefix.is_empty() {
+            bail!(
+                "refusing to benchmark code from a fork, because no `untrusted_command_prefix` is \
+                configured to sandbox it"
+            );
+        }
+
+        let mut suite = self.clone();
+        suite.command_prefix = self.untrusted_command_prefix.clone();
+        Ok(suite)
+    }
+
+    /// Returns a command that runs the program with the suite's `command_prefix`
+    pub(crate) fn command(&self, program: impl AsRef<OsStr>) -> Command {
+        match self.command_prefix.split_first() {
+            Some((prefix_program, prefix_args)) => {
+                let mut command = Command::new(prefix_program);
+                command.args(prefix_args).arg(program);
+                command
+            }
+            None => Command::new(program),
+        }
+    }
+
+    /// Returns the path to a scenario's callgrind output of a job's output
+    pub fn callgrind_path(&self, job_output_dir: &Path, scenario: &str) -> PathBuf {
+        self.results_path(job_output_dir)
+            .join(&self.callgrind_dir)
+            .join(scenario)
+    }
+}
+
+impl Default for BenchSuiteConfig {
+    fn default() -> Self {
+        fn strings(args: &[&str]) -> Vec<String> {
+            args.iter().map(|s| s.to_string()).collect()
+        }
+
+        Self {
+            bench_dir: PathBuf::from("ci-bench"),
+            build_command: strings(&["cargo", "build", "--locked", "--release"]),
+            bench_executable: PathBuf::from("target/release/rustls-ci-bench"),
+            icount_args: strings(&["run-all", "--output-dir", "{results_dir}"]),
+            walltime_args: strings(&["walltime", "--iterations-per-scenario", "100"]),
+            max_rss_args: Vec::new(),
+            rapl_zone: None,
+            artifact_build_command: Vec::new(),
+            artifacts: Vec::new(),
+            results_dir: PathBuf::from("results"),
+            icounts_file: PathBuf::from("icounts.csv"),
+            walltimes_file: PathBuf::from("walltimes.csv"),
+            max_rss_file: PathBuf::from("max_rss.csv"),
+            energy_file: PathBuf::from("energy.csv"),
+            callgrind_dir: PathBuf::from("callgrind"),
+            client_scenario_suffix: "_client".to_string(),
+            server_scenario_suffix: "_server".to_string(),
+            quick_scenarios: Vec::new(),
+            quick_icount_args: Vec::new(),
+            filter_icount_args: Vec::new(),
+            filter_walltime_args: Vec::new(),
+            command_prefix: Vec::new(),
+            untrusted_command_prefix: Vec::new(),
+        }
+    }
+}
+
 /// A bench runner that runs benchmarks locally
 #[derive(Debug)]
-pub struct LocalBenchRunner;
+pub struct LocalBenchRunner {
+    /// Where the commits to benchmark are fetched from
+    source: Arc<dyn SourceProvider>,
+    /// When set, measurements are delayed until the host is idle
+    idle_check: Option<HostIdleConfig>,
+    /// When set, the stability of the environment is verified before measuring
+    determinism_check: Option<DeterminismCheckConfig>,
+}
+
+impl LocalBenchRunner {
+    pub fn new(
+        source: Arc<dyn SourceProvider>,
+        idle_check: Option<HostIdleConfig>,
+        determinism_check: Option<DeterminismCheckConfig>,
+    ) -> Self {
+        Self {
+            source,
+            idle_check,
+            determinism_check,
+        }
+    }
+
+    /// Runs the determinism check (if configured), benchmarking its scenario under valgrind
+    fn check_determinism(
+        &self,
+        bench_suite: &BenchSuiteConfig,
+        bench_exe_path: &Path,
+        bench_path: &Path,
+        job_output_dir: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
+        let Some(config) = &self.determinism_check else {
+            return Ok(());
+        };
+
+        if bench_suite.quick_icount_args.is_empty() {
+            warn!("skipping determinism check, since the bench suite can't run a single scenario");
+            return Ok(());
+        }
+
+        let check_suite =
+            bench_suite.scenarios_suite(std::slice::from_ref(&config.scenario), &[], &[]);
+        config.run(job_output_dir, |run_dir| {
+            run_icount_benchmarks(
+                &check_suite,
+                bench_exe_path,
+                bench_path,
+                run_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            let results = read_icount_results(&check_suite.icounts_path(run_dir))?;
+            Ok(results.get(&config.scenario).copied())
+        })
+    }
+
+    /// Waits until the host is idle, as defined in the configuration (if any)
+    fn wait_for_idle_host(&self, cancellation: &CancellationToken) {
+        if let Some(config) = &self.idle_check {
+            config.wait_for_idle_host(cancellation);
+        }
+    }
+}
+
+/// How often the host load is checked while waiting for the host to become idle
+const IDLE_CHECK_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Describes when the host is considered idle enough to start measuring
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HostIdleConfig {
+    /// Maximum 1-minute load average, in percent (e.g. 50 means a load average of 0.5)
+    pub max_load_average_percent: u32,
+    /// Maximum number of running processes (including the app itself)
+    pub max_running_processes: u32,
+    /// Minimum available memory, in MiB
+    pub min_available_memory_mib: u64,
+    /// Maximum time to wait for the host to become idle
+    pub max_wait_minutes: u32,
+}
+
+impl HostIdleConfig {
+    /// Waits until the host is idle
+    ///
+    /// The wait is bounded: once the configured maximum is reached, the measurement proceeds
+    /// anyway (with a warning) instead of stalling the queue
+    pub fn wait_for_idle_host(&self, cancellation: &CancellationToken) {
+        let start = Instant::now();
+        let max_wait = Duration::from_secs(60 * u64::from(self.max_wait_minutes));
+        loop {
+            let load = match HostLoad::current() {
+                Ok(load) => load,
+                Err(e) => {
+                    warn!(
+                        cause = e.to_string(),
+                        "unable to determine host load, skipping idle check"
+                    );
+                    return;
+                }
+            };
+
+            let Some(reason) = load.busy_reason(self) else {
+                trace!("host is idle ({load:?})");
+                return;
+            };
+
+            if start.elapsed() >= max_wait {
+                warn!("host is still busy after waiting, measuring anyway ({reason})");
+                return;
+            }
+
+            if cancellation.is_cancelled() {
+                return;
+            }
+
+            info!("host is busy, delaying measurement ({reason})");
+            std::thread::sleep(IDLE_CHECK_POLL_INTERVAL);
+        }
+    }
+}
+
+impl Default for HostIdleConfig {
+    fn default() -> Self {
+        Self {
+            max_load_average_percent: 50,
+            max_running_processes: 2,
+            min_available_memory_mib: 1024,
+            max_wait_minutes: 10,
+        }
+    }
+}
+
+/// Describes how to verify that the environment is stable, before measuring instruction counts
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct DeterminismCheckConfig {
+    /// The icount scenario that is benchmarked twice (a short one keeps the check cheap)
+    pub scenario: String,
+    /// Maximum relative difference between both instruction counts, in parts per million
+    #[serde(default)]
+    pub max_difference_ppm: u32,
+    /// How many times the check is repeated when it fails, before flagging the run as unstable
+    #[serde(default = "default_determinism_check_retries")]
+    pub retries: u32,
+}
+
+fn default_determinism_check_retries() -> u32 {
+    1
+}
+
+impl DeterminismCheckConfig {
+    /// Measures the configured scenario twice in a row (which also warms up the harness) and
+    /// verifies that both instruction counts match, within the configured tolerance
+    ///
+    /// The scenario is measured by `measure`, which gets a fresh output dir for each run and
+    /// returns the scenario's instruction count (`None` if the scenario wasn't benchmarked).
+    ///
+    /// Instruction counts are deterministic, so a mismatch hints at the environment leaking into
+    /// the measurements (e.g. ASLR or frequency scaling). The check is repeated up to the
+    /// configured number of retries, after which the run is flagged as environmentally unstable by
+    /// storing the discrepancy in its output dir (see [`read_determinism_discrepancy`])
+    pub fn run(
+        &self,
+        job_output_dir: &Path,
+        mut measure: impl FnMut(&Path) -> anyhow::Result<Option<f64>>,
+    ) -> anyhow::Result<()> {
+        let check_dir = job_output_dir.join(DETERMINISM_CHECK_DIR);
+        let max_diff_ratio = f64::from(self.max_difference_ppm) / 1_000_000.0;
+        for attempt in 0..=self.retries {
+            let mut icounts = Vec::new();
+            for run in ["first", "second"] {
+                let run_dir = check_dir.join(run);
+                fs::create_dir_all(&run_dir).context("unable to create dir for check output")?;
+                let icount = measure(&run_dir)?
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "the determinism check scenario `{}` was not benchmarked",
+                            self.scenario
+                        )
+                    })
+                    .context(FailureClass::HarnessCrash)?;
+                icounts.push(icount);
+            }
+
+            let discrepancy = DeterminismDiscrepancy {
+                scenario: self.scenario.clone(),
+                first_icount: icounts[0],
+                second_icount: icounts[1],
+            };
+            if discrepancy.diff_ratio().abs() <= max_diff_ratio {
+                trace!("determinism check passed ({discrepancy:?})");
+                return Ok(());
+            }
+
+            warn!(
+                "determinism check failed (attempt {} of {}): {discrepancy:?}",
+                attempt + 1,
+                self.retries + 1
+            );
+            if attempt == self.retries {
+                let json = serde_json::to_string(&discrepancy).expect("unreachable code");
+                fs::write(job_output_dir.join(UNSTABLE_ENVIRONMENT_FILE), json)
+                    .context("unable to write determinism check results")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A snapshot of the host's load, as reported by Linux' `/proc` filesystem
+#[derive(Debug, PartialEq)]
+struct HostLoad {
+    /// The 1-minute load average
+    load_average: f64,
+    /// The number of currently running processes
+    running_processes: u32,
+    /// The available memory, in MiB
+    available_memory_mib: u64,
+}
+
+impl HostLoad {
+    fn current() -> anyhow::Result<Self> {
+        let loadavg = fs::read_to_string("/proc/loadavg").context("unable to read loadavg")?;
+        let meminfo = fs::read_to_string("/proc/meminfo").context("unable to read meminfo")?;
+        Self::parse(&loadavg, &meminfo)
+    }
+
+    fn parse(loadavg: &str, meminfo: &str) -> anyhow::Result<Self> {
+        // Example: `0.41 0.44 0.41 2/72 30221`
+        let mut loadavg_parts = loadavg.split_whitespace();
+        let load_average = loadavg_parts
+            .next()
+            .context("loadavg is empty")?
+            .parse()
+            .context("invalid load average")?;
+        let running_processes = loadavg_parts
+            .nth(2)
+            .and_then(|procs| procs.split('/').next())
+            .context("loadavg is missing the process count")?
+            .parse()
+            .context("invalid running process count")?;
+
+        // Example: `MemAvailable:    5516448 kB`
+        let available_memory_kib: u64 = meminfo
+            .lines()
+            .find_map(|line| line.strip_prefix("MemAvailable:"))
+            .and_then(|value| value.split_whitespace().next())
+            .context("meminfo is missing MemAvailable")?
+            .parse()
+            .context("invalid available memory")?;
+
+        Ok(Self {
+            load_average,
+            running_processes,
+            available_memory_mib: available_memory_kib / 1024,
+        })
+    }
+
+    /// Returns the reason why the host is considered busy, if any
+    fn busy_reason(&self, config: &HostIdleConfig) -> Option<String> {
+        let max_load_average = config.max_load_average_percent as f64 / 100.0;
+        if self.load_average > max_load_average {
+            Some(format!(
+                "load average {:.2} > {max_load_average:.2}",
+                self.load_average
+            ))
+        } else if self.running_processes > config.max_running_processes {
+            Some(format!(
+                "{} running processes > {}",
+                self.running_processes, config.max_running_processes
+            ))
+        } else if self.available_memory_mib < config.min_available_memory_mib {
+            Some(format!(
+                "{} MiB available memory < {} MiB",
+                self.available_memory_mib, config.min_available_memory_mib
+            ))
+        } else {
+            None
+        }
+    }
+}
 
 impl BenchRunner for LocalBenchRunner {
     fn checkout_and_run_benchmarks(
@@ -31,126 +648,853 @@ impl BenchRunner for LocalBenchRunner {
         commit: &CommitIdentifier,
         checkout_target_dir: &Path,
         job_output_dir: &Path,
+        bench_suite: &BenchSuiteConfig,
         command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
     ) -> anyhow::Result<()> {
-        trace!(
-            "checking out {} at commit {}",
-            commit.clone_url,
-            commit.commit_sha
-        );
-        trace!(
-            "checkout target directory: {}",
-            checkout_target_dir.display()
-        );
+        // The commands of this run, from which the reproduction steps are derived
+        let first_log = command_logs.len();
 
-        // Init
-        let mut command = Command::new("git");
-        command.arg("init").current_dir(checkout_target_dir);
+        checkout(
+            commit,
+            checkout_target_dir,
+            bench_suite,
+            self.source.as_ref(),
+            command_logs,
+            cancellation,
+        )?;
+        build_benchmarks(
+            checkout_target_dir,
+            job_output_dir,
+            bench_suite,
+            command_logs,
+            cancellation,
+        )?;
 
-        run_command(command, command_logs)?;
+        let bench_path = checkout_target_dir.join(&bench_suite.bench_dir);
+        let bench_exe_path = checkout_target_dir.join(&bench_suite.bench_executable);
 
-        // Configure remote
-        let mut command = Command::new("git");
-        command
-            .arg("remote")
-            .arg("add")
-            .arg("origin")
-            .arg(&commit.clone_url)
-            .current_dir(checkout_target_dir);
+        // Run icount benchmarks
+        if bench_suite.icount_args.is_empty() {
+            trace!("skipping icount benchmarks");
+        } else {
+            self.wait_for_idle_host(cancellation);
+            self.check_determinism(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            let start = Instant::now();
+            run_icount_benchmarks(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            trace!(
+                "icount benchmarks run in {:.2} s",
+                (Instant::now() - start).as_secs_f64()
+            );
+        }
+
+        // Run memory usage benchmarks
+        if bench_suite.max_rss_args.is_empty() {
+            trace!("skipping memory usage benchmarks");
+        } else {
+            trace!("running memory usage benchmarks");
+            let start = Instant::now();
+            run_max_rss_benchmarks(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            trace!(
+                "memory usage benchmarks run in {:.2} s",
+                (Instant::now() - start).as_secs_f64()
+            );
+        }
+
+        if bench_suite.walltime_args.is_empty() {
+            trace!("skipping walltime benchmarks");
+        } else {
+            // Run walltime benchmarks
+            self.wait_for_idle_host(cancellation);
+            trace!("running walltime benchmarks");
+            let start = Instant::now();
+            run_walltime_benchmarks(
+                bench_suite,
+                &bench_exe_path,
+                &bench_path,
+                job_output_dir,
+                command_logs,
+                cancellation,
+            )?;
+
+            trace!(
+                "walltime benchmarks run in {:.2} s",
+                (Instant::now() - start).as_secs_f64()
+            );
+        }
+
+        build_artifacts(
+            checkout_target_dir,
+            job_output_dir,
+            bench_suite,
+            command_logs,
+            cancellation,
+        )?;
+
+        write_reproduction(
+            &command_logs[first_log..],
+            checkout_target_dir,
+            job_output_dir,
+            &bench_path,
+        )
+    }
+}
 
-        run_command(command, command_logs)?;
+/// Fetches the commits to benchmark into a local git repository
+///
+/// Commits are identified by the clone URL of their repository (usually taken from a GitHub webhook
+/// payload), but providers are free to fetch them from elsewhere (e.g. a mirror), which lets
+/// deployments without access to GitHub benchmark them
+pub trait SourceProvider: Send + Sync + Debug {
+    /// Fetches the commit from the repository at `clone_url` into the git repository at
+    /// `repo_dir`, so it can be checked out
+    fn fetch(
+        &self,
+        clone_url: &str,
+        commit_sha: &str,
+        repo_dir: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()>;
+}
 
-        // Fetch relevant commit
-        let git_ref = &commit.commit_sha;
+/// A source provider that fetches commits through `git fetch`, from their clone URL or from the
+/// mirror configured for it
+#[derive(Debug, Default)]
+pub struct GitSourceProvider {
+    mirrors: Vec<SourceMirrorConfig>,
+}
+
+impl GitSourceProvider {
+    pub fn new(mirrors: Vec<SourceMirrorConfig>) -> Self {
+        Self { mirrors }
+    }
+
+    /// Returns the URL the repository at `clone_url` is fetched from, taking mirrors into account
+    /// (the first matching mirror wins)
+    fn fetch_url(&self, clone_url: &str) -> String {
+        self.mirrors
+            .iter()
+            .find_map(|mirror| {
+                let rest = clone_url.strip_prefix(&mirror.url_prefix)?;
+                Some(format!("{}{rest}", mirror.mirror_prefix))
+            })
+            .unwrap_or_else(|| clone_url.to_string())
+    }
+}
+
+impl SourceProvider for GitSourceProvider {
+    fn fetch(
+        &self,
+        clone_url: &str,
+        commit_sha: &str,
+        repo_dir: &Path,
+        command_logs: &mut Vec<Log>,
+        cancellation: &CancellationToken,
+    ) -> anyhow::Result<()> {
         let mut command = Command::new("git");
         command
             .arg("fetch")
-            .arg("origin")
-            .arg(git_ref)
-            .current_dir(checkout_target_dir);
+            .arg(self.fetch_url(clone_url))
+            .arg(commit_sha)
+            .current_dir(repo_dir);
 
-        run_command(command, command_logs)?;
+        run_command(command, command_logs, FailureClass::Clone, cancellation)
+    }
+}
 
-        // Checkout commit
-        let mut command = Command::new("git");
-        command
-            .arg("checkout")
-            .arg(git_ref)
-            .current_dir(checkout_target_dir);
+/// A git repository mirroring others, from which their commits are fetched instead (e.g. a local
+/// mirror in an air-gapped deployment, or a GitLab instance)
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct SourceMirrorConfig {
+    /// The prefix of the clone URLs that are mirrored (e.g. `https://github.com/rustls/`)
+    pub url_prefix: String,
+    /// The prefix replacing `url_prefix` in the mirror's URLs (e.g. `/srv/git/rustls/`)
+    pub mirror_prefix: String,
+}
 
-        run_command(command, command_logs)?;
+/// Checks out the specified commit (applying its patch series and pinning its harness, if any)
+pub(crate) fn checkout(
+    commit: &CommitIdentifier,
+    checkout_target_dir: &Path,
+    bench_suite: &BenchSuiteConfig,
+    source: &dyn SourceProvider,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    trace!(
+        "checking out {} at commit {}",
+        commit.clone_url,
+        commit.commit_sha
+    );
+    trace!(
+        "checkout target directory: {}",
+        checkout_target_dir.display()
+    );
 
-        // Build benchmarks
-        let bench_path = checkout_target_dir.join("ci-bench");
-        trace!("building benchmarks");
+    // Init
+    let mut command = Command::new("git");
+    command.arg("init").current_dir(checkout_target_dir);
 
-        let start = Instant::now();
-        let mut command = Command::new("cargo");
-        command
-            .arg("build")
-            .arg("--locked")
-            .arg("--release")
-            .current_dir(&bench_path);
+    run_command(
+        command,
+        command_logs,
+        FailureClass::Infrastructure,
+        cancellation,
+    )?;
+
+    // Fetch relevant commit
+    let git_ref = &commit.commit_sha;
+    source.fetch(
+        &commit.clone_url,
+        git_ref,
+        checkout_target_dir,
+        command_logs,
+        cancellation,
+    )?;
+
+    // Checkout commit
+    let mut command = Command::new("git");
+    command
+        .arg("checkout")
+        .arg(git_ref)
+        .current_dir(checkout_target_dir);
 
-        run_command(command, command_logs)?;
+    run_command(command, command_logs, FailureClass::Clone, cancellation)?;
 
+    if let Some(series) = &commit.patch_series {
         trace!(
-            "benchmarks built in {:.2} s",
-            (Instant::now() - start).as_secs_f64()
+            "applying patch series {}..{} from {}",
+            series.first_commit,
+            series.last_commit,
+            series.clone_url
         );
 
-        // Run icount benchmarks
-        let bench_exe_path = checkout_target_dir.join("target/release/rustls-ci-bench");
-        fs::create_dir_all(job_output_dir).context("Unable to create dir for job output")?;
+        // Fetch the series (the parent of its first commit is fetched along with it)
+        source.fetch(
+            &series.clone_url,
+            &series.last_commit,
+            checkout_target_dir,
+            command_logs,
+            cancellation,
+        )?;
 
-        let start = Instant::now();
-        let mut command = Command::new(&bench_exe_path);
+        // Apply it on top of the checked out commit
+        let mut command = Command::new("git");
         command
-            .arg("run-all")
-            .arg("--output-dir")
-            .arg(job_output_dir.join("results"))
-            .current_dir(&bench_path);
+            .arg("-c")
+            .arg("user.name=ci-bench-runner")
+            .arg("-c")
+            .arg("user.email=ci-bench-runner@localhost")
+            .arg("cherry-pick")
+            .arg(format!("{}^..{}", series.first_commit, series.last_commit))
+            .current_dir(checkout_target_dir);
 
-        run_command(command, command_logs)?;
+        run_command(command, command_logs, FailureClass::Clone, cancellation)?;
+    }
 
+    if let Some(harness) = &commit.pinned_harness {
         trace!(
-            "icount benchmarks run in {:.2} s",
-            (Instant::now() - start).as_secs_f64()
+            "pinning the bench harness to {} from {}",
+            harness.commit_sha,
+            harness.clone_url
         );
 
-        // Run walltime benchmarks (under setarch to disable ASLR, to reduce noise)
-        trace!("running walltime benchmarks");
-        let start = Instant::now();
+        // Fetch the commit with the harness
+        source.fetch(
+            &harness.clone_url,
+            &harness.commit_sha,
+            checkout_target_dir,
+            command_logs,
+            cancellation,
+        )?;
 
-        let mut command = Command::new("setarch");
+        // Replace the checked out harness by it (removing files the pinned harness lacks)
+        let mut command = Command::new("git");
         command
-            .arg("-R")
-            .arg(bench_exe_path)
-            .arg("walltime")
-            .arg("--iterations-per-scenario")
-            .arg("100")
-            .current_dir(&bench_path);
-
-        run_command(command, command_logs)?;
-
-        // The walltimes are printed to stdout and captured in the logs, but we want them in a file
-        fs::write(
-            walltimes_path(job_output_dir),
-            &command_logs.last().unwrap().stdout,
+            .arg("checkout")
+            .arg("--no-overlay")
+            .arg(&harness.commit_sha)
+            .arg("--")
+            .arg(&bench_suite.bench_dir)
+            .current_dir(checkout_target_dir);
+
+        run_command(command, command_logs, FailureClass::Clone, cancellation)?;
+    }
+
+    Ok(())
+}
+
+/// Builds the benchmarks of a checkout, recording the time of the build in the job's output dir
+/// (see [`read_build_time`])
+pub(crate) fn build_benchmarks(
+    checkout_target_dir: &Path,
+    job_output_dir: &Path,
+    bench_suite: &BenchSuiteConfig,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let bench_path = checkout_target_dir.join(&bench_suite.bench_dir);
+    trace!("building benchmarks");
+
+    let start = Instant::now();
+    let Some((program, args)) = bench_suite.build_command.split_first() else {
+        bail!("the bench suite's build command is empty");
+    };
+    let mut command = bench_suite.command(program);
+    command.args(args).current_dir(&bench_path);
+
+    run_command(command, command_logs, FailureClass::Build, cancellation)?;
+
+    let build_time = Instant::now() - start;
+    trace!("benchmarks built in {:.2} s", build_time.as_secs_f64());
+
+    fs::create_dir_all(job_output_dir).context("Unable to create dir for job output")?;
+
+    // The checkout is fresh, so this is the time of a clean release build
+    let json = serde_json::to_string(&(build_time.as_nanos() as f64)).expect("unreachable code");
+    fs::write(job_output_dir.join(BUILD_TIME_FILE), json).context("unable to write build time")?;
+
+    Ok(())
+}
+
+/// Builds the artifacts whose size is tracked (if any), storing their sizes in the job's output
+/// dir (see [`read_artifact_sizes`])
+pub(crate) fn build_artifacts(
+    checkout_target_dir: &Path,
+    job_output_dir: &Path,
+    bench_suite: &BenchSuiteConfig,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let Some((program, args)) = bench_suite.artifact_build_command.split_first() else {
+        trace!("skipping artifact size tracking");
+        return Ok(());
+    };
+
+    trace!("building artifacts to track their size");
+    let mut command = bench_suite.command(program);
+    command.args(args).current_dir(checkout_target_dir);
+
+    run_command(command, command_logs, FailureClass::Build, cancellation)?;
+
+    let sizes = artifact_sizes(checkout_target_dir, &bench_suite.artifacts);
+    let json = serde_json::to_string(&sizes).expect("unreachable code");
+    fs::write(job_output_dir.join(ARTIFACT_SIZES_FILE), json)
+        .context("unable to write artifact sizes")
+}
+
+/// Stores the steps to reproduce a run, derived from its logs, in the job's output dir (see
+/// [`read_reproduction`])
+pub(crate) fn write_reproduction(
+    logs: &[Log],
+    checkout_target_dir: &Path,
+    job_output_dir: &Path,
+    bench_path: &Path,
+) -> anyhow::Result<()> {
+    let reproduction = Reproduction::from_logs(
+        logs,
+        checkout_target_dir,
+        job_output_dir,
+        tool_versions(bench_path),
+    );
+    let json = serde_json::to_string(&reproduction).expect("unreachable code");
+    fs::write(job_output_dir.join(REPRODUCTION_FILE), json)
+        .context("unable to write reproduction steps")
+}
+
+/// Runs the icount benchmarks of a bench suite, storing their results in the job's output dir
+fn run_icount_benchmarks(
+    bench_suite: &BenchSuiteConfig,
+    bench_exe_path: &Path,
+    bench_path: &Path,
+    job_output_dir: &Path,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let results_path = bench_suite.results_path(job_output_dir);
+    let mut command = bench_suite.command(bench_exe_path);
+    command
+        .args(
+            bench_suite
+                .icount_args
+                .iter()
+                .map(|arg| arg.replace("{results_dir}", &results_path.display().to_string())),
         )
-        .context("failed to write walltimes to disk")?;
+        .current_dir(bench_path);
 
-        trace!(
-            "walltime benchmarks run in {:.2} s",
-            (Instant::now() - start).as_secs_f64()
-        );
+    run_command(
+        command,
+        command_logs,
+        FailureClass::HarnessCrash,
+        cancellation,
+    )?;
 
-        Ok(())
+    let icounts_path = bench_suite.icounts_path(job_output_dir);
+    if !icounts_path.is_file() {
+        return Err(anyhow!(
+            "the icount benchmarks did not produce the expected results file at {}",
+            icounts_path.display()
+        )
+        .context(FailureClass::HarnessCrash));
+    }
+
+    Ok(())
+}
+
+/// Runs the walltime benchmarks of a bench suite (under setarch to disable ASLR, to reduce noise),
+/// storing their results in the job's output dir
+///
+/// The energy benchmarks run afterwards, if a RAPL zone is configured
+pub(crate) fn run_walltime_benchmarks(
+    bench_suite: &BenchSuiteConfig,
+    bench_exe_path: &Path,
+    bench_path: &Path,
+    job_output_dir: &Path,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let mut command = bench_suite.command("setarch");
+    command
+        .arg("-R")
+        .arg(bench_exe_path)
+        .args(&bench_suite.walltime_args)
+        .current_dir(bench_path);
+
+    run_command(
+        command,
+        command_logs,
+        FailureClass::HarnessCrash,
+        cancellation,
+    )?;
+
+    // The walltimes are printed to stdout and captured in the logs, but we want them in a file
+    let results_path = bench_suite.results_path(job_output_dir);
+    fs::create_dir_all(&results_path).context("Unable to create dir for results")?;
+    fs::write(
+        bench_suite.walltimes_path(job_output_dir),
+        &command_logs.last().unwrap().stdout,
+    )
+    .context("failed to write walltimes to disk")?;
+
+    if let Some(rapl_zone) = &bench_suite.rapl_zone {
+        run_energy_benchmarks(
+            bench_suite,
+            rapl_zone,
+            bench_exe_path,
+            bench_path,
+            job_output_dir,
+            command_logs,
+            cancellation,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Runs the memory usage benchmarks of a bench suite, storing their results in the job's output
+/// dir
+pub(crate) fn run_max_rss_benchmarks(
+    bench_suite: &BenchSuiteConfig,
+    bench_exe_path: &Path,
+    bench_path: &Path,
+    job_output_dir: &Path,
+    command_logs: &mut Vec<Log>,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
+    let results_path = bench_suite.results_path(job_output_dir);
+    fs::create_dir_all(&results_path).context("Unable to create dir for results")?;
+
+    let mut command = bench_suite.command(bench_exe_path);
+    command
+        .args(
+            bench_suite
+                .max_rss_args
+                .iter()
+                .map(|arg| arg.replace("{results_dir}", &results_path.display().to_string())),
+        )
+        .current_dir(bench_path);
+
+    run_command(
+        command,
+        command_logs,
+        FailureClass::HarnessCrash,
+        cancellation,
+    )?;
+
+    let max_rss_path = bench_suite.max_rss_path(job_output_dir);
+    if !max_rss_path.is_file() {
+        return Err(anyhow!(
+            "the memory usage benchmarks did not produce the expected results file at {}",
+            max_rss_path.display()
+        )
+        .context(FailureClass::HarnessCrash));
+    }
+
+    Ok(())
+}
+
+/// The name of the file where the discrepancy found by the determinism check is stored, in the
+/// output dir of the run (it is missing if the check passed or didn't run)
+static UNSTABLE_ENVIRONMENT_FILE: &str = "unstable_environment.json";
+
+/// The subdirectory of a run's output dir holding the results of the determinism check
+static DETERMINISM_CHECK_DIR: &str = "determinism_check";
+
+/// Differing instruction counts of a scenario that was benchmarked twice in a row with the same
+/// build, which means the environment is unstable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeterminismDiscrepancy {
+    pub scenario: String,
+    pub first_icount: f64,
+    pub second_icount: f64,
+}
+
+impl DeterminismDiscrepancy {
+    /// Returns the ratio of change of the second measurement respective to the first one
+    pub fn diff_ratio(&self) -> f64 {
+        (self.second_icount - self.first_icount) / self.first_icount
+    }
+}
+
+/// Returns the discrepancy found by the determinism check of the run whose output is stored in the
+/// provided dir, if the run was flagged as environmentally unstable
+pub fn read_determinism_discrepancy(
+    job_output_dir: &Path,
+) -> anyhow::Result<Option<DeterminismDiscrepancy>> {
+    let path = job_output_dir.join(UNSTABLE_ENVIRONMENT_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path).context("unable to read determinism check results")?;
+    let discrepancy = serde_json::from_str(&json).context("invalid determinism check results")?;
+    Ok(Some(discrepancy))
+}
+
+/// The name of the file where the wall time of the benchmarks' build is stored (in nanoseconds), in
+/// the output dir of the run
+static BUILD_TIME_FILE: &str = "build_time.json";
+
+/// Returns the wall time of the build (in nanoseconds) of the run whose output is stored in the
+/// provided dir, if it was measured
+pub fn read_build_time(job_output_dir: &Path) -> anyhow::Result<Option<f64>> {
+    let path = job_output_dir.join(BUILD_TIME_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path).context("unable to read build time")?;
+    let build_time = serde_json::from_str(&json).context("invalid build time")?;
+    Ok(Some(build_time))
+}
+
+/// The name of the file where the size of the tracked artifacts is stored, in the output dir of
+/// the run (it is missing if artifact sizes are not tracked)
+static ARTIFACT_SIZES_FILE: &str = "artifact_sizes.json";
+
+/// Returns the size in bytes of the artifacts, by path, skipping artifacts that don't exist
+fn artifact_sizes(checkout_dir: &Path, artifacts: &[PathBuf]) -> BTreeMap<String, u64> {
+    let mut sizes = BTreeMap::new();
+    for artifact in artifacts {
+        match fs::metadata(checkout_dir.join(artifact)) {
+            Ok(metadata) => {
+                sizes.insert(artifact.display().to_string(), metadata.len());
+            }
+            Err(e) => trace!("skipping size of artifact {}: {e}", artifact.display()),
+        }
+    }
+
+    sizes
+}
+
+/// Returns the size in bytes of the artifacts built by the run whose output is stored in the
+/// provided dir, if artifact sizes are tracked
+pub fn read_artifact_sizes(job_output_dir: &Path) -> anyhow::Result<Option<BTreeMap<String, u64>>> {
+    let path = job_output_dir.join(ARTIFACT_SIZES_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path).context("unable to read artifact sizes")?;
+    let sizes = serde_json::from_str(&json).context("invalid artifact sizes")?;
+    Ok(Some(sizes))
+}
+
+/// The name of the file describing how to reproduce the measurements of a run, in the output dir
+/// of the run
+static REPRODUCTION_FILE: &str = "reproduction.json";
+
+/// The steps to reproduce the measurements of a run locally, derived from the commands that were
+/// actually executed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Reproduction {
+    /// Shell commands, to be run from an empty directory
+    pub commands: Vec<String>,
+    /// Versions of the tools used to build and run the benchmarks
+    pub toolchain: Vec<String>,
+}
+
+impl Reproduction {
+    /// Turns the logs of a run into shell commands, replacing the paths that only exist on the
+    /// bench runner by the `$REPO` (checkout) and `$OUTPUT` (job output) variables
+    fn from_logs(
+        logs: &[Log],
+        checkout_dir: &Path,
+        job_output_dir: &Path,
+        toolchain: Vec<String>,
+    ) -> Self {
+        let checkout = checkout_dir.display().to_string();
+        let output = job_output_dir.display().to_string();
+
+        let mut commands = vec![
+            r#"export REPO="$PWD/repo" OUTPUT="$PWD/output""#.to_string(),
+            r#"mkdir -p "$REPO" "$OUTPUT" && cd "$REPO""#.to_string(),
+        ];
+        for log in logs {
+            let command = log
+                .command
+                .replace(&output, "$OUTPUT")
+                .replace(&checkout, "$REPO");
+            let command = match Path::new(&log.cwd).strip_prefix(checkout_dir) {
+                Ok(dir) if !dir.as_os_str().is_empty() => {
+                    format!("(cd {} && {command})", dir.display())
+                }
+                _ => command,
+            };
+            commands.push(command);
+        }
+
+        Self {
+            commands,
+            toolchain,
+        }
+    }
+}
+
+/// Returns the versions of the tools used to build and run the benchmarks, as reported from the
+/// provided dir (which might select a toolchain of its own)
+///
+/// Tools that can't be found are left out
+fn tool_versions(dir: &Path) -> Vec<String> {
+    ["rustc", "cargo", "valgrind"]
+        .into_iter()
+        .filter_map(|program| {
+            let output = Command::new(program)
+                .arg("--version")
+                .current_dir(dir)
+                .output()
+                .ok()?;
+            let version = String::from_utf8_lossy(&output.stdout);
+            let version = version.lines().next()?.trim();
+            (output.status.success() && !version.is_empty()).then(|| version.to_string())
+        })
+        .collect()
+}
+
+/// Returns the steps to reproduce the measurements of the run whose output is stored in the
+/// provided dir, if available
+pub fn read_reproduction(job_output_dir: &Path) -> anyhow::Result<Option<Reproduction>> {
+    let path = job_output_dir.join(REPRODUCTION_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+
+    let json = fs::read_to_string(&path).context("unable to read reproduction steps")?;
+    let reproduction = serde_json::from_str(&json).context("invalid reproduction steps")?;
+    Ok(Some(reproduction))
+}
+
+/// Returns whether the scenario's name matches the glob, where `*` matches any sequence of
+/// characters and `?` matches a single character
+pub fn matches_glob(glob: &str, scenario: &str) -> bool {
+    let glob = glob.chars().collect::<Vec<_>>();
+    let scenario = scenario.chars().collect::<Vec<_>>();
+
+    // Backtracking matcher, where only the last `*` needs to be revisited
+    let (mut g, mut s) = (0, 0);
+    let mut last_star = None;
+    while s < scenario.len() {
+        match glob.get(g) {
+            Some('*') => {
+                last_star = Some((g, s));
+                g += 1;
+            }
+            Some(&c) if c == '?' || c == scenario[s] => {
+                g += 1;
+                s += 1;
+            }
+            _ => match last_star {
+                Some((star_g, star_s)) => {
+                    last_star = Some((star_g, star_s + 1));
+                    g = star_g + 1;
+                    s = star_s + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    glob[g..].iter().all(|&c| c == '*')
+}
+
+/// Executables that must be available in the PATH to run and compare benchmarks
+static REQUIRED_EXECUTABLES: &[&str] =
+    &["git", "cargo", "setarch", "valgrind", "callgrind_annotate"];
+
+/// Returns the required executables that could not be found in the PATH
+pub fn missing_executables() -> Vec<&'static str> {
+    let paths: Vec<_> = env::var_os("PATH")
+        .map(|path| env::split_paths(&path).collect())
+        .unwrap_or_default();
+
+    REQUIRED_EXECUTABLES
+        .iter()
+        .copied()
+        .filter(|exe| !paths.iter().any(|dir| dir.join(exe).is_file()))
+        .collect()
+}
+
+/// The exit status used by `timeout(1)` when the command it wraps times out
+const TIMEOUT_EXIT_STATUS: i32 = 124;
+
+/// The class of a benchmarking failure, used to explain the failure to users and to keep track of
+/// failure rates
+///
+/// The runner attaches the class to its errors as context, so it can be recovered from the error
+/// chain through [`FailureClass::of`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FailureClass {
+    /// The commit (or patch series) could not be fetched or checked out
+    Clone,
+    /// The benchmarks could not be built
+    Build,
+    /// The benchmark harness crashed or did not produce the expected results
+    HarnessCrash,
+    /// A command took longer than allowed (i.e. it was wrapped in `timeout` and exited with 124)
+    Timeout,
+    /// Anything else, which usually means something is wrong with the benchmarking infrastructure
+    Infrastructure,
+}
+
+impl FailureClass {
+    /// Returns the class of the error, defaulting to an infrastructure error for unclassified ones
+    pub fn of(error: &anyhow::Error) -> Self {
+        error
+            .downcast_ref::<FailureClass>()
+            .copied()
+            .unwrap_or(FailureClass::Infrastructure)
+    }
+
+    /// Parses a class from its stable identifier (see [`FailureClass::as_str`])
+    pub fn parse(class: &str) -> Option<Self> {
+        Some(match class {
+            "clone" => FailureClass::Clone,
+            "build" => FailureClass::Build,
+            "harness_crash" => FailureClass::HarnessCrash,
+            "timeout" => FailureClass::Timeout,
+            "infrastructure" => FailureClass::Infrastructure,
+            _ => return None,
+        })
+    }
+
+    /// A stable identifier for the class, as stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FailureClass::Clone => "clone",
+            FailureClass::Build => "build",
+            FailureClass::HarnessCrash => "harness_crash",
+            FailureClass::Timeout => "timeout",
+            FailureClass::Infrastructure => "infrastructure",
+        }
+    }
+
+    /// A human-readable headline describing the failure
+    pub fn headline(&self) -> &'static str {
+        match self {
+            FailureClass::Clone => "The code to benchmark could not be checked out",
+            FailureClass::Build => "The benchmarks failed to build",
+            FailureClass::HarnessCrash => "The benchmark harness crashed",
+            FailureClass::Timeout => "The benchmarks timed out",
+            FailureClass::Infrastructure => "The benchmarking infrastructure ran into an error",
+        }
+    }
+
+    /// A suggestion of what to do next about the failure
+    pub fn next_step(&self) -> &'static str {
+        match self {
+            FailureClass::Clone => {
+                "Make sure the commits still exist (e.g. they haven't been lost to a force-push) and, \
+                for patch series, that they apply cleanly on top of the baseline."
+            }
+            FailureClass::Build => {
+                "Check the build logs below. If the PR builds locally, the benchmarks might need \
+                to be adapted to the PR's changes."
+            }
+            FailureClass::HarnessCrash => {
+                "Check the benchmark logs below, and try reproducing the crash locally by running \
+                the benchmarks."
+            }
+            FailureClass::Timeout => {
+                "The PR might have made the benchmarks much slower. Check the logs below to find \
+                out which step took too long."
+            }
+            FailureClass::Infrastructure => {
+                "This is probably not caused by the PR. Try again later, and reach out to the \
+                maintainers of the benchmarking infrastructure if the problem persists."
+            }
+        }
+    }
+}
+
+impl Display for FailureClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let description = match self {
+            FailureClass::Clone => "clone failure",
+            FailureClass::Build => "build failure",
+            FailureClass::HarnessCrash => "harness crash",
+            FailureClass::Timeout => "timeout",
+            FailureClass::Infrastructure => "infrastructure error",
+        };
+        f.write_str(description)
     }
 }
 
 /// Runs a command and pushes its logs to the provided buffer
-fn run_command(mut command: Command, logs: &mut Vec<Log>) -> anyhow::Result<()> {
+///
+/// If the command fails, the error is classified as `class` (or as a timeout, if the command's
+/// exit status indicates it timed out or the job exceeded its time limit). The command is killed if
+/// the job gets cancelled.
+pub(crate) fn run_command(
+    mut command: Command,
+    logs: &mut Vec<Log>,
+    class: FailureClass,
+    cancellation: &CancellationToken,
+) -> anyhow::Result<()> {
     // Get the command string
     let mut command_str = String::new();
     command_str.push_str(&command.get_program().to_string_lossy());
@@ -165,10 +1509,39 @@ fn run_command(mut command: Command, logs: &mut Vec<Log>) -> anyhow::Result<()>
         .map(|p| p.display().to_string())
         .unwrap_or("/".to_string());
 
-    // Run the command
-    let output = command.output().context(format!(
-        "failed to start command: `{command_str}` at cwd `{cwd}`"
-    ))?;
+    // Run the command, unless the job was cancelled (the process groups are locked until the
+    // command is registered, so a concurrent cancellation can't miss it)
+    let mut process_groups = cancellation.0.process_groups.lock().unwrap();
+    if cancellation.is_timed_out() {
+        return Err(
+            anyhow!("the job exceeded its time limit before running `{command_str}`")
+                .context(FailureClass::Timeout),
+        );
+    }
+    if cancellation.is_cancelled() {
+        bail!("the job was cancelled before running `{command_str}`");
+    }
+    let child = command
+        .process_group(0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context(format!(
+            "failed to start command: `{command_str}` at cwd `{cwd}`"
+        ))?;
+    let process_group = child.id();
+    process_groups.insert(process_group);
+    drop(process_groups);
+
+    let output = child.wait_with_output();
+    cancellation
+        .0
+        .process_groups
+        .lock()
+        .unwrap()
+        .remove(&process_group);
+    let output = output.context(format!("failed to wait for command: `{command_str}`"))?;
 
     logs.push(Log {
         command: command_str,
@@ -180,10 +1553,22 @@ fn run_command(mut command: Command, logs: &mut Vec<Log>) -> anyhow::Result<()>
     // Propagate errors
     if !output.status.success() {
         let command_str = &logs.last().unwrap().command;
-        bail!(
+        if cancellation.is_timed_out() {
+            return Err(anyhow!(
+                "`{command_str}` was killed because the job exceeded its time limit"
+            )
+            .context(FailureClass::Timeout));
+        }
+
+        let class = match output.status.code() {
+            Some(TIMEOUT_EXIT_STATUS) => FailureClass::Timeout,
+            _ => class,
+        };
+        return Err(anyhow!(
             "`{command_str}` exited with exit status {:?}",
             output.status.code()
-        );
+        )
+        .context(class));
     }
 
     Ok(())
@@ -223,3 +1608,375 @@ fn write_log_part(s: &mut String, part_name: &str, part: &str) {
         writeln!(s, "\n```\n{}\n```\n", part.trim_end()).ok();
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_command_classifies_failures() {
+        let run = |script: &str| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(script);
+            run_command(
+                command,
+                &mut Vec::new(),
+                FailureClass::Build,
+                &CancellationToken::default(),
+            )
+        };
+
+        assert!(run("exit 0").is_ok());
+        assert_eq!(
+            FailureClass::of(&run("exit 1").unwrap_err()),
+            FailureClass::Build
+        );
+        assert_eq!(
+            FailureClass::of(&run("exit 124").unwrap_err()),
+            FailureClass::Timeout
+        );
+
+        // Classes survive additional context, and unclassified errors are infrastructure errors
+        let error = run("exit 1").context("benchmarking failed").unwrap_err();
+        assert_eq!(FailureClass::of(&error), FailureClass::Build);
+        assert_eq!(
+            FailureClass::of(&anyhow!("disk full")),
+            FailureClass::Infrastructure
+        );
+    }
+
+    #[test]
+    fn cancellation_kills_running_commands() {
+        let cancellation = CancellationToken::default();
+        let run = |cancellation: &CancellationToken| {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg("sleep 30; echo done");
+            run_command(
+                command,
+                &mut Vec::new(),
+                FailureClass::HarnessCrash,
+                cancellation,
+            )
+        };
+
+        let start = Instant::now();
+        let running = std::thread::spawn({
+            let cancellation = cancellation.clone();
+            move || run(&cancellation)
+        });
+        while cancellation.0.process_groups.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        cancellation.cancel();
+
+        assert!(running.join().unwrap().is_err());
+        assert!(start.elapsed() < Duration::from_secs(10));
+
+        // No further commands are started
+        assert!(cancellation.is_cancelled());
+        assert!(run(&cancellation).is_err());
+    }
+
+    #[test]
+    fn timed_out_commands_are_classified_as_timeouts() {
+        let cancellation = CancellationToken::default();
+        let running = std::thread::spawn({
+            let cancellation = cancellation.clone();
+            move || {
+                let mut command = Command::new("sh");
+                command.arg("-c").arg("sleep 30; echo done");
+                run_command(command, &mut Vec::new(), FailureClass::Build, &cancellation)
+            }
+        });
+        while cancellation.0.process_groups.lock().unwrap().is_empty() {
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        cancellation.time_out();
+
+        let error = running.join().unwrap().unwrap_err();
+        assert_eq!(FailureClass::of(&error), FailureClass::Timeout);
+        assert!(format!("{error:#}").contains("exceeded its time limit"));
+    }
+
+    #[test]
+    fn quick_suite_only_runs_quick_icount_scenarios() {
+        assert_eq!(BenchSuiteConfig::default().quick_suite(), None);
+
+        let config = BenchSuiteConfig {
+            quick_scenarios: vec!["a".to_string(), "b".to_string()],
+            quick_icount_args: vec!["--scenarios".to_string(), "{scenarios}".to_string()],
+            ..BenchSuiteConfig::default()
+        };
+        let quick = config.quick_suite().unwrap();
+        assert_eq!(
+            quick.icount_args,
+            [
+                "run-all",
+                "--output-dir",
+                "{results_dir}",
+                "--scenarios",
+                "a,b"
+            ]
+        );
+        assert!(quick.walltime_args.is_empty());
+    }
+
+    #[test]
+    fn filtered_suite_passes_glob_to_benchmarks() {
+        let config = BenchSuiteConfig {
+            filter_icount_args: vec!["--filter".to_string(), "{filter}".to_string()],
+            ..BenchSuiteConfig::default()
+        };
+        let filtered = config.filtered_suite("handshake_*").unwrap();
+        assert_eq!(
+            filtered.icount_args,
+            [
+                "run-all",
+                "--output-dir",
+                "{results_dir}",
+                "--filter",
+                "handshake_*"
+            ]
+        );
+        assert_eq!(filtered.walltime_args, config.walltime_args);
+
+        // Without filter arguments, every scenario would be benchmarked
+        let error = BenchSuiteConfig::default()
+            .filtered_suite("handshake_*")
+            .unwrap_err();
+        assert!(error.to_string().contains("filter_icount_args"));
+    }
+
+    #[test]
+    fn untrusted_suite_uses_stricter_command_prefix() {
+        let strings = |args: &[&str]| args.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let config = BenchSuiteConfig {
+            command_prefix: strings(&["nice", "-n", "-5"]),
+            untrusted_command_prefix: strings(&["bwrap", "--unshare-net"]),
+            ..BenchSuiteConfig::default()
+        };
+
+        let command = config.command("cargo");
+        assert_eq!(command.get_program(), "nice");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["-n", "-5", "cargo"]
+        );
+
+        let command = config.untrusted_suite().unwrap().command("cargo");
+        assert_eq!(command.get_program(), "bwrap");
+        assert_eq!(
+            command.get_args().collect::<Vec<_>>(),
+            ["--unshare-net", "cargo"]
+        );
+
+        // Forks are never run without a stricter sandbox
+        let config = BenchSuiteConfig::default();
+        assert!(config.untrusted_suite().is_err());
+    }
+
+    #[test]
+    fn determinism_discrepancy_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(read_determinism_discrepancy(dir.path()).unwrap(), None);
+
+        let discrepancy = DeterminismDiscrepancy {
+            scenario: "handshake_tls13".to_string(),
+            first_icount: 1000.0,
+            second_icount: 1010.0,
+        };
+        let json = serde_json::to_string(&discrepancy).unwrap();
+        fs::write(dir.path().join(UNSTABLE_ENVIRONMENT_FILE), json).unwrap();
+        assert_eq!(
+            read_determinism_discrepancy(dir.path()).unwrap(),
+            Some(discrepancy.clone())
+        );
+        assert_eq!(discrepancy.diff_ratio(), 0.01);
+    }
+
+    #[test]
+    fn determinism_check_flags_unstable_runs_after_retries() {
+        let config = DeterminismCheckConfig {
+            scenario: "handshake_tls13".to_string(),
+            max_difference_ppm: 1000,
+            retries: 1,
+        };
+
+        // Differences within the tolerance pass
+        let dir = tempfile::tempdir().unwrap();
+        let mut icounts = [1000.0, 1000.5].into_iter();
+        config.run(dir.path(), |_| Ok(icounts.next())).unwrap();
+        assert_eq!(read_determinism_discrepancy(dir.path()).unwrap(), None);
+
+        // A retry that passes clears the run
+        let dir = tempfile::tempdir().unwrap();
+        let mut icounts = [1000.0, 1010.0, 1000.0, 1000.0].into_iter();
+        config.run(dir.path(), |_| Ok(icounts.next())).unwrap();
+        assert_eq!(read_determinism_discrepancy(dir.path()).unwrap(), None);
+
+        // Once the retries are exhausted, the last discrepancy is stored
+        let dir = tempfile::tempdir().unwrap();
+        let mut icounts = [1000.0, 1010.0, 1000.0, 1020.0].into_iter();
+        config.run(dir.path(), |_| Ok(icounts.next())).unwrap();
+        let discrepancy = read_determinism_discrepancy(dir.path()).unwrap().unwrap();
+        assert_eq!(discrepancy.second_icount, 1020.0);
+
+        // A scenario that wasn't benchmarked means the harness is broken
+        let dir = tempfile::tempdir().unwrap();
+        let error = config.run(dir.path(), |_| Ok(None)).unwrap_err();
+        assert_eq!(FailureClass::of(&error), FailureClass::HarnessCrash);
+    }
+
+    #[test]
+    fn reproduction_from_logs_replaces_runner_paths() {
+        let log = |command: &str, cwd: &str| Log {
+            command: command.to_string(),
+            cwd: cwd.to_string(),
+            stdout: Vec::new(),
+            stderr: Vec::new(),
+        };
+        let logs = [
+            log("git checkout abc123", "/tmp/checkout"),
+            log("cargo build --locked --release", "/tmp/checkout/ci-bench"),
+            log(
+                "/tmp/checkout/target/release/rustls-ci-bench run-all --output-dir /jobs/1/results",
+                "/tmp/checkout/ci-bench",
+            ),
+        ];
+
+        let reproduction = Reproduction::from_logs(
+            &logs,
+            Path::new("/tmp/checkout"),
+            Path::new("/jobs/1"),
+            vec!["rustc 1.75.0".to_string()],
+        );
+        assert_eq!(
+            &reproduction.commands[2..],
+            [
+                "git checkout abc123",
+                "(cd ci-bench && cargo build --locked --release)",
+                "(cd ci-bench && $REPO/target/release/rustls-ci-bench run-all --output-dir $OUTPUT/results)",
+            ]
+        );
+        assert_eq!(reproduction.toolchain, ["rustc 1.75.0"]);
+    }
+
+    #[test]
+    fn glob_matching() {
+        assert!(matches_glob("handshake_tls13*", "handshake_tls13_client"));
+        assert!(matches_glob("*_client", "handshake_tls13_client"));
+        assert!(matches_glob(
+            "handshake_*_client",
+            "handshake_tls13_resume_client"
+        ));
+        assert!(matches_glob(
+            "transfer_tls1?_client",
+            "transfer_tls12_client"
+        ));
+        assert!(matches_glob("exact", "exact"));
+        assert!(!matches_glob("handshake_tls13*", "handshake_tls12_client"));
+        assert!(!matches_glob("*_server", "handshake_tls13_client"));
+        assert!(!matches_glob("exact", "exact_not"));
+    }
+
+    #[test]
+    fn walltime_suite_only_runs_walltime_benchmarks() {
+        let walltime = BenchSuiteConfig::default().walltime_suite().unwrap();
+        assert!(walltime.icount_args.is_empty());
+        assert_eq!(
+            walltime.walltime_args,
+            BenchSuiteConfig::default().walltime_args
+        );
+
+        let config = BenchSuiteConfig {
+            walltime_args: Vec::new(),
+            ..BenchSuiteConfig::default()
+        };
+        assert_eq!(config.walltime_suite(), None);
+    }
+
+    #[test]
+    fn scenarios_suite_only_runs_provided_scenarios() {
+        let config = BenchSuiteConfig {
+            quick_icount_args: vec!["--scenarios".to_string(), "{scenarios}".to_string()],
+            max_rss_args: vec!["max-rss".to_string()],
+            ..BenchSuiteConfig::default()
+        };
+
+        let scenarios = |names: &[&str]| names.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+        let suite = config.scenarios_suite(&scenarios(&["a", "b"]), &[], &[]);
+        assert!(suite.walltime_args.is_empty());
+        assert!(suite.max_rss_args.is_empty());
+        assert_eq!(suite.icount_args.len(), config.icount_args.len() + 2);
+        assert_eq!(suite.icount_args.last().unwrap(), "a,b");
+
+        let suite = config.scenarios_suite(&[], &scenarios(&["c"]), &[]);
+        assert!(suite.icount_args.is_empty());
+        assert_eq!(suite.walltime_args, config.walltime_args);
+
+        let suite = config.scenarios_suite(&[], &[], &scenarios(&["d"]));
+        assert!(suite.icount_args.is_empty());
+        assert!(suite.walltime_args.is_empty());
+        assert_eq!(suite.max_rss_args, config.max_rss_args);
+    }
+
+    #[test]
+    fn host_load_parse_and_busy_reason() {
+        let loadavg = "0.41 0.44 0.41 2/72 30221\n";
+        let meminfo = "MemTotal:       16384000 kB\nMemAvailable:    5516448 kB\n";
+        let load = HostLoad::parse(loadavg, meminfo).unwrap();
+        assert_eq!(
+            load,
+            HostLoad {
+                load_average: 0.41,
+                running_processes: 2,
+                available_memory_mib: 5387,
+            }
+        );
+
+        let config = HostIdleConfig::default();
+        assert_eq!(load.busy_reason(&config), None);
+
+        let config = HostIdleConfig {
+            max_load_average_percent: 25,
+            ..HostIdleConfig::default()
+        };
+        assert_eq!(
+            load.busy_reason(&config).unwrap(),
+            "load average 0.41 > 0.25"
+        );
+    }
+
+    #[test]
+    fn host_load_parse_invalid() {
+        assert!(HostLoad::parse("", "MemAvailable: 1 kB").is_err());
+        assert!(HostLoad::parse("0.41 0.44 0.41 2/72 30221", "").is_err());
+    }
+
+    #[test]
+    fn git_source_provider_uses_mirrors() {
+        let provider = GitSourceProvider::new(vec![
+            SourceMirrorConfig {
+                url_prefix: "https://github.com/rustls/".to_string(),
+                mirror_prefix: "/srv/git/rustls/".to_string(),
+            },
+            SourceMirrorConfig {
+                url_prefix: "https://github.com/".to_string(),
+                mirror_prefix: "https://gitlab.example.com/github/".to_string(),
+            },
+        ]);
+
+        assert_eq!(
+            provider.fetch_url("https://github.com/rustls/rustls.git"),
+            "/srv/git/rustls/rustls.git"
+        );
+        assert_eq!(
+            provider.fetch_url("https://github.com/someone/rustls.git"),
+            "https://gitlab.example.com/github/someone/rustls.git"
+        );
+        assert_eq!(
+            provider.fetch_url("https://example.com/rustls.git"),
+            "https://example.com/rustls.git"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use serde::{Deserialize, Serialize};
+use time::{OffsetDateTime, Time};
+use tracing::{error, info};
+
+use crate::event_queue::EventQueue;
+
+/// How far ahead the next occurrence of a schedule is looked for (long enough to reach the next
+/// February 29th)
+const MAX_SCHEDULE_LOOKAHEAD: time::Duration = time::Duration::days(4 * 366);
+
+/// An internal job enqueued on a schedule, besides the jobs triggered by webhook events
+#[derive(Debug, Clone, Eq, PartialEq, Deserialize)]
+pub struct ScheduledJobConfig {
+    /// Identifies the job in the logs and in the job list
+    pub name: String,
+    /// When to enqueue the job, as a cron expression in UTC (e.g. `0 3 * * *` for every night at
+    /// 3:00)
+    pub cron: String,
+    pub task: ScheduledTask,
+}
+
+/// The work done by a scheduled job
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScheduledTask {
+    /// Benchmarks the head of main again, even if it was already benchmarked, to catch drifts of
+    /// the environment between pushes
+    BenchMain,
+    /// Compares the head of main against itself, to measure how noisy each scenario is
+    NoiseCalibration,
+    /// Deletes the records that are no longer useful (e.g. old dropped webhooks)
+    PruneDb,
+}
+
+/// A scheduled job, as enqueued in the event queue
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJobRequest {
+    /// The name of the scheduled job in the configuration
+    pub name: String,
+    pub task: ScheduledTask,
+}
+
+/// A parsed cron expression, with the standard five fields (minute, hour, day of the month, month
+/// and day of the week)
+///
+/// Fields accept `*`, numbers, ranges (`1-5`), steps (`*/15` or `0-30/10`) and lists of them
+/// (`1,15`). Like in cron, a day matches if it matches either of the day fields when both are
+/// restricted.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CronSchedule {
+    minutes: u64,
+    hours: u64,
+    days_of_month: u64,
+    months: u64,
+    days_of_week: u64,
+    days_of_month_restricted: bool,
+    days_of_week_restricted: bool,
+}
+
+impl FromStr for CronSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(expression: &str) -> Result<Self, Self::Err> {
+        let fields: Vec<_> = expression.split_whitespace().collect();
+        let [minutes, hours, days_of_month, months, days_of_week] = fields[..] else {
+            bail!("expected 5 fields in cron expression `{expression}`");
+        };
+
+        let mut parsed_days_of_week = parse_cron_field(days_of_week, 0, 7)
+            .with_context(|| format!("invalid day of the week in `{expression}`"))?;
+        // Both 0 and 7 mean Sunday
+        if parsed_days_of_week & (1 << 7) != 0 {
+            parsed_days_of_week |= 1;
+        }
+
+        Ok(Self {
+            minutes: parse_cron_field(minutes, 0, 59)
+                .with_context(|| format!("invalid minute in `{expression}`"))?,
+            hours: parse_cron_field(hours, 0, 23)
+                .with_context(|| format!("invalid hour in `{expression}`"))?,
+            days_of_month: parse_cron_field(days_of_month, 1, 31)
+                .with_context(|| format!("invalid day of the month in `{expression}`"))?,
+            months: parse_cron_field(months, 1, 12)
+                .with_context(|| format!("invalid month in `{expression}`"))?,
+            days_of_week: parsed_days_of_week,
+            days_of_month_restricted: days_of_month != "*",
+            days_of_week_restricted: days_of_week != "*",
+        })
+    }
+}
+
+impl CronSchedule {
+    /// Returns the first time matching the schedule strictly after `after`, if any
+    pub fn next_after(&self, after: OffsetDateTime) -> Option<OffsetDateTime> {
+        let limit = after + MAX_SCHEDULE_LOOKAHEAD;
+        let start_of_minute = Time::from_hms(after.hour(), after.minute(), 0).ok()?;
+        let mut next = after.replace_time(start_of_minute) + time::Duration::minutes(1);
+
+        while next < limit {
+            let start_of_day = next.replace_time(Time::MIDNIGHT);
+            if !matches(self.months, u8::from(next.month())) || !self.matches_day(next) {
+                next = start_of_day + time::Duration::days(1);
+            } else if !matches(self.hours, next.hour()) {
+                next = start_of_day + time::Duration::hours(i64::from(next.hour()) + 1);
+            } else if !matches(self.minutes, next.minute()) {
+                next += time::Duration::minutes(1);
+            } else {
+                return Some(next);
+            }
+        }
+
+        None
+    }
+
+    fn matches_day(&self, date: OffsetDateTime) -> bool {
+        let day_of_month = matches(self.days_of_month, date.day());
+        let day_of_week = matches(self.days_of_week, date.weekday().number_days_from_sunday());
+        if self.days_of_month_restricted && self.days_of_week_restricted {
+            day_of_month || day_of_week
+        } else {
+            day_of_month && day_of_week
+        }
+    }
+}
+
+fn matches(field: u64, value: u8) -> bool {
+    field & (1 << value) != 0
+}
+
+/// Parses a field of a cron expression into a bit set of the values it matches
+fn parse_cron_field(field: &str, min: u8, max: u8) -> anyhow::Result<u64> {
+    let mut values = 0;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse().context("invalid step")?),
+            None => (item, 1),
+        };
+        if step == 0 {
+            bail!("the step must be positive");
+        }
+
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (
+                start.parse().context("invalid range start")?,
+                end.parse().context("invalid range end")?,
+            ),
+            None => {
+                let value = range.parse().context("invalid value")?;
+                // Like in cron, `5/10` means starting at 5 every 10 until the end
+                let end = if step > 1 { max } else { value };
+                (value, end)
+            }
+        };
+        if start < min || end > max || start > end {
+            bail!("`{item}` is out of the {min}-{max} range");
+        }
+
+        for value in (start..=end).step_by(step) {
+            values |= 1 << value;
+        }
+    }
+
+    Ok(values)
+}
+
+/// Spawns a background task per scheduled job, which enqueues the job each time its schedule is
+/// due
+///
+/// Fails if any of the schedules is invalid, so a typo is noticed when deploying
+pub fn spawn_scheduled_jobs(
+    jobs: &[ScheduledJobConfig],
+    event_queue: EventQueue,
+) -> anyhow::Result<()> {
+    let mut schedules = Vec::new();
+    for job in jobs {
+        let schedule: CronSchedule = job
+            .cron
+            .parse()
+            .with_context(|| format!("invalid schedule for scheduled job `{}`", job.name))?;
+        schedules.push((job.clone(), schedule));
+    }
+
+    for (job, schedule) in schedules {
+        let event_queue = event_queue.clone();
+        tokio::spawn(async move {
+            loop {
+                let now = OffsetDateTime::now_utc();
+                let Some(next_run) = schedule.next_after(now) else {
+                    error!("scheduled job `{}` will never run again", job.name);
+                    break;
+                };
+                info!("scheduled job `{}` will run at {next_run}", job.name);
+                let wait = Duration::try_from(next_run - now).unwrap_or_default();
+                tokio::time::sleep(wait).await;
+
+                let request = ScheduledJobRequest {
+                    name: job.name.clone(),
+                    task: job.task,
+                };
+                if let Err(e) = event_queue.enqueue_scheduled_job(&request).await {
+                    error!(
+                        cause = format!("{e:#}"),
+                        "unable to enqueue scheduled job `{}`", job.name
+                    );
+                }
+            }
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use time::{Date, Month};
+
+    use super::*;
+
+    fn datetime(year: i32, month: u8, day: u8, hour: u8, minute: u8) -> OffsetDateTime {
+        let month = Month::try_from(month).unwrap();
+        Date::from_calendar_date(year, month, day)
+            .unwrap()
+            .with_hms(hour, minute, 0)
+            .unwrap()
+            .assume_utc()
+    }
+
+    fn next(cron: &str, after: OffsetDateTime) -> OffsetDateTime {
+        cron.parse::<CronSchedule>()
+            .unwrap()
+            .next_after(after)
+            .unwrap()
+    }
+
+    #[test]
+    fn next_occurrence_of_schedules() {
+        // Every night at 3:00
+        let schedule = "0 3 * * *";
+        assert_eq!(
+            next(schedule, datetime(2024, 1, 15, 2, 59)),
+            datetime(2024, 1, 15, 3, 0)
+        );
+        assert_eq!(
+            next(schedule, datetime(2024, 1, 15, 3, 0)),
+            datetime(2024, 1, 16, 3, 0)
+        );
+
+        // Every Monday at 9:30 (2024-01-15 is a Monday)
+        assert_eq!(
+            next("30 9 * * 1", datetime(2024, 1, 15, 10, 0)),
+            datetime(2024, 1, 22, 9, 30)
+        );
+
+        // Every 15 minutes, and on Sundays written as 7
+        assert_eq!(
+            next("*/15 * * * *", datetime(2024, 1, 15, 10, 16)),
+            datetime(2024, 1, 15, 10, 30)
+        );
+        assert_eq!(
+            next("0 0 * * 7", datetime(2024, 1, 15, 10, 0)),
+            datetime(2024, 1, 21, 0, 0)
+        );
+
+        // Leap days, and either day field matching when both are restricted
+        assert_eq!(
+            next("0 0 29 2 *", datetime(2024, 3, 1, 0, 0)),
+            datetime(2028, 2, 29, 0, 0)
+        );
+        assert_eq!(
+            next("0 0 1 * 5", datetime(2024, 1, 15, 10, 0)),
+            datetime(2024, 1, 19, 0, 0)
+        );
+    }
+
+    #[test]
+    fn invalid_schedules_are_rejected() {
+        for schedule in [
+            "0 3 * *",
+            "60 * * * *",
+            "0 3 * * 8",
+            "*/0 * * * *",
+            "5-1 * * * *",
+        ] {
+            assert!(
+                schedule.parse::<CronSchedule>().is_err(),
+                "`{schedule}` was accepted"
+            );
+        }
+    }
+}
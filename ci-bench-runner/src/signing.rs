@@ -0,0 +1,123 @@
+use anyhow::{anyhow, Context};
+use ring::signature::{Ed25519KeyPair, KeyPair};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::db::{ComparisonResult, Verdict};
+use crate::AppConfig;
+
+/// The algorithm used to sign benchmark data, as reported by the public key endpoint
+pub static SIGNING_ALGORITHM: &str = "ed25519";
+
+/// Signs benchmark data with the deployment's key, so copies of the data that end up elsewhere
+/// (e.g. mirrors or exports) can be verified against the deployment's public key
+pub struct Signer {
+    key_pair: Ed25519KeyPair,
+}
+
+impl Signer {
+    /// Creates a signer from the configured key, or returns `None` if signing is disabled
+    pub fn from_config(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let Some(seed) = &config.signing_key else {
+            return Ok(None);
+        };
+
+        let seed = hex::decode(seed.trim()).context("the signing key is not valid hex")?;
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&seed)
+            .map_err(|_| anyhow!("the signing key must be a 32-byte Ed25519 seed"))?;
+        Ok(Some(Self { key_pair }))
+    }
+
+    /// Returns the hex-encoded public key, against which signatures can be verified
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key_pair.public_key().as_ref())
+    }
+
+    /// Signs the message, returning the hex-encoded signature
+    pub fn sign(&self, message: &[u8]) -> String {
+        hex::encode(self.key_pair.sign(message).as_ref())
+    }
+
+    /// Signs a summary of a stored comparison, returning the summary along with its signature
+    pub fn sign_comparison(
+        &self,
+        comparison_id: Uuid,
+        baseline_commit: &str,
+        candidate_commit: &str,
+        result: &ComparisonResult,
+    ) -> SignedSummary {
+        let summary = ComparisonSummary {
+            comparison_id,
+            baseline_commit,
+            candidate_commit,
+            verdict: result.verdict(),
+            result,
+        };
+        let summary = serde_json::to_string(&summary).expect("unreachable code");
+        let signature = self.sign(summary.as_bytes());
+
+        SignedSummary { summary, signature }
+    }
+}
+
+/// The data that gets signed for each comparison
+#[derive(Serialize)]
+struct ComparisonSummary<'a> {
+    comparison_id: Uuid,
+    baseline_commit: &'a str,
+    candidate_commit: &'a str,
+    verdict: Verdict,
+    #[serde(flatten)]
+    result: &'a ComparisonResult,
+}
+
+/// A JSON summary of a comparison, along with its signature
+///
+/// The summary is kept as the exact string that was signed, so it can be verified byte by byte
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SignedSummary {
+    pub summary: String,
+    /// Hex-encoded signature of the summary's UTF-8 bytes
+    pub signature: String,
+}
+
+#[cfg(test)]
+mod test {
+    use ring::signature::{UnparsedPublicKey, ED25519};
+
+    use super::*;
+    use crate::db::{ComparisonSubResult, ScenarioKind};
+
+    #[test]
+    fn signed_comparison_verifies_against_public_key() {
+        let key_pair = Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap();
+        let signer = Signer { key_pair };
+
+        let result = ComparisonResult::from_iter([
+            (
+                ScenarioKind::Icount,
+                ComparisonSubResult {
+                    diffs: Vec::new(),
+                    scenarios_missing_in_baseline: vec!["new_scenario".to_string()],
+                },
+            ),
+            (
+                ScenarioKind::Walltime,
+                ComparisonSubResult {
+                    diffs: Vec::new(),
+                    scenarios_missing_in_baseline: Vec::new(),
+                },
+            ),
+        ]);
+        let signed = signer.sign_comparison(Uuid::nil(), "abc", "def", &result);
+        assert!(signed.summary.contains(r#""candidate_commit":"def""#));
+
+        let public_key = hex::decode(signer.public_key_hex()).unwrap();
+        let public_key = UnparsedPublicKey::new(&ED25519, public_key);
+        let signature = hex::decode(&signed.signature).unwrap();
+        assert!(public_key
+            .verify(signed.summary.as_bytes(), &signature)
+            .is_ok());
+        assert!(public_key.verify(b"tampered", &signature).is_err());
+    }
+}
@@ -0,0 +1,31 @@
+use askama::Template;
+
+use crate::AppConfig;
+
+/// How far back scenario histories go, unless told otherwise
+pub const SCENARIO_HISTORY_WINDOW: time::Duration = time::Duration::days(90);
+
+/// A simple HTML page plotting the icount results of main over time, one chart per scenario
+///
+/// The charts are drawn client-side, using the data returned by the
+/// `/api/scenarios/<name>/history` endpoint
+#[derive(Template)]
+#[template(path = "trends_dashboard.html")]
+struct TrendsDashboard<'a> {
+    scenarios: &'a [String],
+    window_days: i64,
+    repo_owner: &'a str,
+    repo_name: &'a str,
+}
+
+/// Renders the trends dashboard for the provided scenarios
+pub fn render_dashboard(config: &AppConfig, scenarios: &[String]) -> String {
+    TrendsDashboard {
+        scenarios,
+        window_days: SCENARIO_HISTORY_WINDOW.whole_days(),
+        repo_owner: &config.github_repo_owner,
+        repo_name: &config.github_repo_name,
+    }
+    .render()
+    .expect("failed to render askama template")
+}
@@ -0,0 +1,121 @@
+use std::borrow::Borrow;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+/// The unit of a benchmark result
+#[derive(Copy, Clone, Debug, Default, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResultUnit {
+    #[default]
+    Instructions = 0,
+    Nanoseconds = 1,
+    Bytes = 2,
+    /// Cache misses (as simulated by cachegrind) or branch misses (as counted by `perf stat`)
+    Misses = 3,
+    /// CPU cycles, as estimated from cachegrind's events
+    Cycles = 4,
+    /// Energy, as measured by RAPL
+    Joules = 5,
+}
+
+impl ResultUnit {
+    /// Returns a scale that has enough resolution to represent both values
+    ///
+    /// Values that are shown next to each other (e.g. the baseline and candidate results of a
+    /// scenario) should be rendered with the same scale, so they are easy to compare
+    pub fn common_scale(&self, x: f64, y: f64) -> DisplayScale {
+        let max = x.abs().max(y.abs());
+        let (divisor, suffix, precision) = match self {
+            ResultUnit::Instructions | ResultUnit::Misses | ResultUnit::Cycles => (1.0, "", 0),
+            ResultUnit::Nanoseconds if max < 1_000.0 => (1.0, " ns", 0),
+            ResultUnit::Nanoseconds if max < 1_000_000.0 => (1_000.0, " µs", 2),
+            ResultUnit::Nanoseconds if max < 1_000_000_000.0 => (1_000_000.0, " ms", 2),
+            ResultUnit::Nanoseconds => (1_000_000_000.0, " s", 2),
+            ResultUnit::Bytes if max < 1024.0 => (1.0, " B", 0),
+            ResultUnit::Bytes if max < 1024.0 * 1024.0 => (1024.0, " KiB", 2),
+            ResultUnit::Bytes if max < 1024.0 * 1024.0 * 1024.0 => (1024.0 * 1024.0, " MiB", 2),
+            ResultUnit::Bytes => (1024.0 * 1024.0 * 1024.0, " GiB", 2),
+            ResultUnit::Joules if max < 1.0 => (0.001, " mJ", 2),
+            ResultUnit::Joules => (1.0, " J", 2),
+        };
+
+        DisplayScale {
+            divisor,
+            suffix,
+            precision,
+        }
+    }
+}
+
+impl TryFrom<i64> for ResultUnit {
+    type Error = anyhow::Error;
+
+    fn try_from(value: i64) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Self::Instructions),
+            1 => Ok(Self::Nanoseconds),
+            2 => Ok(Self::Bytes),
+            3 => Ok(Self::Misses),
+            4 => Ok(Self::Cycles),
+            5 => Ok(Self::Joules),
+            unit => bail!("invalid result unit: {unit}"),
+        }
+    }
+}
+
+/// How to render values of a [`ResultUnit`] in reports (see [`ResultUnit::common_scale`])
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct DisplayScale {
+    divisor: f64,
+    suffix: &'static str,
+    precision: usize,
+}
+
+impl DisplayScale {
+    /// Renders the value (expressed in the base unit) with this scale
+    pub fn format(&self, value: impl Borrow<f64>) -> String {
+        // Note: we need to use `Borrow` to sidestep askama limitations (templates pass some
+        // arguments by reference)
+        let value = *value.borrow() / self.divisor;
+        format!("{value:.0$}{1}", self.precision, self.suffix)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn common_scale_fits_both_values() {
+        let scale = |x: f64, y: f64| ResultUnit::Nanoseconds.common_scale(x, y);
+        assert_eq!(scale(500.0, 999.0).format(500.0), "500 ns");
+        assert_eq!(scale(500.0, 1_999.0).format(500.0), "0.50 µs");
+        assert_eq!(scale(1_000.0, 1_999.0).format(1_000.0), "1.00 µs");
+        assert_eq!(scale(1_000_000.0, 1_999.0).format(1_999.0), "0.00 ms");
+        assert_eq!(scale(1_000_000_000.0, 1_999.0).format(1_999.0), "0.00 s");
+        assert_eq!(scale(-2_000.0, 1.0).format(-2_000.0), "-2.00 µs");
+
+        let scale = |x: f64, y: f64| ResultUnit::Bytes.common_scale(x, y);
+        assert_eq!(scale(512.0, 1_000.0).format(512.0), "512 B");
+        assert_eq!(scale(512.0, 2_048.0).format(512.0), "0.50 KiB");
+        assert_eq!(scale(3_145_728.0, 1.0).format(3_145_728.0), "3.00 MiB");
+
+        let scale = |x: f64, y: f64| ResultUnit::Instructions.common_scale(x, y);
+        assert_eq!(scale(123_456_789.0, 1.0).format(1.0), "1");
+    }
+
+    #[test]
+    fn format_renders_the_scaled_value() {
+        let format = |unit: ResultUnit, value: f64| unit.common_scale(value, value).format(value);
+        assert_eq!(format(ResultUnit::Nanoseconds, 100.0), "100 ns");
+        assert_eq!(format(ResultUnit::Nanoseconds, 1_500.0), "1.50 µs");
+        assert_eq!(format(ResultUnit::Nanoseconds, 1_250_000.0), "1.25 ms");
+        assert_eq!(format(ResultUnit::Nanoseconds, 1_420_000_000.0), "1.42 s");
+        assert_eq!(format(ResultUnit::Bytes, 5_368_709_120.0), "5.00 GiB");
+        assert_eq!(format(ResultUnit::Joules, 0.125), "125.00 mJ");
+        assert_eq!(format(ResultUnit::Joules, 3.5), "3.50 J");
+        assert_eq!(format(ResultUnit::Instructions, -42.0), "-42");
+        assert_eq!(format(ResultUnit::Instructions, 123_456_789.0), "123456789");
+    }
+}